@@ -0,0 +1,343 @@
+# English messages. This catalog is compiled into the bot and is used
+# whenever a message is missing from the selected language.
+#
+# To add a language, put a `<code>.ftl` file with the same keys into the
+# `locales` directory next to the bot. Module and command descriptions and
+# help messages can be translated too, using the following keys:
+#   <module>-description
+#   <module>-<command>-description
+#   <module>-<command>-help
+# where <module> is the module name in lowercase with symbols replaced by
+# dashes and <command> is the first name of the command, for example
+# `fun-fraktur-help` or `wolfram-alpha-wolphramalpha-description`.
+
+locale-name = English
+
+## Shared
+
+command-name-taken = There's already a `!{ $name }` command.
+command-not-found = Could not find the `!{ $command }` command in any of the modules!
+channel-info-error = Huh, I couldn't get this channel's info for some reason. Try again I guess?
+something-broken = Something's broken. :/ ({ $error })
+invalid-command-id = Invalid ID
+storage-error = I couldn't save that. :( ({ $error })
+locale-unknown = There's no `{ $code }` language. Available languages: { $available }.
+
+## Bot
+
+bot-message-too-long = I tried sending a message but Discord told me it was too long. :(
+bot-private-channel-error = Error creating a private channel: `{ $error }`.
+
+pipeline-too-long = Sorry, but pipelines can't be longer than { $max } commands.
+pipeline-not-pipeable = The output of `!{ $command }` can't be piped into another command.
+command-crashed = Oops, something went wrong. Please report this with the error ID `{ $id }`.
+command-disabled = `{ $command }` is disabled because it kept crashing.
+command-timed-out = Sorry, that took too long (over { $seconds } seconds), so I gave up.
+command-wrong-scope = `{ $command }` can only be used { $places }.
+scope-servers = on servers
+scope-nsfw = in NSFW channels
+scope-private = in private messages
+scope-groups = in group chats
+scope-separator = { " or " }
+
+## Admin
+
+admin-member-info-error = Sorry, I couldn't get your member info.
+admin-required = Only admins can do that.
+admin-roles = Admin roles:
+admin-role-removed = this role was removed
+admin-no-roles = There are no admin roles yet.
+admin-no-role-mentions = You didn't mention any roles.
+admin-nuke-error = Error getting the recent messages.
+admin-nuked = Deleted { $count } messages.
+admin-nuke-export = Messages in { $channel } deleted with `!nuke` by **{ $user }** ({ $count }):
+admin-nuke-export-error = Couldn't post the messages in the mod-log channel, so they weren't deleted.
+admin-nuke-export-no-mod-log = There's no mod-log channel to export the messages to, set one with `!auditlog channel`.
+admin-nuke-export-on = `!nuke` exports the messages to the mod-log channel before deleting them.
+admin-nuke-export-off = `!nuke` doesn't export the messages unless asked with `--export`.
+admin-nuked-partially = Deleted { $count } of { $total } messages, I couldn't delete the rest.
+admin-language-set = The default language on this server is now { $locale }.
+admin-language-reset = The default language on this server is now English.
+admin-audit-log = Recent admin actions:
+admin-audit-log-empty = There are no admin actions in the audit log.
+admin-audit-log-entry = { $time } **{ $user }** in { $channel }: `{ $command }` — { $outcome }
+admin-audit-log-failed = failed: { $error }
+admin-audit-log-done = done
+admin-audit-log-nuked = deleted { $count } messages
+admin-invalid-argument = I don't understand `{ $argument }`, see `!help { $command }`.
+admin-member-not-found = That user isn't on this server.
+admin-unknown-role = That's not a role on this server.
+admin-target-outranks = You can't do that to **{ $user }**, their highest role isn't lower than yours.
+admin-bot-outranked = I can't do that to **{ $user }**, their highest role isn't lower than mine.
+admin-moderation-error = Couldn't do that: { $error }
+admin-kicked = Kicked **{ $user }**.
+admin-banned = Banned **{ $user }**.
+admin-banned-for = Banned **{ $user }** for { $duration }.
+admin-unbanned = Unbanned **{ $user }**.
+admin-muted = Muted **{ $user }**.
+admin-muted-for = Muted **{ $user }** for { $duration }.
+admin-unmuted = Unmuted **{ $user }**.
+admin-not-notified = I couldn't tell them in a private message.
+admin-dm-kicked = You were kicked from **{ $server }**.
+admin-dm-banned = You were banned from **{ $server }**.
+admin-dm-banned-for = You were banned from **{ $server }** for { $duration }.
+admin-dm-muted = You were muted on **{ $server }**.
+admin-dm-muted-for = You were muted on **{ $server }** for { $duration }.
+admin-dm-reason = Reason: { $reason }
+admin-no-mute-role = There's no mute role, set one with `!mute role <role mention>`.
+admin-mute-role = Muted members get the { $role } role.
+admin-mute-role-set = Muted members will get the { $role } role. I denied it talking in { $count } channels.
+admin-mute-role-removed = Removed the mute role.
+admin-ban-expired = The ban of **{ $user }** expired, so I unbanned them.
+admin-mute-expired = The mute of **{ $user }** expired, so I unmuted them.
+admin-timed-action-failed = Couldn't undo the expired ban or mute of **{ $user }**: { $error }
+admin-warned = Warned **{ $user }**, they have { $count } active warnings now.
+admin-dm-warned = You were warned on **{ $server }**: { $reason }
+admin-warnings = **{ $user }** has { $count } active warnings:
+admin-warning = { $time } by **{ $moderator }**: { $reason }
+admin-warning-expired = { $time } by **{ $moderator }** (expired): { $reason }
+admin-no-warnings = **{ $user }** has no warnings.
+admin-warning-cleared = Removed warning { $number } of **{ $user }**.
+admin-warnings-cleared = Removed the warnings of **{ $user }**.
+admin-no-such-warning = **{ $user }** has no warning { $number }.
+admin-warning-expiry = Warnings expire after { $days } days.
+admin-warning-no-expiry = Warnings don't expire.
+admin-warning-policy = At:
+admin-warning-policy-empty = Nothing happens automatically at any number of warnings.
+admin-warning-policy-step = { $count } warnings, { $action }
+admin-warning-policy-set = At { $count } warnings, members will be { $action }.
+admin-warning-policy-removed = Nothing happens at { $count } warnings anymore.
+admin-escalation-mute = muted
+admin-escalation-mute-for = muted for { $duration }
+admin-escalation-kick = kicked
+admin-escalation-ban = banned
+admin-escalation-ban-for = banned for { $duration }
+admin-escalation-reason = Reached { $count } warnings.
+admin-auto-warned = They have { $count } active warnings now.
+admin-auto-escalated = They have { $count } active warnings now, so they were { $action }.
+admin-mod-log-set = Admin actions will be posted in { $channel }.
+admin-mod-log-off = Admin actions won't be posted anymore.
+admin-mod-log-unknown-channel = That's not a channel on this server.
+
+## Archive
+
+archive-status-on = The messages on this server are archived for { $days } days. There are { $archived-days } days in the archive.
+archive-status-off = The messages on this server aren't archived. An admin can turn it on with `!archive on`.
+archive-invalid-days = The number of days should be between 1 and { $max }.
+archive-on = The messages on this server will be archived for { $days } days.
+archive-off = The messages on this server won't be archived anymore.
+archive-cleared = Deleted the archived messages.
+archive-invalid-filter = I don't understand `{ $filter }`. Use `from:@user`, `in:#channel`, `before:YYYY-MM-DD` and `after:YYYY-MM-DD`.
+archive-no-results = Nothing found.
+archive-results = Found these messages:
+archive-result = { $time } **{ $user }** in { $channel }: { $text } <{ $link }>
+archive-result-deleted = { $time } **{ $user }** in { $channel } (deleted): { $text }
+
+## Automod
+
+automod-status = The automod rules of this server:
+automod-rule-on = `{ $rule }`: on, at { $limit }, { $action }.
+automod-rule-off = `{ $rule }`: off.
+automod-limit-duplicates = { $threshold } identical messages in { $window } seconds
+automod-limit-mentions = { $threshold } mentions in a message
+automod-limit-caps = { $threshold }% capital letters
+automod-limit-emoji = { $threshold } emoji in a message
+automod-limit-invites = { $threshold } invites in a message
+automod-limit-links = { $threshold } links in a message
+automod-limit-attachments = { $threshold } attachments in { $window } seconds
+automod-action-delete = delete the message
+automod-action-warn = delete the message and warn the author
+automod-action-mute = delete the message and mute the author
+automod-action-kick = delete the message and kick the author
+automod-action-ban = ban the author
+automod-unknown-rule = There's no `{ $rule }` rule. The rules are { $rules }.
+automod-rule-enabled = Turned on the `{ $rule }` rule, at { $limit } it will { $action }.
+automod-rule-disabled = Turned off the `{ $rule }` rule.
+automod-limit-set = The `{ $rule }` rule now triggers at { $limit }.
+automod-action-set = The `{ $rule }` rule will now { $action }.
+automod-invalid-threshold = The threshold should be between 1 and { $max }.
+automod-invalid-window = The window should be between 1 and { $max } seconds.
+automod-no-window = The `{ $rule }` rule looks at one message at a time.
+automod-invalid-action = The actions are `delete`, `warn`, `mute`, `kick` and `ban`.
+automod-no-mute-role = there's no mute role
+automod-warning = { $user }, { $reason }
+automod-warning-reason = Automod `{ $rule }`: { $reason }
+automod-reason-duplicates = please don't send the same message over and over.
+automod-reason-mentions = please don't mention so many people.
+automod-reason-caps = please don't shout.
+automod-reason-emoji = please don't send so many emoji.
+automod-reason-invites = invites to other servers aren't allowed here.
+automod-reason-links = links aren't allowed here.
+automod-reason-attachments = please don't send so many attachments.
+automod-log = **Automod** `{ $rule }`: **{ $user }** in { $channel }, had to { $action }.
+automod-log-failed = **Automod** `{ $rule }`: **{ $user }** in { $channel }, couldn't { $action }: { $error }
+
+## Fun
+
+fun-temperature = { $value }°{ $scale } is **{ $converted-value }**°{ $converted-scale }.
+fun-roll = { $user } rolled **{ $number }**!
+fun-pick = { $user }: I pick { $option }!
+fun-info-server =
+    Server ID: { $server-id },
+    Owner ID: { $owner-id },
+    Member count: { $member-count },
+    Icon: { $icon },
+    Roles:
+fun-info-channel = Channel ID: { $channel-id }
+fun-info-not-available = N/A
+fun-room-made-private-channel = I made a private channel?! How did I what.
+fun-room-made-group = I made a group?! How did I what.
+fun-room-error = Couldn't create a new channel: { $error } :/
+
+## Hello
+
+hello-greeting = Hi, { $user }! { $emoji }
+
+## Invite
+
+invite-link = Follow this link to invite the bot to your server: { $link }
+
+## Language
+
+language-current = Your language is { $locale }. Available languages: { $available }.
+language-set = Your language is now { $locale }.
+language-reset = You will now get replies in the server language.
+
+## Modules
+
+modules-list = List of available modules:
+modules-no-commands = There are no commands defined by this module.
+modules-command-list = Command list:
+modules-not-found = There is no module called `{ $module }`.
+modules-available-commands = Available commands:
+modules-command-entry = (module `{ $module }`): { $description }
+modules-command-scope = Can only be used { $places }.
+modules-cannot-unload = The { $module } module can't be unloaded.
+modules-loaded = Loaded the { $module } module.
+modules-already-loaded = The { $module } module is already loaded.
+modules-unloaded = Unloaded the { $module } module. It will be back after a restart.
+modules-already-unloaded = The { $module } module is already unloaded.
+modules-help-intro =
+    Bot version { $version } using **discord-rs**.
+    `!mods` - list modules!
+    `!mod <name>` - list commands of a module!
+    `!help <command>` - help for a command!
+
+    Or simply:
+    `!commands` - list all commands!
+
+## Custom Commands
+
+custom-commands-invalid-name = Command names can only have letters, digits, `-` and `_`, and be at most { $max } characters long.
+custom-commands-too-long = Replies can be at most { $max } characters long.
+custom-commands-too-many = A server can have at most { $max } custom commands.
+custom-commands-added = Added the `!{ $name }` command.
+custom-commands-edited = Changed the reply of `!{ $name }`.
+custom-commands-deleted = Deleted the `!{ $name }` command.
+custom-commands-not-found = There's no `!{ $name }` custom command.
+custom-commands-list = Custom commands on this server:
+custom-commands-none = There are no custom commands on this server yet.
+
+## Owner
+
+owner-required = Only the bot owners can do that.
+owner-status =
+    Bot version { $version }, up for { $uptime }.
+    Servers: { $servers } on { $shards } shards
+    Threads: { $threads }
+    Modules: { $modules }
+    Cache: { $cache-hits } hits, { $cache-misses } misses, { $cache-entries } entries ({ $cache-size } KiB)
+owner-status-unloaded = Unloaded modules: { $modules }
+owner-status-unknown = unknown
+owner-config-reloaded = Reloaded the config.
+owner-config-error = Couldn't reload the config: { $error }
+owner-shutdown = Shutting down. Bye!
+owner-unknown-server = I'm not on a server with the ID { $server }.
+owner-leaving = Leaving **{ $server }**.
+owner-servers = I'm on { $count } servers:
+owner-server-entry = - **{ $name }** ({ $id }): { $members } members
+owner-servers-more = ...and { $count } more.
+owner-broadcast-started = Sending the message to { $count } servers, this can take a while.
+owner-broadcast-done = Sent the message to { $sent } servers, couldn't send it to { $failed }.
+
+## Roles
+
+roles-none = There are no self-assignable roles on this server.
+roles-list = You can give yourself these roles with `!iam <role>`:
+roles-group = { $group } (only one of): { $roles }
+roles-limit = You can have at most { $limit } of them at a time.
+roles-added = { $roles } can be self-assigned now.
+roles-added-to-group = { $roles } can be self-assigned now, only one of the `{ $group }` group at a time.
+roles-removed = { $roles } can't be self-assigned anymore.
+roles-elevated = { $role } can't be self-assignable, it has moderation or management permissions.
+roles-above-you = { $role } can't be self-assignable, it isn't below your highest role.
+roles-above-bot = { $role } can't be self-assignable, it isn't below my highest role.
+roles-invalid-group = Group names can only have letters, digits, `-` and `_`, and be at most { $max } characters long.
+roles-invalid-limit = The limit should be a number from 1 to { $max }, or `off`.
+roles-limit-set = Members can have at most { $limit } self-assignable roles now.
+roles-limit-off = Members can have any number of self-assignable roles now.
+roles-unknown = There's no self-assignable role called `{ $role }`, see `!roles`.
+roles-given = You have the { $role } role now.
+roles-given-instead = You have the { $role } role now, instead of { $removed }.
+roles-taken = You don't have the { $role } role anymore.
+roles-already-have = You already have the { $role } role.
+roles-dont-have = You don't have the { $role } role.
+roles-limit-reached = You can have at most { $limit } self-assignable roles, drop one with `!iamnot <role>` first.
+roles-error = I couldn't change your roles, do I have the Manage Roles permission and a role above that one? ({ $error })
+
+## Scripts
+
+scripts-invalid-name = Script names can only have letters, digits, `-` and `_`, and be at most { $max } characters long.
+scripts-too-long = Scripts can be at most { $max } characters long.
+scripts-too-many = A server can have at most { $max } scripts.
+scripts-added = Added the `!{ $name }` script.
+scripts-updated = Updated the `!{ $name }` script.
+scripts-description-set = Updated the description of `!{ $name }`.
+scripts-removed = Removed the `!{ $name }` script.
+scripts-not-found = There's no `!{ $name }` script.
+scripts-list = Scripts on this server:
+scripts-no-scripts = There are no scripts on this server yet.
+scripts-syntax-error = The script has an error: { $error }
+scripts-error = The script failed: { $error }
+
+## Speedrun
+
+speedrun-world-records = World records for **{ $game }**:
+speedrun-world-record = { $category }: **{ $time }** by { $players }!
+speedrun-no-world-records = **{ $game }** has no world records. :|
+speedrun-personal-bests = **{ $player }**'s personal bests in **{ $game }**:
+speedrun-personal-best = { $category }: **{ $time }** - { $place }
+speedrun-no-personal-bests = **{ $player }** has no personal bests in **{ $game }**. :|
+speedrun-player-list = { $players } and { $last-player }
+speedrun-nameless-player = nameless player
+# Ordinal numbers by their CLDR plural category.
+speedrun-ordinal-one = { $place }st
+speedrun-ordinal-two = { $place }nd
+speedrun-ordinal-few = { $place }rd
+speedrun-ordinal-other = { $place }th
+speedrun-network-error = Couldn't communicate with https://www.speedrun.com. :( ({ $error })
+speedrun-no-such-game = There's no such game on speedrun.com! :O
+speedrun-no-such-player = There's no such player on speedrun.com! :O
+speedrun-no-categories = *{ $game }* doesn't seem to have any categories. :/
+
+## Spotify
+
+spotify-invalid-invocation = invalid invocation of !spotify
+spotify-network-error = Couldn't reach Spotify: { $error }
+spotify-error =
+    Spotify doesn't want you to do that: { $status }
+    { $error }
+
+## Wolfram!Alpha
+
+wolfram-alpha-input-interpretation = Input interpretation:
+wolfram-alpha-result = Result:
+wolfram-alpha-not-understood = Wolfram!Alpha couldn't understand your input. :/
+wolfram-alpha-no-result = Wolfram!Alpha didn't return a result pod. This probably means that the standard computation time exceeded.
+wolfram-alpha-image-only = Wolfram!Alpha returned the result only as an image. :/
+wolfram-alpha-image-error = Something's broken. :/ (Couldn't get the resulting image returned by the API: { $error })
+wolfram-alpha-network-error = Couldn't communicate with http://api.wolframalpha.com. :( ({ $error })
+
+## YouTube
+
+youtube-error = Google doesn't want you to do that
@@ -1,87 +1,537 @@
+use circular_queue::CircularQueue;
+use command_table::CommandTable;
 use discord;
 use discord::*;
 use discord::model::*;
 use hyper::status::StatusCode;
-use module::Module;
+use locale::{Locale, Localization};
+use module::{CancellationToken, Module};
+use rest::{self, Webhook};
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::collections::hash_map::HashMap;
 use std::io::Read;
 use std::sync::{Arc, RwLock};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// Discord allows only one shard to identify every 5 seconds.
+const IDENTIFY_INTERVAL_SECS: u64 = 5;
+
+// How many times a dropped shard tries to reconnect, and the wait after the first failure,
+// doubled after every next one.
+const RECONNECT_ATTEMPTS: u32 = 6;
+const RECONNECT_BACKOFF_SECS: u64 = 5;
+
+// How many command messages per channel are remembered for re-running them on edit.
+const COMMAND_MESSAGE_QUEUE_SIZE: usize = 64;
+
+// Discord IDs start with the milliseconds since 2015-01-01.
+const DISCORD_EPOCH_MILLIS: u64 = 1420070400000;
+
+// Discord refuses to bulk delete messages older than two weeks, a minute less leaves some leeway.
+const BULK_DELETE_MAX_AGE_SECS: u64 = 14 * 86400 - 60;
+
+// Seconds since the Unix epoch when the message, or anything else with this ID, was created.
+pub fn message_time(id: u64) -> u64 {
+    ((id >> 22) + DISCORD_EPOCH_MILLIS) / 1000
+}
+
+// The cancellation token of the command running on the current thread, for the code without a Bot.
+// Outside of commands it never gets cancelled.
+pub fn current_cancellation_token() -> CancellationToken {
+    COMMAND_RUN.with(|run| {
+                         run.borrow()
+                             .as_ref()
+                             .map(|run| run.cancellation.clone())
+                             .unwrap_or_default()
+                     })
+}
+
+// A command message and the messages the bot sent in response to it.
+struct CommandMessage {
+    command: MessageId,
+    outputs: Vec<MessageId>,
+    rerun_on_edit: bool,
+}
+
+// The command running on the current thread, see Bot::run_command().
+struct CommandRun {
+    channel: ChannelId,
+    // Outputs of the previous run which haven't been reused yet.
+    previous: Vec<MessageId>,
+    outputs: Vec<MessageId>,
+    cancellation: CancellationToken,
+}
+
+thread_local! {
+	static COMMAND_RUN: RefCell<Option<CommandRun>> = RefCell::new(None);
+}
 
 pub struct BotThreadUnsafe {
-    connection: Connection,
+    // Events from all shards. None means that one of the connections was closed.
+    events: Receiver<Option<Event>>,
     sync_part: Arc<Bot>,
 }
 
 pub struct Bot {
     discord: Discord,
-    state: RwLock<State>,
+    // For the requests discord-rs can't make, see rest.rs.
+    token: String,
+    // One state per shard, indexed by the shard ID.
+    states: Vec<RwLock<State>>,
     modules: Vec<Box<Module>>,
+    command_table: CommandTable,
+    localization: Localization,
+    command_messages: RwLock<HashMap<ChannelId, CircularQueue<CommandMessage>>>,
+    // Indices of the modules unloaded with `!modules unload`.
+    disabled_modules: RwLock<HashSet<usize>>,
+    started: Instant,
+    // The webhook of the bot in every channel it was needed in,
+    // None if the bot isn't allowed to manage the webhooks there.
+    webhooks: RwLock<HashMap<ChannelId, Option<Webhook>>>,
 }
 
 impl BotThreadUnsafe {
-    pub fn new(discord: Discord, modules: Vec<Box<Module>>) -> Self {
+    pub fn new(discord: Discord,
+               token: String,
+               shard_count: u8,
+               modules: Vec<Box<Module>>,
+               command_table: CommandTable,
+               localization: Localization)
+               -> Self {
         // Connect.
-        let (connection, ready) = discord.connect().expect("Connect failed");
-        println!("[Ready] {} is serving {} servers.",
-                 ready.user.username,
-                 ready.servers.len());
+        let mut connections = Vec::new();
+        let mut states = Vec::new();
+
+        for shard in 0..shard_count {
+            if shard > 0 {
+                thread::sleep(Duration::from_secs(IDENTIFY_INTERVAL_SECS));
+            }
+
+            let (connection, ready) = discord.connect_sharded(shard, shard_count).expect("Connect failed");
+            println!("[Ready] {} is serving {} servers on shard {}/{}.",
+                     ready.user.username,
+                     ready.servers.len(),
+                     shard + 1,
+                     shard_count);
+
+            connections.push(connection);
+            states.push(RwLock::new(State::new(ready)));
+        }
+
+        let sync_part = Arc::new(Bot {
+                                     discord: discord,
+                                     token: token,
+                                     states: states,
+                                     modules: modules,
+                                     command_table: command_table,
+                                     localization: localization,
+                                     command_messages: RwLock::new(HashMap::new()),
+                                     disabled_modules: RwLock::new(HashSet::new()),
+                                     started: Instant::now(),
+                                     webhooks: RwLock::new(HashMap::new()),
+                                 });
+
+        // Each shard receives events on its own thread.
+        let (sender, receiver) = mpsc::channel();
+
+        for (shard, connection) in connections.into_iter().enumerate() {
+            let bot = sync_part.clone();
+            let sender = sender.clone();
+
+            thread::spawn(move || bot.receive_events(shard, connection, sender));
+        }
 
         BotThreadUnsafe {
-            connection: connection,
-            sync_part: Arc::new(Bot {
-                                    discord: discord,
-                                    state: RwLock::new(State::new(ready)),
-                                    modules: modules,
-                                }),
+            events: receiver,
+            sync_part: sync_part,
         }
     }
 
     pub fn receive_event(&mut self) -> Option<Event> {
-        let event = match self.connection.recv_event() {
-            Ok(event) => event,
-            Err(err) => {
-                println!("[Warning] Receive error: {:?}.", err);
-
-                match err {
-                    discord::Error::WebSocket(..) => {
-                        // The connection was dropped, try to reconnect.
-                        let (new_connection, ready) = self.sync_part.discord.connect().expect("Connect failed");
-                        self.connection = new_connection;
-                        *self.sync_part.state.write().unwrap() = State::new(ready);
-                        println!("[Ready] Reconnected successfully.");
+        self.events.recv().ok().and_then(|x| x)
+    }
+
+    pub fn get_sync(&self) -> &Arc<Bot> {
+        &self.sync_part
+    }
+}
+
+impl Bot {
+    pub fn get_modules(&self) -> &Vec<Box<Module>> {
+        &self.modules
+    }
+
+    // The modules which haven't been unloaded.
+    pub fn get_enabled_modules(&self) -> Vec<&Module> {
+        self.modules
+            .iter()
+            .enumerate()
+            .filter(|&(i, _)| self.is_module_enabled(i))
+            .map(|(_, m)| &**m)
+            .collect()
+    }
+
+    pub fn is_module_enabled(&self, index: usize) -> bool {
+        !self.disabled_modules.read().unwrap().contains(&index)
+    }
+
+    // Loads or unloads the module until the bot restarts. Returns false if it already was.
+    pub fn set_module_enabled(&self, index: usize, enabled: bool) -> bool {
+        let mut disabled = self.disabled_modules.write().unwrap();
+
+        if enabled {
+            disabled.remove(&index)
+        } else {
+            disabled.insert(index)
+        }
+    }
+
+    pub fn get_uptime(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    // Returns the module index and the command ID of the built-in command with the given lowercase name.
+    pub fn find_command(&self, name: &str) -> Option<(usize, u32)> {
+        self.command_table.find(name)
+    }
+
+    // Returns the module which has a command with the given name on the server, built-in or custom.
+    pub fn get_command_module(&self, server: ServerId, name: &str) -> Option<&Module> {
+        match self.find_command(name) {
+            Some((i, _)) => Some(&*self.modules[i]),
+            None => {
+                self.modules
+                    .iter()
+                    .find(|m| m.custom_commands(server).iter().any(|c| c.name == name))
+                    .map(|m| &**m)
+            }
+        }
+    }
+
+    pub fn get_shard_count(&self) -> usize {
+        self.states.len()
+    }
+
+    // The states of all shards.
+    pub fn get_states(&self) -> &[RwLock<State>] {
+        &self.states
+    }
+
+    // Returns the state of the shard which has the given channel.
+    // Private channels and unknown channels belong to the first shard.
+    pub fn get_state_for_channel(&self, channel: ChannelId) -> &RwLock<State> {
+        for state in &self.states {
+            if state.read().unwrap().find_channel(channel).is_some() {
+                return state;
+            }
+        }
+
+        &self.states[0]
+    }
+
+    // Returns the state of the shard which has the given server.
+    pub fn get_state_for_server(&self, server: ServerId) -> &RwLock<State> {
+        &self.states[((server.0 >> 22) % self.states.len() as u64) as usize]
+    }
+
+    fn receive_events(&self, shard: usize, mut connection: Connection, events: Sender<Option<Event>>) {
+        loop {
+            let event = match connection.recv_event() {
+                Ok(event) => event,
+                Err(err) => {
+                    println!("[Warning] Receive error on shard {}: {:?}.", shard + 1, err);
+
+                    match err {
+                        discord::Error::WebSocket(..) => {
+                            // The connection was dropped, try to reconnect.
+                            match self.reconnect(shard) {
+                                Some(new_connection) => connection = new_connection,
+                                None => {
+                                    // The servers of this shard would go silent otherwise,
+                                    // stop the bot so that it gets restarted.
+                                    let _ = events.send(None);
+                                    return;
+                                }
+                            }
+                        }
+                        discord::Error::Closed(..) => {
+                            let _ = events.send(None);
+                            return;
+                        }
+                        _ => {}
                     }
-                    discord::Error::Closed(..) => return None,
-                    _ => {}
+
+                    continue;
                 }
+            };
+
+            self.states[shard].write().unwrap().update(&event);
 
-                return self.receive_event();
+            if events.send(Some(event)).is_err() {
+                // The main loop has stopped.
+                return;
+            }
+        }
+    }
+
+    // Reconnects the shard, waiting longer after every failed attempt.
+    fn reconnect(&self, shard: usize) -> Option<Connection> {
+        for attempt in 0..RECONNECT_ATTEMPTS {
+            match self.discord.connect_sharded(shard as u8, self.states.len() as u8) {
+                Ok((connection, ready)) => {
+                    *self.states[shard].write().unwrap() = State::new(ready);
+                    println!("[Ready] Shard {} reconnected successfully.", shard + 1);
+                    return Some(connection);
+                }
+                Err(err) => {
+                    println!("[Warning] Reconnecting shard {} failed: {:?}.", shard + 1, err);
+                    thread::sleep(Duration::from_secs(RECONNECT_BACKOFF_SECS << attempt));
+                }
             }
+        }
+
+        println!("[Warning] Giving up on reconnecting shard {}.", shard + 1);
+        None
+    }
+
+    pub fn get_localization(&self) -> &Localization {
+        &self.localization
+    }
+
+    // Returns the locale for replying to the given message.
+    // Don't call this while holding the state lock.
+    pub fn get_locale<'a>(&'a self, message: &Message) -> Locale<'a> {
+        let server = self.get_server_for_channel(message.channel_id);
+        self.localization.get_locale(Some(message.author.id), server)
+    }
+
+    // Returns the locale of the server the channel belongs to.
+    // Don't call this while holding the state lock.
+    pub fn get_channel_locale<'a>(&'a self, channel: ChannelId) -> Locale<'a> {
+        let server = self.get_server_for_channel(channel);
+        self.localization.get_locale(None, server)
+    }
+
+    // Returns the server the channel belongs to, None for private channels and groups.
+    // Don't call this while holding the state lock.
+    pub fn get_server_for_channel(&self, channel: ChannelId) -> Option<ServerId> {
+        match self.get_state_for_channel(channel).read().unwrap().find_channel(channel) {
+            Some(ChannelRef::Public(server, _)) => Some(server.id),
+            _ => None,
+        }
+    }
+
+    // Returns whether the server channel is marked NSFW.
+    // Channels named `nsfw...` count too, that's how they used to be marked.
+    // Don't call this while holding the state lock.
+    pub fn is_nsfw_channel(&self, channel: ChannelId) -> bool {
+        let named_nsfw = match self.get_state_for_channel(channel).read().unwrap().find_channel(channel) {
+            Some(ChannelRef::Public(_, channel)) => channel.name.to_lowercase().starts_with("nsfw"),
+            _ => return false,
         };
 
-        self.sync_part.state.write().unwrap().update(&event);
+        named_nsfw ||
+        match rest::is_nsfw(&self.token, channel) {
+            Ok(nsfw) => nsfw,
+            Err(err) => {
+                println!("[Warning] Couldn't check whether a channel is NSFW: {}", err);
+                false
+            }
+        }
+    }
 
-        Some(event)
+    // Runs a command handler, remembering what it sends to the channel of the message.
+    // If the message was handled before, its previous outputs are edited in place and the
+    // ones which weren't needed this time are deleted.
+    // Once the cancellation token is cancelled, nothing the handler sends gets through.
+    pub fn run_command<F: FnOnce()>(&self,
+                                    message: &Message,
+                                    rerun_on_edit: bool,
+                                    cancellation: CancellationToken,
+                                    handler: F) {
+        let mut previous = self.take_command_outputs(message.channel_id, message.id);
+        // Outputs are reused in the order they were sent.
+        previous.reverse();
+
+        COMMAND_RUN.with(|run| {
+            *run.borrow_mut() = Some(CommandRun {
+                                         channel: message.channel_id,
+                                         previous: previous,
+                                         outputs: Vec::new(),
+                                         cancellation: cancellation,
+                                     })
+        });
+
+        handler();
+
+        let run = COMMAND_RUN.with(|run| run.borrow_mut().take()).unwrap();
+        self.delete_messages(message.channel_id, &run.previous);
+
+        if run.outputs.is_empty() {
+            return;
+        }
+
+        let command = CommandMessage {
+            command: message.id,
+            outputs: run.outputs,
+            rerun_on_edit: rerun_on_edit,
+        };
+
+        let mut command_messages = self.command_messages.write().unwrap();
+        let queue = command_messages
+            .entry(message.channel_id)
+            .or_insert_with(|| CircularQueue::new(COMMAND_MESSAGE_QUEUE_SIZE));
+
+        // A re-run replaces the old entry.
+        match queue.iter_mut().find(|x| x.command == message.id) {
+            Some(x) => *x = command,
+            None => queue.push(command),
+        }
     }
 
-    pub fn get_sync(&self) -> &Arc<Bot> {
-        &self.sync_part
+    // Returns the cancellation token of the command running on the current thread.
+    pub fn cancellation_token(&self) -> CancellationToken {
+        current_cancellation_token()
     }
-}
 
-impl Bot {
-    pub fn get_modules(&self) -> &Vec<Box<Module>> {
-        &self.modules
+    fn is_cancelled(&self) -> bool {
+        self.cancellation_token().is_cancelled()
+    }
+
+    // Returns true if the message is a recent command which should be re-run when it's edited.
+    pub fn reruns_on_edit(&self, channel: ChannelId, message: MessageId) -> bool {
+        self.command_messages
+            .read()
+            .unwrap()
+            .get(&channel)
+            .map(|x| {
+                     x.iter()
+                         .any(|x| x.command == message && x.rerun_on_edit && !x.outputs.is_empty())
+                 }) == Some(true)
+    }
+
+    // Deletes the messages the bot sent in response to the command message.
+    pub fn delete_command_outputs(&self, channel: ChannelId, message: MessageId) {
+        let outputs = self.take_command_outputs(channel, message);
+        self.delete_messages(channel, &outputs);
+    }
+
+    fn take_command_outputs(&self, channel: ChannelId, message: MessageId) -> Vec<MessageId> {
+        self.command_messages
+            .write()
+            .unwrap()
+            .get_mut(&channel)
+            .and_then(|x| x.iter_mut().find(|x| x.command == message))
+            .map(|x| x.outputs.split_off(0))
+            .unwrap_or_default()
     }
 
-    pub fn get_state(&self) -> &RwLock<State> {
-        &self.state
+    // Sends a message, or edits an output of the previous run of the current command.
+    fn send_message(&self, channel: ChannelId, text: &str) -> Result<Message> {
+        if self.is_cancelled() {
+            return Err(discord::Error::Other("The command was cancelled."));
+        }
+
+        let previous = COMMAND_RUN.with(|run| match *run.borrow_mut() {
+                                            Some(ref mut run) if run.channel == channel => run.previous.pop(),
+                                            _ => None,
+                                        });
+
+        let result = match previous {
+            Some(id) => {
+                self.discord
+                    .edit_message(channel, id, text)
+                    .or_else(|_| self.discord.send_message(channel, text, "", false))
+            }
+            None => self.discord.send_message(channel, text, "", false),
+        };
+
+        if let Ok(ref message) = result {
+            self.remember_output(message.channel_id, message.id);
+        }
+
+        result
+    }
+
+    fn remember_output(&self, channel: ChannelId, message: MessageId) {
+        COMMAND_RUN.with(|run| if let Some(ref mut run) = *run.borrow_mut() {
+                             if run.channel == channel {
+                                 run.outputs.push(message);
+                             }
+                         });
     }
 
     pub fn send(&self, channel: ChannelId, text: &str) {
-        self.handle_error(channel, self.discord.send_message(channel, text, "", false));
+        self.handle_error(channel, self.send_message(channel, text));
+    }
+
+    // Sends a message under a custom name and avatar through a webhook of the channel.
+    // Where the bot can't use webhooks, sends it as usual with the name in front.
+    // Unlike send(), an output of the previous run of the command isn't edited but replaced.
+    pub fn send_as(&self, channel: ChannelId, name: &str, avatar_url: Option<&str>, text: &str) {
+        if self.is_cancelled() {
+            return;
+        }
+
+        // Private channels and groups don't have webhooks.
+        if self.get_server_for_channel(channel).is_some() {
+            // The webhook might have been deleted, then it's created again.
+            for _ in 0..2 {
+                let webhook = match self.get_webhook(channel) {
+                    Some(webhook) => webhook,
+                    None => break,
+                };
+
+                match rest::execute(&webhook, name, avatar_url, text) {
+                    Ok(message) => {
+                        self.remember_output(channel, message);
+                        return;
+                    }
+                    Err(rest::Error::NotFound) => {
+                        self.webhooks.write().unwrap().remove(&channel);
+                    }
+                    Err(rest::Error::MissingPermissions) => {
+                        self.webhooks.write().unwrap().insert(channel, None);
+                        break;
+                    }
+                    Err(err) => {
+                        println!("[Warning] Couldn't send a message through a webhook: {}", err);
+                        break;
+                    }
+                }
+            }
+        }
+
+        self.send(channel, &format!("**{}**: {}", name, text));
+    }
+
+    // Returns the webhook of the bot in the channel, creating it on first use.
+    fn get_webhook(&self, channel: ChannelId) -> Option<Webhook> {
+        if let Some(webhook) = self.webhooks.read().unwrap().get(&channel) {
+            return webhook.clone();
+        }
+
+        match rest::find_or_create(&self.token, channel) {
+            Ok(webhook) => {
+                self.webhooks.write().unwrap().insert(channel, Some(webhook.clone()));
+                Some(webhook)
+            }
+            Err(rest::Error::MissingPermissions) => {
+                self.webhooks.write().unwrap().insert(channel, None);
+                None
+            }
+            Err(err) => {
+                println!("[Warning] Couldn't get the webhook of a channel: {}", err);
+                None
+            }
+        }
     }
 
     pub fn send_and_get(&self, channel: ChannelId, text: &str) -> Option<Message> {
-        let result = self.discord.send_message(channel, text, "", false);
+        let result = self.send_message(channel, text);
 
         if result.is_ok() {
             Some(result.unwrap())
@@ -100,6 +550,7 @@ impl Bot {
         }
     }
 
+    #[allow(dead_code)]
     pub fn edit(&self, channel: ChannelId, message: MessageId, text: &str) {
         self.handle_error(channel, self.discord.edit_message(channel, message, text));
     }
@@ -113,30 +564,88 @@ impl Bot {
             }
 
             Err(err) => {
+                let text = self.get_channel_locale(error_reporting_channel)
+                    .format("bot-private-channel-error", &[("error", &format!("{:?}", err))]);
+
                 self.handle_error(error_reporting_channel,
                                   self.discord
-                                      .send_message(error_reporting_channel,
-                                                    &format!("Error creating a private channel: `{:?}`.", err),
-                                                    "",
-                                                    false));
+                                      .send_message(error_reporting_channel, &text, "", false));
             }
         }
     }
 
+    // Sends a private message without reporting errors anywhere, returns whether it was sent.
+    // People can turn off private messages from the members of their servers.
+    pub fn try_send_pm(&self, user: UserId, text: &str) -> bool {
+        self.discord
+            .create_private_channel(user)
+            .and_then(|channel| self.discord.send_message(channel.id, text, "", false))
+            .is_ok()
+    }
+
     pub fn send_file<R: Read>(&self, channel: ChannelId, text: &str, file: R, filename: &str) {
-        self.handle_error(channel,
-                          self.discord.send_file(channel, text, file, filename));
+        self.send_file_and_get(channel, text, file, filename);
+    }
+
+    pub fn send_file_and_get<R: Read>(&self, channel: ChannelId, text: &str, file: R, filename: &str) -> Option<Message> {
+        if self.is_cancelled() {
+            return None;
+        }
+
+        let result = self.discord.send_file(channel, text, file, filename);
+
+        if let Ok(ref message) = result {
+            self.remember_output(message.channel_id, message.id);
+        }
+
+        match result {
+            Ok(message) => Some(message),
+            Err(err) => {
+                self.handle_error::<()>(channel, Err(err));
+                None
+            }
+        }
     }
 
     pub fn broadcast_typing(&self, channel: ChannelId) {
         self.handle_error(channel, self.discord.broadcast_typing(channel));
     }
 
-    pub fn delete_messages(&self, channel: ChannelId, messages: &[MessageId]) {
-        // The Discord API accepts up to 100 at once.
-        for chunk in messages.chunks(100) {
-            self.handle_error(channel, self.discord.delete_messages(channel, chunk));
+    // Returns how many of the messages were deleted.
+    pub fn delete_messages(&self, channel: ChannelId, messages: &[MessageId]) -> usize {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|x| x.as_secs()).unwrap_or(0);
+        let (recent, old): (Vec<MessageId>, Vec<MessageId>) =
+            messages.iter().partition(|x| message_time(x.0) + BULK_DELETE_MAX_AGE_SECS > now);
+
+        let mut deleted = 0;
+
+        // The Discord API accepts up to 100 at once, and at least 2.
+        for chunk in recent.chunks(100) {
+            let result = if chunk.len() == 1 {
+                self.discord.delete_message(channel, chunk[0])
+            } else {
+                self.discord.delete_messages(channel, chunk)
+            };
+
+            if result.is_ok() {
+                deleted += chunk.len();
+            }
+
+            self.handle_error(channel, result);
         }
+
+        // The old ones have to go one by one.
+        for id in old {
+            let result = self.discord.delete_message(channel, id);
+
+            if result.is_ok() {
+                deleted += 1;
+            }
+
+            self.handle_error(channel, result);
+        }
+
+        deleted
     }
 
     pub fn get_message(&self, channel: ChannelId, message: MessageId) -> Result<Message> {
@@ -147,10 +656,58 @@ impl Bot {
         self.handle_error_and_return(self.discord.get_messages(channel, what, Some(limit)))
     }
 
+    pub fn delete_message(&self, channel: ChannelId, message: MessageId) -> Result<()> {
+        self.handle_error_and_return(self.discord.delete_message(channel, message))
+    }
+
     pub fn get_member(&self, server: ServerId, user: UserId) -> Result<Member> {
         self.handle_error_and_return(self.discord.get_member(server, user))
     }
 
+    pub fn kick_member(&self, server: ServerId, user: UserId) -> Result<()> {
+        self.handle_error_and_return(self.discord.kick_member(server, user))
+    }
+
+    pub fn ban_member(&self, server: ServerId, user: UserId, delete_message_days: u32) -> Result<()> {
+        self.handle_error_and_return(self.discord.add_ban(server, user, delete_message_days))
+    }
+
+    pub fn unban_member(&self, server: ServerId, user: UserId) -> Result<()> {
+        self.handle_error_and_return(self.discord.remove_ban(server, user))
+    }
+
+    // Gives the member the role, if they don't have it yet.
+    pub fn add_member_role(&self, server: ServerId, user: UserId, role: RoleId) -> Result<()> {
+        let mut roles = try!(self.get_member(server, user)).roles;
+
+        if roles.contains(&role) {
+            return Ok(());
+        }
+
+        roles.push(role);
+        self.handle_error_and_return(self.discord.edit_member_roles(server, user, &roles))
+    }
+
+    pub fn remove_member_role(&self, server: ServerId, user: UserId, role: RoleId) -> Result<()> {
+        let mut roles = try!(self.get_member(server, user)).roles;
+
+        if !roles.contains(&role) {
+            return Ok(());
+        }
+
+        roles.retain(|x| *x != role);
+        self.handle_error_and_return(self.discord.edit_member_roles(server, user, &roles))
+    }
+
+    // Replaces all roles of the member at once.
+    pub fn set_member_roles(&self, server: ServerId, user: UserId, roles: &[RoleId]) -> Result<()> {
+        self.handle_error_and_return(self.discord.edit_member_roles(server, user, roles))
+    }
+
+    pub fn leave_server(&self, server: ServerId) -> Result<Server> {
+        self.handle_error_and_return(self.discord.leave_server(server))
+    }
+
     pub fn create_channel(&self, server: ServerId, name: &str, kind: ChannelType) -> Result<Channel> {
         self.handle_error_and_return(self.discord.create_channel(server, name, kind))
     }
@@ -168,8 +725,24 @@ impl Bot {
                        .and_then(|x| if x.len() == 0 { None } else { Some(x) })
                        .and_then(|x| x[0].as_str()) {
                     if msg == "String value is too long." {
+                        // This can be called while the state is locked, so don't wait for it.
+                        let server = self.states
+                            .iter()
+                            .filter_map(|state| match state.try_read() {
+                                            Ok(state) => {
+                                                match state.find_channel(channel) {
+                                                    Some(ChannelRef::Public(server, _)) => Some(server.id),
+                                                    _ => None,
+                                                }
+                                            }
+                                            Err(_) => None,
+                                        })
+                            .next();
+
                         self.send(channel,
-                                  "I tried sending a message but Discord told me it was too long. :(");
+                                  &self.localization
+                                       .get_locale(None, server)
+                                       .get("bot-message-too-long"));
                     }
                 }
             }
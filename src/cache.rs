@@ -0,0 +1,333 @@
+use std::collections::hash_map::{DefaultHasher, HashMap};
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use url::Url;
+
+pub const DEFAULT_MAX_SIZE: usize = 16 * 1024 * 1024;
+
+// The query parameters which carry API keys, their values are left out of the cache files.
+const CREDENTIAL_PARAMETERS: [&'static str; 7] =
+    ["key", "api_key", "apikey", "appid", "token", "access_token", "client_secret"];
+
+// A cached response body.
+struct Entry {
+    body: Arc<Vec<u8>>,
+    // Seconds since the Unix epoch.
+    expires: u64,
+    // For evicting the least recently used entries first.
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct Entries {
+    map: HashMap<String, Entry>,
+    // The total size of the bodies.
+    size: usize,
+    // Counts the lookups, used instead of the time for last_used.
+    clock: u64,
+    hits: u64,
+    misses: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+    pub size: usize,
+}
+
+// Response bodies by their URL, each kept until its TTL runs out.
+// Once the bodies take more than max_size bytes, the least recently used ones are dropped.
+// With a directory, the entries are also written there so that they survive restarts.
+pub struct Cache {
+    entries: Mutex<Entries>,
+    max_size: usize,
+    directory: Option<PathBuf>,
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|x| x.as_secs()).unwrap_or(0)
+}
+
+// The URL as it's written into the cache files, with the values of the credential parameters replaced.
+fn redact_url(url: &str) -> String {
+    let mut parsed = match Url::parse(url) {
+        Ok(parsed) => parsed,
+        Err(_) => return url.to_owned(),
+    };
+
+    let is_credential = |name: &str| CREDENTIAL_PARAMETERS.contains(&&*name.to_lowercase());
+
+    if !parsed.query_pairs().any(|(name, _)| is_credential(&name)) {
+        return url.to_owned();
+    }
+
+    let pairs = parsed.query_pairs()
+        .map(|(name, value)| {
+            let value = if is_credential(&name) { "redacted".to_owned() } else { value.into_owned() };
+            (name.into_owned(), value)
+        })
+        .collect::<Vec<_>>();
+
+    parsed.query_pairs_mut().clear().extend_pairs(pairs);
+    parsed.as_str().to_owned()
+}
+
+impl Entries {
+    fn remove(&mut self, url: &str) {
+        if let Some(entry) = self.map.remove(url) {
+            self.size -= entry.body.len();
+        }
+    }
+
+    fn evict(&mut self, max_size: usize) {
+        while self.size > max_size {
+            let oldest = match self.map.iter().min_by_key(|&(_, x)| x.last_used) {
+                Some((url, _)) => url.clone(),
+                None => return,
+            };
+
+            self.remove(&oldest);
+        }
+    }
+}
+
+impl Cache {
+    pub fn new(max_size: usize, directory: Option<PathBuf>) -> Self {
+        let cache = Cache {
+            entries: Mutex::new(Entries::default()),
+            max_size: max_size,
+            directory: directory,
+        };
+
+        if let Err(err) = cache.remove_expired_files() {
+            println!("[Warning] Failed to clean up the cache directory: {}", err);
+        }
+
+        cache
+    }
+
+    // Returns the body cached for the URL, unless it has expired.
+    pub fn get(&self, url: &str) -> Option<Arc<Vec<u8>>> {
+        let now = now();
+        let mut entries = self.entries.lock().unwrap();
+        entries.clock += 1;
+        let clock = entries.clock;
+
+        let cached = match entries.map.get_mut(url) {
+            Some(ref mut entry) if entry.expires > now => {
+                entry.last_used = clock;
+                Some(entry.body.clone())
+            }
+            _ => None,
+        };
+
+        let cached = match cached {
+            Some(body) => Some(body),
+            None => {
+                entries.remove(url);
+
+                // Entries on disk come back into memory on first use.
+                match self.read_file(url) {
+                    Some((expires, body)) if expires > now => {
+                        let body = Arc::new(body);
+                        entries.size += body.len();
+                        entries.map.insert(url.to_owned(),
+                                           Entry {
+                                               body: body.clone(),
+                                               expires: expires,
+                                               last_used: clock,
+                                           });
+                        entries.evict(self.max_size);
+                        Some(body)
+                    }
+                    _ => None,
+                }
+            }
+        };
+
+        if cached.is_some() {
+            entries.hits += 1;
+        } else {
+            entries.misses += 1;
+        }
+
+        cached
+    }
+
+    // Caches the body for the given number of seconds.
+    pub fn insert(&self, url: &str, body: Vec<u8>, ttl: u64) {
+        if ttl == 0 || body.len() > self.max_size {
+            return;
+        }
+
+        let expires = now() + ttl;
+
+        if let Err(err) = self.write_file(url, expires, &body) {
+            println!("[Warning] Failed to write a cache file: {}", err);
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        entries.remove(url);
+        entries.clock += 1;
+
+        let entry = Entry {
+            body: Arc::new(body),
+            expires: expires,
+            last_used: entries.clock,
+        };
+
+        entries.size += entry.body.len();
+        entries.map.insert(url.to_owned(), entry);
+        entries.evict(self.max_size);
+    }
+
+    pub fn stats(&self) -> Stats {
+        let entries = self.entries.lock().unwrap();
+
+        Stats {
+            hits: entries.hits,
+            misses: entries.misses,
+            entries: entries.map.len(),
+            size: entries.size,
+        }
+    }
+
+    fn file_path(&self, url: &str) -> Option<PathBuf> {
+        let mut hasher = DefaultHasher::new();
+        url.hash(&mut hasher);
+
+        self.directory.as_ref().map(|x| x.join(format!("{:016x}", hasher.finish())))
+    }
+
+    // The files start with the expiry time and the URL without credentials on their own lines, followed by the body.
+    fn write_file(&self, url: &str, expires: u64, body: &[u8]) -> io::Result<()> {
+        let path = match self.file_path(url) {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+
+        try!(fs::create_dir_all(path.parent().unwrap()));
+        let mut file = try!(File::create(path));
+        try!(write!(file, "{}\n{}\n", expires, redact_url(url)));
+        file.write_all(body)
+    }
+
+    fn read_file(&self, url: &str) -> Option<(u64, Vec<u8>)> {
+        self.file_path(url)
+            .and_then(|path| read_cache_file(&path).ok())
+            // Different URLs can end up in the same file.
+            .and_then(|(expires, file_url, body)| if file_url == redact_url(url) { Some((expires, body)) } else { None })
+    }
+
+    fn remove_expired_files(&self) -> io::Result<()> {
+        let directory = match self.directory {
+            Some(ref directory) => directory,
+            None => return Ok(()),
+        };
+
+        let files = match fs::read_dir(directory) {
+            Ok(files) => files,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err),
+        };
+
+        let now = now();
+
+        for file in files {
+            let path = try!(file).path();
+
+            match read_cache_file(&path) {
+                Ok((expires, _, _)) if expires > now => {}
+                _ => try!(fs::remove_file(path)),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn read_cache_file(path: &Path) -> io::Result<(u64, String, Vec<u8>)> {
+    let mut data = Vec::new();
+    try!(File::open(path).and_then(|mut x| x.read_to_end(&mut data)));
+
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "not a cache file");
+
+    let mut parts = data.splitn(3, |&x| x == b'\n');
+    let expires = try!(parts.next()
+                           .and_then(|x| String::from_utf8_lossy(x).parse().ok())
+                           .ok_or_else(&invalid));
+    let url = try!(parts.next().map(|x| String::from_utf8_lossy(x).into_owned()).ok_or_else(&invalid));
+    let body = try!(parts.next().ok_or_else(&invalid)).to_vec();
+
+    Ok((expires, url, body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Cache, Stats, redact_url};
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn hits_and_misses() {
+        let cache = Cache::new(1024, None);
+
+        assert_eq!(None, cache.get("a"));
+        cache.insert("a", b"body".to_vec(), 60);
+        assert_eq!(Some(b"body".to_vec()), cache.get("a").map(|x| (*x).clone()));
+
+        // A TTL of 0 means not caching.
+        cache.insert("b", b"body".to_vec(), 0);
+        assert_eq!(None, cache.get("b"));
+
+        assert_eq!(Stats {
+                       hits: 1,
+                       misses: 2,
+                       entries: 1,
+                       size: 4,
+                   },
+                   cache.stats());
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let cache = Cache::new(10, None);
+
+        cache.insert("a", vec![0; 4], 60);
+        cache.insert("b", vec![0; 4], 60);
+        cache.get("a");
+        cache.insert("c", vec![0; 4], 60);
+
+        assert!(cache.get("a").is_some());
+        assert!(cache.get("b").is_none());
+        assert!(cache.get("c").is_some());
+        assert_eq!(8, cache.stats().size);
+    }
+
+    #[test]
+    fn disk() {
+        let directory = env::temp_dir().join("yalter-bot-cache-test");
+        let _ = fs::remove_dir_all(&directory);
+
+        Cache::new(1024, Some(directory.clone())).insert("a", b"line\nline".to_vec(), 60);
+
+        let cache = Cache::new(1024, Some(directory));
+        assert_eq!(Some(b"line\nline".to_vec()), cache.get("a").map(|x| (*x).clone()));
+        assert_eq!(None, cache.get("b"));
+    }
+
+    #[test]
+    fn redacts_credentials() {
+        assert_eq!("https://example.com/search?part=snippet&key=redacted&q=a+b",
+                   redact_url("https://example.com/search?part=snippet&key=secret&q=a+b"));
+        assert_eq!("https://example.com/v2/query?appid=redacted&input=1",
+                   redact_url("https://example.com/v2/query?appid=secret&input=1"));
+        assert_eq!("https://example.com/search?q=key", redact_url("https://example.com/search?q=key"));
+    }
+}
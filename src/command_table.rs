@@ -0,0 +1,218 @@
+use locale::module_key;
+use module::Module;
+use std::collections::hash_map::{Entry, HashMap};
+use std::fmt;
+
+// What to do about command names used by more than one module.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConflictPolicy {
+    // Refuse to start.
+    Fail,
+    // The first module wins.
+    Warn,
+    // Nobody gets the plain name, the commands are only available as `!module.command`.
+    Namespace,
+}
+
+// Something wrong with the command names of the loaded modules.
+#[derive(Debug, PartialEq)]
+pub enum Problem {
+    // The name is used by several commands, in the given modules.
+    Collision(String, Vec<&'static str>),
+    // Input is lowercased, so this name can never match.
+    Uppercase(&'static str, String),
+    // A command without names, which can't be used at all.
+    NoNames(&'static str, u32),
+}
+
+// Maps command names to the modules which handle them.
+// Every command is also available as `!<module>.<command>`, for example `!fun.roll`,
+// where <module> is the module name in lowercase with symbols replaced by dashes.
+pub struct CommandTable {
+    // Command name -> module index and command ID.
+    commands: HashMap<String, (usize, u32)>,
+}
+
+impl ConflictPolicy {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "fail" => Some(ConflictPolicy::Fail),
+            "warn" => Some(ConflictPolicy::Warn),
+            "namespace" => Some(ConflictPolicy::Namespace),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Problem::Collision(ref name, ref modules) => {
+                write!(f, "`!{}` is a command in more than one place: {}", name, modules.join(", "))
+            }
+            Problem::Uppercase(module, ref name) => {
+                write!(f, "{}: `!{}` has uppercase letters, so it can never be used", module, name)
+            }
+            Problem::NoNames(module, id) => write!(f, "{}: the command with ID {} has no names", module, id),
+        }
+    }
+}
+
+impl CommandTable {
+    // Builds the table, returning it along with the problems found.
+    // The policy only decides who gets colliding names, failing is up to the caller.
+    pub fn new(modules: &[Box<Module>], policy: ConflictPolicy) -> (Self, Vec<Problem>) {
+        let mut problems = Vec::new();
+        // Name -> every command which has it, in the order of the modules.
+        let mut claims: HashMap<&str, Vec<(usize, u32)>> = HashMap::new();
+        let mut commands = HashMap::new();
+
+        for (i, module) in modules.iter().enumerate() {
+            // Sorted so that the problems come out the same every time.
+            let mut ids: Vec<u32> = module.commands().keys().cloned().collect();
+            ids.sort();
+
+            for id in ids {
+                let names = module.commands()[&id];
+
+                if names.is_empty() {
+                    problems.push(Problem::NoNames(module.name(), id));
+                }
+
+                for &name in names {
+                    if name.chars().any(char::is_uppercase) {
+                        problems.push(Problem::Uppercase(module.name(), name.to_owned()));
+                    }
+
+                    claims.entry(name).or_default().push((i, id));
+                    commands.insert(format!("{}.{}", module_key(&**module), name), (i, id));
+                }
+            }
+        }
+
+        let mut names: Vec<&str> = claims.keys().cloned().collect();
+        names.sort();
+
+        for name in names {
+            let claim = &claims[name];
+
+            if claim.len() > 1 {
+                problems.push(Problem::Collision(name.to_owned(),
+                                                 claim.iter().map(|&(i, _)| modules[i].name()).collect()));
+
+                if policy == ConflictPolicy::Namespace {
+                    continue;
+                }
+            }
+
+            // The namespaced names win, in case a module has a command with a dot in it.
+            if let Entry::Vacant(entry) = commands.entry(name.to_owned()) {
+                entry.insert(claim[0]);
+            }
+        }
+
+        (CommandTable { commands: commands }, problems)
+    }
+
+    // Returns the module index and the command ID for the lowercase command name.
+    pub fn find(&self, name: &str) -> Option<(usize, u32)> {
+        self.commands.get(name).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CommandTable, ConflictPolicy, Problem};
+    use bot::Bot;
+    use discord::model::Message;
+    use module::Module;
+    use std::collections::hash_map::HashMap;
+
+    struct TestModule {
+        name: &'static str,
+        commands: HashMap<u32, &'static [&'static str]>,
+    }
+
+    impl Module for TestModule {
+        fn new() -> Result<Box<Module>, String> {
+            Err("unused".to_owned())
+        }
+
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        fn description(&self) -> &'static str {
+            ""
+        }
+
+        fn commands(&self) -> &HashMap<u32, &[&str]> {
+            &self.commands
+        }
+
+        fn command_description(&self, _id: u32) -> &str {
+            ""
+        }
+
+        fn command_help_message(&self, _id: u32) -> &str {
+            ""
+        }
+
+        fn handle(&self, _bot: &Bot, _message: &Message, _id: u32, _text: &str) {}
+    }
+
+    fn module(name: &'static str, commands: &[(u32, &'static [&'static str])]) -> Box<Module> {
+        Box::new(TestModule {
+                     name: name,
+                     commands: commands.iter().cloned().collect(),
+                 })
+    }
+
+    fn modules() -> Vec<Box<Module>> {
+        static ROLL: [&'static str; 2] = ["roll", "dice"];
+        static PICK: [&'static str; 1] = ["pick"];
+        static OTHER_ROLL: [&'static str; 1] = ["roll"];
+        static UPPERCASE: [&'static str; 1] = ["Shout"];
+        static NOTHING: [&'static str; 0] = [];
+
+        vec![module("Fun", &[(0, &ROLL), (1, &PICK)]),
+             module("Wolfram!Alpha", &[(0, &OTHER_ROLL), (1, &UPPERCASE), (2, &NOTHING)])]
+    }
+
+    #[test]
+    fn problems() {
+        let (_, problems) = CommandTable::new(&modules(), ConflictPolicy::Warn);
+
+        assert_eq!(vec![Problem::Uppercase("Wolfram!Alpha", "Shout".to_owned()),
+                        Problem::NoNames("Wolfram!Alpha", 2),
+                        Problem::Collision("roll".to_owned(), vec!["Fun", "Wolfram!Alpha"])],
+                   problems);
+    }
+
+    #[test]
+    fn warn_first_module_wins() {
+        let (table, _) = CommandTable::new(&modules(), ConflictPolicy::Warn);
+
+        assert_eq!(Some((0, 0)), table.find("roll"));
+        assert_eq!(Some((0, 0)), table.find("dice"));
+        assert_eq!(Some((0, 1)), table.find("pick"));
+        assert_eq!(Some((1, 0)), table.find("wolfram-alpha.roll"));
+        assert_eq!(None, table.find("nope"));
+    }
+
+    #[test]
+    fn namespace_collisions() {
+        let (table, _) = CommandTable::new(&modules(), ConflictPolicy::Namespace);
+
+        assert_eq!(None, table.find("roll"));
+        assert_eq!(Some((0, 0)), table.find("dice"));
+        assert_eq!(Some((0, 0)), table.find("fun.roll"));
+        assert_eq!(Some((1, 0)), table.find("wolfram-alpha.roll"));
+    }
+
+    #[test]
+    fn no_problems() {
+        let (_, problems) = CommandTable::new(&modules()[..1], ConflictPolicy::Fail);
+        assert!(problems.is_empty());
+    }
+}
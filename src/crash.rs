@@ -0,0 +1,164 @@
+use discord::model::Message;
+use rand;
+use std::any::Any;
+use std::backtrace::Backtrace;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// A command is disabled after this many panics within CRASH_WINDOW_SECS.
+const MAX_CRASHES: usize = 3;
+const CRASH_WINDOW_SECS: u64 = 60 * 60;
+
+// What the panic hook saw, kept until the dispatcher writes the report.
+struct Panic {
+    location: String,
+    backtrace: String,
+}
+
+thread_local! {
+	static LAST_PANIC: RefCell<Option<Panic>> = RefCell::new(None);
+}
+
+// Writes crash reports for panicking commands and disables the ones which keep panicking.
+pub struct CrashReports {
+    directory: PathBuf,
+    // Recent panics of every command.
+    crashes: Mutex<HashMap<String, Vec<Instant>>>,
+    // Disabled until the bot restarts.
+    disabled: RwLock<HashSet<String>>,
+}
+
+// Makes panics remember their location and backtrace for the crash report.
+// The panic is still printed as usual.
+pub fn install_panic_hook() {
+    let default_hook = panic::take_hook();
+
+    panic::set_hook(Box::new(move |info| {
+        let location = info.location()
+            .map(|x| format!("{}:{}", x.file(), x.line()))
+            .unwrap_or_else(|| "unknown".to_owned());
+
+        LAST_PANIC.with(|x| {
+                            *x.borrow_mut() = Some(Panic {
+                                                       location: location,
+                                                       backtrace: Backtrace::force_capture().to_string(),
+                                                   })
+                        });
+
+        default_hook(info);
+    }));
+}
+
+fn panic_message(payload: &Box<Any + Send>) -> String {
+    if let Some(x) = payload.downcast_ref::<&str>() {
+        x.to_string()
+    } else if let Some(x) = payload.downcast_ref::<String>() {
+        x.clone()
+    } else {
+        "(not a string)".to_owned()
+    }
+}
+
+impl CrashReports {
+    pub fn new(directory: &str) -> Self {
+        CrashReports {
+            directory: PathBuf::from(directory),
+            crashes: Mutex::new(HashMap::new()),
+            disabled: RwLock::new(HashSet::new()),
+        }
+    }
+
+    pub fn is_disabled(&self, command: &str) -> bool {
+        self.disabled.read().unwrap().contains(command)
+    }
+
+    // Writes a crash report for a panic caught on the current thread and returns its error ID.
+    // Disables the command if it panicked too often recently.
+    pub fn report(&self, command: &str, message: &Message, arguments: &str, payload: &Box<Any + Send>) -> String {
+        let id = format!("{:08x}", rand::random::<u32>());
+        let panic = LAST_PANIC.with(|x| x.borrow_mut().take());
+
+        let mut report = format!("Error ID: {}\n\
+                                  Time: {}\n\
+                                  Command: {}\n\
+                                  Arguments: {}\n\
+                                  Message: {}\n\
+                                  Author: {} ({})\n\
+                                  Channel: {}\n\n\
+                                  Panic: {}\n",
+                                 id,
+                                 SystemTime::now().duration_since(UNIX_EPOCH).map(|x| x.as_secs()).unwrap_or(0),
+                                 command,
+                                 arguments,
+                                 message.content,
+                                 message.author.name,
+                                 message.author.id,
+                                 message.channel_id,
+                                 panic_message(payload));
+
+        if let Some(panic) = panic {
+            report.push_str(&format!("Location: {}\n\nBacktrace:\n{}\n", panic.location, panic.backtrace));
+        }
+
+        let path = self.directory.join(format!("{}.txt", id));
+        match self.write(&path, &report) {
+            Ok(()) => println!("[CRITICAL] `{}` panicked, see {}", command, path.display()),
+            Err(err) => println!("[CRITICAL] `{}` panicked, couldn't write the crash report: {}\n{}", command, err, report),
+        }
+
+        if self.record_crash(command) {
+            println!("[CRITICAL] Disabled `{}` after {} crashes.", command, MAX_CRASHES);
+        }
+
+        id
+    }
+
+    fn write(&self, path: &Path, report: &str) -> io::Result<()> {
+        try!(fs::create_dir_all(&self.directory));
+        let mut file = try!(File::create(path));
+        file.write_all(report.as_bytes())
+    }
+
+    // Returns true if the command got disabled.
+    fn record_crash(&self, command: &str) -> bool {
+        let mut crashes = self.crashes.lock().unwrap();
+        let recent = crashes.entry(command.to_owned()).or_default();
+
+        let window = Duration::from_secs(CRASH_WINDOW_SECS);
+        recent.retain(|x| x.elapsed() < window);
+        recent.push(Instant::now());
+
+        if recent.len() < MAX_CRASHES {
+            return false;
+        }
+
+        recent.clear();
+        self.disabled.write().unwrap().insert(command.to_owned());
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CrashReports, MAX_CRASHES};
+
+    #[test]
+    fn disable_after_crashes() {
+        let reports = CrashReports::new("crashes");
+
+        for _ in 1..MAX_CRASHES {
+            assert!(!reports.record_crash("!roll"));
+        }
+
+        assert!(!reports.is_disabled("!roll"));
+        assert!(reports.record_crash("!roll"));
+        assert!(reports.is_disabled("!roll"));
+        assert!(!reports.is_disabled("!pick"));
+    }
+}
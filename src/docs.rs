@@ -0,0 +1,158 @@
+use locale::{Catalog, Locale};
+use module::{CommandScope, Module};
+use modules;
+use serde_json::{self, Map, Value};
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+
+// Every module in the order the bot loads them.
+// The reference needs neither the API keys, the client ID nor the storage, so they're left out.
+pub fn all_modules() -> Vec<Box<Module>> {
+    vec![modules::hello::Module::new(),
+         modules::modules::Module::new(),
+         modules::fun::Module::new(),
+         modules::speedruncom::Module::new(),
+         Ok(Box::new(modules::admin::Module::without_storage()) as Box<Module>),
+         Ok(Box::new(modules::wolframalpha::Module::with_api_key(String::new())) as Box<Module>),
+         Ok(Box::new(modules::invite::Module::without_link()) as Box<Module>),
+         Ok(Box::new(modules::youtube::Module::with_api_key(String::new())) as Box<Module>),
+         Ok(Box::new(modules::spotify::Module::with_api_key(String::new())) as Box<Module>),
+         modules::language::Module::new(),
+         modules::scripts::Module::new(),
+         modules::customcommands::Module::new(),
+         modules::owner::Module::new(),
+         modules::archive::Module::new(),
+         modules::automod::Module::new(),
+         modules::roles::Module::new()]
+            .into_iter()
+            .map(|m| m.expect("Couldn't create a module"))
+            .collect()
+}
+
+// The commands of the module ordered by their IDs, which is the order they're declared in.
+fn sorted_commands(module: &Module) -> Vec<(u32, &[&str])> {
+    let mut commands = module.commands().iter().map(|(&id, &names)| (id, names)).collect::<Vec<_>>();
+    commands.sort_by_key(|x| x.0);
+    commands
+}
+
+// The command reference in Markdown.
+pub fn markdown(modules: &[Box<Module>], locale: &Locale) -> String {
+    let mut buf = String::from("# Command reference\n\nGenerated with `yalter-bot --dump-help`, don't edit it by hand.\n");
+
+    for m in modules {
+        buf.push_str(&format!("\n## {}\n{}\n", m.name(), locale.module_description(&**m)));
+
+        for (id, names) in sorted_commands(&**m) {
+            let names = names.iter().map(|x| format!("`!{}`", x)).collect::<Vec<_>>();
+
+            buf.push_str(&format!("\n### {}\n{}\n\n", names.join(", "), locale.command_description(&**m, id)));
+
+            // The help messages have one usage per line, which Markdown would join otherwise.
+            buf.push_str(&locale.command_help_message(&**m, id).replace('\n', "  \n"));
+            buf.push('\n');
+
+            let scope = m.command_scope(id);
+            if scope != CommandScope::anywhere() {
+                buf.push_str(&format!("\n*{}*\n",
+                                      locale.format("modules-command-scope",
+                                                    &[("places", &locale.command_scope(scope))])));
+            }
+        }
+    }
+
+    buf
+}
+
+// The command reference as JSON, for other tools.
+pub fn json(modules: &[Box<Module>], locale: &Locale) -> Value {
+    let modules = modules.iter()
+        .map(|m| {
+            let commands = sorted_commands(&**m)
+                .into_iter()
+                .map(|(id, names)| {
+                    let mut command = Map::new();
+                    command.insert("names".to_owned(), Value::from(names.to_vec()));
+                    command.insert("description".to_owned(), Value::from(locale.command_description(&**m, id)));
+                    command.insert("help".to_owned(), Value::from(locale.command_help_message(&**m, id)));
+                    command.insert("scope".to_owned(), serde_json::to_value(m.command_scope(id)).unwrap());
+                    Value::Object(command)
+                })
+                .collect::<Vec<_>>();
+
+            let mut module = Map::new();
+            module.insert("name".to_owned(), Value::from(m.name()));
+            module.insert("description".to_owned(), Value::from(locale.module_description(&**m)));
+            module.insert("commands".to_owned(), Value::Array(commands));
+            Value::Object(module)
+        })
+        .collect();
+
+    Value::Array(modules)
+}
+
+// Writes `commands.md` and `commands.json` into the directory, in English.
+pub fn dump_help(directory: &str) -> io::Result<()> {
+    let modules = all_modules();
+    let catalog = Catalog::fallback();
+    let locale = Locale::from_catalog(&catalog);
+
+    let directory = Path::new(directory);
+    try!(fs::create_dir_all(directory));
+
+    try!(try!(File::create(directory.join("commands.md"))).write_all(markdown(&modules, &locale).as_bytes()));
+
+    let json = serde_json::to_string_pretty(&json(&modules, &locale)).unwrap();
+    try!(try!(File::create(directory.join("commands.json"))).write_all(json.as_bytes()));
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{all_modules, json, markdown, sorted_commands};
+    use locale::{Catalog, Locale};
+
+    #[test]
+    fn every_command_has_help() {
+        let catalog = Catalog::fallback();
+        let locale = Locale::from_catalog(&catalog);
+
+        for m in all_modules() {
+            assert!(!locale.module_description(&*m).is_empty(), "{} has no description", m.name());
+
+            for (id, names) in sorted_commands(&*m) {
+                let description = locale.command_description(&*m, id);
+                let help = locale.command_help_message(&*m, id);
+
+                assert!(!description.is_empty(), "`!{}` has no description", names[0]);
+                // The help message should show how to use the command.
+                assert!(names.iter().any(|x| help.contains(&format!("`!{}", x))),
+                        "the help message of `!{}` doesn't show its usage: {}",
+                        names[0],
+                        help);
+            }
+        }
+    }
+
+    #[test]
+    fn reference_lists_every_command() {
+        let catalog = Catalog::fallback();
+        let locale = Locale::from_catalog(&catalog);
+        let modules = all_modules();
+
+        let markdown = markdown(&modules, &locale);
+        assert!(markdown.contains("\n### `!roll`"));
+        assert!(markdown.contains("*Can only be used on servers.*"));
+
+        let json = json(&modules, &locale);
+        let count = modules.iter().map(|m| m.commands().len()).sum::<usize>();
+        assert_eq!(count,
+                   json.as_array()
+                       .unwrap()
+                       .iter()
+                       .map(|m| m["commands"].as_array().unwrap().len())
+                       .sum::<usize>());
+    }
+}
@@ -0,0 +1,343 @@
+use bot;
+use cache::Cache;
+use hyper;
+use hyper::client::{Client, Response};
+use hyper::header::{ContentType, Headers, UserAgent};
+use hyper::status::{StatusClass, StatusCode};
+use module::CancellationToken;
+use serde_json::Value;
+use std::io::Read;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use url::Url;
+
+pub const DEFAULT_TIMEOUT_SECS: u64 = 15;
+
+// A request is retried this many times on connection errors and 5xx responses.
+const MAX_RETRIES: u32 = 3;
+// The delay before the first retry, doubled after each one.
+const RETRY_DELAY_MILLIS: u64 = 500;
+
+// How long responses are cached unless `cache.ttl.<service>` in the config says otherwise.
+const DEFAULT_CACHE_TTL_SECS: u64 = 600;
+
+// Passing this with a command skips the cache.
+const FRESH_FLAG: &'static str = "--fresh";
+
+lazy_static! {
+	static ref USERAGENT: UserAgent = UserAgent(concat!("yalter-bot/", env!("CARGO_PKG_VERSION")).to_string());
+}
+
+// The HTTP client shared by all modules, see ::HTTP.
+// Connections are kept alive and reused between requests.
+pub struct Http {
+    client: Client,
+    // The read and write timeout of every attempt.
+    timeout: Duration,
+    retry_delay: Duration,
+    cache: Cache,
+    // Returns the cache TTL of a service in seconds.
+    cache_ttl: fn(&str) -> u64,
+}
+
+// A response read into memory, see Http::get_cached().
+pub struct CachedResponse {
+    pub status: StatusCode,
+    pub body: Arc<Vec<u8>>,
+}
+
+impl Http {
+    pub fn new(timeout: Duration, cache: Cache) -> Self {
+        Self::with_client(Client::new(), timeout, cache)
+    }
+
+    fn with_client(mut client: Client, timeout: Duration, cache: Cache) -> Self {
+        client.set_read_timeout(Some(timeout));
+        client.set_write_timeout(Some(timeout));
+
+        Http {
+            client: client,
+            timeout: timeout,
+            retry_delay: Duration::from_millis(RETRY_DELAY_MILLIS),
+            cache: cache,
+            cache_ttl: cache_ttl,
+        }
+    }
+
+    // Sends a POST request with a JSON body. It isn't retried, since it might have gone through.
+    pub fn post_json(&self, url: &str, mut headers: Headers, body: &Value) -> hyper::Result<Response> {
+        headers.set(USERAGENT.clone());
+        headers.set(ContentType::json());
+
+        let body = body.to_string();
+        self.client.post(url).headers(headers).body(&body[..]).send()
+    }
+
+    pub fn get_cached(&self, service: &str, url: &str, fresh: bool) -> hyper::Result<CachedResponse> {
+        self.get_cached_with_headers(service, url, Headers::new(), fresh)
+    }
+
+    // Like get_with_headers(), but successful responses are cached by the URL for the TTL of the service.
+    // `fresh` skips the cache, the response still gets cached though.
+    pub fn get_cached_with_headers(&self,
+                                   service: &str,
+                                   url: &str,
+                                   headers: Headers,
+                                   fresh: bool)
+                                   -> hyper::Result<CachedResponse> {
+        if !fresh {
+            if let Some(body) = self.cache.get(url) {
+                return Ok(CachedResponse {
+                              status: StatusCode::Ok,
+                              body: body,
+                          });
+            }
+        }
+
+        let mut response = try!(self.get_with_headers(url, headers));
+        let mut body = Vec::new();
+        try!(response.read_to_end(&mut body));
+
+        if response.status.is_success() {
+            self.cache.insert(url, body.clone(), (self.cache_ttl)(service));
+        }
+
+        Ok(CachedResponse {
+               status: response.status,
+               body: Arc::new(body),
+           })
+    }
+
+    pub fn get_cache(&self) -> &Cache {
+        &self.cache
+    }
+
+    pub fn get(&self, url: &str) -> hyper::Result<Response> {
+        self.get_with_headers(url, Headers::new())
+    }
+
+    // Sends a GET request with the bot's User-Agent.
+    // If the server keeps failing, the last 5xx response is returned as is.
+    // Within a command, it isn't retried once the command is cancelled or if the retry could outlast the command.
+    pub fn get_with_headers(&self, url: &str, headers: Headers) -> hyper::Result<Response> {
+        self.get_until(url, headers, &bot::current_cancellation_token())
+    }
+
+    fn get_until(&self, url: &str, mut headers: Headers, cancellation: &CancellationToken) -> hyper::Result<Response> {
+        headers.set(USERAGENT.clone());
+
+        let mut delay = self.retry_delay;
+        let mut retries = 0;
+
+        loop {
+            let result = self.client.get(url).headers(headers.clone()).send();
+
+            let retry = match result {
+                Ok(ref response) => response.status.class() == StatusClass::ServerError,
+                Err(hyper::Error::Io(_)) => true,
+                Err(_) => false,
+            };
+
+            if !retry || retries == MAX_RETRIES {
+                return result;
+            }
+
+            let out_of_time = cancellation.is_cancelled() ||
+                              cancellation.remaining().iter().any(|&x| x < delay + self.timeout);
+
+            if out_of_time {
+                return result;
+            }
+
+            match result {
+                Ok(ref response) => println!("[Warning] GET {} returned {}, retrying.", url, response.status),
+                Err(ref err) => println!("[Warning] GET {} failed: {}, retrying.", url, err),
+            }
+
+            thread::sleep(delay);
+            delay *= 2;
+            retries += 1;
+        }
+    }
+}
+
+fn cache_ttl(service: &str) -> u64 {
+    ::CONF
+        .read()
+        .unwrap()
+        .pointer(&format!("/cache/ttl/{}", service))
+        .and_then(|x| x.as_u64())
+        .unwrap_or(DEFAULT_CACHE_TTL_SECS)
+}
+
+// Removes `--fresh` from the start or the end of the command arguments,
+// returning whether it was there.
+pub fn take_fresh_flag(text: &str) -> (&str, bool) {
+    let text = text.trim();
+
+    if text == FRESH_FLAG {
+        ("", true)
+    } else if text.starts_with(FRESH_FLAG) && text[FRESH_FLAG.len()..].starts_with(char::is_whitespace) {
+        (text[FRESH_FLAG.len()..].trim_start(), true)
+    } else if text.ends_with(FRESH_FLAG) && text[..text.len() - FRESH_FLAG.len()].ends_with(char::is_whitespace) {
+        (text[..text.len() - FRESH_FLAG.len()].trim_end(), true)
+    } else {
+        (text, false)
+    }
+}
+
+// Returns the base URL of a service: `base_urls.<service>` in the config if it's there,
+// so that the modules can be pointed at other servers, otherwise the default.
+pub fn base_url(service: &str, default: &str) -> Url {
+    base_url_from(&::CONF.read().unwrap(), service, default)
+}
+
+fn base_url_from(conf: &Value, service: &str, default: &str) -> Url {
+    if let Some(url) = conf.pointer(&format!("/base_urls/{}", service)).and_then(|x| x.as_str()) {
+        match Url::parse(url) {
+            Ok(url) => return url,
+            Err(err) => println!("[Warning] Ignoring the invalid base URL of {}: {}", service, err),
+        }
+    }
+
+    Url::parse(default).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Http, base_url_from, take_fresh_flag};
+    use cache::Cache;
+    use hyper::client::Client;
+    use hyper::header::Headers;
+    use hyper::net::HttpConnector;
+    use hyper::status::StatusCode;
+    use module::CancellationToken;
+    use serde_json;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+    use std::time::Duration;
+
+    // Serves the given statuses to the requests in order, returning the URL of the server
+    // and a thread which returns the requests it got.
+    fn mock_server(statuses: Vec<&'static str>) -> (String, thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+
+        let server = thread::spawn(move || {
+            let mut requests = Vec::new();
+
+            for status in statuses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut buf = [0; 4096];
+                let len = stream.read(&mut buf).unwrap();
+                requests.push(String::from_utf8_lossy(&buf[..len]).into_owned());
+
+                write!(stream,
+                       "HTTP/1.1 {}\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok",
+                       status)
+                        .unwrap();
+            }
+
+            requests
+        });
+
+        (url, server)
+    }
+
+    // Plain HTTP is enough for the mock servers.
+    fn http() -> Http {
+        let mut http = Http::with_client(Client::with_connector(HttpConnector),
+                                         Duration::from_secs(5),
+                                         Cache::new(1024, None));
+        http.retry_delay = Duration::from_millis(1);
+        http.cache_ttl = |_| 60;
+        http
+    }
+
+    #[test]
+    fn retries_server_errors() {
+        let (url, server) = mock_server(vec!["503 Service Unavailable", "500 Internal Server Error", "200 OK"]);
+
+        let mut response = http().get(&url).unwrap();
+        let mut body = String::new();
+        response.read_to_string(&mut body).unwrap();
+
+        assert_eq!(StatusCode::Ok, response.status);
+        assert_eq!("ok", body);
+
+        let requests = server.join().unwrap();
+        assert_eq!(3, requests.len());
+        assert!(requests.iter().all(|x| x.contains(concat!("yalter-bot/", env!("CARGO_PKG_VERSION")))));
+    }
+
+    #[test]
+    fn gives_up_eventually() {
+        let (url, server) = mock_server(vec!["502 Bad Gateway"; 4]);
+
+        assert_eq!(StatusCode::BadGateway, http().get(&url).unwrap().status);
+        assert_eq!(4, server.join().unwrap().len());
+    }
+
+    #[test]
+    fn no_retry_past_the_deadline() {
+        let (url, server) = mock_server(vec!["503 Service Unavailable"]);
+
+        // Another attempt could take the whole 5 second timeout.
+        let cancellation = CancellationToken::with_timeout(Duration::from_secs(3));
+        assert_eq!(StatusCode::ServiceUnavailable,
+                   http().get_until(&url, Headers::new(), &cancellation).unwrap().status);
+        assert_eq!(1, server.join().unwrap().len());
+    }
+
+    #[test]
+    fn no_retry_once_cancelled() {
+        let (url, server) = mock_server(vec!["503 Service Unavailable"]);
+
+        let cancellation = CancellationToken::default();
+        cancellation.cancel();
+        assert_eq!(StatusCode::ServiceUnavailable,
+                   http().get_until(&url, Headers::new(), &cancellation).unwrap().status);
+        assert_eq!(1, server.join().unwrap().len());
+    }
+
+    #[test]
+    fn no_retry_on_client_errors() {
+        let (url, server) = mock_server(vec!["404 Not Found"]);
+
+        assert_eq!(StatusCode::NotFound, http().get(&url).unwrap().status);
+        assert_eq!(1, server.join().unwrap().len());
+    }
+
+    #[test]
+    fn caches_successful_responses() {
+        let (url, server) = mock_server(vec!["500 Internal Server Error", "200 OK", "200 OK"]);
+        let http = http();
+
+        assert_eq!(b"ok".to_vec(), *http.get_cached("mock", &url, false).unwrap().body);
+        assert_eq!(b"ok".to_vec(), *http.get_cached("mock", &url, false).unwrap().body);
+        assert_eq!(b"ok".to_vec(), *http.get_cached("mock", &url, true).unwrap().body);
+
+        assert_eq!(3, server.join().unwrap().len());
+        assert_eq!(1, http.get_cache().stats().hits);
+    }
+
+    #[test]
+    fn fresh_flag() {
+        assert_eq!(("Half-Life", true), take_fresh_flag("--fresh Half-Life"));
+        assert_eq!(("Half-Life", true), take_fresh_flag("Half-Life --fresh"));
+        assert_eq!(("", true), take_fresh_flag(" --fresh "));
+        assert_eq!(("Half-Life --freshness", false), take_fresh_flag("Half-Life --freshness"));
+    }
+
+    #[test]
+    fn base_url_override() {
+        let conf = serde_json::from_str(r#"{ "base_urls": { "mock": "http://127.0.0.1:1234/api/" } }"#).unwrap();
+
+        assert_eq!("http://127.0.0.1:1234/api/",
+                   base_url_from(&conf, "mock", "https://example.com/").as_str());
+        assert_eq!("https://example.com/",
+                   base_url_from(&conf, "other", "https://example.com/").as_str());
+    }
+}
@@ -0,0 +1,451 @@
+use discord::model::{ServerId, UserId};
+use module::{CommandScope, Module};
+use std::collections::BTreeMap;
+use std::collections::hash_map::HashMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::Read;
+use storage::{self, Scope};
+
+// The locale used when there's no setting or when a message is missing from a catalog.
+pub const FALLBACK_LOCALE: &'static str = "en";
+
+// Additional catalogs are loaded from `<LOCALES_DIRECTORY>/<code>.ftl`.
+const LOCALES_DIRECTORY: &'static str = "locales";
+
+// How deep messages can reference other messages, which also stops reference cycles.
+const MAX_REFERENCE_DEPTH: usize = 8;
+
+// The name of the storage the settings are kept in.
+const STORAGE_NAME: &'static str = "locale";
+
+// The English catalog is compiled in so that there's always something to fall back to.
+static FALLBACK_CATALOG: &'static str = include_str!("../locales/en.ftl");
+
+// A set of messages in one language.
+//
+// The format is a subset of Fluent (http://projectfluent.org/):
+// `key = value` lines, indented lines continuing the previous value,
+// `{ $name }` placeable arguments, `{ other-key }` message references,
+// `{ "{" }` string literals and `#` comments.
+pub struct Catalog {
+    messages: HashMap<String, String>,
+}
+
+// Arguments for the placeables of a message.
+pub type Args<'a> = [(&'a str, &'a fmt::Display)];
+
+pub struct Localization {
+    catalogs: BTreeMap<String, Catalog>,
+}
+
+// A resolved locale: the requested catalog followed by the fallback one.
+pub struct Locale<'a> {
+    catalogs: Vec<&'a Catalog>,
+}
+
+impl Catalog {
+    // The built-in English catalog.
+    pub fn fallback() -> Catalog {
+        Catalog::parse(FALLBACK_CATALOG)
+    }
+
+    pub fn parse(source: &str) -> Catalog {
+        let mut messages = HashMap::new();
+        let mut current: Option<(String, String)> = None;
+        // Blank lines are kept only if the value continues after them.
+        let mut blank_lines = 0;
+
+        for line in source.lines() {
+            if line.trim().is_empty() {
+                blank_lines += 1;
+                continue;
+            }
+
+            if line.starts_with(char::is_whitespace) {
+                let line = line.trim();
+
+                if line.starts_with('.') {
+                    // Attributes aren't supported.
+                    continue;
+                }
+
+                if let Some((_, ref mut value)) = current {
+                    if !value.is_empty() {
+                        value.push('\n');
+
+                        for _ in 0..blank_lines {
+                            value.push('\n');
+                        }
+                    }
+                    value.push_str(line);
+                }
+
+                blank_lines = 0;
+                continue;
+            }
+
+            blank_lines = 0;
+
+            if let Some((key, value)) = current.take() {
+                messages.insert(key, value);
+            }
+
+            if line.starts_with('#') {
+                continue;
+            }
+
+            if let Some(pos) = line.find('=') {
+                let (key, value) = line.split_at(pos);
+                let key = key.trim();
+
+                if !key.is_empty() {
+                    current = Some((key.to_owned(), value.split_at(1).1.trim().to_owned()));
+                }
+            }
+        }
+
+        if let Some((key, value)) = current {
+            messages.insert(key, value);
+        }
+
+        Catalog { messages: messages }
+    }
+
+    pub fn load_from_file(path: &str) -> Result<Catalog, String> {
+        let mut file = try!(File::open(path).map_err(|e| e.to_string()));
+        let mut source = String::new();
+        try!(file.read_to_string(&mut source).map_err(|e| e.to_string()));
+
+        Ok(Catalog::parse(&source))
+    }
+
+    fn get(&self, key: &str) -> Option<&str> {
+        self.messages.get(key).map(|x| x.as_str())
+    }
+}
+
+impl Localization {
+    pub fn new() -> Self {
+        let mut catalogs = BTreeMap::new();
+        catalogs.insert(FALLBACK_LOCALE.to_owned(), Catalog::parse(FALLBACK_CATALOG));
+
+        if let Ok(entries) = fs::read_dir(LOCALES_DIRECTORY) {
+            for path in entries.filter_map(|x| x.ok()).map(|x| x.path()) {
+                if path.extension().and_then(|x| x.to_str()) != Some("ftl") {
+                    continue;
+                }
+
+                let code = match path.file_stem().and_then(|x| x.to_str()) {
+                    Some(code) => code.to_owned(),
+                    None => continue,
+                };
+
+                match Catalog::load_from_file(&path.to_string_lossy()) {
+                    Ok(catalog) => {
+                        catalogs.insert(code, catalog);
+                    }
+
+                    Err(err) => println!("[Warning] Could not load the `{}` locale: {}", code, err),
+                }
+            }
+        }
+
+        Localization { catalogs: catalogs }
+    }
+
+    // Codes of all loaded locales.
+    pub fn available_locales(&self) -> Vec<&str> {
+        self.catalogs.keys().map(|x| x.as_str()).collect()
+    }
+
+    pub fn has_locale(&self, code: &str) -> bool {
+        self.catalogs.contains_key(code)
+    }
+
+    pub fn get_user_locale(&self, user: UserId) -> Option<String> {
+        self.get_setting(Scope::User(user))
+    }
+
+    pub fn get_server_locale(&self, server: ServerId) -> Option<String> {
+        self.get_setting(Scope::Server(server))
+    }
+
+    // Passing None resets the setting.
+    pub fn set_user_locale(&self, user: UserId, code: Option<&str>) -> storage::Result<()> {
+        self.set_setting(Scope::User(user), code)
+    }
+
+    // Passing None resets the setting.
+    pub fn set_server_locale(&self, server: ServerId, code: Option<&str>) -> storage::Result<()> {
+        self.set_setting(Scope::Server(server), code)
+    }
+
+    fn get_setting(&self, scope: Scope) -> Option<String> {
+        match ::STORAGE.get(STORAGE_NAME, scope, "locale") {
+            Ok(code) => code,
+
+            Err(err) => {
+                println!("[Warning] Failed to get the locale setting: {}", err);
+                None
+            }
+        }
+    }
+
+    fn set_setting(&self, scope: Scope, code: Option<&str>) -> storage::Result<()> {
+        match code {
+            Some(code) => ::STORAGE.set(STORAGE_NAME, scope, "locale", &code),
+            None => ::STORAGE.remove(STORAGE_NAME, scope, "locale"),
+        }
+    }
+
+    // Resolves the locale for a user, preferring their own setting over the server one.
+    pub fn get_locale<'a>(&'a self, user: Option<UserId>, server: Option<ServerId>) -> Locale<'a> {
+        let code = user.and_then(|x| self.get_user_locale(x))
+            .or_else(|| server.and_then(|x| self.get_server_locale(x)));
+
+        match code {
+            Some(code) => self.get_locale_by_code(&code),
+            None => self.get_locale_by_code(FALLBACK_LOCALE),
+        }
+    }
+
+    // Returns the locale with the given code, or the fallback one if there's no such locale.
+    pub fn get_locale_by_code<'a>(&'a self, code: &str) -> Locale<'a> {
+        let (code, catalog) = match self.catalogs.get_key_value(code) {
+            Some(x) => x,
+            None => self.catalogs.get_key_value(FALLBACK_LOCALE).unwrap(),
+        };
+
+        let mut catalogs = vec![catalog];
+        if code != FALLBACK_LOCALE {
+            catalogs.push(&self.catalogs[FALLBACK_LOCALE]);
+        }
+
+        Locale { catalogs: catalogs }
+    }
+}
+
+impl<'a> Locale<'a> {
+    // A locale with just the one catalog, for when there's no Localization.
+    pub fn from_catalog(catalog: &'a Catalog) -> Locale<'a> {
+        Locale { catalogs: vec![catalog] }
+    }
+
+    fn lookup(&self, key: &str) -> Option<&'a str> {
+        self.catalogs.iter().filter_map(|x| x.get(key)).next()
+    }
+
+    // Returns the message with the given key.
+    pub fn get(&self, key: &str) -> String {
+        self.format(key, &[])
+    }
+
+    // Returns the message with the given key, filling in the arguments.
+    pub fn format(&self, key: &str, args: &Args) -> String {
+        match self.lookup(key) {
+            Some(message) => self.format_pattern(message, args),
+            None => {
+                println!("[Warning] Missing the `{}` message.", key);
+                key.to_owned()
+            }
+        }
+    }
+
+    fn format_pattern(&self, pattern: &str, args: &Args) -> String {
+        self.format_pattern_nested(pattern, args, 0)
+    }
+
+    // Formats a pattern `depth` message references deep.
+    fn format_pattern_nested(&self, pattern: &str, args: &Args, depth: usize) -> String {
+        let mut buf = String::new();
+        let mut rest = pattern;
+
+        while let Some(start) = rest.find('{') {
+            let (before, after) = rest.split_at(start);
+            buf.push_str(before);
+
+            // String literals may contain braces, so skip over them first.
+            let literal_end = if after[1..].trim_start().starts_with('"') {
+                let quote = after.find('"').unwrap();
+                after[quote + 1..].find('"').map(|x| quote + 1 + x)
+            } else {
+                Some(0)
+            };
+
+            let end = match literal_end.and_then(|x| after[x..].find('}').map(|end| x + end)) {
+                Some(end) => end,
+                None => {
+                    rest = after;
+                    break;
+                }
+            };
+
+            let (placeable, after) = after.split_at(end + 1);
+            let expression = placeable[1..placeable.len() - 1].trim();
+
+            if expression.starts_with('$') {
+                match args.iter().find(|x| x.0 == &expression[1..]) {
+                    Some(&(_, value)) => buf.push_str(&value.to_string()),
+                    None => buf.push_str(placeable),
+                }
+            } else if expression.len() >= 2 && expression.starts_with('"') && expression.ends_with('"') {
+                buf.push_str(&expression[1..expression.len() - 1]);
+            } else {
+                match self.lookup(expression) {
+                    // References don't get the arguments so that they can't recurse through them,
+                    // and the depth is limited so that cycles between messages end.
+                    Some(message) if depth < MAX_REFERENCE_DEPTH => {
+                        buf.push_str(&self.format_pattern_nested(message, &[], depth + 1))
+                    }
+                    _ => buf.push_str(placeable),
+                }
+            }
+
+            rest = after;
+        }
+
+        buf.push_str(rest);
+        buf
+    }
+
+    pub fn module_description(&self, module: &Module) -> String {
+        match self.lookup(&format!("{}-description", module_key(module))) {
+            Some(message) => self.format_pattern(message, &[]),
+            None => module.description().to_owned(),
+        }
+    }
+
+    pub fn command_description(&self, module: &Module, id: u32) -> String {
+        match self.lookup(&format!("{}-description", command_key(module, id))) {
+            Some(message) => self.format_pattern(message, &[]),
+            None => module.command_description(id).to_owned(),
+        }
+    }
+
+    pub fn command_help_message(&self, module: &Module, id: u32) -> String {
+        match self.lookup(&format!("{}-help", command_key(module, id))) {
+            Some(message) => self.format_pattern(message, &[]),
+            None => module.command_help_message(id).to_owned(),
+        }
+    }
+
+    // Lists where a command can be used, like `on servers or in group chats`.
+    pub fn command_scope(&self, scope: CommandScope) -> String {
+        let mut places = Vec::new();
+
+        if scope.servers {
+            places.push(self.get(if scope.nsfw_only { "scope-nsfw" } else { "scope-servers" }));
+        }
+        if scope.private {
+            places.push(self.get("scope-private"));
+        }
+        if scope.groups {
+            places.push(self.get("scope-groups"));
+        }
+
+        places.join(&self.get("scope-separator"))
+    }
+}
+
+// The message key prefix for a module: its name in lowercase with symbols replaced by dashes.
+// For example, `Wolfram!Alpha` becomes `wolfram-alpha`.
+pub fn module_key(module: &Module) -> String {
+    let mut key = String::new();
+
+    for c in module.name().chars().flat_map(|c| c.to_lowercase()) {
+        if c.is_alphanumeric() {
+            key.push(c);
+        } else if !key.is_empty() && !key.ends_with('-') {
+            key.push('-');
+        }
+    }
+
+    while key.ends_with('-') {
+        key.pop();
+    }
+
+    key
+}
+
+// The message key prefix for a command: the module key followed by the first command name.
+pub fn command_key(module: &Module, id: u32) -> String {
+    match module.commands().get(&id).and_then(|x| x.first()) {
+        Some(name) => format!("{}-{}", module_key(module), name),
+        None => format!("{}-{}", module_key(module), id),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Catalog, FALLBACK_CATALOG, Locale};
+    use module::CommandScope;
+
+    fn locale<'a>(catalog: &'a Catalog) -> Locale<'a> {
+        Locale { catalogs: vec![catalog] }
+    }
+
+    #[test]
+    fn catalog_parse_usual() {
+        let catalog = Catalog::parse("# Comment\nhello = Hello!\n\nbye=Bye.");
+        assert_eq!(Some("Hello!"), catalog.get("hello"));
+        assert_eq!(Some("Bye."), catalog.get("bye"));
+        assert_eq!(None, catalog.get("# Comment"));
+    }
+
+    #[test]
+    fn catalog_parse_multiline() {
+        let catalog = Catalog::parse("help =\n    First line;\n    second line.\n\n    Third line.\n\nnext = Next");
+        assert_eq!(Some("First line;\nsecond line.\n\nThird line."), catalog.get("help"));
+        assert_eq!(Some("Next"), catalog.get("next"));
+    }
+
+    #[test]
+    fn catalog_parse_fallback() {
+        let catalog = Catalog::parse(FALLBACK_CATALOG);
+        assert!(catalog.get("locale-name").is_some());
+    }
+
+    #[test]
+    fn format_arguments() {
+        let catalog = Catalog::parse("roll = { $user } rolled **{ $number }**!");
+        assert_eq!("Bob rolled **42**!",
+                   locale(&catalog).format("roll", &[("user", &"Bob"), ("number", &42)]));
+    }
+
+    #[test]
+    fn format_reference_cycle() {
+        let catalog = Catalog::parse("a = a{ b }\nb = b{ a }\nself = { self }");
+        assert!(locale(&catalog).format("a", &[]).starts_with("abab"));
+        assert!(locale(&catalog).format("self", &[]).ends_with("{ self }"));
+    }
+
+    #[test]
+    fn format_missing_argument() {
+        let catalog = Catalog::parse("roll = { $user } rolled { $number }!");
+        assert_eq!("Bob rolled { $number }!", locale(&catalog).format("roll", &[("user", &"Bob")]));
+    }
+
+    #[test]
+    fn format_literal_and_reference() {
+        let catalog = Catalog::parse("name = Bot\nhello = { \"{\" }{ name }{ \"}\" }");
+        assert_eq!("{Bot}", locale(&catalog).get("hello"));
+    }
+
+    #[test]
+    fn format_missing_message() {
+        let catalog = Catalog::parse("");
+        assert_eq!("missing-key", locale(&catalog).get("missing-key"));
+    }
+
+    #[test]
+    fn command_scope() {
+        let catalog = Catalog::parse(FALLBACK_CATALOG);
+        let locale = locale(&catalog);
+
+        assert_eq!("on servers", locale.command_scope(CommandScope::servers_only()));
+        assert_eq!("in NSFW channels", locale.command_scope(CommandScope::nsfw_only()));
+        assert_eq!("in private messages or in group chats",
+                   locale.command_scope(CommandScope { servers: false, ..CommandScope::anywhere() }));
+    }
+}
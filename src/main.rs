@@ -4,6 +4,8 @@ extern crate hyper;
 extern crate lazy_static;
 extern crate rand;
 extern crate regex;
+#[cfg(feature = "sqlite")]
+extern crate rusqlite;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
@@ -11,30 +13,126 @@ extern crate serde_json;
 extern crate url;
 extern crate xml;
 
-use std::sync::Arc;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, RwLock};
+use std::sync::mpsc::{self, RecvTimeoutError};
 use std::thread;
+use std::time::Duration;
 use std::fs::File;
+use std::path::PathBuf;
 
 extern crate discord;
 use discord::{ChannelRef, Discord};
 use discord::model::*;
 
 mod module;
-use module::Module;
+use module::{CancellationToken, CommandScope, Module};
+
+mod locale;
+use locale::Localization;
 
 mod bot;
 use bot::*;
 
+mod storage;
+use storage::Storage;
+
+mod script;
+
+mod crash;
+use crash::CrashReports;
+
+mod command_table;
+use command_table::{CommandTable, ConflictPolicy};
+
+mod cache;
+use cache::Cache;
+
+mod http;
+use http::Http;
+
+mod rest;
+
+mod docs;
+
 lazy_static! {
-    static ref CONF: serde_json::value::Value = {
-        let config = File::open("bot.json").or_else(|_| File::open(std::env::args().nth(1).expect("No bot.json found"))).expect("No bot.json found");
-        let json_result = serde_json::from_reader::<File, serde_json::Value>(config);
+    // Replaced by `!reload-config`, see reload_config().
+    static ref CONF: RwLock<serde_json::value::Value> = {
+        match load_config() {
+            Ok(v) => RwLock::new(v),
+            Err(e) => panic!("{}", e),
+        }
+    };
+
+    static ref STORAGE: Storage = {
+        let conf = CONF.read().unwrap();
+        let backend = conf.pointer("/storage").and_then(|x| x.as_str()).unwrap_or("json");
+        let directory = conf.pointer("/storage_directory").and_then(|x| x.as_str()).unwrap_or("storage");
+
+        match Storage::open(backend, directory) {
+            Ok(storage) => storage,
+            Err(e) => panic!("Couldn't open the storage: {}", e),
+        }
+    };
+
+    static ref CRASH_REPORTS: CrashReports = CrashReports::new(CONF.read()
+                                                                  .unwrap()
+                                                                  .pointer("/crash_report_directory")
+                                                                  .and_then(|x| x.as_str())
+                                                                  .unwrap_or("crashes"));
+
+    static ref HTTP: Http = {
+        let conf = CONF.read().unwrap();
+        let timeout = conf.pointer("/http_timeout").and_then(|x| x.as_u64()).unwrap_or(http::DEFAULT_TIMEOUT_SECS);
+        let max_size = conf.pointer("/cache/max_size").and_then(|x| x.as_u64()).map(|x| x as usize);
+        let directory = conf.pointer("/cache/directory").and_then(|x| x.as_str()).map(PathBuf::from);
+
+        Http::new(Duration::from_secs(timeout),
+                  Cache::new(max_size.unwrap_or(cache::DEFAULT_MAX_SIZE), directory))
+    };
+}
+
+// `--import-memory=<path>` imports the admin roles from an old memory.json into the storage and exits.
+const IMPORT_MEMORY_ARG: &'static str = "--import-memory=";
+
+// `--dump-help[=<directory>]` writes the command reference into the directory, `docs` by default, and exits.
+const DUMP_HELP_ARG: &'static str = "--dump-help";
+
+// The maximum number of commands in a pipeline.
+const MAX_PIPELINE_LENGTH: usize = 5;
+
+const DEFAULT_COMMAND_TIMEOUT_SECS: u64 = 30;
+
+// How often the modules get Module::handle_tick() called.
+const TICK_INTERVAL_SECS: u64 = 30;
 
-        match json_result {
-            Ok(v) => v,
-            Err(e) => panic!("JSON error: {}", e),
+fn load_config() -> Result<serde_json::Value, String> {
+    let config = match File::open("bot.json") {
+        Ok(file) => file,
+        Err(_) => {
+            let path = try!(std::env::args().skip(1).find(|x| !x.starts_with("--")).ok_or("No bot.json found"));
+            try!(File::open(path).map_err(|_| "No bot.json found"))
         }
     };
+
+    serde_json::from_reader::<File, serde_json::Value>(config).map_err(|e| format!("JSON error: {}", e))
+}
+
+// Reads bot.json again. Settings which are only used at startup, like the API keys,
+// need a restart to change.
+pub fn reload_config() -> Result<(), String> {
+    let config = try!(load_config());
+    *CONF.write().unwrap() = config;
+    Ok(())
+}
+
+// How long commands may run unless their module says otherwise.
+fn default_command_timeout() -> Duration {
+    Duration::from_secs(CONF.read()
+                            .unwrap()
+                            .pointer("/command_timeout")
+                            .and_then(|x| x.as_u64())
+                            .unwrap_or(DEFAULT_COMMAND_TIMEOUT_SECS))
 }
 
 mod modules {
@@ -47,6 +145,13 @@ mod modules {
     pub mod admin;
     pub mod youtube;
     pub mod spotify;
+    pub mod language;
+    pub mod scripts;
+    pub mod customcommands;
+    pub mod owner;
+    pub mod archive;
+    pub mod automod;
+    pub mod roles;
 }
 
 fn parse_command(message: &str) -> Option<(&str, &str)> {
@@ -89,53 +194,427 @@ fn parse_command(message: &str) -> Option<(&str, &str)> {
     }
 }
 
-fn handle_command(bot: Arc<Bot>, message: Arc<Message>, command: &str, text: &str) {
+// Splits a message into commands separated by `|`.
+// A `|` only separates commands when it's followed by another command,
+// so `!wa |x| = 2` is still a single command.
+fn parse_pipeline(message: &str) -> Option<Vec<(&str, &str)>> {
+    let mut stages = Vec::new();
+    let mut rest = message;
+
+    loop {
+        let split = rest.char_indices()
+            .filter(|&(_, c)| c == '|')
+            .map(|(pos, _)| pos)
+            .find(|&pos| parse_command(rest.split_at(pos + 1).1.trim_start()).is_some());
+
+        match split {
+            Some(pos) => {
+                let (a, b) = rest.split_at(pos);
+
+                // Trailing whitespace before the `|` isn't a part of the arguments.
+                match parse_command(a.trim_end()) {
+                    Some(stage) => stages.push(stage),
+                    None => return None,
+                }
+
+                // Chop off the `|` and the whitespace after it.
+                rest = b.split_at(1).1.trim_start();
+            }
+
+            None => {
+                match parse_command(rest) {
+                    Some(stage) => stages.push(stage),
+                    None => return None,
+                }
+
+                return Some(stages);
+            }
+        }
+    }
+}
+
+// Combines the arguments of a command with the output of the previous command in the pipeline.
+fn pipe_input(text: &str, input: &str) -> String {
+    if text.is_empty() {
+        input.to_string()
+    } else {
+        format!("{} {}", text, input)
+    }
+}
+
+// A command found by find_command().
+enum Target {
+    // A module index and a command ID.
+    Builtin(usize, u32),
+    // A module index and the name of a custom command of that module.
+    Custom(usize, String),
+}
+
+fn find_command(bot: &Bot, server: Option<ServerId>, command: &str) -> Option<Target> {
     let command = command.to_lowercase();
 
-    let mut index = None;
+    // Commands of unloaded modules are treated as if they didn't exist.
+    if let Some((i, id)) = bot.find_command(&command) {
+        if bot.is_module_enabled(i) {
+            return Some(Target::Builtin(i, id));
+        }
+    }
+
+    // Custom commands only exist on servers.
+    if let Some(server) = server {
+        for i in 0..bot.get_modules().len() {
+            if bot.is_module_enabled(i) &&
+               bot.get_modules()[i]
+                   .custom_commands(server)
+                   .iter()
+                   .any(|x| x.name == command) {
+                return Some(Target::Custom(i, command));
+            }
+        }
+    }
+
+    None
+}
+
+fn rerun_on_edit(bot: &Bot, target: &Target) -> bool {
+    match *target {
+        Target::Builtin(i, id) => bot.get_modules()[i].rerun_on_edit(id),
+        Target::Custom(i, ref name) => bot.get_modules()[i].rerun_custom_on_edit(name),
+    }
+}
+
+fn command_timeout(bot: &Bot, target: &Target) -> Duration {
+    match *target {
+        Target::Builtin(i, id) => bot.get_modules()[i].command_timeout(id).unwrap_or_else(default_command_timeout),
+        Target::Custom(..) => default_command_timeout(),
+    }
+}
+
+fn command_scope(bot: &Bot, target: &Target) -> CommandScope {
+    match *target {
+        Target::Builtin(i, id) => bot.get_modules()[i].command_scope(id),
+        Target::Custom(..) => CommandScope::servers_only(),
+    }
+}
+
+// Checks that the command can be used in the channel of the message, telling the user if it can't.
+fn check_scope(bot: &Bot, message: &Message, target: &Target) -> bool {
+    let scope = command_scope(bot, target);
+
+    if scope == CommandScope::anywhere() {
+        return true;
+    }
+
+    let (allowed, on_server) = match bot.get_state_for_channel(message.channel_id)
+              .read()
+              .unwrap()
+              .find_channel(message.channel_id) {
+        Some(ChannelRef::Public(..)) => (scope.servers, true),
+        Some(ChannelRef::Private(_)) => (scope.private, false),
+        Some(ChannelRef::Group(_)) => (scope.groups, false),
+        // The command can deal with it.
+        None => (true, false),
+    };
+
+    // Checked last since it might take a request.
+    if allowed && (!on_server || !scope.nsfw_only || bot.is_nsfw_channel(message.channel_id)) {
+        return true;
+    }
+
+    let locale = bot.get_locale(message);
+    bot.send(message.channel_id,
+             &locale.format("command-wrong-scope",
+                            &[("command", &command_name(bot, target)),
+                              ("places", &locale.command_scope(scope))]));
+    false
+}
+
+// The name of the command for logs and crash reports, like `!roll`.
+fn command_name(bot: &Bot, target: &Target) -> String {
+    match *target {
+        Target::Builtin(i, id) => {
+            let module = &bot.get_modules()[i];
+            format!("!{}.{}", locale::module_key(&**module), module.commands()[&id][0])
+        }
+        Target::Custom(_, ref name) => format!("!{}", name),
+    }
+}
+
+// Calls a handler of the command, catching panics.
+// A panic is written to a crash report and the user gets its error ID. Returns None in that case,
+// when the command is disabled for panicking too often and when it can't be used in the channel.
+fn call_handler<R, F: FnOnce() -> R>(bot: &Bot, message: &Message, target: &Target, text: &str, handler: F) -> Option<R> {
+    let command = command_name(bot, target);
+
+    if CRASH_REPORTS.is_disabled(&command) {
+        bot.send(message.channel_id,
+                 &bot.get_locale(message).format("command-disabled", &[("command", &command)]));
+        return None;
+    }
+
+    if !check_scope(bot, message, target) {
+        return None;
+    }
+
+    match panic::catch_unwind(AssertUnwindSafe(handler)) {
+        Ok(x) => Some(x),
+        Err(payload) => {
+            let id = CRASH_REPORTS.report(&command, message, text, &payload);
+            bot.send(message.channel_id,
+                     &bot.get_locale(message).format("command-crashed", &[("id", &id)]));
+            None
+        }
+    }
+}
+
+// Runs a command handler on its own thread.
+// If it doesn't finish in time, it's cancelled and the user is told so.
+fn spawn_command<F>(bot: Arc<Bot>, message: Arc<Message>, rerun_on_edit: bool, timeout: Duration, handler: F)
+    where F: FnOnce(&Bot, &Message) + Send + 'static
+{
+    thread::spawn(move || {
+        let cancellation = CancellationToken::with_timeout(timeout);
+        let (sender, receiver) = mpsc::channel();
+
+        {
+            let bot = bot.clone();
+            let message = message.clone();
+            let cancellation = cancellation.clone();
+
+            thread::spawn(move || {
+                bot.run_command(&message, rerun_on_edit, cancellation, || handler(&bot, &message));
+                let _ = sender.send(());
+            });
+        }
+
+        if let Err(RecvTimeoutError::Timeout) = receiver.recv_timeout(timeout) {
+            cancellation.cancel();
+
+            println!("[Warning] Cancelled a command after {} seconds: `{}`",
+                     timeout.as_secs(),
+                     message.content);
+
+            bot.send(message.channel_id,
+                     &bot.get_locale(&message)
+                          .format("command-timed-out", &[("seconds", &timeout.as_secs())]));
+        }
+    });
+}
+
+// Handles the commands in a message. `rerun` is true if the message was edited.
+fn handle_commands(bot: Arc<Bot>, message: Arc<Message>, server: Option<ServerId>, mut stages: Vec<(&str, &str)>, rerun: bool) {
+    if stages.len() == 1 {
+        let (command, text) = stages.pop().unwrap();
+        handle_command(bot, message, server, command, text, rerun);
+    } else {
+        handle_pipeline(bot, message, server, stages, rerun);
+    }
+}
+
+fn handle_command(bot: Arc<Bot>, message: Arc<Message>, server: Option<ServerId>, command: &str, text: &str, rerun: bool) {
+    let target = match find_command(&bot, server, command) {
+        Some(target) => target,
+        None => {
+            if rerun {
+                bot.delete_command_outputs(message.channel_id, message.id);
+            }
+            return;
+        }
+    };
+
+    let rerun_on_edit = rerun_on_edit(&bot, &target);
+    let timeout = command_timeout(&bot, &target);
+
+    // An edit never runs a command with side effects.
+    if rerun && !rerun_on_edit {
+        bot.delete_command_outputs(message.channel_id, message.id);
+        return;
+    }
 
-    'outer: for i in 0..bot.get_modules().len() {
-        let module = &bot.get_modules()[i];
+    let text_copy = text.to_string();
 
-        for (&id, &cmds) in module.commands() {
-            if let Some(_) = cmds.iter().find(|&&x| x == command) {
-                index = Some((i, id));
-                break 'outer;
+    spawn_command(bot, message, rerun_on_edit, timeout, move |bot, message| {
+        call_handler(bot, message, &target, &text_copy, || match target {
+            Target::Builtin(i, id) => bot.get_modules()[i].handle(bot, message, id, &text_copy),
+            Target::Custom(i, ref name) => bot.get_modules()[i].handle_custom(bot, message, name, &text_copy),
+        });
+    });
+}
+
+fn handle_pipeline(bot: Arc<Bot>, message: Arc<Message>, server: Option<ServerId>, stages: Vec<(&str, &str)>, rerun: bool) {
+    if stages.len() > MAX_PIPELINE_LENGTH {
+        let text = bot.get_locale(&message).format("pipeline-too-long", &[("max", &MAX_PIPELINE_LENGTH)]);
+        send_pipeline_error(bot, message, text);
+        return;
+    }
+
+    // Look up every command first so that nothing runs if one of them doesn't exist.
+    let mut commands = Vec::new();
+    for &(command, text) in &stages {
+        match find_command(&bot, server, command) {
+            Some(target) => commands.push((command.to_string(), target, text.to_string())),
+            None => {
+                let text = bot.get_locale(&message)
+                    .format("command-not-found", &[("command", &command.to_lowercase())]);
+                send_pipeline_error(bot, message, text);
+                return;
             }
         }
     }
 
-    if let Some((i, id)) = index {
-        let text_copy = text.to_string();
+    let rerun_on_edit = commands.iter().all(|x| rerun_on_edit(&bot, &x.1));
+    // The commands run one after another.
+    let timeout = commands.iter().fold(Duration::from_secs(0), |sum, x| sum + command_timeout(&bot, &x.1));
 
-        thread::spawn(move || { bot.get_modules()[i].handle(&bot, &message, id, &text_copy); });
+    // An edit never runs a command with side effects.
+    if rerun && !rerun_on_edit {
+        bot.delete_command_outputs(message.channel_id, message.id);
+        return;
     }
+
+    spawn_command(bot, message, rerun_on_edit, timeout, move |bot, message| {
+        let last = commands.len() - 1;
+        let mut input: Option<String> = None;
+
+        for (n, (command, target, text)) in commands.into_iter().enumerate() {
+            if bot.cancellation_token().is_cancelled() {
+                break;
+            }
+
+            let text = match input.take() {
+                Some(input) => pipe_input(&text, &input),
+                None => text,
+            };
+
+            // The last command sends its output as usual.
+            if n == last {
+                call_handler(bot, message, &target, &text, || match target {
+                    Target::Builtin(i, id) => bot.get_modules()[i].handle(bot, message, id, &text),
+                    Target::Custom(i, ref name) => bot.get_modules()[i].handle_custom(bot, message, name, &text),
+                });
+                break;
+            }
+
+            let output = call_handler(bot, message, &target, &text, || match target {
+                Target::Builtin(i, id) => bot.get_modules()[i].handle_output(bot, message, id, &text),
+                Target::Custom(i, ref name) => bot.get_modules()[i].handle_custom_output(bot, message, name, &text),
+            });
+
+            let output = match output {
+                Some(output) => output,
+                // The user has been told already.
+                None => break,
+            };
+
+            match output {
+                Some(Ok(output)) => input = Some(output),
+
+                Some(Err(err)) => {
+                    bot.send(message.channel_id, &err);
+                    break;
+                }
+
+                None => {
+                    bot.send(message.channel_id,
+                             &bot.get_locale(message)
+                                  .format("pipeline-not-pipeable", &[("command", &command.to_lowercase())]));
+                    break;
+                }
+            }
+        }
+    });
 }
 
-fn handle_attachment(bot: Arc<Bot>, message: Arc<Message>) {
-    thread::spawn(move || for module in bot.get_modules() {
-                      module.handle_attachment(&bot, &message);
+// Nothing in the pipeline ran, so the error can be replaced when the message is edited.
+fn send_pipeline_error(bot: Arc<Bot>, message: Arc<Message>, text: String) {
+    thread::spawn(move || {
+        bot.run_command(&message,
+                        true,
+                        CancellationToken::default(),
+                        || bot.send(message.channel_id, &text))
+    });
+}
+
+fn handle_message(bot: Arc<Bot>, message: Arc<Message>) {
+    thread::spawn(move || for module in bot.get_enabled_modules() {
+                      module.handle_message(&bot, &message);
+
+                      if !message.attachments.is_empty() {
+                          module.handle_attachment(&bot, &message);
+                      }
                   });
 }
 
-fn handle_message_update(bot: Arc<Bot>, channel_id: ChannelId, id: MessageId) {
-    thread::spawn(move || for module in bot.get_modules() {
-                      module.handle_message_update(&bot, channel_id, id);
+// `content` is None for updates which don't change the text, like embeds being added.
+fn handle_message_update(bot: Arc<Bot>, channel_id: ChannelId, id: MessageId, content: Option<String>) {
+    thread::spawn(move || {
+        if content.is_some() && bot.reruns_on_edit(channel_id, id) {
+            if let Ok(message) = bot.get_message(channel_id, id) {
+                let server = bot.get_server_for_channel(channel_id);
+                let message = Arc::new(message);
+
+                match parse_pipeline(&message.content) {
+                    Some(stages) => handle_commands(bot.clone(), message.clone(), server, stages, true),
+                    None => bot.delete_command_outputs(channel_id, id),
+                }
+            }
+        }
+
+        for module in bot.get_enabled_modules() {
+            module.handle_message_update(&bot, channel_id, id, content.as_deref());
+        }
+    });
+}
+
+fn handle_message_delete(bot: Arc<Bot>, channel_id: ChannelId, ids: Vec<MessageId>) {
+    thread::spawn(move || for id in ids {
+                      bot.delete_command_outputs(channel_id, id);
+
+                      for module in bot.get_enabled_modules() {
+                          module.handle_message_delete(&bot, channel_id, id);
+                      }
                   });
 }
 
-fn handle_message_delete(bot: Arc<Bot>, channel_id: ChannelId, id: MessageId) {
-    thread::spawn(move || for module in bot.get_modules() {
-                      module.handle_message_delete(&bot, channel_id, id);
+fn start_ticking(bot: Arc<Bot>) {
+    thread::spawn(move || loop {
+                      thread::sleep(Duration::from_secs(TICK_INTERVAL_SECS));
+
+                      for module in bot.get_enabled_modules() {
+                          module.handle_tick(&bot);
+                      }
                   });
 }
 
 fn main() {
+    if let Some(arg) = std::env::args().find(|x| x.starts_with(IMPORT_MEMORY_ARG)) {
+        match modules::admin::import_memory_file(&arg[IMPORT_MEMORY_ARG.len()..]) {
+            Ok(count) => println!("Imported the admin roles of {} servers.", count),
+            Err(err) => println!("[CRITICAL] Failed to import the memory: {}", err),
+        }
+
+        return;
+    }
+
+    if let Some(arg) = std::env::args().find(|x| x == DUMP_HELP_ARG || x.starts_with(&format!("{}=", DUMP_HELP_ARG))) {
+        let directory = arg.split_once('=').map_or("docs", |x| x.1);
+
+        match docs::dump_help(directory) {
+            Ok(()) => println!("Wrote the command reference to `{}`.", directory),
+            Err(err) => println!("[CRITICAL] Failed to write the command reference: {}", err),
+        }
+
+        return;
+    }
+
+    crash::install_panic_hook();
 
     // Log in to the API.
-    let discord = Discord::from_bot_token(CONF.pointer("/discord_token").unwrap().as_str().unwrap()).expect("Login failed");
+    let conf = CONF.read().unwrap().clone();
+    let token = conf.pointer("/discord_token").unwrap().as_str().unwrap();
+    let discord = Discord::from_bot_token(token).expect("Login failed");
 
-    let modules = vec![modules::hello::Module::new(),
+    let modules: Vec<Box<Module>> = vec![modules::hello::Module::new(),
                        modules::modules::Module::new(),
                        modules::fun::Module::new(),
                        modules::speedruncom::Module::new(),
@@ -143,7 +622,14 @@ fn main() {
                        modules::wolframalpha::Module::new(),
                        modules::invite::Module::new(),
                        modules::youtube::Module::new(),
-                       modules::spotify::Module::new()]
+                       modules::spotify::Module::new(),
+                       modules::language::Module::new(),
+                       modules::scripts::Module::new(),
+                       modules::customcommands::Module::new(),
+                       modules::owner::Module::new(),
+                       modules::archive::Module::new(),
+                       modules::automod::Module::new(),
+                       modules::roles::Module::new()]
             .into_iter()
             .filter_map(|m| match m {
                             Ok(m) => Some(m),
@@ -154,7 +640,40 @@ fn main() {
                         })
             .collect();
 
-    let mut bot = BotThreadUnsafe::new(discord, modules);
+    // Big bots have to split their servers between multiple gateway connections.
+    let shard_count = match conf.pointer("/shards") {
+        Some(v) if v.as_str() == Some("auto") => discord.suggested_shard_count().expect("Couldn't get the suggested shard count") as u8,
+        Some(v) => v.as_u64().expect("`shards` must be a number or \"auto\"") as u8,
+        None => 1,
+    };
+
+    if shard_count == 0 {
+        panic!("`shards` must be at least 1");
+    }
+
+    let policy = match conf.pointer("/command_conflicts").and_then(|x| x.as_str()) {
+        Some(name) => ConflictPolicy::parse(name).expect("`command_conflicts` must be \"fail\", \"warn\" or \"namespace\""),
+        None => ConflictPolicy::Warn,
+    };
+
+    let (command_table, problems) = CommandTable::new(&modules, policy);
+
+    for problem in &problems {
+        println!("[Warning] {}", problem);
+    }
+
+    if policy == ConflictPolicy::Fail && !problems.is_empty() {
+        panic!("Found {} problems with the command names, see above", problems.len());
+    }
+
+    let mut bot = BotThreadUnsafe::new(discord,
+                                       token.to_owned(),
+                                       shard_count,
+                                       modules,
+                                       command_table,
+                                       Localization::new());
+
+    start_ticking(bot.get_sync().clone());
 
     // Main loop.
     loop {
@@ -167,71 +686,82 @@ fn main() {
 
         match event {
             Event::MessageCreate(message) => {
-                let state = bot.get_sync().get_state().read().unwrap();
-
-                // Skip the message if it comes from us.
-                if message.author.id == state.user().id {
-                    continue;
-                }
-
-                match state.find_channel(message.channel_id) {
-                    Some(ChannelRef::Public(server, channel)) => {
-                        println!("[`{}` `#{}`] `{}`: `{}`",
-                                 server.name,
-                                 channel.name,
-                                 message.author.name,
-                                 message.content);
+                // The state lock has to be released before handling the message,
+                // the handlers take it again.
+                let server_id = {
+                    let state = bot.get_sync()
+                        .get_state_for_channel(message.channel_id)
+                        .read()
+                        .unwrap();
+
+                    // Skip the message if it comes from us.
+                    if message.author.id == state.user().id {
+                        continue;
                     }
 
-                    Some(ChannelRef::Group(group)) => {
-                        println!("[Group `{}`] `{}`: `{}`",
-                                 group.name(),
-                                 message.author.name,
-                                 message.content);
-                    }
+                    match state.find_channel(message.channel_id) {
+                        Some(ChannelRef::Public(server, channel)) => {
+                            println!("[`{}` `#{}`] `{}`: `{}`",
+                                     server.name,
+                                     channel.name,
+                                     message.author.name,
+                                     message.content);
+                        }
 
-                    Some(ChannelRef::Private(channel)) => {
-                        if message.author.name == channel.recipient.name {
-                            println!("[Private] `{}`: `{}`", message.author.name, message.content);
-                        } else {
-                            println!("[Private] To `{}`: `{}`",
-                                     channel.recipient.name,
+                        Some(ChannelRef::Group(group)) => {
+                            println!("[Group `{}`] `{}`: `{}`",
+                                     group.name(),
+                                     message.author.name,
                                      message.content);
                         }
+
+                        Some(ChannelRef::Private(channel)) => {
+                            if message.author.name == channel.recipient.name {
+                                println!("[Private] `{}`: `{}`", message.author.name, message.content);
+                            } else {
+                                println!("[Private] To `{}`: `{}`",
+                                         channel.recipient.name,
+                                         message.content);
+                            }
+                        }
+
+                        None => {
+                            println!("[Unknown Channel] `{}`: `{}`",
+                                     message.author.name,
+                                     message.content)
+                        }
                     }
 
-                    None => {
-                        println!("[Unknown Channel] `{}`: `{}`",
-                                 message.author.name,
-                                 message.content)
+                    match state.find_channel(message.channel_id) {
+                        Some(ChannelRef::Public(server, _)) => Some(server.id),
+                        _ => None,
                     }
-                }
+                };
 
                 let message_shared = Arc::new(message);
 
                 // Handle the commands.
-                if let Some((command, text)) = parse_command(&message_shared.content) {
-                    handle_command(bot.get_sync().clone(),
-                                   message_shared.clone(),
-                                   command,
-                                   text);
+                if let Some(stages) = parse_pipeline(&message_shared.content) {
+                    handle_commands(bot.get_sync().clone(), message_shared.clone(), server_id, stages, false);
                 }
 
-                // Handle the attachments.
-                if message_shared.attachments.len() > 0 {
-                    handle_attachment(bot.get_sync().clone(), message_shared);
-                }
+                // Let the modules see the message, like the archive and the attachment handlers.
+                handle_message(bot.get_sync().clone(), message_shared);
             }
 
-            Event::MessageUpdate { id, channel_id, .. } => {
-                handle_message_update(bot.get_sync().clone(), channel_id, id);
+            Event::MessageUpdate { id, channel_id, content, .. } => {
+                handle_message_update(bot.get_sync().clone(), channel_id, id, content);
             }
 
             Event::MessageDelete {
                 channel_id,
                 message_id,
             } => {
-                handle_message_delete(bot.get_sync().clone(), channel_id, message_id);
+                handle_message_delete(bot.get_sync().clone(), channel_id, vec![message_id]);
+            }
+
+            Event::MessageDeleteBulk { channel_id, ids } => {
+                handle_message_delete(bot.get_sync().clone(), channel_id, ids);
             }
 
             _ => {} // Discard other events.
@@ -241,7 +771,7 @@ fn main() {
 
 #[cfg(test)]
 mod tests {
-    use super::parse_command;
+    use super::{parse_command, parse_pipeline, pipe_input};
 
     #[test]
     fn parse_command_noargs() {
@@ -295,4 +825,46 @@ mod tests {
         assert_eq!(Some(("КрутаяКоманда1337💖忠犬ハ", "チ公Да")),
                    parse_command("!КрутаяКоманда1337💖忠犬ハ チ公Да"));
     }
+
+    #[test]
+    fn parse_pipeline_single() {
+        assert_eq!(Some(vec![("command", "arg ")]), parse_pipeline("!command arg "));
+    }
+
+    #[test]
+    fn parse_pipeline_usual() {
+        assert_eq!(Some(vec![("pick", "a;b;c"), ("fraktur", "")]),
+                   parse_pipeline("!pick a;b;c | !fraktur"));
+    }
+
+    #[test]
+    fn parse_pipeline_no_spaces() {
+        assert_eq!(Some(vec![("wa", "2+2"), ("aesthetic", ""), ("smallcaps", "")]),
+                   parse_pipeline("!wa 2+2|!aesthetic|!smallcaps"));
+    }
+
+    #[test]
+    fn parse_pipeline_bar_in_arguments() {
+        assert_eq!(Some(vec![("wa", "|x| = 2 | y")]), parse_pipeline("!wa |x| = 2 | y"));
+    }
+
+    #[test]
+    fn parse_pipeline_empty_command() {
+        assert_eq!(Some(vec![("pick", "a;b | !")]), parse_pipeline("!pick a;b | !"));
+    }
+
+    #[test]
+    fn parse_pipeline_notcommand() {
+        assert_eq!(None, parse_pipeline("Hello | !fraktur"));
+    }
+
+    #[test]
+    fn pipe_input_no_arguments() {
+        assert_eq!("output", pipe_input("", "output"));
+    }
+
+    #[test]
+    fn pipe_input_arguments() {
+        assert_eq!("Half-Life output", pipe_input("Half-Life", "output"));
+    }
 }
@@ -1,7 +1,104 @@
 use std::collections::hash_map::HashMap;
 use bot::Bot;
-use discord::model::{ChannelId, Message, MessageId};
+use discord::model::{ChannelId, Message, MessageId, ServerId};
 use std::marker::{Send, Sync};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+// The text output of a command.
+// Err is sent to the user as-is and stops the pipeline it's a part of.
+pub type CommandOutput = Result<String, String>;
+
+// A command defined at runtime on a server, like a script.
+pub struct CustomCommand {
+    // Must be lowercase.
+    pub name: String,
+    pub description: String,
+    pub help_message: String,
+}
+
+// Gets cancelled by the dispatcher when a command takes too long, see Bot::cancellation_token().
+// Handlers which make several requests should check it between them and stop early.
+#[derive(Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+    // When the command gets cancelled, None if it can run for as long as it wants.
+    deadline: Option<Instant>,
+}
+
+// Where a command can be used, see Module::command_scope().
+// The dispatcher refuses to run the command anywhere else.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct CommandScope {
+    pub servers: bool,
+    pub private: bool,
+    pub groups: bool,
+    // On servers, only in the channels marked NSFW.
+    pub nsfw_only: bool,
+}
+
+impl CommandScope {
+    pub fn anywhere() -> Self {
+        CommandScope {
+            servers: true,
+            private: true,
+            groups: true,
+            nsfw_only: false,
+        }
+    }
+
+    pub fn servers_only() -> Self {
+        CommandScope {
+            servers: true,
+            private: false,
+            groups: false,
+            nsfw_only: false,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn private_only() -> Self {
+        CommandScope {
+            servers: false,
+            private: true,
+            groups: false,
+            nsfw_only: false,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn nsfw_only() -> Self {
+        CommandScope {
+            servers: true,
+            private: false,
+            groups: false,
+            nsfw_only: true,
+        }
+    }
+}
+
+impl CancellationToken {
+    pub fn with_timeout(timeout: Duration) -> Self {
+        CancellationToken {
+            cancelled: Arc::default(),
+            deadline: Some(Instant::now() + timeout),
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    // The time left until the deadline, None if there's no deadline.
+    pub fn remaining(&self) -> Option<Duration> {
+        self.deadline.map(|x| x.saturating_duration_since(Instant::now()))
+    }
+}
 
 pub trait Module: Send + Sync {
     fn new() -> Result<Box<Module>, String> where Self: Sized;
@@ -26,12 +123,62 @@ pub trait Module: Send + Sync {
     // A function that handles the given command.
     fn handle(&self, bot: &Bot, message: &Message, id: u32, text: &str);
 
+    // A function that handles the given command and returns its output instead of sending it.
+    // Commands which return Some can be used in pipelines, like `!pick a;b;c | !fraktur`.
+    fn handle_output(&self, _bot: &Bot, _message: &Message, _id: u32, _text: &str) -> Option<CommandOutput> {
+        None
+    }
+
+    // Commands defined at runtime on the given server, in addition to the ones from commands().
+    // These are checked only if no module has a matching command in commands().
+    fn custom_commands(&self, _server: ServerId) -> Vec<CustomCommand> {
+        Vec::new()
+    }
+
+    // A function that handles a command returned by custom_commands().
+    fn handle_custom(&self, _bot: &Bot, _message: &Message, _name: &str, _text: &str) {}
+
+    // Like handle_output(), for a command returned by custom_commands().
+    fn handle_custom_output(&self, _bot: &Bot, _message: &Message, _name: &str, _text: &str) -> Option<CommandOutput> {
+        None
+    }
+
+    // Whether the command is re-run when someone edits the message with it,
+    // editing the previous output in place. The output is also deleted along with the message.
+    // Only commands without side effects, which just reply with something, should return true.
+    fn rerun_on_edit(&self, _id: u32) -> bool {
+        false
+    }
+
+    // Like rerun_on_edit(), for a command returned by custom_commands().
+    fn rerun_custom_on_edit(&self, _name: &str) -> bool {
+        false
+    }
+
+    // Where the command can be used. Custom commands only exist on servers.
+    fn command_scope(&self, _id: u32) -> CommandScope {
+        CommandScope::anywhere()
+    }
+
+    // How long the command may run before it's cancelled.
+    // None means the default, `command_timeout` in the config.
+    fn command_timeout(&self, _id: u32) -> Option<Duration> {
+        None
+    }
+
+    // A function that gets called for every message, except the ones sent by the bot.
+    fn handle_message(&self, _bot: &Bot, _message: &Message) {}
+
     // A function that gets called when someone sends a message with an attachment.
     fn handle_attachment(&self, _bot: &Bot, _message: &Message) {}
 
     // A function that gets called when someone edits a message.
-    fn handle_message_update(&self, _bot: &Bot, _channel_id: ChannelId, _id: MessageId) {}
+    // `content` is the new text, None if the text didn't change, like when an embed was added.
+    fn handle_message_update(&self, _bot: &Bot, _channel_id: ChannelId, _id: MessageId, _content: Option<&str>) {}
 
     // A function that gets called when someone deletes a message.
     fn handle_message_delete(&self, _bot: &Bot, _channel_id: ChannelId, _id: MessageId) {}
+
+    // A function that gets called every half a minute, for things which have to happen at some point later.
+    fn handle_tick(&self, _bot: &Bot) {}
 }
@@ -1,179 +1,603 @@
 use bot::Bot;
 use discord::*;
 use discord::model::*;
-use module;
+use locale::Locale;
+use module::{self, CommandScope};
 use regex::Regex;
 use serde_json;
+use serde_json::{Map, Value};
 use std;
 use std::collections::BTreeMap;
 use std::collections::hash_map::HashMap;
-use std::error;
-use std::fmt;
 use std::fs::File;
 use std::io;
-use std::sync::{RwLock, RwLockReadGuard};
+use std::sync::RwLockReadGuard;
+use std::time::{SystemTime, UNIX_EPOCH};
+use storage::{self, Migration, Scope};
 
-#[derive(Serialize, Deserialize)]
-struct Memory {
+// Admin's persistent data, kept in the shared storage.
+pub struct Memory;
+
+// The format of the file the data used to be kept in before the shared storage.
+#[derive(Deserialize)]
+struct LegacyMemory {
     // The map is from ServerId into an array of RoleIds.
     admin_roles: BTreeMap<String, Vec<u64>>,
 }
 
+// A privileged action, recorded in the audit log of the server.
+#[derive(Clone, Serialize, Deserialize)]
+struct AuditEntry {
+    // Seconds since the Unix epoch.
+    time: u64,
+    actor: u64,
+    // Names are kept so that listing the log doesn't mention anyone.
+    actor_name: String,
+    channel: u64,
+    // The command, like `!nuke`.
+    action: String,
+    parameters: String,
+    success: bool,
+    outcome: String,
+}
+
 pub struct Module<'a> {
     commands: HashMap<u32, &'a [&'a str]>,
-    memory: RwLock<Memory>,
+    memory: Memory,
 }
 
 lazy_static! {
-	static ref NUKE_REGEX: Regex = Regex::new(r"\s*(([0-9]+)(\s|$)).*").unwrap();
+	// A word or a "quoted string".
+	static ref NUKE_ARGUMENT_REGEX: Regex = Regex::new(r#""([^"]*)"|(\S+)"#).unwrap();
+	static ref USER_MENTION_REGEX: Regex = Regex::new(r"^<@!?([0-9]+)>$").unwrap();
+	static ref NUKE_EXPORT_REGEX: Regex = Regex::new(r"(?i)^\s*export(?:\s+(on|off))?\s*$").unwrap();
+	static ref DURATION_REGEX: Regex = Regex::new(r"^(?:[0-9]+[smhdw])+$").unwrap();
+	static ref DURATION_PART_REGEX: Regex = Regex::new(r"([0-9]+)([smhdw])").unwrap();
+	// The role can be a mention or an ID.
+	static ref MUTE_ROLE_REGEX: Regex = Regex::new(r"(?i)^\s*role(?:\s+(?:<@&)?([0-9]+|off)>?)?\s*$").unwrap();
+	static ref LINK_REGEX: Regex = Regex::new(r"(?i)\bhttps?://\S").unwrap();
 	static ref ADMIN_REGEX: Regex = Regex::new(r"\s*(list|add|remove)(\s|$).*").unwrap();
+	static ref LANGUAGE_REGEX: Regex = Regex::new(r"^\s*(\S+)\s*$").unwrap();
+	static ref AUDIT_LOG_REGEX: Regex = Regex::new(r"^\s*([0-9]+)?").unwrap();
+	static ref MOD_LOG_REGEX: Regex = Regex::new(r"^\s*channel\s+(<#([0-9]+)>|off)\s*$").unwrap();
+}
+const LEGACY_MEMORY_FILENAME: &'static str = "memory.json";
+
+// The name of the storage the memory is kept in.
+const STORAGE_NAME: &'static str = "admin";
+static MIGRATIONS: [Migration; 1] = [import_legacy_memory];
+
+// The number of audit log entries kept per server.
+const MAX_AUDIT_LOG_ENTRIES: usize = 1000;
+// The number of entries `!auditlog` shows by default and at most.
+const DEFAULT_AUDIT_LOG_COUNT: usize = 10;
+const MAX_AUDIT_LOG_COUNT: usize = 25;
+
+// The most messages `!nuke` deletes at once, and how far back it looks for them.
+const MAX_NUKE_COUNT: u64 = 1000;
+const MAX_NUKE_SCAN: usize = 5000;
+// Discord returns at most this many messages per request.
+const MESSAGES_PER_PAGE: u64 = 100;
+
+// The longest timed ban or mute, a year.
+const MAX_TIMED_ACTION_SECS: u64 = 365 * 86400;
+
+const MAX_POLICY_WARNINGS: u64 = 100;
+const MAX_WARNING_EXPIRY_DAYS: u64 = 3650;
+
+// What `!nuke` deletes, all the filters have to match.
+#[derive(Debug, Default)]
+struct NukeFilter {
+    count: Option<u64>,
+    users: Vec<UserId>,
+    bots: bool,
+    attachments: bool,
+    links: bool,
+    // Lowercase.
+    contains: Option<String>,
+    regex: Option<Regex>,
+    after: Option<MessageId>,
+    before: Option<MessageId>,
+    // Not a filter: whether to export the messages to the mod-log first, None for the server default.
+    export: Option<bool>,
+}
+
+// A message `!nuke` deleted, as it's exported to the mod-log.
+#[derive(Serialize)]
+struct NukedMessage {
+    id: u64,
+    author: u64,
+    author_name: String,
+    timestamp: String,
+    content: String,
+    attachments: Vec<String>,
+    embeds: Vec<Value>,
+}
+
+// What has to be done when a timed ban or mute expires.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum TimedActionKind {
+    Unban,
+    Unmute,
+}
+
+// Kept in the storage, so that the bans and the mutes end after restarts too.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct TimedAction {
+    kind: TimedActionKind,
+    user: u64,
+    // For the mod-log, since they might not be on the server anymore.
+    user_name: String,
+    // Seconds since the Unix epoch.
+    expires: u64,
+}
+
+// The arguments of `!kick`, `!ban`, `!unban`, `!mute` and `!unmute`.
+#[derive(Debug, PartialEq)]
+struct ModerationArgs {
+    user: UserId,
+    // Seconds.
+    duration: Option<u64>,
+    reason: Option<String>,
+}
+
+// What the moderation commands need to know about the server, mostly for the role hierarchy checks.
+struct Hierarchy {
+    server: ServerId,
+    server_name: String,
+    owner: UserId,
+    roles: Vec<Role>,
+    bot: UserId,
+}
+
+// A warning, kept per member.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Warning {
+    // Seconds since the Unix epoch.
+    time: u64,
+    moderator: u64,
+    moderator_name: String,
+    reason: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum EscalationAction {
+    Mute,
+    Kick,
+    Ban,
+}
+
+// What happens to a member when they get this many active warnings.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+struct EscalationStep {
+    warnings: u64,
+    action: EscalationAction,
+    // Seconds, for mutes and bans.
+    duration: Option<u64>,
 }
-const MEMORY_FILENAME: &'static str = "memory.json";
 
 enum Commands {
     Admin = 0,
     Nuke = 1,
+    Language = 2,
+    AuditLog = 3,
+    Kick = 4,
+    Ban = 5,
+    Unban = 6,
+    Mute = 7,
+    Unmute = 8,
+    Warn = 9,
+    Warnings = 10,
+    ClearWarn = 11,
+    WarnPolicy = 12,
 }
 
-impl Memory {
-    fn load_from_file() -> MyResult<Self> {
-        let file = try!(File::open(MEMORY_FILENAME));
-        let mut memory: Memory = try!(serde_json::de::from_reader(file));
+// Parses the `!nuke` arguments. Err has the argument which doesn't make sense.
+fn parse_nuke(text: &str) -> std::result::Result<NukeFilter, String> {
+    let mut filter = NukeFilter::default();
+
+    let mut args = NUKE_ARGUMENT_REGEX.captures_iter(text)
+        .map(|x| x.get(1).or_else(|| x.get(2)).unwrap().as_str());
 
-        let mut keys_to_remove = Vec::new();
-        for (server, roles) in &mut memory.admin_roles {
-            if roles.len() == 0 {
-                keys_to_remove.push(server.clone());
-            } else {
-                roles.sort();
-                roles.dedup();
+    while let Some(arg) = args.next() {
+        match arg.to_lowercase().as_str() {
+            "--bots" => filter.bots = true,
+            "--attachments" => filter.attachments = true,
+            "--links" => filter.links = true,
+            "--export" => filter.export = Some(true),
+            "--no-export" => filter.export = Some(false),
+            "--contains" => filter.contains = Some(try!(args.next().ok_or(arg)).to_lowercase()),
+            "--regex" => {
+                let pattern = try!(args.next().ok_or(arg));
+                filter.regex = Some(try!(Regex::new(pattern).map_err(|_| pattern.to_owned())));
             }
-        }
+            "--after" | "--before" => {
+                let id = try!(args.next().and_then(|x| x.parse().ok()).ok_or(arg));
 
-        for key in keys_to_remove {
-            memory.admin_roles.remove(&key);
+                if arg.eq_ignore_ascii_case("--after") {
+                    filter.after = Some(MessageId(id));
+                } else {
+                    filter.before = Some(MessageId(id));
+                }
+            }
+            _ => {
+                if let Some(caps) = USER_MENTION_REGEX.captures(arg) {
+                    filter.users.push(UserId(caps[1].parse().unwrap()));
+                } else {
+                    match arg.parse::<u64>() {
+                        Ok(count) if filter.count.is_none() && (1..=MAX_NUKE_COUNT).contains(&count) => {
+                            filter.count = Some(count)
+                        }
+                        _ => return Err(arg.to_owned()),
+                    }
+                }
+            }
         }
-
-        Ok(memory)
     }
 
-    fn save_to_file(&self) -> MyResult<()> {
-        let mut file = try!(File::create(MEMORY_FILENAME));
-        try!(serde_json::ser::to_writer(&mut file, &self));
+    Ok(filter)
+}
 
-        Ok(())
+impl NukeFilter {
+    fn matches(&self, message: &Message) -> bool {
+        (self.users.is_empty() || self.users.contains(&message.author.id)) && (!self.bots || message.author.bot) &&
+        (!self.attachments || !message.attachments.is_empty()) &&
+        (!self.links || LINK_REGEX.is_match(&message.content)) &&
+        self.contains.iter().all(|x| message.content.to_lowercase().contains(x.as_str())) &&
+        self.regex.iter().all(|x| x.is_match(&message.content))
     }
+}
 
-    pub fn get_admin_roles(&self, server: ServerId) -> Option<&Vec<u64>> {
-        self.admin_roles.get(&server.0.to_string())
+impl From<&Message> for NukedMessage {
+    fn from(message: &Message) -> Self {
+        NukedMessage {
+            id: message.id.0,
+            author: message.author.id.0,
+            author_name: message.author.name.clone(),
+            timestamp: message.timestamp.clone(),
+            content: message.content.clone(),
+            attachments: message.attachments.iter().map(|x| x.url.clone()).collect(),
+            // discord-rs has its own version of serde_json.
+            embeds: message.embeds.iter().filter_map(|x| serde_json::from_str(&x.to_string()).ok()).collect(),
+        }
     }
+}
 
-    pub fn remove_admin_roles(&mut self, server: ServerId, roles: &Vec<RoleId>) {
-        let mut remove = false;
+// The exported messages for people rather than for tools.
+fn format_nuked(messages: &[NukedMessage]) -> String {
+    let mut buf = String::new();
 
-        if let Some(server_admin_roles) = self.admin_roles.get_mut(&server.0.to_string()) {
-            server_admin_roles.retain(|x| roles.iter().filter(|r| r.0 == *x).next().is_none());
+    for message in messages {
+        buf.push_str(&format!("[{}] {} ({}): {}\n",
+                              message.timestamp,
+                              message.author_name,
+                              message.author,
+                              message.content));
 
-            if server_admin_roles.len() == 0 {
-                remove = true;
-            }
+        for attachment in &message.attachments {
+            buf.push_str(&format!("    Attachment: {}\n", attachment));
         }
 
-        if remove {
-            self.admin_roles.remove(&server.0.to_string());
+        for embed in &message.embeds {
+            buf.push_str(&format!("    Embed: {}\n", embed));
         }
+    }
+
+    buf
+}
+
+// Reads a legacy memory file, returning None if there's no such file.
+fn read_legacy_memory(path: &str) -> std::result::Result<Option<LegacyMemory>, String> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.to_string()),
+    };
 
-        if let Err(err) = self.save_to_file() {
-            println!("[CRITICAL] Could not save memory to file: {}", err);
+    serde_json::de::from_reader(file).map(Some).map_err(|e| e.to_string())
+}
+
+// Moves the admin roles from the legacy memory file into the storage.
+fn import_legacy_memory(data: &mut Map<String, Value>) -> std::result::Result<(), String> {
+    let memory = match try!(read_legacy_memory(LEGACY_MEMORY_FILENAME)) {
+        Some(memory) => memory,
+        None => return Ok(()),
+    };
+
+    for (server, mut roles) in memory.admin_roles {
+        let server = match server.parse() {
+            Ok(server) => ServerId(server),
+            Err(_) => continue,
+        };
+
+        if roles.is_empty() {
+            continue;
         }
+
+        roles.sort();
+        roles.dedup();
+
+        let mut values = Map::new();
+        values.insert("admin_roles".to_owned(), Value::from(roles));
+        data.insert(Scope::Server(server).key(), Value::Object(values));
     }
 
-    pub fn add_admin_roles(&mut self, server: ServerId, roles: &Vec<RoleId>) {
-        {
-            let server_admin_roles = self.admin_roles
-                .entry(server.0.to_string())
-                .or_insert(Vec::new());
+    Ok(())
+}
+
+// Adds the admin roles from a memory file to the ones in the storage.
+// Returns the number of servers imported.
+pub fn import_memory_file(path: &str) -> std::result::Result<usize, String> {
+    let memory = match try!(read_legacy_memory(path)) {
+        Some(memory) => memory,
+        None => return Err(format!("{} doesn't exist", path)),
+    };
 
-            for role in roles {
-                server_admin_roles.push(role.0);
+    try!(::STORAGE.register(STORAGE_NAME, &MIGRATIONS).map_err(|e| e.to_string()));
+
+    let mut count = 0;
+
+    for (server, roles) in memory.admin_roles {
+        let server = match server.parse() {
+            Ok(server) => ServerId(server),
+            Err(_) => {
+                println!("[Warning] Skipping an invalid server ID: {}", server);
+                continue;
             }
+        };
 
-            server_admin_roles.sort();
-            server_admin_roles.dedup();
+        if roles.is_empty() {
+            continue;
         }
 
-        if let Err(err) = self.save_to_file() {
-            println!("[CRITICAL] Could not save memory to file: {}", err);
-        }
+        let roles = roles.into_iter().map(RoleId).collect();
+        try!(Memory.add_admin_roles(server, &roles).map_err(|e| e.to_string()));
+        count += 1;
     }
-}
 
-#[derive(Debug)]
-enum MyError {
-    IO(io::Error),
-    Json(serde_json::error::Error),
+    Ok(count)
 }
 
-impl fmt::Display for MyError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match *self {
-            MyError::IO(ref err) => write!(f, "IO error: {}", err),
-            MyError::Json(ref err) => write!(f, "JSON error: {}", err),
-        }
+// Returns whether the user is the owner of the server or has one of its admin roles.
+// Returns None if the member info couldn't be retrieved.
+pub fn is_admin(bot: &Bot, server: &LiveServer, user: UserId) -> Option<bool> {
+    if user.0 == server.owner_id.0 {
+        return Some(true);
     }
+
+    let admin_roles = match Memory.get_admin_roles(server.id) {
+        Ok(Some(admin_roles)) => admin_roles,
+        Ok(None) => return Some(false),
+        Err(err) => {
+            println!("[Warning] Failed to get the admin roles: {}", err);
+            return Some(false);
+        }
+    };
+
+    bot.get_member(server.id, user)
+        .ok()
+        .map(|member| member.roles.iter().any(|role| admin_roles.contains(&role.0)))
 }
 
-impl error::Error for MyError {
-    fn description(&self) -> &str {
-        match *self {
-            MyError::IO(ref err) => err.description(),
-            MyError::Json(ref err) => err.description(),
+// Checks that the author of the message is an admin, replying if they aren't.
+// Don't call this while holding the state lock.
+pub fn check_admin(bot: &Bot, message: &Message, locale: &Locale) -> bool {
+    let state = bot.get_state_for_channel(message.channel_id).read().unwrap();
+
+    let has_permission = match state.find_channel(message.channel_id) {
+        Some(ChannelRef::Public(server, _)) => is_admin(bot, server, message.author.id),
+        _ => Some(false),
+    };
+
+    match has_permission {
+        Some(true) => true,
+        Some(false) => {
+            bot.send(message.channel_id, &locale.get("admin-required"));
+            false
+        }
+        None => {
+            bot.send(message.channel_id, &locale.get("admin-member-info-error"));
+            false
         }
     }
+}
 
-    fn cause(&self) -> Option<&error::Error> {
-        match *self {
-            MyError::IO(ref err) => Some(err),
-            MyError::Json(ref err) => Some(err),
-        }
+// Records a privileged action in the audit log of the server and mirrors it to the mod-log channel.
+// `action` is the command, like `!nuke`, and the outcome says what happened or what went wrong.
+// Both the parameters and the outcome may have user text in them, they're made harmless when posted.
+pub fn audit(bot: &Bot,
+             server: ServerId,
+             message: &Message,
+             action: &str,
+             parameters: &str,
+             outcome: &std::result::Result<String, String>) {
+    let (success, outcome) = match *outcome {
+        Ok(ref x) => (true, x.clone()),
+        Err(ref x) => (false, x.clone()),
+    };
+
+    let entry = AuditEntry {
+        time: SystemTime::now().duration_since(UNIX_EPOCH).map(|x| x.as_secs()).unwrap_or(0),
+        actor: message.author.id.0,
+        actor_name: message.author.name.clone(),
+        channel: message.channel_id.0,
+        action: action.to_owned(),
+        parameters: parameters.trim().to_owned(),
+        success: success,
+        outcome: outcome,
+    };
+
+    let result = ::STORAGE.update(STORAGE_NAME,
+                                  Scope::Server(server),
+                                  "audit_log",
+                                  |log: Option<Vec<AuditEntry>>| {
+        let mut log = log.unwrap_or_default();
+        log.push(entry.clone());
+
+        let excess = log.len().saturating_sub(MAX_AUDIT_LOG_ENTRIES);
+        log.drain(..excess);
+
+        Some(log)
+    });
+
+    if let Err(err) = result {
+        println!("[Warning] Failed to write to the audit log: {}", err);
     }
+
+    let locale = bot.get_localization().get_locale(None, Some(server));
+    post_to_mod_log(bot, server, &format_audit_entry(&locale, &entry));
 }
 
-impl From<io::Error> for MyError {
-    fn from(err: io::Error) -> MyError {
-        MyError::IO(err)
+// Posts the text in the mod-log channel of the server, if there's one.
+pub fn post_to_mod_log(bot: &Bot, server: ServerId, text: &str) {
+    match get_mod_log_channel(server) {
+        Ok(Some(channel)) => bot.send(channel, text),
+        Ok(None) => {}
+        Err(err) => println!("[Warning] Failed to get the mod-log channel: {}", err),
     }
 }
 
-impl From<serde_json::error::Error> for MyError {
-    fn from(err: serde_json::error::Error) -> MyError {
-        MyError::Json(err)
+fn get_mod_log_channel(server: ServerId) -> storage::Result<Option<ChannelId>> {
+    ::STORAGE.get(STORAGE_NAME, Scope::Server(server), "mod_log_channel").map(|x| x.map(ChannelId))
+}
+
+// Uploads the messages `!nuke` is about to delete to the mod-log channel. Err has the reply.
+fn export_nuked(bot: &Bot,
+                server: ServerId,
+                message: &Message,
+                messages: &[Message],
+                locale: &Locale)
+                -> std::result::Result<(), String> {
+    let channel = match get_mod_log_channel(server) {
+        Ok(Some(channel)) => channel,
+        Ok(None) => return Err(locale.get("admin-nuke-export-no-mod-log")),
+        Err(err) => return Err(locale.format("storage-error", &[("error", &err)])),
+    };
+
+    let mut exported = messages.iter().map(NukedMessage::from).collect::<Vec<_>>();
+    exported.sort_by_key(|x| x.id);
+
+    let name = format!("nuke-{}-{}", message.channel_id.0, message.id.0);
+    let text = locale.format("admin-nuke-export",
+                             &[("user", &message.author.name),
+                               ("channel", &message.channel_id.mention()),
+                               ("count", &exported.len())]);
+    let json = serde_json::to_string_pretty(&exported).unwrap();
+
+    if bot.send_file_and_get(channel, &text, json.as_bytes(), &format!("{}.json", name)).is_none() ||
+       bot.send_file_and_get(channel, "", format_nuked(&exported).as_bytes(), &format!("{}.txt", name)).is_none() {
+        return Err(locale.get("admin-nuke-export-error"));
     }
+
+    Ok(())
 }
 
-type MyResult<T> = std::result::Result<T, MyError>;
+fn format_audit_entry(locale: &Locale, entry: &AuditEntry) -> String {
+    let outcome = if entry.success {
+        entry.outcome.clone()
+    } else {
+        locale.format("admin-audit-log-failed", &[("error", &entry.outcome)])
+    };
 
-impl<'a> module::Module for Module<'a> {
-    fn new() -> std::result::Result<Box<module::Module>, String> {
-        let mut map: HashMap<u32, &[&str]> = HashMap::new();
-        static ADMIN: [&'static str; 1] = ["admin"];
-        map.insert(Commands::Admin as u32, &ADMIN);
-        static NUKE: [&'static str; 1] = ["nuke"];
-        map.insert(Commands::Nuke as u32, &NUKE);
+    // A backtick would close the code span around the command.
+    let command = format!("{} {}", entry.action, entry.parameters).replace('`', "'");
+
+    locale.format("admin-audit-log-entry",
+                  &[("time", &format_time(entry.time)),
+                    ("user", &entry.actor_name),
+                    ("channel", &ChannelId(entry.channel).mention()),
+                    ("command", &command.trim()),
+                    ("outcome", &escape_mentions(&outcome))])
+}
+
+// Breaks up `@everyone`, `@here` and user and role mentions so that reposting the text doesn't ping anyone.
+pub fn escape_mentions(text: &str) -> String {
+    text.replace('@', "@\u{200B}")
+}
 
-        let memory = match Memory::load_from_file() {
-            Ok(m) => m,
+// Formats seconds since the Unix epoch like `2017-05-21 13:02 UTC`.
+pub fn format_time(time: u64) -> String {
+    // The civil_from_days algorithm by Howard Hinnant, with days counted from 0000-03-01.
+    let days = time / 86400 + 719468;
+    let era = days / 146097;
+    let day_of_era = days % 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let (year, month) = if month < 10 {
+        (year_of_era + era * 400, month + 3)
+    } else {
+        (year_of_era + era * 400 + 1, month - 9)
+    };
 
-            Err(err) => {
-                println!("[CRITICAL] Failed to load memory: {}", err);
-                Memory { admin_roles: BTreeMap::new() }
+    format!("{}-{:02}-{:02} {:02}:{:02} UTC",
+            year,
+            month,
+            day,
+            time % 86400 / 3600,
+            time % 3600 / 60)
+}
+
+impl Memory {
+    pub fn get_admin_roles(&self, server: ServerId) -> storage::Result<Option<Vec<u64>>> {
+        ::STORAGE.get(STORAGE_NAME, Scope::Server(server), "admin_roles")
+    }
+
+    // The role given to muted members.
+    pub fn get_mute_role(&self, server: ServerId) -> storage::Result<Option<RoleId>> {
+        ::STORAGE.get(STORAGE_NAME, Scope::Server(server), "mute_role").map(|x| x.map(RoleId))
+    }
+
+    pub fn set_mute_role(&self, server: ServerId, role: Option<RoleId>) -> storage::Result<()> {
+        match role {
+            Some(role) => ::STORAGE.set(STORAGE_NAME, Scope::Server(server), "mute_role", &role.0),
+            None => ::STORAGE.remove(STORAGE_NAME, Scope::Server(server), "mute_role"),
+        }
+    }
+
+    pub fn remove_admin_roles(&self, server: ServerId, roles: &Vec<RoleId>) -> storage::Result<()> {
+        ::STORAGE.update(STORAGE_NAME,
+                         Scope::Server(server),
+                         "admin_roles",
+                         |admin_roles: Option<Vec<u64>>| {
+            admin_roles.map(|mut x| {
+                                x.retain(|x| roles.iter().filter(|r| r.0 == *x).next().is_none());
+                                x
+                            })
+                .and_then(|x| if x.is_empty() { None } else { Some(x) })
+        })
+    }
+
+    pub fn add_admin_roles(&self, server: ServerId, roles: &Vec<RoleId>) -> storage::Result<()> {
+        ::STORAGE.update(STORAGE_NAME,
+                         Scope::Server(server),
+                         "admin_roles",
+                         |admin_roles: Option<Vec<u64>>| {
+            let mut admin_roles = admin_roles.unwrap_or_default();
+
+            for role in roles {
+                admin_roles.push(role.0);
             }
-        };
 
-        Ok(Box::new(Module {
-                        commands: map,
-                        memory: RwLock::new(memory),
-                    }))
+            admin_roles.sort();
+            admin_roles.dedup();
+
+            Some(admin_roles)
+        })
+    }
+}
+
+
+impl<'a> module::Module for Module<'a> {
+    fn new() -> std::result::Result<Box<module::Module>, String> {
+        if let Err(err) = ::STORAGE.register(STORAGE_NAME, &MIGRATIONS) {
+            println!("[CRITICAL] Failed to load memory: {}", err);
+        }
+
+        Ok(Box::new(Module::without_storage()))
     }
 
     fn name(&self) -> &'static str {
@@ -192,6 +616,17 @@ impl<'a> module::Module for Module<'a> {
         match id {
             x if x == Commands::Admin as u32 => "Manage the admin roles.",
             x if x == Commands::Nuke as u32 => "Deletes past messages.",
+            x if x == Commands::Language as u32 => "Sets the default language on this server.",
+            x if x == Commands::AuditLog as u32 => "Shows the recent admin actions.",
+            x if x == Commands::Kick as u32 => "Kicks a member from the server.",
+            x if x == Commands::Ban as u32 => "Bans someone from the server, for good or for a while.",
+            x if x == Commands::Unban as u32 => "Lifts a ban.",
+            x if x == Commands::Mute as u32 => "Stops a member from talking, for good or for a while.",
+            x if x == Commands::Unmute as u32 => "Lets a muted member talk again.",
+            x if x == Commands::Warn as u32 => "Warns a member.",
+            x if x == Commands::Warnings as u32 => "Lists the warnings of a member.",
+            x if x == Commands::ClearWarn as u32 => "Removes the warnings of a member.",
+            x if x == Commands::WarnPolicy as u32 => "Sets what happens to members with many warnings.",
             _ => panic!("Admin::command_description - invalid id."),
         }
     }
@@ -203,53 +638,81 @@ impl<'a> module::Module for Module<'a> {
                  `!admin add <role mention(-s)>` - Add a role (roles) to the admin roles.\n\
                  `!admin remove <role mention(-s)>` - Remove a role (roles) from the admin roles."
             }
-            x if x == Commands::Nuke as u32 => "`!nuke <how many> [whose]` - Deletes the specified number of messages in the current channel. If any user mentions are present after the count, deletes the specified number of messages written by each of the people mentioned, and only theirs.",
+            x if x == Commands::Nuke as u32 => {
+                "`!nuke <how many> [whose] [filters]` - Deletes the given number of the most recent messages in the current channel which match all of the filters, up to 1000. \
+                 If any users are mentioned, deletes only their messages. The filters are:\n\
+                 `--bots` - Messages by bots.\n\
+                 `--attachments` - Messages with attachments.\n\
+                 `--links` - Messages with links.\n\
+                 `--contains <text>` - Messages with the text, put it in quotes if it has spaces.\n\
+                 `--regex <pattern>` - Messages matching the regular expression.\n\
+                 `--after <message ID>` - Messages after this one. The count can be left out with this filter.\n\
+                 `--before <message ID>` - Messages before this one.\n\
+                 `--export`, `--no-export` - Whether to post the messages in the mod-log channel, as JSON and as text, before deleting them.\n\
+                 `!nuke export [on|off]` - Shows or sets whether the messages are exported by default."
+            }
+            x if x == Commands::Language as u32 => "`!serverlanguage <code>` - Sets the language the bot replies in on this server, for everyone who hasn't picked their own with `!language`. `!serverlanguage default` resets it to English.",
+            x if x == Commands::AuditLog as u32 => {
+                "`!auditlog [how many] [whose]` - Shows the most recent admin actions on this server, optionally only the ones by the mentioned user.\n\
+                 `!auditlog channel <channel mention>` - Also posts every admin action in the given channel.\n\
+                 `!auditlog channel off` - Stops posting admin actions."
+            }
+            x if x == Commands::Kick as u32 => {
+                "`!kick <user> [reason]` - Kicks the mentioned member, or the one with the given ID, and tells them why in a private message."
+            }
+            x if x == Commands::Ban as u32 => {
+                "`!ban <user> [duration] [reason]` - Bans the mentioned user, or the one with the given ID, and tells them why in a private message. \
+                 The duration is like `30m`, `12h`, `7d` or `1w2d`, up to a year, the ban is for good without it."
+            }
+            x if x == Commands::Unban as u32 => "`!unban <user ID>` - Lifts the ban of the user.",
+            x if x == Commands::Mute as u32 => {
+                "`!mute <user> [duration] [reason]` - Gives the mentioned member the mute role, for the given duration like `30m` or `1d` or until `!unmute`.\n\
+                 `!mute role [<role mention>|off]` - Shows or sets the mute role. Setting it denies the role sending messages, adding reactions and speaking in every channel."
+            }
+            x if x == Commands::Unmute as u32 => "`!unmute <user>` - Takes the mute role away from the mentioned member.",
+            x if x == Commands::Warn as u32 => {
+                "`!warn <user> <reason>` - Warns the mentioned member in a private message and keeps the warning. \
+                 Reaching a number of warnings in `!warnpolicy` mutes, kicks or bans them."
+            }
+            x if x == Commands::Warnings as u32 => "`!warnings <user>` - Lists the warnings of the mentioned member, with who gave them and why.",
+            x if x == Commands::ClearWarn as u32 => {
+                "`!clearwarn <user> [number]` - Removes the warning with the number from `!warnings`, or all of the warnings of the mentioned member."
+            }
+            x if x == Commands::WarnPolicy as u32 => {
+                "`!warnpolicy` - Shows what happens at how many warnings and when the warnings expire.\n\
+                 `!warnpolicy <warnings> mute|kick|ban [duration]` - Mutes, kicks or bans members when they reach the number of active warnings, for example `!warnpolicy 3 mute 1h`.\n\
+                 `!warnpolicy <warnings> off` - Removes the action for the number of warnings.\n\
+                 `!warnpolicy expiry <days>|never` - Sets after how many days the warnings stop counting. They're still listed by `!warnings`."
+            }
             _ => panic!("Admin::command_help_message - invalid id."),
         }
     }
 
+    fn rerun_on_edit(&self, _id: u32) -> bool {
+        false
+    }
+
+    fn command_scope(&self, _id: u32) -> CommandScope {
+        CommandScope::servers_only()
+    }
+
     fn handle(&self, bot: &Bot, message: &Message, id: u32, text: &str) {
-        let state = bot.get_state().read().unwrap();
+        let locale = bot.get_locale(message);
+        let state = bot.get_state_for_channel(message.channel_id).read().unwrap();
 
         let has_permission = match state.find_channel(message.channel_id) {
-            Some(ChannelRef::Private(_)) => {
-                bot.send(message.channel_id,
-                         "Sorry, but you cannot use the admin commands through PMs. They don't make much sense here anyways.");
-                return;
-            }
-
             Some(ChannelRef::Public(server, _)) => {
-                if message.author.id.0 == server.owner_id.0 {
-                    true
-                } else if let Some(admin_roles) = self.memory.read().unwrap().get_admin_roles(server.id) {
-                    if let Ok(member) = bot.get_member(server.id, message.author.id) {
-                        let mut found = false;
-
-                        for role in member.roles {
-                            if admin_roles.contains(&role.0) {
-                                found = true;
-                            }
-                        }
-
-                        found
-                    } else {
-                        bot.send(message.channel_id,
-                                 "Sorry, I couldn't get your member info.");
+                match is_admin(bot, server, message.author.id) {
+                    Some(x) => x,
+                    None => {
+                        bot.send(message.channel_id, &locale.get("admin-member-info-error"));
                         return;
                     }
-                } else {
-                    false
                 }
             }
 
-            Some(ChannelRef::Group(_)) => {
-                bot.send(message.channel_id, "Admin commands in groups? Hm.");
-                return;
-            }
-
-            None => {
-                bot.send(message.channel_id,
-                         "Huh, I couldn't get this channel's info for some reason. Try again I guess?");
+            _ => {
+                bot.send(message.channel_id, &locale.get("channel-info-error"));
                 return;
             }
         };
@@ -259,15 +722,73 @@ impl<'a> module::Module for Module<'a> {
         }
 
         match id {
-            x if x == Commands::Admin as u32 => self.handle_admin(bot, message, text, state),
-            x if x == Commands::Nuke as u32 => self.handle_nuke(bot, message, text),
+            x if x == Commands::Admin as u32 => self.handle_admin(bot, message, text, &locale, state),
+            x if x == Commands::Nuke as u32 => self.handle_nuke(bot, message, text, &locale, state),
+            x if x == Commands::Language as u32 => self.handle_language(bot, message, text, &locale, state),
+            x if x == Commands::AuditLog as u32 => self.handle_audit_log(bot, message, text, &locale, state),
+            x if x == Commands::Kick as u32 || x == Commands::Ban as u32 || x == Commands::Unban as u32 ||
+                 x == Commands::Mute as u32 || x == Commands::Unmute as u32 => {
+                self.handle_moderation(bot, message, x, text, &locale, state)
+            }
+            x if x == Commands::Warn as u32 => self.handle_warn(bot, message, text, &locale, state),
+            x if x == Commands::Warnings as u32 => self.handle_warnings(bot, message, text, &locale, state),
+            x if x == Commands::ClearWarn as u32 => self.handle_clear_warn(bot, message, text, &locale, state),
+            x if x == Commands::WarnPolicy as u32 => self.handle_warn_policy(bot, message, text, &locale, state),
             _ => panic!("Admin::handle - invalid id."),
         }
     }
+
+    fn handle_tick(&self, bot: &Bot) {
+        match ::STORAGE.scopes_with(STORAGE_NAME, "timed_actions") {
+            Ok(scopes) => {
+                for scope in scopes {
+                    if let Scope::Server(server) = scope {
+                        run_timed_actions(bot, &self.memory, server);
+                    }
+                }
+            }
+            Err(err) => println!("[Warning] Failed to get the timed actions: {}", err),
+        }
+    }
 }
 
 impl<'a> Module<'a> {
-    fn handle_admin(&self, bot: &Bot, message: &Message, text: &str, state: RwLockReadGuard<State>) {
+    // Doesn't load the memory from the storage, also used for the command reference, see docs.rs.
+    pub fn without_storage() -> Self {
+        let mut map: HashMap<u32, &[&str]> = HashMap::new();
+        static ADMIN: [&'static str; 1] = ["admin"];
+        map.insert(Commands::Admin as u32, &ADMIN);
+        static NUKE: [&'static str; 1] = ["nuke"];
+        map.insert(Commands::Nuke as u32, &NUKE);
+        static LANGUAGE: [&'static str; 2] = ["serverlanguage", "serverlang"];
+        map.insert(Commands::Language as u32, &LANGUAGE);
+        static AUDIT_LOG: [&'static str; 1] = ["auditlog"];
+        map.insert(Commands::AuditLog as u32, &AUDIT_LOG);
+        static KICK: [&'static str; 1] = ["kick"];
+        map.insert(Commands::Kick as u32, &KICK);
+        static BAN: [&'static str; 1] = ["ban"];
+        map.insert(Commands::Ban as u32, &BAN);
+        static UNBAN: [&'static str; 1] = ["unban"];
+        map.insert(Commands::Unban as u32, &UNBAN);
+        static MUTE: [&'static str; 1] = ["mute"];
+        map.insert(Commands::Mute as u32, &MUTE);
+        static UNMUTE: [&'static str; 1] = ["unmute"];
+        map.insert(Commands::Unmute as u32, &UNMUTE);
+        static WARN: [&'static str; 1] = ["warn"];
+        map.insert(Commands::Warn as u32, &WARN);
+        static WARNINGS: [&'static str; 1] = ["warnings"];
+        map.insert(Commands::Warnings as u32, &WARNINGS);
+        static CLEAR_WARN: [&'static str; 1] = ["clearwarn"];
+        map.insert(Commands::ClearWarn as u32, &CLEAR_WARN);
+        static WARN_POLICY: [&'static str; 1] = ["warnpolicy"];
+        map.insert(Commands::WarnPolicy as u32, &WARN_POLICY);
+        Module {
+            commands: map,
+            memory: Memory,
+        }
+    }
+
+    fn handle_admin(&self, bot: &Bot, message: &Message, text: &str, locale: &Locale, state: RwLockReadGuard<State>) {
         if let Some(caps) = ADMIN_REGEX.captures(&text.to_lowercase()) {
             // No need to recheck, we did that in handle().
             let server = match state.find_channel(message.channel_id).unwrap() {
@@ -279,82 +800,1552 @@ impl<'a> Module<'a> {
 
             match caps.get(1).unwrap().as_str() {
                 "list" => {
-                    if let Some(admin_roles) = self.memory.read().unwrap().get_admin_roles(server.id) {
-                        let mut buf = "Admin roles:".to_owned();
+                    let admin_roles = match self.memory.get_admin_roles(server.id) {
+                        Ok(admin_roles) => admin_roles,
+                        Err(err) => {
+                            bot.send(message.channel_id,
+                                     &locale.format("something-broken", &[("error", &err)]));
+                            return;
+                        }
+                    };
+
+                    if let Some(admin_roles) = admin_roles {
+                        let mut buf = locale.get("admin-roles");
 
-                        for role_id in admin_roles {
+                        for role_id in &admin_roles {
                             buf.push_str(&format!("\n- {} ", role_id));
 
                             buf.push_str(&if let Some(role) = server.roles.iter().filter(|x| x.id.0 == *role_id).next() {
                                              format!("`{}`", role.name)
                                          } else {
-                                             " this role was removed".to_owned()
+                                             locale.get("admin-role-removed")
                                          });
                         }
 
                         bot.send(message.channel_id, &buf);
                     } else {
-                        bot.send(message.channel_id, "There are no admin roles yet.");
+                        bot.send(message.channel_id, &locale.get("admin-no-roles"));
                     }
                 }
 
                 "add" => {
                     if message.mention_roles.len() > 0 {
-                        self.memory
-                            .write()
-                            .unwrap()
-                            .add_admin_roles(server.id, &message.mention_roles);
+                        let outcome = match self.memory.add_admin_roles(server.id, &message.mention_roles) {
+                            Ok(()) => Ok(locale.get("admin-audit-log-done")),
+                            Err(err) => {
+                                bot.send(message.channel_id,
+                                         &locale.format("storage-error", &[("error", &err)]));
+                                Err(err.to_string())
+                            }
+                        };
+
+                        audit(bot,
+                              server.id,
+                              message,
+                              "!admin add",
+                              &role_names(server, &message.mention_roles),
+                              &outcome);
                     } else {
-                        bot.send(message.channel_id, "You didn't mention any roles.");
+                        bot.send(message.channel_id, &locale.get("admin-no-role-mentions"));
                     }
                 }
 
                 "remove" => {
                     if message.mention_roles.len() > 0 {
-                        self.memory
-                            .write()
-                            .unwrap()
-                            .remove_admin_roles(server.id, &message.mention_roles);
+                        let outcome = match self.memory.remove_admin_roles(server.id, &message.mention_roles) {
+                            Ok(()) => Ok(locale.get("admin-audit-log-done")),
+                            Err(err) => {
+                                bot.send(message.channel_id,
+                                         &locale.format("storage-error", &[("error", &err)]));
+                                Err(err.to_string())
+                            }
+                        };
+
+                        audit(bot,
+                              server.id,
+                              message,
+                              "!admin remove",
+                              &role_names(server, &message.mention_roles),
+                              &outcome);
                     } else {
-                        bot.send(message.channel_id, "You didn't mention any roles.");
+                        bot.send(message.channel_id, &locale.get("admin-no-role-mentions"));
                     }
                 }
 
                 _ => {
                     bot.send(message.channel_id,
-                             <Module as module::Module>::command_help_message(&self, Commands::Admin as u32));
+                             &locale.command_help_message(self, Commands::Admin as u32));
                 }
             }
         } else {
             bot.send(message.channel_id,
-                     <Module as module::Module>::command_help_message(&self, Commands::Admin as u32));
-        }
-    }
-
-    fn handle_nuke(&self, bot: &Bot, message: &Message, text: &str) {
-        if let Some(amount) = NUKE_REGEX
-               .captures(text)
-               .and_then(|x| x.get(2))
-               .and_then(|x| x.as_str().parse::<u64>().ok())
-               .map(|x| x + 1)
-               .and_then(|x| if x <= 1 { None } else { Some(x) }) {
-            let mentioned_user_ids: Vec<UserId> = message.mentions.iter().map(|x| x.id).collect();
-
-            if let Ok(recent_message_ids) =
-                bot.get_messages(message.channel_id, GetMessages::MostRecent, amount)
-                    .map(|x| {
-                             x.into_iter()
-                                 .filter(|msg| mentioned_user_ids.len() == 0 || mentioned_user_ids.contains(&msg.author.id))
-                                 .map(|msg| msg.id)
-                                 .collect::<Vec<MessageId>>()
-                         }) {
-                bot.delete_messages(message.channel_id, &recent_message_ids);
-            } else {
-                bot.send(message.channel_id, "Error getting the recent messages.");
+                     &locale.command_help_message(self, Commands::Admin as u32));
+        }
+    }
+
+    fn handle_nuke(&self, bot: &Bot, message: &Message, text: &str, locale: &Locale, state: RwLockReadGuard<State>) {
+        // No need to recheck, we did that in handle().
+        let server_id = match state.find_channel(message.channel_id).unwrap() {
+            ChannelRef::Public(server, _) => server.id,
+            _ => {
+                panic!("Did I just witness some memory corruption?");
+            }
+        };
+
+        drop(state);
+
+        if let Some(caps) = NUKE_EXPORT_REGEX.captures(text) {
+            self.handle_nuke_export(bot, message, text, locale, server_id, caps.get(1).map(|x| x.as_str()));
+            return;
+        }
+
+        let filter = match parse_nuke(text) {
+            Ok(ref filter) if filter.count.is_none() && filter.after.is_none() => {
+                bot.send(message.channel_id,
+                         &locale.command_help_message(self, Commands::Nuke as u32));
+                return;
+            }
+            Ok(filter) => filter,
+            Err(argument) => {
+                bot.send(message.channel_id,
+                         &locale.format("admin-invalid-argument", &[("argument", &argument), ("command", &"nuke")]));
+                return;
+            }
+        };
+
+        let count = filter.count.unwrap_or(MAX_NUKE_COUNT) as usize;
+        let mut nuked = Vec::new();
+        let mut scanned = 0;
+        let mut before = filter.before.unwrap_or(message.id);
+
+        // Goes back page by page until there are enough messages or it reaches the --after one.
+        let result = loop {
+            if nuked.len() == count || scanned >= MAX_NUKE_SCAN || bot.cancellation_token().is_cancelled() {
+                break Ok(());
+            }
+
+            let page = match bot.get_messages(message.channel_id, GetMessages::Before(before), MESSAGES_PER_PAGE) {
+                Ok(page) => page,
+                Err(_) => break Err(()),
+            };
+
+            let oldest = match page.iter().map(|x| x.id).min() {
+                Some(oldest) => oldest,
+                None => break Ok(()),
+            };
+
+            scanned += page.len();
+            before = oldest;
+
+            for msg in page.into_iter().filter(|x| filter.after.iter().all(|&after| x.id > after)) {
+                if nuked.len() < count && filter.matches(&msg) {
+                    nuked.push(msg);
+                }
+            }
+
+            if filter.after.iter().any(|&after| oldest <= after) {
+                break Ok(());
+            }
+        };
+
+        // Names instead of the mentions.
+        let mut parameters = text.to_owned();
+        for user in &message.mentions {
+            for mention in &[format!("<@{}>", user.id.0), format!("<@!{}>", user.id.0)] {
+                parameters = parameters.replace(mention.as_str(), &format!("@{}", user.name));
             }
+        }
+
+        let export = match filter.export {
+            Some(export) => export,
+            None => {
+                ::STORAGE.get(STORAGE_NAME, Scope::Server(server_id), "nuke_export")
+                    .unwrap_or(None)
+                    .unwrap_or(false)
+            }
+        };
+
+        // Nothing is deleted if the messages couldn't be exported.
+        let result = result.map_err(|_| locale.get("admin-nuke-error")).and_then(|_| if export && !nuked.is_empty() {
+            export_nuked(bot, server_id, message, &nuked, locale)
         } else {
-            bot.send(message.channel_id,
-                     <Module as module::Module>::command_help_message(&self, Commands::Nuke as u32));
+            Ok(())
+        });
+
+        let outcome = match result {
+            Ok(()) => {
+                let ids = nuked.iter().map(|x| x.id).collect::<Vec<_>>();
+
+                bot.delete_messages(message.channel_id, &[message.id]);
+                let deleted = bot.delete_messages(message.channel_id, &ids);
+
+                if deleted == ids.len() {
+                    bot.send(message.channel_id,
+                             &locale.format("admin-nuked", &[("count", &deleted)]));
+                } else {
+                    bot.send(message.channel_id,
+                             &locale.format("admin-nuked-partially", &[("count", &deleted), ("total", &ids.len())]));
+                }
+
+                Ok(locale.format("admin-audit-log-nuked", &[("count", &deleted)]))
+            }
+            Err(err) => {
+                bot.send(message.channel_id, &err);
+                Err(err)
+            }
+        };
+
+        audit(bot, server_id, message, "!nuke", &parameters, &outcome);
+    }
+
+    fn handle_nuke_export(&self,
+                          bot: &Bot,
+                          message: &Message,
+                          text: &str,
+                          locale: &Locale,
+                          server_id: ServerId,
+                          setting: Option<&str>) {
+        let (result, reply) = match setting.map(|x| x.to_lowercase()) {
+            None => {
+                let export = ::STORAGE.get(STORAGE_NAME, Scope::Server(server_id), "nuke_export")
+                    .unwrap_or(None)
+                    .unwrap_or(false);
+                bot.send(message.channel_id,
+                         &locale.get(if export { "admin-nuke-export-on" } else { "admin-nuke-export-off" }));
+                return;
+            }
+            Some(ref x) if x == "on" => {
+                (::STORAGE.set(STORAGE_NAME, Scope::Server(server_id), "nuke_export", &true),
+                 locale.get("admin-nuke-export-on"))
+            }
+            Some(_) => {
+                (::STORAGE.remove(STORAGE_NAME, Scope::Server(server_id), "nuke_export"),
+                 locale.get("admin-nuke-export-off"))
+            }
+        };
+
+        match result {
+            Ok(()) => {
+                audit(bot, server_id, message, "!nuke", text, &Ok(reply.clone()));
+                bot.send(message.channel_id, &reply);
+            }
+            Err(err) => {
+                audit(bot, server_id, message, "!nuke", text, &Err(err.to_string()));
+                bot.send(message.channel_id,
+                         &locale.format("storage-error", &[("error", &err)]));
+            }
         }
     }
+
+    fn handle_language(&self, bot: &Bot, message: &Message, text: &str, locale: &Locale, state: RwLockReadGuard<State>) {
+        // No need to recheck, we did that in handle().
+        let server_id = match state.find_channel(message.channel_id).unwrap() {
+            ChannelRef::Public(server, _) => server.id,
+            _ => {
+                panic!("Did I just witness some memory corruption?");
+            }
+        };
+
+        // The localization might need the state lock later on.
+        drop(state);
+
+        let code = match LANGUAGE_REGEX.captures(text).and_then(|x| x.get(1)) {
+            Some(code) => code.as_str(),
+            None => {
+                bot.send(message.channel_id,
+                         &locale.command_help_message(self, Commands::Language as u32));
+                return;
+            }
+        };
+
+        let localization = bot.get_localization();
+
+        if code == "default" {
+            let result = localization.set_server_locale(server_id, None);
+            audit(bot,
+                  server_id,
+                  message,
+                  "!serverlanguage",
+                  code,
+                  &result.as_ref().map(|_| locale.get("admin-audit-log-done")).map_err(|e| e.to_string()));
+
+            if let Err(err) = result {
+                bot.send(message.channel_id,
+                         &locale.format("storage-error", &[("error", &err)]));
+                return;
+            }
+
+            bot.send(message.channel_id, &locale.get("admin-language-reset"));
+        } else if localization.has_locale(code) {
+            let result = localization.set_server_locale(server_id, Some(code));
+            audit(bot,
+                  server_id,
+                  message,
+                  "!serverlanguage",
+                  code,
+                  &result.as_ref().map(|_| locale.get("admin-audit-log-done")).map_err(|e| e.to_string()));
+
+            if let Err(err) = result {
+                bot.send(message.channel_id,
+                         &locale.format("storage-error", &[("error", &err)]));
+                return;
+            }
+
+            let new_locale = localization.get_locale(None, Some(server_id));
+            bot.send(message.channel_id,
+                     &new_locale.format("admin-language-set", &[("locale", &new_locale.get("locale-name"))]));
+        } else {
+            bot.send(message.channel_id,
+                     &locale.format("locale-unknown",
+                                    &[("code", &code), ("available", &localization.available_locales().join(", "))]));
+        }
+    }
+    fn handle_audit_log(&self, bot: &Bot, message: &Message, text: &str, locale: &Locale, state: RwLockReadGuard<State>) {
+        // No need to recheck, we did that in handle().
+        let server_id = match state.find_channel(message.channel_id).unwrap() {
+            ChannelRef::Public(server, _) => server.id,
+            _ => {
+                panic!("Did I just witness some memory corruption?");
+            }
+        };
+
+        if let Some(caps) = MOD_LOG_REGEX.captures(text) {
+            let channel = caps.get(2).and_then(|x| x.as_str().parse().ok()).map(ChannelId);
+
+            if let Some(channel) = channel {
+                match state.find_channel(channel) {
+                    Some(ChannelRef::Public(server, _)) if server.id == server_id => {}
+                    _ => {
+                        bot.send(message.channel_id, &locale.get("admin-mod-log-unknown-channel"));
+                        return;
+                    }
+                }
+            }
+
+            drop(state);
+            self.handle_mod_log_channel(bot, message, locale, server_id, channel);
+            return;
+        }
+
+        drop(state);
+
+        let count = AUDIT_LOG_REGEX.captures(text)
+            .and_then(|x| x.get(1))
+            .and_then(|x| x.as_str().parse().ok())
+            .unwrap_or(DEFAULT_AUDIT_LOG_COUNT)
+            .min(MAX_AUDIT_LOG_COUNT);
+        let actor = message.mentions.first().map(|x| x.id.0);
+
+        let log: Vec<AuditEntry> = match ::STORAGE.get(STORAGE_NAME, Scope::Server(server_id), "audit_log") {
+            Ok(log) => log.unwrap_or_default(),
+            Err(err) => {
+                bot.send(message.channel_id,
+                         &locale.format("something-broken", &[("error", &err)]));
+                return;
+            }
+        };
+
+        let mut entries: Vec<&AuditEntry> = log.iter()
+            .rev()
+            .filter(|x| actor.is_none() || actor == Some(x.actor))
+            .take(count)
+            .collect();
+
+        if entries.is_empty() {
+            bot.send(message.channel_id, &locale.get("admin-audit-log-empty"));
+            return;
+        }
+
+        // Oldest first.
+        entries.reverse();
+
+        let mut buf = locale.get("admin-audit-log");
+        for entry in entries {
+            buf.push_str("\n- ");
+            buf.push_str(&format_audit_entry(locale, entry));
+        }
+
+        bot.send(message.channel_id, &buf);
+    }
+
+    fn handle_mod_log_channel(&self,
+                              bot: &Bot,
+                              message: &Message,
+                              locale: &Locale,
+                              server_id: ServerId,
+                              channel: Option<ChannelId>) {
+        let (result, reply) = match channel {
+            Some(channel) => {
+                (::STORAGE.set(STORAGE_NAME, Scope::Server(server_id), "mod_log_channel", &channel.0),
+                 locale.format("admin-mod-log-set", &[("channel", &channel.mention())]))
+            }
+            None => {
+                (::STORAGE.remove(STORAGE_NAME, Scope::Server(server_id), "mod_log_channel"),
+                 locale.get("admin-mod-log-off"))
+            }
+        };
+
+        let parameters = match channel {
+            Some(channel) => format!("channel {}", channel.mention()),
+            None => "channel off".to_owned(),
+        };
+
+        match result {
+            Ok(()) => {
+                audit(bot, server_id, message, "!auditlog", &parameters, &Ok(reply.clone()));
+                bot.send(message.channel_id, &reply);
+            }
+            Err(err) => {
+                audit(bot, server_id, message, "!auditlog", &parameters, &Err(err.to_string()));
+                bot.send(message.channel_id,
+                         &locale.format("storage-error", &[("error", &err)]));
+            }
+        }
+    }
+
+    // Checks that both the author of the message and the bot are above the target in the role hierarchy.
+    // Err has the reply.
+    fn check_hierarchy(&self,
+                       bot: &Bot,
+                       hierarchy: &Hierarchy,
+                       actor: UserId,
+                       target: &Member,
+                       locale: &Locale)
+                       -> std::result::Result<(), String> {
+        let outranked = locale.format("admin-target-outranks", &[("user", &target.user.name)]);
+        let bot_outranked = locale.format("admin-bot-outranked", &[("user", &target.user.name)]);
+
+        if target.user.id == hierarchy.owner {
+            return Err(outranked);
+        }
+
+        let target_position = top_role_position(&hierarchy.roles, &target.roles);
+
+        for &(user, ref error) in &[(actor, outranked.clone()), (hierarchy.bot, bot_outranked)] {
+            // The owner is above everyone.
+            if user == hierarchy.owner {
+                continue;
+            }
+
+            let roles = try!(bot.get_member(hierarchy.server, user)
+                                 .map(|x| x.roles)
+                                 .map_err(|_| locale.get("admin-member-info-error")));
+
+            if top_role_position(&hierarchy.roles, &roles) <= target_position {
+                return Err(error.clone());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle_moderation(&self,
+                         bot: &Bot,
+                         message: &Message,
+                         id: u32,
+                         text: &str,
+                         locale: &Locale,
+                         state: RwLockReadGuard<State>) {
+        let hierarchy = get_hierarchy(&state, message.channel_id);
+        drop(state);
+
+        let command = moderation_command_name(id);
+        let with_duration = id == Commands::Ban as u32 || id == Commands::Mute as u32;
+
+        if id == Commands::Mute as u32 {
+            if let Some(caps) = MUTE_ROLE_REGEX.captures(text) {
+                self.handle_mute_role(bot, message, locale, &hierarchy, caps.get(1).map(|x| x.as_str()));
+                return;
+            }
+        }
+
+        let args = match parse_moderation(text, with_duration) {
+            Ok(args) => args,
+            Err(ref argument) if argument.is_empty() => {
+                bot.send(message.channel_id, &locale.command_help_message(self, id));
+                return;
+            }
+            Err(argument) => {
+                bot.send(message.channel_id,
+                         &locale.format("admin-invalid-argument", &[("argument", &argument), ("command", &command)]));
+                return;
+            }
+        };
+
+        self.moderate(bot, message, id, args, locale, &hierarchy);
+    }
+
+    // Kicks, bans, unbans, mutes or unmutes, replies and records it in the audit log.
+    fn moderate(&self,
+                bot: &Bot,
+                message: &Message,
+                id: u32,
+                args: ModerationArgs,
+                locale: &Locale,
+                hierarchy: &Hierarchy) {
+        let server_id = hierarchy.server;
+        let command = moderation_command_name(id);
+
+        let mute_role = if id == Commands::Mute as u32 || id == Commands::Unmute as u32 {
+            match self.memory.get_mute_role(server_id) {
+                Ok(Some(role)) => Some(role),
+                Ok(None) => {
+                    bot.send(message.channel_id, &locale.get("admin-no-mute-role"));
+                    return;
+                }
+                Err(err) => {
+                    bot.send(message.channel_id,
+                             &locale.format("something-broken", &[("error", &err)]));
+                    return;
+                }
+            }
+        } else {
+            None
+        };
+
+        // People who aren't on the server can still be banned and unbanned.
+        let target = match bot.get_member(server_id, args.user) {
+            Ok(member) => Some(member),
+            Err(_) if id == Commands::Ban as u32 || id == Commands::Unban as u32 => None,
+            Err(_) => {
+                bot.send(message.channel_id, &locale.get("admin-member-not-found"));
+                return;
+            }
+        };
+
+        let name = target.as_ref().map_or_else(|| args.user.0.to_string(), |x| x.user.name.clone());
+
+        if let Some(ref target) = target {
+            if let Err(reply) = self.check_hierarchy(bot, hierarchy, message.author.id, target, locale) {
+                bot.send(message.channel_id, &reply);
+                return;
+            }
+        }
+
+        // They're told before, since after a kick or a ban the bot might not be able to reach them.
+        let notified = match (target.is_some(), id) {
+            (true, x) if x == Commands::Kick as u32 || x == Commands::Ban as u32 || x == Commands::Mute as u32 => {
+                let dm_locale = bot.get_localization().get_locale(Some(args.user), Some(server_id));
+                let key = match (command, args.duration) {
+                    ("kick", _) => "admin-dm-kicked",
+                    ("ban", None) => "admin-dm-banned",
+                    ("ban", Some(_)) => "admin-dm-banned-for",
+                    (_, None) => "admin-dm-muted",
+                    (_, Some(_)) => "admin-dm-muted-for",
+                };
+
+                let mut dm = dm_locale.format(key,
+                                              &[("server", &hierarchy.server_name),
+                                                ("duration", &format_duration(args.duration.unwrap_or(0)))]);
+
+                if let Some(ref reason) = args.reason {
+                    dm.push('\n');
+                    dm.push_str(&dm_locale.format("admin-dm-reason", &[("reason", reason)]));
+                }
+
+                bot.try_send_pm(args.user, &dm)
+            }
+            _ => true,
+        };
+
+        let result = match id {
+            x if x == Commands::Kick as u32 => bot.kick_member(server_id, args.user),
+            x if x == Commands::Ban as u32 => bot.ban_member(server_id, args.user, 0),
+            x if x == Commands::Unban as u32 => bot.unban_member(server_id, args.user),
+            x if x == Commands::Mute as u32 => bot.add_member_role(server_id, args.user, mute_role.unwrap()),
+            _ => bot.remove_member_role(server_id, args.user, mute_role.unwrap()),
+        };
+
+        // A new ban or mute replaces the timed one, and unbanning or unmuting cancels it.
+        let kind = if id == Commands::Ban as u32 || id == Commands::Unban as u32 {
+            TimedActionKind::Unban
+        } else {
+            TimedActionKind::Unmute
+        };
+
+        let result = result.map_err(|e| e.to_string()).and_then(|_| if id == Commands::Kick as u32 {
+            Ok(())
+        } else {
+            let action = args.duration.map(|duration| {
+                TimedAction {
+                    kind: kind,
+                    user: args.user.0,
+                    user_name: name.clone(),
+                    expires: now() + duration,
+                }
+            });
+
+            schedule(server_id, kind, args.user, action).map_err(|e| e.to_string())
+        });
+
+        let outcome = result.map(|_| {
+            let key = match (command, args.duration) {
+                ("kick", _) => "admin-kicked",
+                ("ban", None) => "admin-banned",
+                ("ban", Some(_)) => "admin-banned-for",
+                ("unban", _) => "admin-unbanned",
+                ("mute", None) => "admin-muted",
+                ("mute", Some(_)) => "admin-muted-for",
+                _ => "admin-unmuted",
+            };
+
+            locale.format(key,
+                          &[("user", &name), ("duration", &format_duration(args.duration.unwrap_or(0)))])
+        });
+
+        // The name instead of the mention.
+        let mut parameters = format!("@{}", name);
+        if let Some(duration) = args.duration {
+            parameters.push_str(&format!(" {}", format_duration(duration)));
+        }
+        if let Some(ref reason) = args.reason {
+            parameters.push_str(&format!(" {}", reason));
+        }
+
+        audit(bot, server_id, message, &format!("!{}", command), &parameters, &outcome);
+
+        match outcome {
+            Ok(mut reply) => {
+                if !notified {
+                    reply.push(' ');
+                    reply.push_str(&locale.get("admin-not-notified"));
+                }
+
+                bot.send(message.channel_id, &reply);
+            }
+            Err(err) => {
+                bot.send(message.channel_id,
+                         &locale.format("admin-moderation-error", &[("error", &err)]));
+            }
+        }
+    }
+
+    fn handle_mute_role(&self,
+                        bot: &Bot,
+                        message: &Message,
+                        locale: &Locale,
+                        hierarchy: &Hierarchy,
+                        role: Option<&str>) {
+        let server_id = hierarchy.server;
+        let role_name = |role: RoleId| match hierarchy.roles.iter().find(|x| x.id == role) {
+            Some(role) => role.name.clone(),
+            None => role.0.to_string(),
+        };
+
+        let role = match role {
+            None => {
+                let reply = match self.memory.get_mute_role(server_id) {
+                    Ok(Some(role)) => locale.format("admin-mute-role", &[("role", &role_name(role))]),
+                    Ok(None) => locale.get("admin-no-mute-role"),
+                    Err(err) => locale.format("something-broken", &[("error", &err)]),
+                };
+
+                bot.send(message.channel_id, &reply);
+                return;
+            }
+            Some(x) if x.eq_ignore_ascii_case("off") => None,
+            Some(x) => {
+                let role = RoleId(x.parse().unwrap());
+
+                if !hierarchy.roles.iter().any(|x| x.id == role) {
+                    bot.send(message.channel_id, &locale.get("admin-unknown-role"));
+                    return;
+                }
+
+                Some(role)
+            }
+        };
+
+        let outcome = self.memory
+            .set_mute_role(server_id, role)
+            .map_err(|e| e.to_string())
+            .map(|_| match role {
+                Some(role) => {
+                    // The role has to be denied talking in every channel, since members can talk by default.
+                    let channels = {
+                        let state = bot.get_state_for_channel(message.channel_id).read().unwrap();
+
+                        match state.find_channel(message.channel_id) {
+                            Some(ChannelRef::Public(server, _)) => {
+                                server.channels.iter().map(|x| (x.id, x.kind)).collect::<Vec<_>>()
+                            }
+                            _ => Vec::new(),
+                        }
+                    };
+
+                    for &(channel, kind) in &channels {
+                        bot.create_permissions(channel,
+                                               PermissionOverwrite {
+                                                   kind: PermissionOverwriteType::Role(role),
+                                                   allow: Permissions::empty(),
+                                                   deny: if kind == ChannelType::Voice {
+                                                       permissions::VOICE_SPEAK
+                                                   } else {
+                                                       permissions::SEND_MESSAGES | permissions::ADD_REACTIONS
+                                                   },
+                                               });
+                    }
+
+                    locale.format("admin-mute-role-set", &[("role", &role_name(role)), ("count", &channels.len())])
+                }
+                None => locale.get("admin-mute-role-removed"),
+            });
+
+        let parameters = match role {
+            Some(role) => format!("role {}", role_name(role)),
+            None => "role off".to_owned(),
+        };
+
+        audit(bot, server_id, message, "!mute", &parameters, &outcome);
+
+        match outcome {
+            Ok(reply) => bot.send(message.channel_id, &reply),
+            Err(err) => {
+                bot.send(message.channel_id,
+                         &locale.format("storage-error", &[("error", &err)]))
+            }
+        }
+    }
+
+    fn handle_warn(&self, bot: &Bot, message: &Message, text: &str, locale: &Locale, state: RwLockReadGuard<State>) {
+        let hierarchy = get_hierarchy(&state, message.channel_id);
+        drop(state);
+
+        let server_id = hierarchy.server;
+
+        let (user, reason) = match parse_moderation(text, false) {
+            Ok(ModerationArgs { user, reason: Some(reason), .. }) => (user, reason),
+            Ok(_) => {
+                bot.send(message.channel_id,
+                         &locale.command_help_message(self, Commands::Warn as u32));
+                return;
+            }
+            Err(ref argument) if argument.is_empty() => {
+                bot.send(message.channel_id,
+                         &locale.command_help_message(self, Commands::Warn as u32));
+                return;
+            }
+            Err(argument) => {
+                bot.send(message.channel_id,
+                         &locale.format("admin-invalid-argument", &[("argument", &argument), ("command", &"warn")]));
+                return;
+            }
+        };
+
+        let target = match bot.get_member(server_id, user) {
+            Ok(member) => member,
+            Err(_) => {
+                bot.send(message.channel_id, &locale.get("admin-member-not-found"));
+                return;
+            }
+        };
+
+        if let Err(reply) = self.check_hierarchy(bot, &hierarchy, message.author.id, &target, locale) {
+            bot.send(message.channel_id, &reply);
+            return;
+        }
+
+        let warning = Warning {
+            time: now(),
+            moderator: message.author.id.0,
+            moderator_name: message.author.name.clone(),
+            reason: reason.clone(),
+        };
+
+        let result = add_warning(server_id, user, warning);
+
+        let outcome = result.as_ref()
+            .map(|&(active, _)| locale.format("admin-warned", &[("user", &target.user.name), ("count", &active)]))
+            .map_err(|e| e.to_string());
+
+        audit(bot,
+              server_id,
+              message,
+              "!warn",
+              &format!("@{} {}", target.user.name, reason),
+              &outcome);
+
+        match outcome {
+            Ok(mut reply) => {
+                let dm_locale = bot.get_localization().get_locale(Some(user), Some(server_id));
+                let dm = dm_locale.format("admin-dm-warned",
+                                          &[("server", &hierarchy.server_name), ("reason", &reason)]);
+
+                if !bot.try_send_pm(user, &dm) {
+                    reply.push(' ');
+                    reply.push_str(&locale.get("admin-not-notified"));
+                }
+
+                bot.send(message.channel_id, &reply);
+            }
+            Err(err) => {
+                bot.send(message.channel_id,
+                         &locale.format("storage-error", &[("error", &err)]));
+                return;
+            }
+        }
+
+        if let Ok((active, Some(step))) = result {
+            let id = match step.action {
+                EscalationAction::Mute => Commands::Mute,
+                EscalationAction::Kick => Commands::Kick,
+                EscalationAction::Ban => Commands::Ban,
+            } as u32;
+
+            let args = ModerationArgs {
+                user: user,
+                duration: step.duration,
+                reason: Some(locale.format("admin-escalation-reason", &[("count", &active)])),
+            };
+
+            self.moderate(bot, message, id, args, locale, &hierarchy);
+        }
+    }
+
+    fn handle_warnings(&self, bot: &Bot, message: &Message, text: &str, locale: &Locale, state: RwLockReadGuard<State>) {
+        let hierarchy = get_hierarchy(&state, message.channel_id);
+        drop(state);
+
+        let server_id = hierarchy.server;
+
+        let user = match parse_moderation(text, false) {
+            Ok(ModerationArgs { user, reason: None, .. }) => user,
+            _ => {
+                bot.send(message.channel_id,
+                         &locale.command_help_message(self, Commands::Warnings as u32));
+                return;
+            }
+        };
+
+        let name = bot.get_member(server_id, user).map(|x| x.user.name).unwrap_or_else(|_| user.0.to_string());
+
+        let (warnings, expiry) = match get_warnings(server_id, user).and_then(|x| get_warning_expiry(server_id).map(|y| (x, y))) {
+            Ok(x) => x,
+            Err(err) => {
+                bot.send(message.channel_id,
+                         &locale.format("something-broken", &[("error", &err)]));
+                return;
+            }
+        };
+
+        if warnings.is_empty() {
+            bot.send(message.channel_id,
+                     &locale.format("admin-no-warnings", &[("user", &name)]));
+            return;
+        }
+
+        let time = now();
+        let active = warnings.iter().filter(|x| is_active(x, expiry, time)).count();
+
+        let mut buf = locale.format("admin-warnings", &[("user", &name), ("count", &active)]);
+
+        for (number, warning) in warnings.iter().enumerate() {
+            buf.push_str(&format!("\n{}. ", number + 1));
+            buf.push_str(&locale.format(if is_active(warning, expiry, time) {
+                                            "admin-warning"
+                                        } else {
+                                            "admin-warning-expired"
+                                        },
+                                        &[("time", &format_time(warning.time)),
+                                          ("moderator", &warning.moderator_name),
+                                          ("reason", &warning.reason)]));
+        }
+
+        bot.send(message.channel_id, &buf);
+    }
+
+    fn handle_clear_warn(&self, bot: &Bot, message: &Message, text: &str, locale: &Locale, state: RwLockReadGuard<State>) {
+        let hierarchy = get_hierarchy(&state, message.channel_id);
+        drop(state);
+
+        let server_id = hierarchy.server;
+
+        let (user, number) = match parse_moderation(text, false) {
+            Ok(ModerationArgs { user, reason: None, .. }) => (user, None),
+            Ok(ModerationArgs { user, reason: Some(x), .. }) => {
+                match x.parse::<usize>() {
+                    Ok(number) if number > 0 => (user, Some(number)),
+                    _ => {
+                        bot.send(message.channel_id,
+                                 &locale.format("admin-invalid-argument", &[("argument", &x), ("command", &"clearwarn")]));
+                        return;
+                    }
+                }
+            }
+            Err(_) => {
+                bot.send(message.channel_id,
+                         &locale.command_help_message(self, Commands::ClearWarn as u32));
+                return;
+            }
+        };
+
+        let name = bot.get_member(server_id, user).map(|x| x.user.name).unwrap_or_else(|_| user.0.to_string());
+
+        let mut found = false;
+        let result = ::STORAGE.update(STORAGE_NAME,
+                                      Scope::Member(server_id, user),
+                                      "warnings",
+                                      |warnings: Option<Vec<Warning>>| {
+            let mut warnings = warnings.unwrap_or_default();
+
+            match number {
+                Some(number) if number <= warnings.len() => {
+                    warnings.remove(number - 1);
+                    found = true;
+                }
+                Some(_) => {}
+                None => {
+                    found = !warnings.is_empty();
+                    warnings.clear();
+                }
+            }
+
+            if warnings.is_empty() { None } else { Some(warnings) }
+        });
+
+        let reply = match (found, number) {
+            (true, Some(number)) => locale.format("admin-warning-cleared", &[("user", &name), ("number", &number)]),
+            (true, None) => locale.format("admin-warnings-cleared", &[("user", &name)]),
+            (false, Some(number)) => locale.format("admin-no-such-warning", &[("user", &name), ("number", &number)]),
+            (false, None) => locale.format("admin-no-warnings", &[("user", &name)]),
+        };
+
+        let mut parameters = format!("@{}", name);
+        if let Some(number) = number {
+            parameters.push_str(&format!(" {}", number));
+        }
+
+        match result {
+            Ok(()) => {
+                if found {
+                    audit(bot, server_id, message, "!clearwarn", &parameters, &Ok(reply.clone()));
+                }
+
+                bot.send(message.channel_id, &reply);
+            }
+            Err(err) => {
+                audit(bot, server_id, message, "!clearwarn", &parameters, &Err(err.to_string()));
+                bot.send(message.channel_id,
+                         &locale.format("storage-error", &[("error", &err)]));
+            }
+        }
+    }
+
+    fn handle_warn_policy(&self, bot: &Bot, message: &Message, text: &str, locale: &Locale, state: RwLockReadGuard<State>) {
+        let server_id = get_hierarchy(&state, message.channel_id).server;
+        drop(state);
+
+        let mut words = text.split_whitespace();
+
+        let (result, reply) = match words.next().map(|x| x.to_lowercase()) {
+            None => {
+                let reply = match get_warning_policy(server_id).and_then(|x| get_warning_expiry(server_id).map(|y| (x, y))) {
+                    Ok((policy, expiry)) => {
+                        let mut buf = match expiry {
+                            Some(days) => locale.format("admin-warning-expiry", &[("days", &days)]),
+                            None => locale.get("admin-warning-no-expiry"),
+                        };
+
+                        buf.push('\n');
+
+                        if policy.is_empty() {
+                            buf.push_str(&locale.get("admin-warning-policy-empty"));
+                        } else {
+                            buf.push_str(&locale.get("admin-warning-policy"));
+
+                            for step in &policy {
+                                buf.push_str("\n- ");
+                                buf.push_str(&locale.format("admin-warning-policy-step",
+                                                            &[("count", &step.warnings),
+                                                              ("action", &describe_escalation(locale, step))]));
+                            }
+                        }
+
+                        buf
+                    }
+                    Err(err) => locale.format("something-broken", &[("error", &err)]),
+                };
+
+                bot.send(message.channel_id, &reply);
+                return;
+            }
+
+            Some(ref x) if x == "expiry" => {
+                match words.next().map(|x| x.to_lowercase()) {
+                    Some(ref x) if x == "never" => {
+                        (::STORAGE.remove(STORAGE_NAME, Scope::Server(server_id), "warning_expiry_days"),
+                         locale.get("admin-warning-no-expiry"))
+                    }
+                    Some(x) => {
+                        match x.parse::<u64>() {
+                            Ok(days) if (1..=MAX_WARNING_EXPIRY_DAYS).contains(&days) => {
+                                (::STORAGE.set(STORAGE_NAME, Scope::Server(server_id), "warning_expiry_days", &days),
+                                 locale.format("admin-warning-expiry", &[("days", &days)]))
+                            }
+                            _ => {
+                                bot.send(message.channel_id,
+                                         &locale.format("admin-invalid-argument",
+                                                        &[("argument", &x), ("command", &"warnpolicy")]));
+                                return;
+                            }
+                        }
+                    }
+                    None => {
+                        bot.send(message.channel_id,
+                                 &locale.command_help_message(self, Commands::WarnPolicy as u32));
+                        return;
+                    }
+                }
+            }
+
+            Some(_) => {
+                let (warnings, step) = match parse_escalation_step(text) {
+                    Ok(x) => x,
+                    Err(ref argument) if argument.is_empty() => {
+                        bot.send(message.channel_id,
+                                 &locale.command_help_message(self, Commands::WarnPolicy as u32));
+                        return;
+                    }
+                    Err(argument) => {
+                        bot.send(message.channel_id,
+                                 &locale.format("admin-invalid-argument",
+                                                &[("argument", &argument), ("command", &"warnpolicy")]));
+                        return;
+                    }
+                };
+
+                let reply = match step {
+                    Some(ref step) => {
+                        locale.format("admin-warning-policy-set",
+                                      &[("count", &warnings), ("action", &describe_escalation(locale, step))])
+                    }
+                    None => locale.format("admin-warning-policy-removed", &[("count", &warnings)]),
+                };
+
+                (::STORAGE.update(STORAGE_NAME,
+                                  Scope::Server(server_id),
+                                  "warning_policy",
+                                  |policy: Option<Vec<EscalationStep>>| {
+                    let mut policy = policy.unwrap_or_default();
+                    policy.retain(|x| x.warnings != warnings);
+                    policy.extend(step);
+                    policy.sort_by_key(|x| x.warnings);
+                    if policy.is_empty() { None } else { Some(policy) }
+                }),
+                 reply)
+            }
+        };
+
+        match result {
+            Ok(()) => {
+                audit(bot, server_id, message, "!warnpolicy", text, &Ok(reply.clone()));
+                bot.send(message.channel_id, &reply);
+            }
+            Err(err) => {
+                audit(bot, server_id, message, "!warnpolicy", text, &Err(err.to_string()));
+                bot.send(message.channel_id,
+                         &locale.format("storage-error", &[("error", &err)]));
+            }
+        }
+    }
+}
+
+// Lists the names of the roles, falling back to the IDs of unknown ones.
+pub fn role_names(server: &LiveServer, roles: &[RoleId]) -> String {
+    roles.iter()
+        .map(|id| match server.roles.iter().find(|x| x.id == *id) {
+                 Some(role) => role.name.clone(),
+                 None => id.0.to_string(),
+             })
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+// Parses a duration like `30m`, `12h` or `1d12h` into seconds.
+fn parse_duration(text: &str) -> Option<u64> {
+    if !DURATION_REGEX.is_match(text) {
+        return None;
+    }
+
+    DURATION_PART_REGEX.captures_iter(text)
+        .map(|caps| {
+            let unit = match &caps[2] {
+                "s" => 1,
+                "m" => 60,
+                "h" => 3600,
+                "d" => 86400,
+                _ => 7 * 86400,
+            };
+
+            caps[1].parse::<u64>().ok().and_then(|x| x.checked_mul(unit))
+        })
+        .try_fold(0u64, |sum, x| x.and_then(|x| sum.checked_add(x)))
+}
+
+// Formats seconds like `1d 12h`, leaving out the parts which are zero.
+fn format_duration(seconds: u64) -> String {
+    let parts = [(seconds / 86400, "d"), (seconds % 86400 / 3600, "h"), (seconds % 3600 / 60, "m"), (seconds % 60, "s")];
+    let parts = parts.iter().filter(|x| x.0 > 0).map(|x| format!("{}{}", x.0, x.1)).collect::<Vec<_>>();
+
+    if parts.is_empty() {
+        "0s".to_owned()
+    } else {
+        parts.join(" ")
+    }
+}
+
+// Parses `<user> [duration] [reason]`. Err has the argument which doesn't make sense, it's empty if the user is missing.
+fn parse_moderation(text: &str, with_duration: bool) -> std::result::Result<ModerationArgs, String> {
+    let mut words = text.split_whitespace().peekable();
+
+    let user = match words.next() {
+        Some(word) => {
+            match USER_MENTION_REGEX.captures(word) {
+                Some(caps) => UserId(caps[1].parse().unwrap()),
+                None => UserId(try!(word.parse().map_err(|_| word.to_owned()))),
+            }
+        }
+        None => return Err(String::new()),
+    };
+
+    let mut duration = None;
+
+    if with_duration {
+        if let Some(seconds) = words.peek().and_then(|x| parse_duration(x)) {
+            if seconds == 0 || seconds > MAX_TIMED_ACTION_SECS {
+                return Err(words.next().unwrap().to_owned());
+            }
+
+            words.next();
+            duration = Some(seconds);
+        }
+    }
+
+    let reason = words.collect::<Vec<_>>().join(" ");
+
+    Ok(ModerationArgs {
+           user: user,
+           duration: duration,
+           reason: if reason.is_empty() { None } else { Some(reason) },
+       })
+}
+
+// Doesn't check the channel, handle() did that.
+fn get_hierarchy(state: &State, channel: ChannelId) -> Hierarchy {
+    match state.find_channel(channel).unwrap() {
+        ChannelRef::Public(server, _) => {
+            Hierarchy {
+                server: server.id,
+                server_name: server.name.clone(),
+                owner: server.owner_id,
+                roles: server.roles.clone(),
+                bot: state.user().id,
+            }
+        }
+        _ => {
+            panic!("Did I just witness some memory corruption?");
+        }
+    }
+}
+
+fn moderation_command_name(id: u32) -> &'static str {
+    match id {
+        x if x == Commands::Kick as u32 => "kick",
+        x if x == Commands::Ban as u32 => "ban",
+        x if x == Commands::Unban as u32 => "unban",
+        x if x == Commands::Mute as u32 => "mute",
+        x if x == Commands::Unmute as u32 => "unmute",
+        _ => panic!("Admin::moderation_command_name - invalid id."),
+    }
+}
+
+// The position of the highest of the roles, 0 for no roles like @everyone.
+pub fn top_role_position(server_roles: &[Role], roles: &[RoleId]) -> i64 {
+    server_roles.iter().filter(|x| roles.contains(&x.id)).map(|x| x.position).max().unwrap_or(0)
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|x| x.as_secs()).unwrap_or(0)
+}
+
+// Replaces the pending action of this kind for the user, or only removes it if `action` is None.
+fn schedule(server: ServerId, kind: TimedActionKind, user: UserId, action: Option<TimedAction>) -> storage::Result<()> {
+    ::STORAGE.update(STORAGE_NAME,
+                     Scope::Server(server),
+                     "timed_actions",
+                     |actions: Option<Vec<TimedAction>>| {
+        let mut actions = actions.unwrap_or_default();
+        actions.retain(|x| x.kind != kind || x.user != user.0);
+        actions.extend(action);
+        if actions.is_empty() { None } else { Some(actions) }
+    })
+}
+
+// Undoes the bans and the mutes which have expired on the server.
+fn run_timed_actions(bot: &Bot, memory: &Memory, server: ServerId) {
+    let time = now();
+    let mut due = Vec::new();
+
+    let result = ::STORAGE.update(STORAGE_NAME,
+                                  Scope::Server(server),
+                                  "timed_actions",
+                                  |actions: Option<Vec<TimedAction>>| {
+        let (expired, pending): (Vec<_>, Vec<_>) = actions.unwrap_or_default().into_iter().partition(|x| x.expires <= time);
+        due = expired;
+        if pending.is_empty() { None } else { Some(pending) }
+    });
+
+    if let Err(err) = result {
+        println!("[Warning] Failed to get the timed actions: {}", err);
+        return;
+    }
+
+    let locale = bot.get_localization().get_locale(None, Some(server));
+
+    for action in due {
+        let user = UserId(action.user);
+
+        let (result, key) = match action.kind {
+            TimedActionKind::Unban => (bot.unban_member(server, user).map_err(|e| e.to_string()), "admin-ban-expired"),
+            TimedActionKind::Unmute => {
+                let result = match memory.get_mute_role(server) {
+                    Ok(Some(role)) => bot.remove_member_role(server, user, role).map_err(|e| e.to_string()),
+                    Ok(None) => Err(locale.get("admin-no-mute-role")),
+                    Err(err) => Err(err.to_string()),
+                };
+
+                (result, "admin-mute-expired")
+            }
+        };
+
+        let text = match result {
+            Ok(()) => locale.format(key, &[("user", &action.user_name)]),
+            Err(err) => {
+                println!("[Warning] Couldn't undo an expired ban or mute: {}", err);
+                locale.format("admin-timed-action-failed", &[("user", &action.user_name), ("error", &err)])
+            }
+        };
+
+        post_to_mod_log(bot, server, &text);
+    }
+}
+
+// Whether the warning still counts towards the warning policy.
+fn is_active(warning: &Warning, expiry_days: Option<u64>, time: u64) -> bool {
+    expiry_days.iter().all(|days| warning.time + days * 86400 > time)
+}
+
+fn get_warnings(server: ServerId, user: UserId) -> storage::Result<Vec<Warning>> {
+    ::STORAGE.get(STORAGE_NAME, Scope::Member(server, user), "warnings").map(|x| x.unwrap_or_default())
+}
+
+// None if the warnings never expire.
+fn get_warning_expiry(server: ServerId) -> storage::Result<Option<u64>> {
+    ::STORAGE.get(STORAGE_NAME, Scope::Server(server), "warning_expiry_days")
+}
+
+// Ordered by the number of warnings.
+fn get_warning_policy(server: ServerId) -> storage::Result<Vec<EscalationStep>> {
+    ::STORAGE.get(STORAGE_NAME, Scope::Server(server), "warning_policy").map(|x| x.unwrap_or_default())
+}
+
+// Gives the member a warning. Returns the number of their active warnings and the step of the warning policy
+// for exactly that many, so that a step doesn't repeat for every next warning.
+fn add_warning(server: ServerId, user: UserId, warning: Warning) -> storage::Result<(usize, Option<EscalationStep>)> {
+    let expiry = try!(get_warning_expiry(server));
+    let policy = try!(get_warning_policy(server));
+
+    let time = warning.time;
+    let mut active = 0;
+    try!(::STORAGE.update(STORAGE_NAME,
+                          Scope::Member(server, user),
+                          "warnings",
+                          |warnings: Option<Vec<Warning>>| {
+        let mut warnings = warnings.unwrap_or_default();
+        warnings.push(warning);
+        active = warnings.iter().filter(|x| is_active(x, expiry, time)).count();
+        Some(warnings)
+    }));
+
+    Ok((active, policy.into_iter().find(|x| x.warnings == active as u64)))
+}
+
+// Gives a warning on behalf of the bot itself, like the automod does, and follows the warning policy.
+// Ok has what happened, for the mod-log.
+pub fn warn_automatically(bot: &Bot,
+                          server: ServerId,
+                          user: &User,
+                          reason: &str)
+                          -> std::result::Result<String, String> {
+    let (moderator, server_name) = {
+        let state = bot.get_state_for_server(server).read().unwrap();
+        let server_name = state.servers().iter().find(|x| x.id == server).map_or_else(String::new, |x| x.name.clone());
+        (state.user().clone(), server_name)
+    };
+
+    let warning = Warning {
+        time: now(),
+        moderator: moderator.id.0,
+        moderator_name: moderator.username,
+        reason: reason.to_owned(),
+    };
+
+    let (active, step) = try!(add_warning(server, user.id, warning).map_err(|e| e.to_string()));
+
+    let dm_locale = bot.get_localization().get_locale(Some(user.id), Some(server));
+    bot.try_send_pm(user.id,
+                    &dm_locale.format("admin-dm-warned", &[("server", &server_name), ("reason", &reason)]));
+
+    let locale = bot.get_localization().get_locale(None, Some(server));
+
+    match step {
+        Some(step) => {
+            try!(escalate(bot, server, &server_name, user, &step, active));
+            Ok(locale.format("admin-auto-escalated",
+                             &[("count", &active), ("action", &describe_escalation(&locale, &step))]))
+        }
+        None => Ok(locale.format("admin-auto-warned", &[("count", &active)])),
+    }
+}
+
+// Mutes, kicks or bans the member by the warning policy without a moderator's command.
+fn escalate(bot: &Bot,
+            server: ServerId,
+            server_name: &str,
+            user: &User,
+            step: &EscalationStep,
+            active: usize)
+            -> std::result::Result<(), String> {
+    let locale = bot.get_localization().get_locale(None, Some(server));
+
+    let mute_role = if step.action == EscalationAction::Mute {
+        match Memory.get_mute_role(server) {
+            Ok(Some(role)) => Some(role),
+            Ok(None) => return Err(locale.get("admin-no-mute-role")),
+            Err(err) => return Err(err.to_string()),
+        }
+    } else {
+        None
+    };
+
+    // They're told before, since after a kick or a ban the bot might not be able to reach them.
+    let dm_locale = bot.get_localization().get_locale(Some(user.id), Some(server));
+    let key = match (step.action, step.duration) {
+        (EscalationAction::Mute, None) => "admin-dm-muted",
+        (EscalationAction::Mute, Some(_)) => "admin-dm-muted-for",
+        (EscalationAction::Kick, _) => "admin-dm-kicked",
+        (EscalationAction::Ban, None) => "admin-dm-banned",
+        (EscalationAction::Ban, Some(_)) => "admin-dm-banned-for",
+    };
+
+    let mut dm = dm_locale.format(key,
+                                  &[("server", &server_name),
+                                    ("duration", &format_duration(step.duration.unwrap_or(0)))]);
+    dm.push('\n');
+    dm.push_str(&dm_locale.format("admin-dm-reason",
+                                  &[("reason", &dm_locale.format("admin-escalation-reason", &[("count", &active)]))]));
+    bot.try_send_pm(user.id, &dm);
+
+    let kind = match step.action {
+        EscalationAction::Mute => {
+            try!(bot.add_member_role(server, user.id, mute_role.unwrap()).map_err(|e| e.to_string()));
+            TimedActionKind::Unmute
+        }
+        EscalationAction::Kick => return bot.kick_member(server, user.id).map_err(|e| e.to_string()),
+        EscalationAction::Ban => {
+            try!(bot.ban_member(server, user.id, 0).map_err(|e| e.to_string()));
+            TimedActionKind::Unban
+        }
+    };
+
+    let action = step.duration.map(|duration| {
+        TimedAction {
+            kind: kind,
+            user: user.id.0,
+            user_name: user.name.clone(),
+            expires: now() + duration,
+        }
+    });
+
+    schedule(server, kind, user.id, action).map_err(|e| e.to_string())
+}
+
+// Parses `<warnings> mute|kick|ban [duration]` or `<warnings> off`, the latter gives None.
+// Err has the argument which doesn't make sense, it's empty if something is missing.
+fn parse_escalation_step(text: &str) -> std::result::Result<(u64, Option<EscalationStep>), String> {
+    let words = text.split_whitespace().collect::<Vec<_>>();
+
+    if words.len() < 2 || words.len() > 3 {
+        return Err(String::new());
+    }
+
+    let warnings = match words[0].parse::<u64>() {
+        Ok(x) if (1..=MAX_POLICY_WARNINGS).contains(&x) => x,
+        _ => return Err(words[0].to_owned()),
+    };
+
+    let action = match words[1].to_lowercase().as_str() {
+        "off" if words.len() == 2 => return Ok((warnings, None)),
+        "mute" => EscalationAction::Mute,
+        "kick" => EscalationAction::Kick,
+        "ban" => EscalationAction::Ban,
+        _ => return Err(words[1].to_owned()),
+    };
+
+    let duration = match words.get(2) {
+        Some(x) if action != EscalationAction::Kick => {
+            match parse_duration(x) {
+                Some(duration) if (1..=MAX_TIMED_ACTION_SECS).contains(&duration) => Some(duration),
+                _ => return Err((*x).to_owned()),
+            }
+        }
+        Some(x) => return Err((*x).to_owned()),
+        None => None,
+    };
+
+    Ok((warnings,
+        Some(EscalationStep {
+                 warnings: warnings,
+                 action: action,
+                 duration: duration,
+             })))
+}
+
+fn describe_escalation(locale: &Locale, step: &EscalationStep) -> String {
+    let key = match (step.action, step.duration) {
+        (EscalationAction::Mute, None) => "admin-escalation-mute",
+        (EscalationAction::Mute, Some(_)) => "admin-escalation-mute-for",
+        (EscalationAction::Kick, _) => "admin-escalation-kick",
+        (EscalationAction::Ban, None) => "admin-escalation-ban",
+        (EscalationAction::Ban, Some(_)) => "admin-escalation-ban-for",
+    };
+
+    locale.format(key, &[("duration", &format_duration(step.duration.unwrap_or(0)))])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EscalationAction, EscalationStep, ModerationArgs, NukedMessage, Warning, escape_mentions,
+                format_duration, format_nuked, format_time, is_active, parse_duration, parse_escalation_step,
+                parse_moderation, parse_nuke};
+    use discord::model::{MessageId, UserId};
+
+    #[test]
+    fn escape_mentions_test() {
+        assert_eq!("@\u{200B}everyone <@\u{200B}&1> <@\u{200B}!2> a", escape_mentions("@everyone <@&1> <@!2> a"));
+    }
+
+    #[test]
+    fn format_time_epoch() {
+        assert_eq!("1970-01-01 00:00 UTC", format_time(0));
+    }
+
+    #[test]
+    fn format_time_leap_day() {
+        assert_eq!("2000-02-29 13:02 UTC", format_time(951825600 + 3723));
+    }
+
+    #[test]
+    fn format_time_recent() {
+        assert_eq!("2026-10-19 00:00 UTC", format_time(1792368000));
+    }
+
+    #[test]
+    fn nuke_filters() {
+        let filter = parse_nuke(r#"50 <@!42> --bots --contains "Free Nitro" --after 1234"#).unwrap();
+        assert_eq!(Some(50), filter.count);
+        assert_eq!(vec![UserId(42)], filter.users);
+        assert!(filter.bots && !filter.links);
+        assert_eq!(Some("free nitro".to_owned()), filter.contains);
+        assert_eq!(Some(MessageId(1234)), filter.after);
+        assert_eq!(None, filter.before);
+
+        assert!(parse_nuke("--regex ^a+$").unwrap().regex.unwrap().is_match("aaa"));
+        assert_eq!(None, parse_nuke("5").unwrap().export);
+        assert_eq!(Some(false), parse_nuke("5 --no-export").unwrap().export);
+    }
+
+    #[test]
+    fn nuked_messages_as_text() {
+        let message = NukedMessage {
+            id: 1,
+            author: 42,
+            author_name: "Runner".to_owned(),
+            timestamp: "2017-05-21T12:00:00.000000+00:00".to_owned(),
+            content: "first".to_owned(),
+            attachments: vec!["https://cdn.discordapp.com/a.png".to_owned()],
+            embeds: Vec::new(),
+        };
+
+        assert_eq!("[2017-05-21T12:00:00.000000+00:00] Runner (42): first\n    Attachment: https://cdn.discordapp.com/a.png\n",
+                   format_nuked(&[message]));
+    }
+
+    #[test]
+    fn nuke_invalid_arguments() {
+        assert_eq!("--regex", parse_nuke("5 --regex").unwrap_err());
+        assert_eq!("(", parse_nuke("5 --regex (").unwrap_err());
+        assert_eq!("--before", parse_nuke("5 --before yesterday").unwrap_err());
+        assert_eq!("5000", parse_nuke("5000").unwrap_err());
+        assert_eq!("6", parse_nuke("5 6").unwrap_err());
+        assert_eq!("--everything", parse_nuke("5 --everything").unwrap_err());
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(Some(1800), parse_duration("30m"));
+        assert_eq!(Some(9 * 86400 + 3600), parse_duration("1w2d1h"));
+        assert_eq!(None, parse_duration("1y"));
+        assert_eq!(None, parse_duration("spam"));
+        assert_eq!("1d 12h", format_duration(86400 + 12 * 3600));
+        assert_eq!("1h 5s", format_duration(3605));
+    }
+
+    #[test]
+    fn moderation_arguments() {
+        assert_eq!(Ok(ModerationArgs {
+                          user: UserId(42),
+                          duration: Some(86400),
+                          reason: Some("spamming links".to_owned()),
+                      }),
+                   parse_moderation("<@!42> 1d spamming links", true));
+        assert_eq!(Ok(ModerationArgs {
+                          user: UserId(42),
+                          duration: None,
+                          reason: Some("1d".to_owned()),
+                      }),
+                   parse_moderation("42 1d", false));
+        assert_eq!(Err(String::new()), parse_moderation("", true));
+        assert_eq!(Err("@someone".to_owned()), parse_moderation("@someone", true));
+        assert_eq!(Err("400d".to_owned()), parse_moderation("<@42> 400d", true));
+    }
+
+    #[test]
+    fn escalation_steps() {
+        assert_eq!(Ok((3,
+                       Some(EscalationStep {
+                                warnings: 3,
+                                action: EscalationAction::Mute,
+                                duration: Some(3600),
+                            }))),
+                   parse_escalation_step("3 mute 1h"));
+        assert_eq!(Ok((5, None)), parse_escalation_step("5 off"));
+        assert_eq!(Err("1h".to_owned()), parse_escalation_step("5 kick 1h"));
+        assert_eq!(Err("0".to_owned()), parse_escalation_step("0 ban"));
+        assert_eq!(Err(String::new()), parse_escalation_step("3"));
+    }
+
+    #[test]
+    fn warning_expiry() {
+        let warning = Warning {
+            time: 1000,
+            moderator: 1,
+            moderator_name: "Mod".to_owned(),
+            reason: "spam".to_owned(),
+        };
+
+        assert!(is_active(&warning, None, 1000 + 1000 * 86400));
+        assert!(is_active(&warning, Some(30), 1000 + 29 * 86400));
+        assert!(!is_active(&warning, Some(30), 1000 + 30 * 86400));
+    }
 }
@@ -0,0 +1,665 @@
+use bot::{Bot, message_time};
+use discord::ChannelRef;
+use discord::model::*;
+use discord::model::permissions::READ_MESSAGES;
+use locale::Locale;
+use module::{self, CommandScope};
+use modules::admin;
+use regex::Regex;
+use std::collections::HashSet;
+use std::collections::hash_map::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+use storage::{self, Scope};
+
+pub struct Module<'a> {
+    commands: HashMap<u32, &'a [&'a str]>,
+}
+
+enum Commands {
+    Archive = 0,
+    Search = 1,
+}
+
+// An archived message. Every message is kept under its own key, `message:<ID>`, so that
+// archiving or changing one doesn't rewrite the others. The IDs are padded so that the keys sort by time.
+#[derive(Clone, Serialize, Deserialize)]
+struct ArchivedMessage {
+    id: u64,
+    channel: u64,
+    author: u64,
+    // Names are kept so that the search results don't mention anyone.
+    author_name: String,
+    content: String,
+    attachments: Vec<String>,
+    // The earlier versions of the content, oldest first.
+    edits: Vec<String>,
+    deleted: bool,
+}
+
+// How many messages were archived on the last day any were.
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+struct DayCount {
+    day: u64,
+    count: usize,
+}
+
+// Servers without settings aren't archived.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+struct Settings {
+    retention_days: u64,
+}
+
+// What `!search` looks for.
+#[derive(Debug, Default, PartialEq)]
+struct Query {
+    // Lowercase, all of them must be in the message.
+    terms: Vec<String>,
+    author: Option<u64>,
+    channel: Option<u64>,
+    // Days since the Unix epoch.
+    before: Option<u64>,
+    after: Option<u64>,
+}
+
+lazy_static! {
+	static ref FROM_REGEX: Regex = Regex::new(r"^from:<@!?([0-9]+)>$").unwrap();
+	static ref IN_REGEX: Regex = Regex::new(r"^in:<#([0-9]+)>$").unwrap();
+	static ref DATE_REGEX: Regex = Regex::new(r"^([0-9]{4})-([0-9]{2})-([0-9]{2})$").unwrap();
+}
+
+const STORAGE_NAME: &'static str = "archive";
+
+const MESSAGE_KEY_PREFIX: &'static str = "message:";
+
+const DEFAULT_RETENTION_DAYS: u64 = 30;
+const MAX_RETENTION_DAYS: u64 = 365;
+// Messages past this many on one server in one day aren't archived.
+const MAX_MESSAGES_PER_DAY: usize = 10000;
+
+const MAX_SEARCH_RESULTS: usize = 10;
+// Longer messages are cut in the search results.
+const MAX_SNIPPET_LENGTH: usize = 100;
+
+fn today() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|x| x.as_secs()).unwrap_or(0) / 86400
+}
+
+fn message_key(id: u64) -> String {
+    format!("{}{:020}", MESSAGE_KEY_PREFIX, id)
+}
+
+fn message_day(id: u64) -> u64 {
+    message_time(id) / 86400
+}
+
+// Parses a date like `2017-05-21` into days since the Unix epoch.
+fn parse_date(text: &str) -> Option<u64> {
+    DATE_REGEX.captures(text)
+        .and_then(|caps| days_from_civil(caps[1].parse().unwrap(), caps[2].parse().unwrap(), caps[3].parse().unwrap()))
+}
+
+// Days since the Unix epoch, None for invalid dates.
+fn days_from_civil(year: u64, month: u64, day: u64) -> Option<u64> {
+    if year < 1970 || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    // The days_from_civil algorithm by Howard Hinnant, the inverse of admin::format_time().
+    let (year, month) = if month > 2 { (year, month - 3) } else { (year - 1, month + 9) };
+    let era = year / 400;
+    let year_of_era = year % 400;
+    let day_of_year = (153 * month + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    Some(era * 146097 + day_of_era - 719468)
+}
+
+// Splits the `!search` arguments into the filters and the terms. Err has the invalid filter.
+fn parse_query(text: &str) -> Result<Query, String> {
+    let mut query = Query::default();
+
+    for word in text.split_whitespace() {
+        let lowercase = word.to_lowercase();
+
+        if let Some(caps) = FROM_REGEX.captures(word) {
+            query.author = caps[1].parse().ok();
+        } else if let Some(caps) = IN_REGEX.captures(word) {
+            query.channel = caps[1].parse().ok();
+        } else if lowercase.starts_with("before:") {
+            query.before = Some(try!(parse_date(&word["before:".len()..]).ok_or_else(|| word.to_owned())));
+        } else if lowercase.starts_with("after:") {
+            query.after = Some(try!(parse_date(&word["after:".len()..]).ok_or_else(|| word.to_owned())));
+        } else if lowercase.starts_with("from:") || lowercase.starts_with("in:") {
+            return Err(word.to_owned());
+        } else {
+            query.terms.push(lowercase);
+        }
+    }
+
+    Ok(query)
+}
+
+impl Query {
+    fn is_empty(&self) -> bool {
+        self.terms.is_empty() && self.author.is_none() && self.channel.is_none()
+    }
+
+    fn includes_day(&self, day: u64) -> bool {
+        self.before.iter().all(|&x| day < x) && self.after.iter().all(|&x| day > x)
+    }
+
+    // `everything` also searches the earlier versions of edited messages.
+    fn matches(&self, message: &ArchivedMessage, everything: bool) -> bool {
+        if self.author.iter().any(|&x| x != message.author) || self.channel.iter().any(|&x| x != message.channel) {
+            return false;
+        }
+
+        let content = if everything {
+            let mut content = message.edits.join("\n");
+            content.push('\n');
+            content.push_str(&message.content);
+            content.to_lowercase()
+        } else {
+            message.content.to_lowercase()
+        };
+
+        self.terms.iter().all(|x| content.contains(x.as_str()))
+    }
+}
+
+fn get_settings(server: ServerId) -> storage::Result<Option<Settings>> {
+    ::STORAGE.get(STORAGE_NAME, Scope::Server(server), "settings")
+}
+
+// IDs of the archived messages, oldest first.
+fn get_message_ids(server: ServerId) -> storage::Result<Vec<u64>> {
+    Ok(try!(::STORAGE.keys(STORAGE_NAME, Scope::Server(server), MESSAGE_KEY_PREFIX))
+           .iter()
+           .filter_map(|x| x[MESSAGE_KEY_PREFIX.len()..].parse().ok())
+           .collect())
+}
+
+// Drops the messages older than the retention period.
+fn remove_old_messages(server: ServerId, settings: Settings) -> storage::Result<()> {
+    let oldest = today().saturating_sub(settings.retention_days);
+
+    for id in try!(get_message_ids(server)).into_iter().take_while(|x| message_day(*x) < oldest) {
+        try!(::STORAGE.remove(STORAGE_NAME, Scope::Server(server), &message_key(id)));
+    }
+
+    Ok(())
+}
+
+fn remove_all_messages(server: ServerId) -> storage::Result<()> {
+    for id in try!(get_message_ids(server)) {
+        try!(::STORAGE.remove(STORAGE_NAME, Scope::Server(server), &message_key(id)));
+    }
+
+    ::STORAGE.remove(STORAGE_NAME, Scope::Server(server), "day_count")
+}
+
+fn archive(server: ServerId, settings: Settings, message: ArchivedMessage) -> storage::Result<()> {
+    let day = message_day(message.id);
+    let mut first_of_day = false;
+    let mut full = false;
+
+    try!(::STORAGE.update(STORAGE_NAME,
+                          Scope::Server(server),
+                          "day_count",
+                          |count: Option<DayCount>| {
+        let mut count = count.unwrap_or_default();
+
+        if day > count.day {
+            first_of_day = true;
+            count = DayCount {
+                day: day,
+                count: 0,
+            };
+        }
+
+        full = count.count >= MAX_MESSAGES_PER_DAY;
+
+        if !full {
+            count.count += 1;
+        }
+
+        Some(count)
+    }));
+
+    if !full {
+        try!(::STORAGE.set(STORAGE_NAME, Scope::Server(server), &message_key(message.id), &message));
+    }
+
+    // The old messages are dropped once a day, when the first message of the next one comes.
+    if first_of_day {
+        try!(remove_old_messages(server, settings));
+    }
+
+    Ok(())
+}
+
+// Changes an archived message, if it's there.
+fn update_archived<F: FnOnce(&mut ArchivedMessage)>(server: ServerId, id: MessageId, f: F) -> storage::Result<()> {
+    ::STORAGE.update(STORAGE_NAME,
+                     Scope::Server(server),
+                     &message_key(id.0),
+                     |message: Option<ArchivedMessage>| {
+        message.map(|mut message| {
+                        f(&mut message);
+                        message
+                    })
+    })
+}
+
+// The settings of the server the channel is on, if it's archived.
+fn archived_server(bot: &Bot, channel: ChannelId) -> Option<(ServerId, Settings)> {
+    let server = bot.get_server_for_channel(channel);
+
+    match server.map(|x| (x, get_settings(x))) {
+        Some((server, Ok(settings))) => settings.map(|x| (server, x)),
+        Some((_, Err(err))) => {
+            println!("[Warning] Failed to get the archive settings: {}", err);
+            None
+        }
+        None => None,
+    }
+}
+
+// Cuts the text to MAX_SNIPPET_LENGTH characters and puts it on one line, without any mentions that would ping.
+fn snippet(text: &str) -> String {
+    let mut snippet = text.split_whitespace().collect::<Vec<_>>().join(" ");
+
+    if let Some((index, _)) = snippet.char_indices().nth(MAX_SNIPPET_LENGTH) {
+        snippet.truncate(index);
+        snippet.push('…');
+    }
+
+    admin::escape_mentions(&snippet.replace('`', "'"))
+}
+
+impl<'a> Module<'a> {
+    fn handle_archive(&self, bot: &Bot, message: &Message, text: &str, locale: &Locale, server: ServerId) {
+        let mut args = text.split_whitespace();
+        let action = args.next().unwrap_or("").to_lowercase();
+
+        let settings = match get_settings(server) {
+            Ok(settings) => settings,
+            Err(err) => {
+                bot.send(message.channel_id,
+                         &locale.format("something-broken", &[("error", &err)]));
+                return;
+            }
+        };
+
+        if action.is_empty() {
+            let reply = match settings {
+                Some(settings) => {
+                    let mut days = get_message_ids(server).unwrap_or_default()
+                        .into_iter()
+                        .map(message_day)
+                        .collect::<Vec<_>>();
+                    days.dedup();
+
+                    let days = days.len();
+                    locale.format("archive-status-on",
+                                  &[("days", &settings.retention_days), ("archived-days", &days)])
+                }
+                None => locale.get("archive-status-off"),
+            };
+
+            bot.send(message.channel_id, &reply);
+            return;
+        }
+
+        if !admin::check_admin(bot, message, locale) {
+            return;
+        }
+
+        let (result, reply) = match action.as_str() {
+            "on" => {
+                let days = match args.next() {
+                    Some(x) => {
+                        match x.parse::<u64>() {
+                            Ok(days) if (1..=MAX_RETENTION_DAYS).contains(&days) => days,
+                            _ => {
+                                bot.send(message.channel_id,
+                                         &locale.format("archive-invalid-days", &[("max", &MAX_RETENTION_DAYS)]));
+                                return;
+                            }
+                        }
+                    }
+                    None => settings.map_or(DEFAULT_RETENTION_DAYS, |x| x.retention_days),
+                };
+
+                let settings = Settings { retention_days: days };
+
+                (::STORAGE.set(STORAGE_NAME, Scope::Server(server), "settings", &settings)
+                     .and_then(|_| remove_old_messages(server, settings)),
+                 locale.format("archive-on", &[("days", &days)]))
+            }
+
+            "off" => {
+                (::STORAGE.remove(STORAGE_NAME, Scope::Server(server), "settings"), locale.get("archive-off"))
+            }
+
+            "clear" => (remove_all_messages(server), locale.get("archive-cleared")),
+
+            _ => {
+                bot.send(message.channel_id,
+                         &locale.command_help_message(self, Commands::Archive as u32));
+                return;
+            }
+        };
+
+        match result {
+            Ok(()) => {
+                admin::audit(bot, server, message, "!archive", text, &Ok(reply.clone()));
+                bot.send(message.channel_id, &reply);
+            }
+            Err(err) => {
+                admin::audit(bot, server, message, "!archive", text, &Err(err.to_string()));
+                bot.send(message.channel_id,
+                         &locale.format("storage-error", &[("error", &err)]));
+            }
+        }
+    }
+
+    fn handle_search(&self, bot: &Bot, message: &Message, text: &str, locale: &Locale, server: ServerId) {
+        let query = match parse_query(text) {
+            Ok(ref query) if query.is_empty() => {
+                bot.send(message.channel_id,
+                         &locale.command_help_message(self, Commands::Search as u32));
+                return;
+            }
+            Ok(query) => query,
+            Err(filter) => {
+                bot.send(message.channel_id,
+                         &locale.format("archive-invalid-filter", &[("filter", &filter)]));
+                return;
+            }
+        };
+
+        match get_settings(server) {
+            Ok(Some(_)) => {}
+            Ok(None) => {
+                bot.send(message.channel_id, &locale.get("archive-status-off"));
+                return;
+            }
+            Err(err) => {
+                bot.send(message.channel_id,
+                         &locale.format("something-broken", &[("error", &err)]));
+                return;
+            }
+        }
+
+        // Only the channels the user can read are searched.
+        // Admins also see the deleted messages and the earlier versions of the edited ones.
+        let (readable, is_admin) = {
+            let state = bot.get_state_for_channel(message.channel_id).read().unwrap();
+
+            match state.find_channel(message.channel_id) {
+                Some(ChannelRef::Public(server, _)) => {
+                    let readable = server.channels
+                        .iter()
+                        .filter(|x| server.permissions_for(x.id, message.author.id).contains(READ_MESSAGES))
+                        .map(|x| x.id.0)
+                        .collect::<HashSet<_>>();
+
+                    (readable, admin::is_admin(bot, server, message.author.id) == Some(true))
+                }
+                _ => {
+                    bot.send(message.channel_id, &locale.get("channel-info-error"));
+                    return;
+                }
+            }
+        };
+
+        let ids = match get_message_ids(server) {
+            Ok(ids) => ids,
+            Err(err) => {
+                bot.send(message.channel_id,
+                         &locale.format("something-broken", &[("error", &err)]));
+                return;
+            }
+        };
+
+        let mut results = Vec::new();
+
+        // Newest first.
+        for id in ids.into_iter().rev().filter(|x| query.includes_day(message_day(*x))) {
+            if results.len() == MAX_SEARCH_RESULTS || bot.cancellation_token().is_cancelled() {
+                break;
+            }
+
+            let archived: Option<ArchivedMessage> =
+                match ::STORAGE.get(STORAGE_NAME, Scope::Server(server), &message_key(id)) {
+                    Ok(archived) => archived,
+                    Err(err) => {
+                        bot.send(message.channel_id,
+                                 &locale.format("something-broken", &[("error", &err)]));
+                        return;
+                    }
+                };
+
+            if let Some(archived) = archived {
+                if readable.contains(&archived.channel) && (is_admin || !archived.deleted) &&
+                   query.matches(&archived, is_admin) {
+                    results.push(archived);
+                }
+            }
+        }
+
+        if results.is_empty() {
+            bot.send(message.channel_id, &locale.get("archive-no-results"));
+            return;
+        }
+
+        let mut buf = locale.get("archive-results");
+
+        for result in results {
+            let args: &[(&str, &::std::fmt::Display)] =
+                &[("time", &admin::format_time(message_time(result.id))),
+                  ("user", &result.author_name),
+                  ("channel", &ChannelId(result.channel).mention()),
+                  ("text", &snippet(&result.content)),
+                  ("link",
+                   &format!("https://discordapp.com/channels/{}/{}/{}", server.0, result.channel, result.id))];
+
+            buf.push_str("\n- ");
+            buf.push_str(&locale.format(if result.deleted {
+                                            "archive-result-deleted"
+                                        } else {
+                                            "archive-result"
+                                        },
+                                        args));
+        }
+
+        bot.send(message.channel_id, &buf);
+    }
+}
+
+impl<'a> module::Module for Module<'a> {
+    fn new() -> Result<Box<module::Module>, String> {
+        let mut map: HashMap<u32, &[&str]> = HashMap::new();
+        static ARCHIVE: [&'static str; 1] = ["archive"];
+        map.insert(Commands::Archive as u32, &ARCHIVE);
+        static SEARCH: [&'static str; 1] = ["search"];
+        map.insert(Commands::Search as u32, &SEARCH);
+        Ok(Box::new(Module { commands: map }))
+    }
+
+    fn name(&self) -> &'static str {
+        "Archive"
+    }
+
+    fn description(&self) -> &'static str {
+        "Keeps the messages of the servers which want it, so that they can be searched."
+    }
+
+    fn commands(&self) -> &HashMap<u32, &[&str]> {
+        &self.commands
+    }
+
+    fn command_description(&self, id: u32) -> &'static str {
+        match id {
+            x if x == Commands::Archive as u32 => "Turns the message archive on or off.",
+            x if x == Commands::Search as u32 => "Searches the message archive.",
+            _ => panic!("Archive::command_description - invalid id."),
+        }
+    }
+
+    fn command_help_message(&self, id: u32) -> &'static str {
+        match id {
+            x if x == Commands::Archive as u32 => {
+                "`!archive` - Shows whether the messages on this server are archived.\n\
+                 `!archive on [days]` - Starts archiving the messages, along with their edits and deletions, keeping them for the given number of days (30 by default).\n\
+                 `!archive off` - Stops archiving, the archived messages stay until `!archive clear`.\n\
+                 `!archive clear` - Deletes the archived messages."
+            }
+            x if x == Commands::Search as u32 => {
+                "`!search <terms> [from:@user] [in:#channel] [before:YYYY-MM-DD] [after:YYYY-MM-DD]` - Finds the most recent archived messages with all of the terms and links to them. \
+                 For example, `!search any% route from:@Runner after:2017-05-01`."
+            }
+            _ => panic!("Archive::command_help_message - invalid id."),
+        }
+    }
+
+    fn rerun_on_edit(&self, id: u32) -> bool {
+        id == Commands::Search as u32
+    }
+
+    fn command_scope(&self, _id: u32) -> CommandScope {
+        CommandScope::servers_only()
+    }
+
+    fn handle(&self, bot: &Bot, message: &Message, id: u32, text: &str) {
+        let locale = bot.get_locale(message);
+
+        let server = match bot.get_server_for_channel(message.channel_id) {
+            Some(server) => server,
+            None => {
+                bot.send(message.channel_id, &locale.get("channel-info-error"));
+                return;
+            }
+        };
+
+        match id {
+            x if x == Commands::Archive as u32 => self.handle_archive(bot, message, text, &locale, server),
+            x if x == Commands::Search as u32 => self.handle_search(bot, message, text, &locale, server),
+            _ => panic!("Archive::handle - invalid id."),
+        }
+    }
+
+    fn handle_message(&self, bot: &Bot, message: &Message) {
+        let (server, settings) = match archived_server(bot, message.channel_id) {
+            Some(x) => x,
+            None => return,
+        };
+
+        let archived = ArchivedMessage {
+            id: message.id.0,
+            channel: message.channel_id.0,
+            author: message.author.id.0,
+            author_name: message.author.name.clone(),
+            content: message.content.clone(),
+            attachments: message.attachments.iter().map(|x| x.url.clone()).collect(),
+            edits: Vec::new(),
+            deleted: false,
+        };
+
+        if let Err(err) = archive(server, settings, archived) {
+            println!("[Warning] Failed to archive a message: {}", err);
+        }
+    }
+
+    fn handle_message_update(&self, bot: &Bot, channel_id: ChannelId, id: MessageId, content: Option<&str>) {
+        let content = match content {
+            Some(content) => content,
+            None => return,
+        };
+
+        let server = match archived_server(bot, channel_id) {
+            Some((server, _)) => server,
+            None => return,
+        };
+
+        let result = update_archived(server, id, |message| if message.content != content {
+            let previous = ::std::mem::replace(&mut message.content, content.to_owned());
+            message.edits.push(previous);
+        });
+
+        if let Err(err) = result {
+            println!("[Warning] Failed to archive an edit: {}", err);
+        }
+    }
+
+    fn handle_message_delete(&self, bot: &Bot, channel_id: ChannelId, id: MessageId) {
+        let server = match archived_server(bot, channel_id) {
+            Some((server, _)) => server,
+            None => return,
+        };
+
+        if let Err(err) = update_archived(server, id, |message| message.deleted = true) {
+            println!("[Warning] Failed to archive a deletion: {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ArchivedMessage, Query, parse_date, parse_query, snippet};
+
+    #[test]
+    fn dates() {
+        assert_eq!(Some(0), parse_date("1970-01-01"));
+        assert_eq!(Some(11016), parse_date("2000-02-29"));
+        assert_eq!(Some(17307), parse_date("2017-05-21"));
+        assert_eq!(None, parse_date("2017-13-01"));
+        assert_eq!(None, parse_date("yesterday"));
+    }
+
+    #[test]
+    fn query() {
+        assert_eq!(Ok(Query {
+                          terms: vec!["any%".to_owned(), "route".to_owned()],
+                          author: Some(42),
+                          channel: Some(7),
+                          before: None,
+                          after: Some(17307),
+                      }),
+                   parse_query("Any% from:<@!42> in:<#7> after:2017-05-21 route"));
+        assert_eq!(Err("before:soon".to_owned()), parse_query("route before:soon"));
+        assert_eq!(Err("from:Runner".to_owned()), parse_query("route from:Runner"));
+    }
+
+    #[test]
+    fn matching() {
+        let message = ArchivedMessage {
+            id: 1,
+            channel: 7,
+            author: 42,
+            author_name: "Runner".to_owned(),
+            content: "The new Any% route".to_owned(),
+            attachments: Vec::new(),
+            edits: vec!["The old route".to_owned()],
+            deleted: false,
+        };
+
+        let query = parse_query("route any%").unwrap();
+        assert!(query.matches(&message, false));
+
+        let query = parse_query("old route").unwrap();
+        assert!(!query.matches(&message, false));
+        assert!(query.matches(&message, true));
+
+        let query = parse_query("route in:<#8>").unwrap();
+        assert!(!query.matches(&message, false));
+    }
+
+    #[test]
+    fn snippets() {
+        assert_eq!("a b 'c'", snippet("a\n  b `c`"));
+        assert_eq!(format!("{}…", "a".repeat(100)), snippet(&"a".repeat(150)));
+        assert_eq!("@\u{200B}everyone <@\u{200B}1>", snippet("@everyone <@1>"));
+    }
+}
@@ -0,0 +1,707 @@
+use bot::Bot;
+use discord::ChannelRef;
+use discord::model::*;
+use locale::Locale;
+use module::{self, CommandScope};
+use modules::admin;
+use regex::Regex;
+use std::collections::{BTreeMap, VecDeque};
+use std::collections::hash_map::HashMap;
+use std::sync::Mutex;
+use storage::{self, Scope};
+
+pub struct Module<'a> {
+    commands: HashMap<u32, &'a [&'a str]>,
+    // The recent messages of every member, for the flood rules.
+    history: Mutex<HashMap<(ServerId, UserId), VecDeque<Recent>>>,
+}
+
+enum Commands {
+    Automod = 0,
+}
+
+// The rules in the order they're checked, only the first one broken counts.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum RuleKind {
+    Duplicates,
+    Mentions,
+    Caps,
+    Emoji,
+    Invites,
+    Links,
+    Attachments,
+}
+
+static RULES: [RuleKind; 7] = [RuleKind::Duplicates,
+                               RuleKind::Mentions,
+                               RuleKind::Caps,
+                               RuleKind::Emoji,
+                               RuleKind::Invites,
+                               RuleKind::Links,
+                               RuleKind::Attachments];
+
+// What happens to whoever breaks a rule. The message is deleted in any case.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Action {
+    Delete,
+    Warn,
+    Mute,
+    Kick,
+    Ban,
+}
+
+// Only the rules which were turned on at some point are stored.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+struct Rule {
+    enabled: bool,
+    // The meaning depends on the rule, see RuleKind::limit_key().
+    threshold: u64,
+    // Seconds, only the flood rules look at more than one message.
+    window: u64,
+    action: Action,
+}
+
+// A message the flood rules remember.
+#[derive(Clone, Debug)]
+struct Recent {
+    // Milliseconds since the Discord epoch.
+    time: u64,
+    // Lowercase, with the whitespace collapsed.
+    content: String,
+    attachments: usize,
+}
+
+lazy_static! {
+	static ref LINK_REGEX: Regex = Regex::new(r"(?i)\bhttps?://\S+").unwrap();
+	static ref INVITE_REGEX: Regex =
+		Regex::new(r"(?i)\b(?:discord\.gg|discord(?:app)?\.com/invite)/[a-z0-9-]+").unwrap();
+	// Custom emoji, mentions and channel links.
+	static ref TAG_REGEX: Regex = Regex::new(r"<[^<>\s]+>").unwrap();
+	static ref CUSTOM_EMOJI_REGEX: Regex = Regex::new(r"<a?:\w+:[0-9]+>").unwrap();
+}
+
+const STORAGE_NAME: &'static str = "automod";
+
+const MAX_THRESHOLD: u64 = 100;
+const MAX_WINDOW: u64 = 300;
+// Shorter messages aren't checked for caps.
+const MIN_CAPS_LETTERS: usize = 10;
+// Older messages are forgotten.
+const MAX_HISTORY: usize = 50;
+// Past this many members the ones who went quiet are forgotten.
+const MAX_TRACKED_MEMBERS: usize = 1000;
+// How much of the history of a banned member Discord deletes.
+const BAN_DELETE_MESSAGE_DAYS: u32 = 1;
+
+impl RuleKind {
+    fn name(self) -> &'static str {
+        match self {
+            RuleKind::Duplicates => "duplicates",
+            RuleKind::Mentions => "mentions",
+            RuleKind::Caps => "caps",
+            RuleKind::Emoji => "emoji",
+            RuleKind::Invites => "invites",
+            RuleKind::Links => "links",
+            RuleKind::Attachments => "attachments",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<RuleKind> {
+        RULES.iter().cloned().find(|x| x.name() == name)
+    }
+
+    // Whether the rule counts messages over a time window instead of looking at one message.
+    fn is_flood(self) -> bool {
+        self == RuleKind::Duplicates || self == RuleKind::Attachments
+    }
+
+    fn default_rule(self) -> Rule {
+        let (threshold, action) = match self {
+            RuleKind::Duplicates => (4, Action::Warn),
+            RuleKind::Mentions => (5, Action::Mute),
+            RuleKind::Caps => (70, Action::Delete),
+            RuleKind::Emoji => (10, Action::Delete),
+            RuleKind::Invites | RuleKind::Links => (1, Action::Delete),
+            RuleKind::Attachments => (5, Action::Warn),
+        };
+
+        Rule {
+            enabled: true,
+            threshold: threshold,
+            window: 10,
+            action: action,
+        }
+    }
+
+    fn limit_key(self) -> &'static str {
+        match self {
+            RuleKind::Duplicates => "automod-limit-duplicates",
+            RuleKind::Mentions => "automod-limit-mentions",
+            RuleKind::Caps => "automod-limit-caps",
+            RuleKind::Emoji => "automod-limit-emoji",
+            RuleKind::Invites => "automod-limit-invites",
+            RuleKind::Links => "automod-limit-links",
+            RuleKind::Attachments => "automod-limit-attachments",
+        }
+    }
+
+    fn reason_key(self) -> &'static str {
+        match self {
+            RuleKind::Duplicates => "automod-reason-duplicates",
+            RuleKind::Mentions => "automod-reason-mentions",
+            RuleKind::Caps => "automod-reason-caps",
+            RuleKind::Emoji => "automod-reason-emoji",
+            RuleKind::Invites => "automod-reason-invites",
+            RuleKind::Links => "automod-reason-links",
+            RuleKind::Attachments => "automod-reason-attachments",
+        }
+    }
+}
+
+impl Action {
+    fn from_name(name: &str) -> Option<Action> {
+        match name {
+            "delete" => Some(Action::Delete),
+            "warn" => Some(Action::Warn),
+            "mute" => Some(Action::Mute),
+            "kick" => Some(Action::Kick),
+            "ban" => Some(Action::Ban),
+            _ => None,
+        }
+    }
+
+    fn key(self) -> &'static str {
+        match self {
+            Action::Delete => "automod-action-delete",
+            Action::Warn => "automod-action-warn",
+            Action::Mute => "automod-action-mute",
+            Action::Kick => "automod-action-kick",
+            Action::Ban => "automod-action-ban",
+        }
+    }
+}
+
+fn normalize(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+// The percentage of capital letters, or 0 if there are too few letters to tell.
+fn caps_percent(text: &str) -> u64 {
+    let text = TAG_REGEX.replace_all(text, "");
+    let letters = text.chars().filter(|x| x.is_alphabetic()).count();
+
+    if letters < MIN_CAPS_LETTERS {
+        return 0;
+    }
+
+    let capitals = text.chars().filter(|x| x.is_uppercase()).count();
+    (capitals * 100 / letters) as u64
+}
+
+fn emoji_count(text: &str) -> u64 {
+    let unicode = text.chars()
+        .filter(|&x| {
+                    let x = x as u32;
+                    // Skin tones modify the emoji before them.
+                    ((0x1F000..=0x1FAFF).contains(&x) && !(0x1F3FB..=0x1F3FF).contains(&x)) ||
+                    (0x2600..=0x27BF).contains(&x)
+                })
+        .count();
+
+    (CUSTOM_EMOJI_REGEX.find_iter(text).count() + unicode) as u64
+}
+
+// How many of the recent messages since `since` have the same content as the last one.
+fn duplicate_count(history: &VecDeque<Recent>, since: u64) -> u64 {
+    let last = match history.back() {
+        Some(last) if !last.content.is_empty() => last,
+        _ => return 0,
+    };
+
+    history.iter().filter(|x| x.time >= since && x.content == last.content).count() as u64
+}
+
+fn attachment_count(history: &VecDeque<Recent>, since: u64) -> u64 {
+    history.iter().filter(|x| x.time >= since).map(|x| x.attachments as u64).sum()
+}
+
+fn get_rules(server: ServerId) -> storage::Result<BTreeMap<String, Rule>> {
+    ::STORAGE.get(STORAGE_NAME, Scope::Server(server), "rules").map(|x| x.unwrap_or_default())
+}
+
+fn set_rule(server: ServerId, kind: RuleKind, rule: Rule) -> storage::Result<()> {
+    ::STORAGE.update(STORAGE_NAME,
+                     Scope::Server(server),
+                     "rules",
+                     |rules: Option<BTreeMap<String, Rule>>| {
+        let mut rules = rules.unwrap_or_default();
+        rules.insert(kind.name().to_owned(), rule);
+        Some(rules)
+    })
+}
+
+fn describe_rule(locale: &Locale, kind: RuleKind, rule: Option<&Rule>) -> String {
+    match rule {
+        Some(rule) if rule.enabled => {
+            locale.format("automod-rule-on",
+                          &[("rule", &kind.name()),
+                            ("limit", &describe_limit(locale, kind, rule)),
+                            ("action", &locale.get(rule.action.key()))])
+        }
+        _ => locale.format("automod-rule-off", &[("rule", &kind.name())]),
+    }
+}
+
+fn describe_limit(locale: &Locale, kind: RuleKind, rule: &Rule) -> String {
+    locale.format(kind.limit_key(),
+                  &[("threshold", &rule.threshold), ("window", &rule.window)])
+}
+
+impl<'a> Module<'a> {
+    // Remembers the message and returns the first enabled rule it breaks.
+    fn broken_rule(&self,
+                   server: ServerId,
+                   message: &Message,
+                   rules: &BTreeMap<String, Rule>)
+                   -> Option<(RuleKind, Rule)> {
+        let time = message.id.0 >> 22;
+
+        let mut history = self.history.lock().unwrap();
+
+        if history.len() > MAX_TRACKED_MEMBERS {
+            let since = time.saturating_sub(MAX_WINDOW * 1000);
+            history.retain(|_, x| x.back().iter().any(|x| x.time >= since));
+        }
+
+        let recent = history.entry((server, message.author.id)).or_default();
+        recent.push_back(Recent {
+                             time: time,
+                             content: normalize(&message.content),
+                             attachments: message.attachments.len(),
+                         });
+
+        while recent.len() > MAX_HISTORY ||
+              recent.front().iter().any(|x| x.time + MAX_WINDOW * 1000 < time) {
+            recent.pop_front();
+        }
+
+        RULES.iter()
+            .filter_map(|&kind| rules.get(kind.name()).filter(|x| x.enabled).map(|x| (kind, *x)))
+            .find(|&(kind, rule)| {
+                let since = time.saturating_sub(rule.window * 1000);
+
+                let count = match kind {
+                    RuleKind::Duplicates => duplicate_count(recent, since),
+                    RuleKind::Mentions => (message.mentions.len() + message.mention_roles.len()) as u64,
+                    RuleKind::Caps => caps_percent(&message.content),
+                    RuleKind::Emoji => emoji_count(&message.content),
+                    RuleKind::Invites => INVITE_REGEX.find_iter(&message.content).count() as u64,
+                    RuleKind::Links => LINK_REGEX.find_iter(&message.content).count() as u64,
+                    RuleKind::Attachments => attachment_count(recent, since),
+                };
+
+                count >= rule.threshold
+            })
+    }
+
+    // Deletes the message, does what the rule says and reports it in the mod-log.
+    fn enforce(&self, bot: &Bot, server: ServerId, message: &Message, kind: RuleKind, rule: Rule) {
+        // Everything the member sent so far has been dealt with.
+        self.history.lock().unwrap().remove(&(server, message.author.id));
+
+        let locale = bot.get_localization().get_locale(None, Some(server));
+        let user = message.author.id;
+
+        let result = if rule.action == Action::Ban {
+            // Banning deletes the message along with the rest.
+            bot.ban_member(server, user, BAN_DELETE_MESSAGE_DAYS).map_err(|e| e.to_string())
+        } else {
+            bot.delete_message(message.channel_id, message.id).map_err(|e| e.to_string())
+        };
+
+        // Ok has what else the mod-log should know.
+        let result = result.and_then(|_| match rule.action {
+            // Goes through the warnings of the admin module, so it counts towards the warning policy.
+            Action::Warn => {
+                let reason = locale.format("automod-warning-reason",
+                                           &[("rule", &kind.name()), ("reason", &locale.get(kind.reason_key()))]);
+                admin::warn_automatically(bot, server, &message.author, &reason).map(Some)
+            }
+            Action::Mute => {
+                match admin::Memory.get_mute_role(server) {
+                    Ok(Some(role)) => bot.add_member_role(server, user, role).map(|_| None).map_err(|e| e.to_string()),
+                    Ok(None) => Err(locale.get("automod-no-mute-role")),
+                    Err(err) => Err(err.to_string()),
+                }
+            }
+            Action::Kick => bot.kick_member(server, user).map(|_| None).map_err(|e| e.to_string()),
+            _ => Ok(None),
+        });
+
+        if result.is_ok() && (rule.action == Action::Warn || rule.action == Action::Mute) {
+            bot.send(message.channel_id,
+                     &locale.format("automod-warning",
+                                    &[("user", &user.mention()), ("reason", &locale.get(kind.reason_key()))]));
+        }
+
+        let args: &[(&str, &::std::fmt::Display)] = &[("rule", &kind.name()),
+                                                        ("user", &message.author.name),
+                                                        ("channel", &message.channel_id.mention()),
+                                                        ("action", &locale.get(rule.action.key()))];
+
+        let log = match result {
+            Ok(note) => {
+                let mut log = locale.format("automod-log", args);
+                if let Some(note) = note {
+                    log.push(' ');
+                    log.push_str(&note);
+                }
+                log
+            }
+            Err(err) => {
+                println!("[Warning] Automod couldn't act on a message: {}", err);
+
+                let mut args = args.to_vec();
+                args.push(("error", &err));
+                locale.format("automod-log-failed", &args)
+            }
+        };
+
+        admin::post_to_mod_log(bot, server, &log);
+    }
+
+    fn handle_status(&self, bot: &Bot, message: &Message, locale: &Locale, server: ServerId) {
+        let rules = match get_rules(server) {
+            Ok(rules) => rules,
+            Err(err) => {
+                bot.send(message.channel_id,
+                         &locale.format("something-broken", &[("error", &err)]));
+                return;
+            }
+        };
+
+        let mut buf = locale.get("automod-status");
+
+        for &kind in RULES.iter() {
+            buf.push_str("\n- ");
+            buf.push_str(&describe_rule(locale, kind, rules.get(kind.name())));
+        }
+
+        buf.push('\n');
+        buf.push_str(&match admin::Memory.get_mute_role(server) {
+                          Ok(Some(role)) => {
+                              let name = self.role_name(bot, message.channel_id, role);
+                              locale.format("admin-mute-role", &[("role", &name)])
+                          }
+                          _ => locale.get("admin-no-mute-role"),
+                      });
+
+        bot.send(message.channel_id, &buf);
+    }
+
+    fn role_name(&self, bot: &Bot, channel: ChannelId, role: RoleId) -> String {
+        let state = bot.get_state_for_channel(channel).read().unwrap();
+
+        match state.find_channel(channel) {
+            Some(ChannelRef::Public(server, _)) => {
+                match server.roles.iter().find(|x| x.id == role) {
+                    Some(role) => role.name.clone(),
+                    None => role.0.to_string(),
+                }
+            }
+            _ => role.0.to_string(),
+        }
+    }
+
+    // Returns the result of the change and the reply, or None if it has already replied.
+    fn handle_rule(&self,
+                   bot: &Bot,
+                   message: &Message,
+                   locale: &Locale,
+                   server: ServerId,
+                   kind: RuleKind,
+                   args: &[&str])
+                   -> Option<(storage::Result<()>, String)> {
+        let rule = match get_rules(server) {
+            Ok(rules) => rules.get(kind.name()).cloned(),
+            Err(err) => {
+                bot.send(message.channel_id,
+                         &locale.format("something-broken", &[("error", &err)]));
+                return None;
+            }
+        };
+
+        if args.is_empty() {
+            bot.send(message.channel_id, &describe_rule(locale, kind, rule.as_ref()));
+            return None;
+        }
+
+        if !admin::check_admin(bot, message, locale) {
+            return None;
+        }
+
+        // Changing a rule which was never turned on starts from the defaults.
+        let mut rule = rule.unwrap_or_else(|| Rule { enabled: false, ..kind.default_rule() });
+        let setting = args[0].to_lowercase();
+        let value = args.get(1).map(|x| x.to_lowercase()).unwrap_or_default();
+
+        let reply = match (setting.as_str(), value.as_str()) {
+            ("on", "") => {
+                rule.enabled = true;
+                locale.format("automod-rule-enabled",
+                              &[("rule", &kind.name()),
+                                ("limit", &describe_limit(locale, kind, &rule)),
+                                ("action", &locale.get(rule.action.key()))])
+            }
+
+            ("off", "") => {
+                rule.enabled = false;
+                locale.format("automod-rule-disabled", &[("rule", &kind.name())])
+            }
+
+            ("threshold", x) => {
+                match x.parse::<u64>() {
+                    Ok(x) if (1..=MAX_THRESHOLD).contains(&x) => rule.threshold = x,
+                    _ => {
+                        bot.send(message.channel_id,
+                                 &locale.format("automod-invalid-threshold", &[("max", &MAX_THRESHOLD)]));
+                        return None;
+                    }
+                }
+
+                locale.format("automod-limit-set",
+                              &[("rule", &kind.name()), ("limit", &describe_limit(locale, kind, &rule))])
+            }
+
+            ("window", x) => {
+                if !kind.is_flood() {
+                    bot.send(message.channel_id,
+                             &locale.format("automod-no-window", &[("rule", &kind.name())]));
+                    return None;
+                }
+
+                match x.parse::<u64>() {
+                    Ok(x) if (1..=MAX_WINDOW).contains(&x) => rule.window = x,
+                    _ => {
+                        bot.send(message.channel_id,
+                                 &locale.format("automod-invalid-window", &[("max", &MAX_WINDOW)]));
+                        return None;
+                    }
+                }
+
+                locale.format("automod-limit-set",
+                              &[("rule", &kind.name()), ("limit", &describe_limit(locale, kind, &rule))])
+            }
+
+            ("action", x) => {
+                match Action::from_name(x) {
+                    Some(action) => rule.action = action,
+                    None => {
+                        bot.send(message.channel_id, &locale.get("automod-invalid-action"));
+                        return None;
+                    }
+                }
+
+                locale.format("automod-action-set",
+                              &[("rule", &kind.name()), ("action", &locale.get(rule.action.key()))])
+            }
+
+            _ => {
+                bot.send(message.channel_id,
+                         &locale.command_help_message(self, Commands::Automod as u32));
+                return None;
+            }
+        };
+
+        Some((set_rule(server, kind, rule), reply))
+    }
+}
+
+impl<'a> module::Module for Module<'a> {
+    fn new() -> Result<Box<module::Module>, String> {
+        let mut map: HashMap<u32, &[&str]> = HashMap::new();
+        static AUTOMOD: [&'static str; 1] = ["automod"];
+        map.insert(Commands::Automod as u32, &AUTOMOD);
+        Ok(Box::new(Module {
+                        commands: map,
+                        history: Mutex::new(HashMap::new()),
+                    }))
+    }
+
+    fn name(&self) -> &'static str {
+        "Automod"
+    }
+
+    fn description(&self) -> &'static str {
+        "Deals with spam according to the rules of the server."
+    }
+
+    fn commands(&self) -> &HashMap<u32, &[&str]> {
+        &self.commands
+    }
+
+    fn command_description(&self, id: u32) -> &'static str {
+        match id {
+            x if x == Commands::Automod as u32 => "Shows or changes the automod rules.",
+            _ => panic!("Automod::command_description - invalid id."),
+        }
+    }
+
+    fn command_help_message(&self, id: u32) -> &'static str {
+        match id {
+            x if x == Commands::Automod as u32 => {
+                "`!automod` - Shows the automod rules of this server.\n\
+                 `!automod <rule> on|off` - Turns the rule on or off. The rules are `duplicates`, `mentions`, `caps`, `emoji`, `invites`, `links` and `attachments`.\n\
+                 `!automod <rule> threshold <number>` - Sets how many identical messages, mentions, percent of capital letters, emoji, invites, links or attachments break the rule.\n\
+                 `!automod <rule> window <seconds>` - Sets how far back the `duplicates` and `attachments` rules look.\n\
+                 `!automod <rule> action delete|warn|mute|kick|ban` - Sets what happens to whoever breaks the rule. Their message is deleted in any case, warnings count towards `!warnpolicy`, muting uses the role set with `!mute role`.\n\
+                 Admins are never affected. Everything automod does is posted in the mod-log channel."
+            }
+            _ => panic!("Automod::command_help_message - invalid id."),
+        }
+    }
+
+    fn command_scope(&self, _id: u32) -> CommandScope {
+        CommandScope::servers_only()
+    }
+
+    fn rerun_on_edit(&self, _id: u32) -> bool {
+        // `!automod` changes the rules, editing it shouldn't change them again.
+        false
+    }
+
+    fn handle(&self, bot: &Bot, message: &Message, id: u32, text: &str) {
+        if id != Commands::Automod as u32 {
+            panic!("Automod::handle - invalid id.");
+        }
+
+        let locale = bot.get_locale(message);
+
+        let server = match bot.get_server_for_channel(message.channel_id) {
+            Some(server) => server,
+            None => {
+                bot.send(message.channel_id, &locale.get("channel-info-error"));
+                return;
+            }
+        };
+
+        let args = text.split_whitespace().collect::<Vec<_>>();
+
+        let change = match args.first().map(|x| x.to_lowercase()) {
+            None => {
+                self.handle_status(bot, message, &locale, server);
+                None
+            }
+
+            Some(x) => {
+                match RuleKind::from_name(&x) {
+                    Some(kind) => self.handle_rule(bot, message, &locale, server, kind, &args[1..]),
+                    None => {
+                        let names = RULES.iter().map(|x| format!("`{}`", x.name())).collect::<Vec<_>>();
+                        bot.send(message.channel_id,
+                                 &locale.format("automod-unknown-rule",
+                                                &[("rule", &x), ("rules", &names.join(", "))]));
+                        None
+                    }
+                }
+            }
+        };
+
+        match change {
+            Some((Ok(()), reply)) => {
+                admin::audit(bot, server, message, "!automod", text, &Ok(reply.clone()));
+                bot.send(message.channel_id, &reply);
+            }
+            Some((Err(err), _)) => {
+                admin::audit(bot, server, message, "!automod", text, &Err(err.to_string()));
+                bot.send(message.channel_id,
+                         &locale.format("storage-error", &[("error", &err)]));
+            }
+            None => {}
+        }
+    }
+
+    fn handle_message(&self, bot: &Bot, message: &Message) {
+        let server = match bot.get_server_for_channel(message.channel_id) {
+            Some(server) => server,
+            None => return,
+        };
+
+        let rules = match get_rules(server) {
+            Ok(rules) => rules,
+            Err(err) => {
+                println!("[Warning] Failed to get the automod rules: {}", err);
+                return;
+            }
+        };
+
+        if !rules.values().any(|x| x.enabled) {
+            return;
+        }
+
+        let (kind, rule) = match self.broken_rule(server, message, &rules) {
+            Some(x) => x,
+            None => return,
+        };
+
+        // Asking Discord whether someone is an admin is slow, so it's only done for those who broke a rule.
+        let exempt = {
+            let state = bot.get_state_for_channel(message.channel_id).read().unwrap();
+
+            match state.find_channel(message.channel_id) {
+                Some(ChannelRef::Public(server, _)) => admin::is_admin(bot, server, message.author.id) != Some(false),
+                _ => true,
+            }
+        };
+
+        if !exempt {
+            self.enforce(bot, server, message, kind, rule);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Recent, attachment_count, caps_percent, duplicate_count, emoji_count, normalize};
+    use std::collections::VecDeque;
+
+    fn recent(time: u64, content: &str, attachments: usize) -> Recent {
+        Recent {
+            time: time,
+            content: normalize(content),
+            attachments: attachments,
+        }
+    }
+
+    #[test]
+    fn caps() {
+        assert_eq!(100, caps_percent("WHY IS THIS NOT WORKING"));
+        assert_eq!(0, caps_percent("OK FINE"));
+        assert_eq!(0, caps_percent("<@1234567890> <:PogChamp:1234567890> ok"));
+        assert_eq!(40, caps_percent("HALF it is ok"));
+    }
+
+    #[test]
+    fn emoji() {
+        assert_eq!(0, emoji_count("no emoji here :)"));
+        assert_eq!(3, emoji_count("😀👍🏻 <a:dance:123>"));
+    }
+
+    #[test]
+    fn floods() {
+        let mut history = VecDeque::new();
+        history.push_back(recent(1000, "spam", 2));
+        history.push_back(recent(5000, "other", 0));
+        history.push_back(recent(9000, "SPAM ", 1));
+        history.push_back(recent(10000, "spam", 1));
+
+        assert_eq!(3, duplicate_count(&history, 0));
+        assert_eq!(2, duplicate_count(&history, 2000));
+        assert_eq!(4, attachment_count(&history, 0));
+        assert_eq!(2, attachment_count(&history, 9000));
+
+        history.push_back(recent(11000, "", 3));
+        assert_eq!(0, duplicate_count(&history, 0));
+    }
+}
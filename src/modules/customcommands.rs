@@ -0,0 +1,394 @@
+use bot::Bot;
+use discord::model::*;
+use locale::Locale;
+use module::{self, CommandOutput, CommandScope, CustomCommand};
+use modules::admin;
+use rand;
+use rand::distributions::{IndependentSample, Range};
+use std::collections::BTreeMap;
+use std::collections::hash_map::HashMap;
+use storage::Scope;
+
+// The name of the storage the commands are kept in.
+const STORAGE_NAME: &'static str = "custom_commands";
+
+const MAX_COMMANDS_PER_SERVER: usize = 100;
+const MAX_NAME_LENGTH: usize = 32;
+const MAX_RESPONSE_LENGTH: usize = 1500;
+
+#[derive(Serialize, Deserialize)]
+struct Response {
+    text: String,
+    // Who last changed the command.
+    author: u64,
+}
+
+pub struct Module<'a> {
+    commands: HashMap<u32, &'a [&'a str]>,
+}
+
+enum Commands {
+    Add = 0,
+    Edit = 1,
+    Delete = 2,
+    List = 3,
+}
+
+fn get_responses(server: ServerId) -> BTreeMap<String, Response> {
+    match ::STORAGE.get(STORAGE_NAME, Scope::Server(server), "commands") {
+        Ok(responses) => responses.unwrap_or_default(),
+        Err(err) => {
+            println!("[Warning] Failed to get the custom commands: {}", err);
+            BTreeMap::new()
+        }
+    }
+}
+
+// Splits the arguments into the lowercase command name and the rest.
+fn split_name(text: &str) -> (String, &str) {
+    let mut parts = text.trim().splitn(2, char::is_whitespace);
+    let name = parts.next().unwrap_or("").trim_matches('!').to_lowercase();
+    (name, parts.next().unwrap_or("").trim())
+}
+
+// Fills in the placeholders of a response.
+//
+// `{name}` is replaced with the variable called `name` and `{a|b|c}` with one of the options,
+// picked with `choose`, which gets the number of options. Anything else is left as is.
+fn render<F>(template: &str, variables: &HashMap<&str, String>, mut choose: F) -> String
+    where F: FnMut(usize) -> usize
+{
+    let mut output = String::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        output.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        // The closest closing brace, unless another placeholder starts before it.
+        let end = match rest[1..].find(&['{', '}'][..]) {
+            Some(i) if rest[1 + i..].starts_with('}') => 1 + i,
+            _ => {
+                output.push('{');
+                rest = &rest[1..];
+                continue;
+            }
+        };
+
+        let placeholder = &rest[1..end];
+
+        if placeholder.contains('|') {
+            let options: Vec<&str> = placeholder.split('|').collect();
+            output.push_str(options[choose(options.len())]);
+        } else if let Some(value) = variables.get(placeholder) {
+            output.push_str(value);
+        } else {
+            output.push_str(&rest[..end + 1]);
+        }
+
+        rest = &rest[end + 1..];
+    }
+
+    output.push_str(rest);
+    output
+}
+
+impl<'a> module::Module for Module<'a> {
+    fn new() -> Result<Box<module::Module>, String> {
+        let mut map: HashMap<u32, &[&str]> = HashMap::new();
+        static ADD: [&'static str; 1] = ["addcmd"];
+        map.insert(Commands::Add as u32, &ADD);
+        static EDIT: [&'static str; 1] = ["editcmd"];
+        map.insert(Commands::Edit as u32, &EDIT);
+        static DELETE: [&'static str; 1] = ["delcmd"];
+        map.insert(Commands::Delete as u32, &DELETE);
+        static LIST: [&'static str; 1] = ["listcmds"];
+        map.insert(Commands::List as u32, &LIST);
+        Ok(Box::new(Module { commands: map }))
+    }
+
+    fn name(&self) -> &'static str {
+        "Custom Commands"
+    }
+
+    fn description(&self) -> &'static str {
+        "Canned replies added by the server admins."
+    }
+
+    fn commands(&self) -> &HashMap<u32, &[&str]> {
+        &self.commands
+    }
+
+    fn command_description(&self, id: u32) -> &'static str {
+        match id {
+            x if x == Commands::Add as u32 => "Adds a custom command.",
+            x if x == Commands::Edit as u32 => "Changes the reply of a custom command.",
+            x if x == Commands::Delete as u32 => "Deletes a custom command.",
+            x if x == Commands::List as u32 => "Lists the custom commands.",
+            _ => panic!("CustomCommands::command_description - invalid id."),
+        }
+    }
+
+    fn command_help_message(&self, id: u32) -> &'static str {
+        match id {
+            x if x == Commands::Add as u32 => {
+                "`!addcmd <name> <reply>` - Adds the `!<name>` command which sends the reply (admin only). \
+                 In the reply, `{user}` is replaced with a mention of whoever used the command, \
+                 `{username}` with their name, `{args}` with the text after the command, \
+                 `{1}` to `{9}` with its words and `{channel}` with the channel. \
+                 `{a|b|c}` picks one of the options at random. For example: \
+                 `!addcmd hug {user} hugs {args}! {:3|<3}`"
+            }
+            x if x == Commands::Edit as u32 => "`!editcmd <name> <reply>` - Changes the reply of a custom command (admin only).",
+            x if x == Commands::Delete as u32 => "`!delcmd <name>` - Deletes a custom command (admin only).",
+            x if x == Commands::List as u32 => "`!listcmds` - Lists the custom commands on this server.",
+            _ => panic!("CustomCommands::command_help_message - invalid id."),
+        }
+    }
+
+    fn rerun_on_edit(&self, id: u32) -> bool {
+        id == Commands::List as u32
+    }
+
+    fn rerun_custom_on_edit(&self, _name: &str) -> bool {
+        true
+    }
+
+    fn command_scope(&self, _id: u32) -> CommandScope {
+        CommandScope::servers_only()
+    }
+
+    fn handle(&self, bot: &Bot, message: &Message, id: u32, text: &str) {
+        let locale = bot.get_locale(message);
+
+        let server = match bot.get_server_for_channel(message.channel_id) {
+            Some(server) => server,
+            None => {
+                bot.send(message.channel_id, &locale.get("channel-info-error"));
+                return;
+            }
+        };
+
+        if id == Commands::List as u32 {
+            self.handle_list(bot, message, &locale, server);
+            return;
+        }
+
+        if !admin::check_admin(bot, message, &locale) {
+            return;
+        }
+
+        match id {
+            x if x == Commands::Add as u32 => self.handle_set(bot, message, &locale, server, text, false),
+            x if x == Commands::Edit as u32 => self.handle_set(bot, message, &locale, server, text, true),
+            x if x == Commands::Delete as u32 => self.handle_delete(bot, message, &locale, server, text),
+            _ => panic!("CustomCommands::handle - invalid id."),
+        }
+    }
+
+    fn custom_commands(&self, server: ServerId) -> Vec<CustomCommand> {
+        get_responses(server)
+            .into_iter()
+            .map(|(name, response)| {
+                     CustomCommand {
+                         name: name,
+                         description: "A custom command.".to_owned(),
+                         help_message: format!("Replies with: {}", response.text),
+                     }
+                 })
+            .collect()
+    }
+
+    fn handle_custom(&self, bot: &Bot, message: &Message, name: &str, text: &str) {
+        match self.respond(bot, message, name, text) {
+            Ok(text) | Err(text) => bot.send(message.channel_id, &text),
+        }
+    }
+
+    fn handle_custom_output(&self, bot: &Bot, message: &Message, name: &str, text: &str) -> Option<CommandOutput> {
+        Some(self.respond(bot, message, name, text))
+    }
+}
+
+impl<'a> Module<'a> {
+    fn respond(&self, bot: &Bot, message: &Message, name: &str, text: &str) -> CommandOutput {
+        let response = bot.get_server_for_channel(message.channel_id)
+            .and_then(|server| get_responses(server).remove(name));
+
+        let response = match response {
+            Some(response) => response,
+            None => {
+                return Err(bot.get_locale(message)
+                               .format("custom-commands-not-found", &[("name", &name)]))
+            }
+        };
+
+        let mut variables = HashMap::new();
+        variables.insert("user", message.author.mention().to_string());
+        variables.insert("username", message.author.name.clone());
+        variables.insert("args", text.trim().to_owned());
+        variables.insert("channel", message.channel_id.mention().to_string());
+
+        static ARGUMENTS: [&'static str; 9] = ["1", "2", "3", "4", "5", "6", "7", "8", "9"];
+        let mut words = text.split_whitespace();
+        for &n in &ARGUMENTS {
+            variables.insert(n, words.next().unwrap_or("").to_owned());
+        }
+
+        let mut rng = rand::thread_rng();
+        Ok(render(&response.text,
+                  &variables,
+                  |count| Range::new(0, count).ind_sample(&mut rng)))
+    }
+
+    fn handle_list(&self, bot: &Bot, message: &Message, locale: &Locale, server: ServerId) {
+        let responses = get_responses(server);
+
+        if responses.is_empty() {
+            bot.send(message.channel_id, &locale.get("custom-commands-none"));
+            return;
+        }
+
+        let mut buf = locale.get("custom-commands-list");
+        buf.push(' ');
+        buf.push_str(&responses.keys().map(|x| format!("`!{}`", x)).collect::<Vec<String>>().join(", "));
+
+        bot.send(message.channel_id, &buf);
+    }
+
+    fn handle_set(&self,
+                  bot: &Bot,
+                  message: &Message,
+                  locale: &Locale,
+                  server: ServerId,
+                  text: &str,
+                  edit: bool) {
+        let id = if edit { Commands::Edit } else { Commands::Add };
+        let (name, response) = split_name(text);
+        let name = name.as_str();
+
+        if name.is_empty() || response.is_empty() {
+            bot.send(message.channel_id, &locale.command_help_message(self, id as u32));
+            return;
+        }
+
+        if name.len() > MAX_NAME_LENGTH ||
+           !name.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_') {
+            bot.send(message.channel_id,
+                     &locale.format("custom-commands-invalid-name", &[("max", &MAX_NAME_LENGTH)]));
+            return;
+        }
+
+        if response.len() > MAX_RESPONSE_LENGTH {
+            bot.send(message.channel_id,
+                     &locale.format("custom-commands-too-long", &[("max", &MAX_RESPONSE_LENGTH)]));
+            return;
+        }
+
+        if !edit && bot.get_command_module(server, name).is_some() {
+            bot.send(message.channel_id,
+                     &locale.format("command-name-taken", &[("name", &name)]));
+            return;
+        }
+
+        let mut result = Ok(());
+        let update = ::STORAGE.update(STORAGE_NAME,
+                                      Scope::Server(server),
+                                      "commands",
+                                      |responses: Option<BTreeMap<String, Response>>| {
+            let mut responses = responses.unwrap_or_default();
+
+            if edit && !responses.contains_key(name) {
+                result = Err(locale.format("custom-commands-not-found", &[("name", &name)]));
+            } else if !edit && responses.len() >= MAX_COMMANDS_PER_SERVER {
+                result = Err(locale.format("custom-commands-too-many", &[("max", &MAX_COMMANDS_PER_SERVER)]));
+            } else {
+                responses.insert(name.to_owned(),
+                                 Response {
+                                     text: response.to_owned(),
+                                     author: message.author.id.0,
+                                 });
+            }
+
+            Some(responses)
+        });
+
+        let outcome = match (update, result) {
+            (Err(err), _) => Err(locale.format("storage-error", &[("error", &err)])),
+            (Ok(()), Err(text)) => Err(text),
+            (Ok(()), Ok(())) if edit => Ok(locale.format("custom-commands-edited", &[("name", &name)])),
+            (Ok(()), Ok(())) => Ok(locale.format("custom-commands-added", &[("name", &name)])),
+        };
+
+        let action = if edit { "!editcmd" } else { "!addcmd" };
+        admin::audit(bot, server, message, action, name, &outcome);
+
+        match outcome {
+            Ok(text) | Err(text) => bot.send(message.channel_id, &text),
+        }
+    }
+
+    fn handle_delete(&self, bot: &Bot, message: &Message, locale: &Locale, server: ServerId, text: &str) {
+        let name = split_name(text).0;
+        let name = name.as_str();
+
+        if name.is_empty() {
+            bot.send(message.channel_id,
+                     &locale.command_help_message(self, Commands::Delete as u32));
+            return;
+        }
+
+        let mut found = false;
+        let update = ::STORAGE.update(STORAGE_NAME,
+                                      Scope::Server(server),
+                                      "commands",
+                                      |responses: Option<BTreeMap<String, Response>>| {
+            let mut responses = responses.unwrap_or_default();
+            found = responses.remove(name).is_some();
+
+            if responses.is_empty() { None } else { Some(responses) }
+        });
+
+        let outcome = match update {
+            Err(err) => Err(locale.format("storage-error", &[("error", &err)])),
+            Ok(()) if found => Ok(locale.format("custom-commands-deleted", &[("name", &name)])),
+            Ok(()) => Err(locale.format("custom-commands-not-found", &[("name", &name)])),
+        };
+
+        admin::audit(bot, server, message, "!delcmd", name, &outcome);
+
+        match outcome {
+            Ok(text) | Err(text) => bot.send(message.channel_id, &text),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::render;
+    use std::collections::hash_map::HashMap;
+
+    fn variables() -> HashMap<&'static str, String> {
+        let mut variables = HashMap::new();
+        variables.insert("user", "<@1>".to_owned());
+        variables.insert("args", "everyone".to_owned());
+        variables
+    }
+
+    #[test]
+    fn render_usual() {
+        assert_eq!("<@1> hugs everyone!",
+                   render("{user} hugs {args}!", &variables(), |_| 0));
+    }
+
+    #[test]
+    fn render_choice() {
+        assert_eq!("c and <@1>", render("{a|b|c} and {user}", &variables(), |n| n - 1));
+    }
+
+    #[test]
+    fn render_unknown_and_unbalanced() {
+        assert_eq!("{nope} { {user <@1>} {",
+                   render("{nope} { {user {user}} {", &variables(), |_| 0));
+    }
+}
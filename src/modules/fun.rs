@@ -1,30 +1,17 @@
 use bot::Bot;
-use circular_queue::CircularQueue;
 use discord::ChannelRef;
 use discord::model::*;
+use locale::Locale;
 use module;
+use module::{CommandOutput, CommandScope};
 use rand;
 use rand::distributions::{IndependentSample, Range};
 use regex::Regex;
 use std::char;
 use std::collections::hash_map::HashMap;
-use std::sync::RwLock;
-
-const COMMAND_MESSAGE_QUEUE_SIZE: usize = 64;
-
-#[derive(Debug, Clone, Copy)]
-struct CommandMessage {
-    /// Message with the command.
-    command: MessageId,
-    /// Author of the message with the command.
-    author: UserId,
-    /// Message the bot sent as a response.
-    output: MessageId,
-}
 
 pub struct Module<'a> {
     commands: HashMap<u32, &'a [&'a str]>,
-    command_messages: RwLock<HashMap<ChannelId, CircularQueue<CommandMessage>>>,
 }
 
 lazy_static! {
@@ -69,10 +56,7 @@ impl<'a> module::Module for Module<'a> {
         map.insert(Commands::Aesthetic as u32, &AESTHETIC);
         static SMALLCAPS: [&'static str; 1] = ["smallcaps"];
         map.insert(Commands::Smallcaps as u32, &SMALLCAPS);
-        Ok(Box::new(Module {
-                        commands: map,
-                        command_messages: RwLock::new(HashMap::new()),
-                    }))
+        Ok(Box::new(Module { commands: map }))
     }
 
     fn name(&self) -> &'static str {
@@ -115,6 +99,19 @@ impl<'a> module::Module for Module<'a> {
         }
     }
 
+    fn rerun_on_edit(&self, id: u32) -> bool {
+        // Re-running would make another room.
+        id != Commands::Room as u32
+    }
+
+    fn command_scope(&self, id: u32) -> CommandScope {
+        if id == Commands::Room as u32 {
+            CommandScope::servers_only()
+        } else {
+            CommandScope::anywhere()
+        }
+    }
+
     fn handle(&self, bot: &Bot, message: &Message, id: u32, text: &str) {
         match id {
             x if x == Commands::Fraktur as u32 => self.handle_fraktur(bot, message, text),
@@ -129,77 +126,51 @@ impl<'a> module::Module for Module<'a> {
         }
     }
 
-    fn handle_message_update(&self, bot: &Bot, channel_id: ChannelId, id: MessageId) {
-        if let Some(output) = self.find_command_message(channel_id, id) {
-            if let Ok(message) = bot.get_message(channel_id, output.output) {
-                bot.edit(channel_id,
-                         output.output,
-                         &format!("{:.2000}",
-                                 &format!("{} said: {}", output.author.mention(), message.content)));
-            }
-        }
-    }
+    fn handle_output(&self, bot: &Bot, message: &Message, id: u32, text: &str) -> Option<CommandOutput> {
+        let locale = bot.get_locale(message);
 
-    fn handle_message_delete(&self, bot: &Bot, channel_id: ChannelId, id: MessageId) {
-        if let Some(output) = self.find_command_message(channel_id, id) {
-            if let Ok(message) = bot.get_message(channel_id, output.output) {
-                bot.edit(channel_id,
-                         output.output,
-                         &format!("{:.2000}",
-                                 &format!("{} said: {}", output.author.mention(), message.content)));
-            }
+        match id {
+            x if x == Commands::Fraktur as u32 => Some(Ok(text.chars().map(frakturize).collect())),
+            x if x == Commands::Temperature as u32 => Some(self.temperature(&locale, text)),
+            x if x == Commands::Roll as u32 => Some(Ok(roll(&locale, message, text))),
+            x if x == Commands::Pick as u32 => Some(self.pick(&locale, message, text)),
+            x if x == Commands::Aesthetic as u32 => Some(Ok(text.chars().map(make_fullwidth).collect())),
+            x if x == Commands::Smallcaps as u32 => Some(Ok(text.chars().map(make_smallcaps).collect())),
+            _ => None,
         }
     }
 }
 
 impl<'a> Module<'a> {
-    fn remember_command_message(&self, channel_id: ChannelId, command_id: MessageId, author: UserId, output_id: MessageId) {
-        self.command_messages
-            .write()
-            .unwrap()
-            .entry(channel_id)
-            .or_insert_with(|| CircularQueue::new(COMMAND_MESSAGE_QUEUE_SIZE))
-            .push(CommandMessage {
-                      command: command_id,
-                      author,
-                      output: output_id,
-                  });
+    fn handle_fraktur(&self, bot: &Bot, message: &Message, text: &str) {
+        bot.send(message.channel_id, &text.chars().map(frakturize).collect::<String>());
     }
 
-    fn find_command_message(&self, channel_id: ChannelId, command_id: MessageId) -> Option<CommandMessage> {
-        self.command_messages
-            .read()
-            .unwrap()
-            .get(&channel_id)
-            .and_then(|x| {
-                          x.iter()
-                              .find(|x| x.command == command_id)
-                              .map(|x| x.clone())
-                      })
+    fn handle_aesthetic(&self, bot: &Bot, message: &Message, text: &str) {
+        bot.send(message.channel_id, &text.chars().map(make_fullwidth).collect::<String>());
     }
 
-    fn handle_fraktur(&self, bot: &Bot, message: &Message, text: &str) {
-        let reply = text.chars().map(frakturize).collect::<String>();
-        if let Some(output) = bot.send_and_get(message.channel_id, &reply) {
-            self.remember_command_message(message.channel_id, message.id, message.author.id, output.id);
-        }
+    fn handle_smallcaps(&self, bot: &Bot, message: &Message, text: &str) {
+        bot.send(message.channel_id, &text.chars().map(make_smallcaps).collect::<String>());
     }
 
-    fn handle_aesthetic(&self, bot: &Bot, message: &Message, text: &str) {
-        let reply = text.chars().map(make_fullwidth).collect::<String>();
-        if let Some(output) = bot.send_and_get(message.channel_id, &reply) {
-            self.remember_command_message(message.channel_id, message.id, message.author.id, output.id);
+    fn handle_temperature(&self, bot: &Bot, message: &Message, text: &str) {
+        match self.temperature(&bot.get_locale(message), text) {
+            Ok(reply) | Err(reply) => bot.send(message.channel_id, &reply),
         }
     }
 
-    fn handle_smallcaps(&self, bot: &Bot, message: &Message, text: &str) {
-        let reply = text.chars().map(make_smallcaps).collect::<String>();
-        if let Some(output) = bot.send_and_get(message.channel_id, &reply) {
-            self.remember_command_message(message.channel_id, message.id, message.author.id, output.id);
+    fn handle_roll(&self, bot: &Bot, message: &Message, text: &str) {
+        bot.send(message.channel_id, &roll(&bot.get_locale(message), message, text));
+    }
+
+    fn handle_pick(&self, bot: &Bot, message: &Message, text: &str) {
+        match self.pick(&bot.get_locale(message), message, text) {
+            Ok(reply) | Err(reply) => bot.send(message.channel_id, &reply),
         }
     }
 
-    fn handle_temperature(&self, bot: &Bot, message: &Message, text: &str) {
+    fn temperature(&self, locale: &Locale, text: &str) -> CommandOutput {
         if let Some(caps) = TEMPERATURE_REGEX.captures(text) {
             let value = caps.get(1).unwrap().as_str().parse::<f32>().unwrap();
             let letter = caps.get(3).unwrap().as_str().chars().next().unwrap();
@@ -207,58 +178,43 @@ impl<'a> Module<'a> {
             let converted_value = match letter {
                 'C' | 'c' => 9f32 * value / 5f32 + 32f32,
                 'F' | 'f' => 5f32 * (value - 32f32) / 9f32,
-                _ => panic!("Regex error in Fun::handle_temperature."),
+                _ => panic!("Regex error in Fun::temperature."),
             };
 
             let converted_letter = match letter {
                 'C' | 'c' => 'F',
                 'F' | 'f' => 'C',
-                _ => panic!("Regex error in Fun::handle_temperature."),
+                _ => panic!("Regex error in Fun::temperature."),
             };
 
-            bot.send(message.channel_id,
-                     &format!("{:.2}°{} is **{:.2}**°{}.",
-                             value,
-                             letter.to_uppercase().next().unwrap(),
-                             converted_value,
-                             converted_letter));
+            Ok(locale.format("fun-temperature",
+                             &[("value", &format!("{:.2}", value)),
+                               ("scale", &letter.to_uppercase().next().unwrap()),
+                               ("converted-value", &format!("{:.2}", converted_value)),
+                               ("converted-scale", &converted_letter)]))
         } else {
-            bot.send(message.channel_id,
-                     <Module as module::Module>::command_help_message(&self, Commands::Temperature as u32));
+            Err(locale.command_help_message(self, Commands::Temperature as u32))
         }
     }
 
-    fn handle_roll(&self, bot: &Bot, message: &Message, text: &str) {
-        let caps = ROLL_REGEX.captures(text).unwrap();
-        let max = caps.get(2)
-            .and_then(|x| x.as_str().parse::<u64>().ok())
-            .map(|x| if x == 0 { 100 } else { x })
-            .unwrap_or(100);
-
-        let mut rng = rand::thread_rng();
-        let number = Range::new(0, max).ind_sample(&mut rng);
-
-        bot.send(message.channel_id,
-                 &format!("{} rolled **{}**!", message.author.mention(), number));
-    }
-
-    fn handle_pick(&self, bot: &Bot, message: &Message, text: &str) {
+    fn pick(&self, locale: &Locale, message: &Message, text: &str) -> CommandOutput {
         let options: Vec<&str> = text.split(';').filter(|x| x.len() > 0).collect();
 
         if options.len() < 2 {
-            bot.send(message.channel_id,
-                     <Module as module::Module>::command_help_message(&self, Commands::Pick as u32));
+            Err(locale.command_help_message(self, Commands::Pick as u32))
         } else {
             let mut rng = rand::thread_rng();
             let index = Range::new(0, options.len()).ind_sample(&mut rng);
 
-            bot.send(message.channel_id,
-                     &format!("{}: I pick {}!", message.author.mention(), options[index]));
+            Ok(locale.format("fun-pick",
+                             &[("user", &message.author.mention()), ("option", &options[index])]))
         }
     }
 
     fn handle_info(&self, bot: &Bot, message: &Message, _text: &str) {
-        match bot.get_state()
+        let locale = bot.get_locale(message);
+
+        match bot.get_state_for_channel(message.channel_id)
                   .read()
                   .unwrap()
                   .find_channel(message.channel_id) {
@@ -267,29 +223,31 @@ impl<'a> Module<'a> {
             }
 
             Some(ChannelRef::Public(server, channel)) => {
-                let mut buf = format!("```Server ID: {},\n\
-                                       Owner ID: {},\n\
-                                       Member count: {},\n\
-                                       Icon: {},\n\
-                                       Roles:",
-                                      server.id.0,
-                                      server.owner_id.0,
-                                      server.member_count,
-                                      if let Some(ref icon) = server.icon {
-                                          &icon
-                                      } else {
-                                          "N/A"
-                                      });
+                let not_available = locale.get("fun-info-not-available");
+
+                let mut buf = format!("```{}",
+                                      locale.format("fun-info-server",
+                                                    &[("server-id", &server.id.0),
+                                                      ("owner-id", &server.owner_id.0),
+                                                      ("member-count", &server.member_count),
+                                                      ("icon",
+                                                       &if let Some(ref icon) = server.icon {
+                                                            icon.as_str()
+                                                        } else {
+                                                            not_available.as_str()
+                                                        })]));
 
                 if server.roles.len() == 0 {
-                    buf.push_str(" N/A");
+                    buf.push_str(&format!(" {}", not_available));
                 } else {
                     for role in &server.roles {
                         buf.push_str(&format!("\n- {} '{}'", role.id.0, role.name));
                     }
                 }
 
-                buf.push_str(&format!("\n\nChannel ID: {}```", channel.id.0));
+                buf.push_str("\n\n");
+                buf.push_str(&locale.format("fun-info-channel", &[("channel-id", &channel.id.0)]));
+                buf.push_str("```");
 
                 bot.send(message.channel_id, &buf);
             }
@@ -299,22 +257,18 @@ impl<'a> Module<'a> {
             }
 
             None => {
-                bot.send(message.channel_id,
-                         "Huh, I couldn't get this channel's info for some reason. Try again I guess?");
+                bot.send(message.channel_id, &locale.get("channel-info-error"));
             }
         }
     }
 
     fn handle_room(&self, bot: &Bot, message: &Message, _text: &str) {
-        match bot.get_state()
+        let locale = bot.get_locale(message);
+
+        match bot.get_state_for_channel(message.channel_id)
                   .read()
                   .unwrap()
                   .find_channel(message.channel_id) {
-            Some(ChannelRef::Private(_)) |
-            Some(ChannelRef::Group(_)) => {
-                bot.send(message.channel_id, "Well, what do you expect me to do?");
-            }
-
             Some(ChannelRef::Public(server, _)) => {
                 if message.mentions.len() > 0 || message.mention_roles.len() > 0 {
                     let number = rand::random::<u64>();
@@ -360,33 +314,45 @@ impl<'a> Module<'a> {
                         }
 
                         Ok(Channel::Private(_)) => {
-                            bot.send(message.channel_id,
-                                     "I made a private channel?! How did I what.");
+                            bot.send(message.channel_id, &locale.get("fun-room-made-private-channel"));
                         }
 
                         Ok(Channel::Group(_)) => {
-                            bot.send(message.channel_id, "I made a group?! How did I what.");
+                            bot.send(message.channel_id, &locale.get("fun-room-made-group"));
                         }
 
                         Err(err) => {
                             bot.send(message.channel_id,
-                                     &format!("Couldn't create a new channel: {} :/", err));
+                                     &locale.format("fun-room-error", &[("error", &err)]));
                         }
                     }
                 } else {
                     bot.send(message.channel_id,
-                             <Module as module::Module>::command_help_message(&self, Commands::Room as u32));
+                             &locale.command_help_message(self, Commands::Room as u32));
                 }
             }
 
-            None => {
-                bot.send(message.channel_id,
-                         "Huh, I couldn't get this channel's info for some reason. Try again I guess?");
+            // Private channels and groups never get here, see command_scope().
+            _ => {
+                bot.send(message.channel_id, &locale.get("channel-info-error"));
             }
         }
     }
 }
 
+fn roll(locale: &Locale, message: &Message, text: &str) -> String {
+    let caps = ROLL_REGEX.captures(text).unwrap();
+    let max = caps.get(2)
+        .and_then(|x| x.as_str().parse::<u64>().ok())
+        .map(|x| if x == 0 { 100 } else { x })
+        .unwrap_or(100);
+
+    let mut rng = rand::thread_rng();
+    let number = Range::new(0, max).ind_sample(&mut rng);
+
+    locale.format("fun-roll", &[("user", &message.author.mention()), ("number", &number)])
+}
+
 fn frakturize(c: char) -> char {
     match c {
         'a'...'z' => char::from_u32(('𝔞' as u32) - ('a' as u32) + (c as u32)).unwrap(),
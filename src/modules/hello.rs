@@ -1,6 +1,8 @@
 use bot::Bot;
 use discord::model::Message;
+use locale::Locale;
 use module;
+use module::CommandOutput;
 use rand;
 use rand::distributions::{IndependentSample, Range};
 use std::collections::hash_map::HashMap;
@@ -41,13 +43,25 @@ impl<'a> module::Module for Module<'a> {
         "`!hello` - Prints a greeting message."
     }
 
-    fn handle(&self, bot: &Bot, message: &Message, _id: u32, _text: &str) {
-        let emojis: [&'static str; 22] = ["👌", "👌🏻", "👌🏼", "👌🏽", "👌🏾", "👌🏿", "👍", "👍🏻", "👍🏼", "👍🏽", "👍🏾", "👍🏿", "🌝", "😄", "🔥", "💯", "🆒", "🚽", "🚾", "❤", "⚠", "✅"];
+    fn rerun_on_edit(&self, _id: u32) -> bool {
+        true
+    }
 
-        let mut rng = rand::thread_rng();
-        let index = Range::new(0, emojis.len()).ind_sample(&mut rng);
+    fn handle(&self, bot: &Bot, message: &Message, _id: u32, _text: &str) {
+        bot.send(message.channel_id, &greeting(&bot.get_locale(message), message));
+    }
 
-        bot.send(message.channel_id,
-                 &format!("Hi, {}! {}", message.author.mention(), emojis[index]));
+    fn handle_output(&self, bot: &Bot, message: &Message, _id: u32, _text: &str) -> Option<CommandOutput> {
+        Some(Ok(greeting(&bot.get_locale(message), message)))
     }
 }
+
+fn greeting(locale: &Locale, message: &Message) -> String {
+    let emojis: [&'static str; 22] = ["👌", "👌🏻", "👌🏼", "👌🏽", "👌🏾", "👌🏿", "👍", "👍🏻", "👍🏼", "👍🏽", "👍🏾", "👍🏿", "🌝", "😄", "🔥", "💯", "🆒", "🚽", "🚾", "❤", "⚠", "✅"];
+
+    let mut rng = rand::thread_rng();
+    let index = Range::new(0, emojis.len()).ind_sample(&mut rng);
+
+    locale.format("hello-greeting",
+                  &[("user", &message.author.mention()), ("emoji", &emojis[index])])
+}
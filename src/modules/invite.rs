@@ -17,15 +17,22 @@ enum Commands {
     Invite = 0,
 }
 
-impl<'a> module::Module for Module<'a> {
-    fn new() -> Result<Box<module::Module>, String> {
+impl<'a> Module<'a> {
+    // Doesn't check the client ID, also used for the command reference, see docs.rs.
+    pub fn without_link() -> Self {
         static INVITE: [&'static str; 1] = ["invite"];
         let mut map: HashMap<u32, &[&str]> = HashMap::new();
         map.insert(Commands::Invite as u32, &INVITE);
+        Module { commands: map }
+    }
+}
+
+impl<'a> module::Module for Module<'a> {
+    fn new() -> Result<Box<module::Module>, String> {
         INVITE_LINK
             .as_ref()
             .map_err(|s| s.clone())
-            .and(Ok(Box::new(Module { commands: map })))
+            .and(Ok(Box::new(Module::without_link())))
     }
 
     fn name(&self) -> &'static str {
@@ -50,8 +57,8 @@ impl<'a> module::Module for Module<'a> {
 
     fn handle(&self, bot: &Bot, message: &Message, _id: u32, _text: &str) {
         bot.send_pm(message.author.id,
-                    &format!("Follow this link to invite the bot to your server: {}",
-                            INVITE_LINK.as_ref().unwrap()),
+                    &bot.get_locale(message)
+                         .format("invite-link", &[("link", INVITE_LINK.as_ref().unwrap())]),
                     message.channel_id);
     }
 }
@@ -0,0 +1,88 @@
+use bot::Bot;
+use discord::model::Message;
+use module;
+use std::collections::hash_map::HashMap;
+
+pub struct Module<'a> {
+    commands: HashMap<u32, &'a [&'a str]>,
+}
+
+enum Commands {
+    Language = 0,
+}
+
+impl<'a> module::Module for Module<'a> {
+    fn new() -> Result<Box<module::Module>, String> {
+        static LANGUAGE: [&'static str; 3] = ["language", "lang", "locale"];
+        let mut map: HashMap<u32, &[&str]> = HashMap::new();
+        map.insert(Commands::Language as u32, &LANGUAGE);
+        Ok(Box::new(Module { commands: map }))
+    }
+
+    fn name(&self) -> &'static str {
+        "Language"
+    }
+
+    fn description(&self) -> &'static str {
+        "Lets you choose the language the bot replies in."
+    }
+
+    fn commands(&self) -> &HashMap<u32, &[&str]> {
+        &self.commands
+    }
+
+    fn command_description(&self, _: u32) -> &'static str {
+        "Sets your language."
+    }
+
+    fn command_help_message(&self, _: u32) -> &'static str {
+        "`!language` - Shows your current language and the available ones.\n\
+         `!language <code>` - Sets the language the bot replies to you in.\n\
+         `!language default` - Goes back to the server language."
+    }
+
+    fn handle(&self, bot: &Bot, message: &Message, _id: u32, text: &str) {
+        let localization = bot.get_localization();
+        let locale = bot.get_locale(message);
+        let code = text.trim();
+
+        if code.is_empty() {
+            let available = localization.available_locales()
+                .into_iter()
+                .map(|code| {
+                         format!("`{}` ({})",
+                                 code,
+                                 localization.get_locale_by_code(code).get("locale-name"))
+                     })
+                .collect::<Vec<String>>()
+                .join(", ");
+
+            bot.send(message.channel_id,
+                     &locale.format("language-current",
+                                    &[("locale", &locale.get("locale-name")), ("available", &available)]));
+        } else if code == "default" {
+            if let Err(err) = localization.set_user_locale(message.author.id, None) {
+                bot.send(message.channel_id,
+                         &locale.format("storage-error", &[("error", &err)]));
+                return;
+            }
+
+            bot.send(message.channel_id,
+                     &bot.get_locale(message).get("language-reset"));
+        } else if localization.has_locale(code) {
+            if let Err(err) = localization.set_user_locale(message.author.id, Some(code)) {
+                bot.send(message.channel_id,
+                         &locale.format("storage-error", &[("error", &err)]));
+                return;
+            }
+
+            let new_locale = bot.get_locale(message);
+            bot.send(message.channel_id,
+                     &new_locale.format("language-set", &[("locale", &new_locale.get("locale-name"))]));
+        } else {
+            bot.send(message.channel_id,
+                     &locale.format("locale-unknown",
+                                    &[("code", &code), ("available", &localization.available_locales().join(", "))]));
+        }
+    }
+}
@@ -1,6 +1,8 @@
 use bot::Bot;
 use discord::model::Message;
-use module;
+use locale::Locale;
+use module::{self, CommandScope, CustomCommand};
+use modules::owner;
 use std::cmp::Ordering;
 use std::collections::hash_map::HashMap;
 
@@ -28,6 +30,40 @@ impl<'a> Command<'a> {
     }
 }
 
+// Returns the custom commands of all modules on the server of the message.
+fn custom_commands<'a>(bot: &'a Bot, message: &Message) -> Vec<(&'a module::Module, CustomCommand)> {
+    let server = match bot.get_server_for_channel(message.channel_id) {
+        Some(server) => server,
+        None => return Vec::new(),
+    };
+
+    let mut commands = Vec::new();
+
+    for m in bot.get_modules() {
+        for c in m.custom_commands(server) {
+            commands.push((&**m, c));
+        }
+    }
+
+    commands.sort_by(|a, b| a.1.name.cmp(&b.1.name));
+    commands
+}
+
+// The description and the help message of a command, followed by where it can be used.
+fn command_help(locale: &Locale, module: &module::Module, id: u32) -> String {
+    let mut buf = format!(": {}\n{}",
+                          locale.command_description(module, id),
+                          locale.command_help_message(module, id));
+
+    let scope = module.command_scope(id);
+    if scope != CommandScope::anywhere() {
+        buf.push('\n');
+        buf.push_str(&locale.format("modules-command-scope", &[("places", &locale.command_scope(scope))]));
+    }
+
+    buf
+}
+
 pub struct Module<'a> {
     commands: HashMap<u32, &'a [&'a str]>,
 }
@@ -40,10 +76,12 @@ enum Commands {
 
 impl<'a> Module<'a> {
     fn handle_modules(&self, bot: &Bot, message: &Message, text: &str) {
+        let locale = bot.get_locale(message);
+
         if text.len() == 0 {
-            let mut buf = "List of available modules:".to_string();
+            let mut buf = locale.get("modules-list");
             for m in bot.get_modules() {
-                buf.push_str(format!("\n- `{}`: {}", m.name(), m.description()).as_str());
+                buf.push_str(format!("\n- `{}`: {}", m.name(), locale.module_description(&**m)).as_str());
             }
 
             bot.send(message.channel_id, &buf);
@@ -52,9 +90,19 @@ impl<'a> Module<'a> {
 
         let text_lc = text.to_lowercase();
 
+        {
+            let mut words = text_lc.splitn(2, char::is_whitespace);
+
+            match (words.next(), words.next()) {
+                (Some("load"), Some(name)) => return self.handle_load(bot, message, name.trim(), true),
+                (Some("unload"), Some(name)) => return self.handle_load(bot, message, name.trim(), false),
+                _ => {}
+            }
+        }
+
         for m in bot.get_modules() {
             if m.name().to_lowercase() == text_lc {
-                let mut buf = format!("`{}`: {}", m.name(), m.description());
+                let mut buf = format!("`{}`: {}", m.name(), locale.module_description(&**m));
 
                 let mut commands: Vec<Command> = Vec::new();
                 for (&id, &cmds) in m.commands() {
@@ -65,12 +113,20 @@ impl<'a> Module<'a> {
                                   });
                 }
 
-                if commands.len() == 0 {
-                    buf.push_str("\nThere are no commands defined by this module.");
+                let custom_commands: Vec<CustomCommand> = custom_commands(bot, message)
+                    .into_iter()
+                    .filter(|&(module, _)| module.name() == m.name())
+                    .map(|(_, c)| c)
+                    .collect();
+
+                if commands.len() == 0 && custom_commands.is_empty() {
+                    buf.push('\n');
+                    buf.push_str(&locale.get("modules-no-commands"));
                 } else {
                     commands.sort_by(|a, b| a.cmp(b));
 
-                    buf.push_str("\nCommand list:");
+                    buf.push('\n');
+                    buf.push_str(&locale.get("modules-command-list"));
                     for c in commands {
                         let mut first = true;
                         for alias in c.names {
@@ -82,7 +138,11 @@ impl<'a> Module<'a> {
                             }
                         }
 
-                        buf.push_str(format!(": {}", c.module.command_description(c.id)).as_str());
+                        buf.push_str(format!(": {}", locale.command_description(c.module, c.id)).as_str());
+                    }
+
+                    for c in custom_commands {
+                        buf.push_str(format!("\n- `!{}`: {}", c.name, c.description).as_str());
                     }
                 }
 
@@ -92,10 +152,53 @@ impl<'a> Module<'a> {
         }
 
         bot.send(message.channel_id,
-                 format!("There is no module called `{}`.", text).as_str());
+                 &locale.format("modules-not-found", &[("module", &text)]));
+    }
+
+    // Loads or unloads a module by its lowercase name, for the bot owners.
+    fn handle_load(&self, bot: &Bot, message: &Message, name: &str, load: bool) {
+        let locale = bot.get_locale(message);
+
+        if !owner::check_owner(bot, message, &locale) {
+            return;
+        }
+
+        let index = match bot.get_modules().iter().position(|m| m.name().to_lowercase() == name) {
+            Some(index) => index,
+            None => {
+                bot.send(message.channel_id,
+                         &locale.format("modules-not-found", &[("module", &name)]));
+                return;
+            }
+        };
+
+        let module_name = bot.get_modules()[index].name();
+
+        // Without these there would be no way to load anything back.
+        if !load && (module_name == module::Module::name(self) || module_name == "Owner") {
+            bot.send(message.channel_id,
+                     &locale.format("modules-cannot-unload", &[("module", &module_name)]));
+            return;
+        }
+
+        let key = match (load, bot.set_module_enabled(index, load)) {
+            (true, true) => "modules-loaded",
+            (true, false) => "modules-already-loaded",
+            (false, true) => "modules-unloaded",
+            (false, false) => "modules-already-unloaded",
+        };
+
+        println!("[Info] `{}` {} the {} module.",
+                 message.author.name,
+                 if load { "loaded" } else { "unloaded" },
+                 module_name);
+
+        bot.send(message.channel_id, &locale.format(key, &[("module", &module_name)]));
     }
 
     fn handle_commands(&self, bot: &Bot, message: &Message, _text: &str) {
+        let locale = bot.get_locale(message);
+
         let mut commands: Vec<Command> = Vec::new();
         for m in bot.get_modules() {
             for (&id, &cmds) in m.commands() {
@@ -109,7 +212,7 @@ impl<'a> Module<'a> {
 
         commands.sort_by(|a, b| a.cmp(b));
 
-        let mut buf = "Available commands:".to_string();
+        let mut buf = locale.get("modules-available-commands");
         for c in commands {
             let mut first = true;
             for alias in c.names {
@@ -121,16 +224,24 @@ impl<'a> Module<'a> {
                 }
             }
 
-            buf.push_str(format!(" (module `{}`): {}",
-                                 c.module.name(),
-                                 c.module.command_description(c.id))
-                                 .as_str());
+            buf.push(' ');
+            buf.push_str(&locale.format("modules-command-entry",
+                                        &[("module", &c.module.name()),
+                                          ("description", &locale.command_description(c.module, c.id))]));
+        }
+
+        for (m, c) in custom_commands(bot, message) {
+            buf.push_str(format!("\n- `!{}` ", c.name).as_str());
+            buf.push_str(&locale.format("modules-command-entry",
+                                        &[("module", &m.name()), ("description", &c.description)]));
         }
 
         bot.send(message.channel_id, &buf);
     }
 
     fn handle_command(&self, bot: &Bot, message: &Message, text: &str) {
+        let locale = bot.get_locale(message);
+
         let text = if text.starts_with('!') {
             text.split_at(1).1
         } else {
@@ -139,19 +250,30 @@ impl<'a> Module<'a> {
 
         if text.len() == 0 {
             bot.send(message.channel_id,
-                     &format!("Bot version {} using **discord-rs**.\n\
-                               `!mods` - list modules!\n\
-                               `!mod <name>` - list commands of a module!\n\
-                               `!help <command>` - help for a command!\n\
-                               \n\
-                               Or simply:\n\
-                               `!commands` - list all commands!",
-                             env!("CARGO_PKG_VERSION")));
+                     &locale.format("modules-help-intro", &[("version", &env!("CARGO_PKG_VERSION"))]));
             return;
         }
 
         let text = text.to_lowercase();
 
+        // The command which actually runs, if there's one.
+        if let Some((i, id)) = bot.find_command(&text) {
+            let m = &bot.get_modules()[i];
+            let mut buf = format!("`!{}`", text);
+
+            for alias in m.commands()[&id] {
+                if *alias != text {
+                    buf.push_str(format!(", `!{}`", alias).as_str());
+                }
+            }
+
+            buf.push_str(&command_help(&locale, &**m, id));
+
+            bot.send(message.channel_id, buf.as_str());
+            return;
+        }
+
+        // Otherwise the name might be used by several modules, which are all listed.
         let mut buf = String::new();
         for m in bot.get_modules() {
             for (&id, &cmds) in m.commands() {
@@ -169,21 +291,23 @@ impl<'a> Module<'a> {
                             }
                         }
 
-                        buf.push_str(format!(": {}\n{}",
-                                             m.command_description(id),
-                                             m.command_help_message(id))
-                                             .as_str());
+                        buf.push_str(&command_help(&locale, &**m, id));
                         break;
                     }
                 }
             }
         }
 
+        // Custom commands are only used when there's no such built-in command.
+        if buf.is_empty() {
+            if let Some((_, c)) = custom_commands(bot, message).into_iter().find(|x| x.1.name == text) {
+                buf = format!("`!{}`: {}\n{}", c.name, c.description, c.help_message);
+            }
+        }
+
         if buf.len() == 0 {
             bot.send(message.channel_id,
-                     format!("Could not find the `!{}` command in any of the modules!",
-                             text)
-                             .as_str());
+                     &locale.format("command-not-found", &[("command", &text)]));
         } else {
             bot.send(message.channel_id, buf.as_str());
         }
@@ -227,7 +351,8 @@ impl<'a> module::Module for Module<'a> {
         match id {
             x if x == Commands::Modules as u32 => {
                 "`!modules` - lists all available modules;\n\
-                 `!modules <name>` - gets information about the specified module and lists its commands."
+                 `!modules <name>` - gets information about the specified module and lists its commands;\n\
+                 `!modules load <name>`, `!modules unload <name>` - turns a module on or off until the bot restarts (bot owners only)."
             }
             x if x == Commands::Commands as u32 => "`!commands` - lists all available commands.",
             x if x == Commands::Command as u32 => "`!help <command>` - gets information about the specified command.",
@@ -235,6 +360,11 @@ impl<'a> module::Module for Module<'a> {
         }
     }
 
+    fn rerun_on_edit(&self, id: u32) -> bool {
+        // `!modules` also loads and unloads modules.
+        id != Commands::Modules as u32
+    }
+
     fn handle(&self, bot: &Bot, message: &Message, id: u32, text: &str) {
         match id {
             x if x == Commands::Modules as u32 => self.handle_modules(bot, message, text),
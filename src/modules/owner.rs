@@ -0,0 +1,375 @@
+use bot::Bot;
+use discord::model::*;
+use locale::Locale;
+use module;
+use std::collections::hash_map::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::process;
+use std::time::Duration;
+
+pub struct Module<'a> {
+    commands: HashMap<u32, &'a [&'a str]>,
+}
+
+enum Commands {
+    Status = 0,
+    ReloadConfig = 1,
+    Shutdown = 2,
+    Leave = 3,
+    Servers = 4,
+    Broadcast = 5,
+}
+
+// How many servers `!servers` lists, the biggest ones first.
+const MAX_LISTED_SERVERS: usize = 25;
+
+// `!broadcast` sends a message per server, which takes far longer than the usual command timeout.
+const BROADCAST_TIMEOUT_SECS: u64 = 60 * 60;
+
+// How often `!broadcast` reports its progress in the console, in servers.
+const BROADCAST_PROGRESS_INTERVAL: usize = 100;
+
+// Returns whether the user is one of the bot owners, listed in `owners` in the config.
+pub fn is_owner(user: UserId) -> bool {
+    let conf = ::CONF.read().unwrap();
+
+    let owners = match conf.pointer("/owners").and_then(|x| x.as_array()) {
+        Some(owners) => owners,
+        None => return false,
+    };
+
+    // The IDs can be numbers or strings, since the bigger ones don't survive every JSON parser.
+    owners.iter()
+        .filter_map(|x| x.as_u64().or_else(|| x.as_str().and_then(|x| x.parse().ok())))
+        .any(|x| x == user.0)
+}
+
+// Checks that the author of the message is a bot owner, replying if they aren't.
+pub fn check_owner(bot: &Bot, message: &Message, locale: &Locale) -> bool {
+    if is_owner(message.author.id) {
+        true
+    } else {
+        bot.send(message.channel_id, &locale.get("owner-required"));
+        false
+    }
+}
+
+// Formats a duration like `3d 4h 5m`.
+fn format_uptime(seconds: u64) -> String {
+    let days = seconds / 86400;
+    let hours = seconds % 86400 / 3600;
+    let minutes = seconds % 3600 / 60;
+
+    if days > 0 {
+        format!("{}d {}h {}m", days, hours, minutes)
+    } else if hours > 0 {
+        format!("{}h {}m", hours, minutes)
+    } else {
+        format!("{}m {}s", minutes, seconds % 60)
+    }
+}
+
+// The number of threads of the bot process, if the OS tells.
+fn thread_count() -> Option<u64> {
+    let mut status = String::new();
+    if File::open("/proc/self/status").and_then(|mut x| x.read_to_string(&mut status)).is_err() {
+        return None;
+    }
+
+    status.lines()
+        .find(|x| x.starts_with("Threads:"))
+        .and_then(|x| x["Threads:".len()..].trim().parse().ok())
+}
+
+// The channel broadcasts go to: the one Discord used to create along with the server,
+// or the topmost text channel if it's gone.
+fn broadcast_channel(server: &LiveServer) -> Option<ChannelId> {
+    let text_channels = server.channels.iter().filter(|x| x.kind == ChannelType::Text);
+
+    if let Some(channel) = text_channels.clone().find(|x| x.id.0 == server.id.0) {
+        return Some(channel.id);
+    }
+
+    text_channels.min_by_key(|x| x.position).map(|x| x.id)
+}
+
+impl<'a> Module<'a> {
+    fn handle_status(&self, bot: &Bot, message: &Message, locale: &Locale) {
+        let servers = bot.get_states()
+            .iter()
+            .map(|x| x.read().unwrap().servers().len())
+            .sum::<usize>();
+
+        let mut loaded = Vec::new();
+        let mut unloaded = Vec::new();
+
+        for (i, m) in bot.get_modules().iter().enumerate() {
+            if bot.is_module_enabled(i) {
+                loaded.push(m.name());
+            } else {
+                unloaded.push(m.name());
+            }
+        }
+
+        let threads = match thread_count() {
+            Some(x) => x.to_string(),
+            None => locale.get("owner-status-unknown"),
+        };
+
+        let cache = ::HTTP.get_cache().stats();
+
+        let mut buf = locale.format("owner-status",
+                                    &[("version", &env!("CARGO_PKG_VERSION")),
+                                      ("uptime", &format_uptime(bot.get_uptime().as_secs())),
+                                      ("servers", &servers),
+                                      ("shards", &bot.get_shard_count()),
+                                      ("threads", &threads),
+                                      ("modules", &loaded.join(", ")),
+                                      ("cache-hits", &cache.hits),
+                                      ("cache-misses", &cache.misses),
+                                      ("cache-entries", &cache.entries),
+                                      ("cache-size", &(cache.size / 1024))]);
+
+        if !unloaded.is_empty() {
+            buf.push('\n');
+            buf.push_str(&locale.format("owner-status-unloaded", &[("modules", &unloaded.join(", "))]));
+        }
+
+        bot.send(message.channel_id, &buf);
+    }
+
+    fn handle_reload_config(&self, bot: &Bot, message: &Message, locale: &Locale) {
+        match ::reload_config() {
+            Ok(()) => {
+                println!("[Info] `{}` reloaded the config.", message.author.name);
+                bot.send(message.channel_id, &locale.get("owner-config-reloaded"));
+            }
+            Err(err) => {
+                bot.send(message.channel_id,
+                         &locale.format("owner-config-error", &[("error", &err)]));
+            }
+        }
+    }
+
+    fn handle_shutdown(&self, bot: &Bot, message: &Message, locale: &Locale) {
+        println!("[Info] `{}` shut the bot down.", message.author.name);
+        bot.send(message.channel_id, &locale.get("owner-shutdown"));
+        process::exit(0);
+    }
+
+    fn handle_leave(&self, bot: &Bot, message: &Message, text: &str, locale: &Locale) {
+        // Without an ID, leaves the server the command was sent on.
+        let server = match text.trim() {
+            "" => bot.get_server_for_channel(message.channel_id),
+            id => id.parse().ok().map(ServerId),
+        };
+
+        let server = match server {
+            Some(server) => server,
+            None => {
+                bot.send(message.channel_id,
+                         &locale.command_help_message(self, Commands::Leave as u32));
+                return;
+            }
+        };
+
+        let name = bot.get_state_for_server(server)
+            .read()
+            .unwrap()
+            .servers()
+            .iter()
+            .find(|x| x.id == server)
+            .map(|x| x.name.clone());
+
+        let name = match name {
+            Some(name) => name,
+            None => {
+                bot.send(message.channel_id,
+                         &locale.format("owner-unknown-server", &[("server", &server.0)]));
+                return;
+            }
+        };
+
+        // Reply first, the reply can't be sent after leaving the server it was sent on.
+        bot.send(message.channel_id, &locale.format("owner-leaving", &[("server", &name)]));
+
+        if bot.leave_server(server).is_ok() {
+            println!("[Info] `{}` made the bot leave `{}`.", message.author.name, name);
+        }
+    }
+
+    fn handle_servers(&self, bot: &Bot, message: &Message, locale: &Locale) {
+        let mut servers = Vec::new();
+
+        for state in bot.get_states() {
+            for server in state.read().unwrap().servers() {
+                servers.push((server.member_count, server.id, server.name.clone()));
+            }
+        }
+
+        servers.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.2.cmp(&b.2)));
+
+        let mut buf = locale.format("owner-servers", &[("count", &servers.len())]);
+
+        for &(members, id, ref name) in servers.iter().take(MAX_LISTED_SERVERS) {
+            buf.push('\n');
+            buf.push_str(&locale.format("owner-server-entry",
+                                        &[("name", name), ("id", &id.0), ("members", &members)]));
+        }
+
+        if servers.len() > MAX_LISTED_SERVERS {
+            buf.push('\n');
+            buf.push_str(&locale.format("owner-servers-more", &[("count", &(servers.len() - MAX_LISTED_SERVERS))]));
+        }
+
+        bot.send(message.channel_id, &buf);
+    }
+
+    fn handle_broadcast(&self, bot: &Bot, message: &Message, text: &str, locale: &Locale) {
+        let text = text.trim();
+
+        if text.is_empty() {
+            bot.send(message.channel_id,
+                     &locale.command_help_message(self, Commands::Broadcast as u32));
+            return;
+        }
+
+        // Collected first so that the state isn't locked while sending.
+        let mut channels = Vec::new();
+        let mut skipped = 0;
+
+        for state in bot.get_states() {
+            for server in state.read().unwrap().servers() {
+                match broadcast_channel(server) {
+                    Some(channel) => channels.push(channel),
+                    None => skipped += 1,
+                }
+            }
+        }
+
+        let total = channels.len();
+        bot.send(message.channel_id,
+                 &locale.format("owner-broadcast-started", &[("count", &total)]));
+
+        let mut sent = 0;
+
+        for (i, channel) in channels.into_iter().enumerate() {
+            if bot.cancellation_token().is_cancelled() {
+                println!("[Warning] Stopped broadcasting the message after {} of {} servers.", i, total);
+                break;
+            }
+
+            if bot.send_and_get(channel, text).is_some() {
+                sent += 1;
+            } else {
+                skipped += 1;
+            }
+
+            if (i + 1) % BROADCAST_PROGRESS_INTERVAL == 0 {
+                println!("[Info] Broadcasting the message: {} of {} servers done.", i + 1, total);
+            }
+        }
+
+        println!("[Info] `{}` broadcast a message to {} servers.", message.author.name, sent);
+
+        bot.send(message.channel_id,
+                 &locale.format("owner-broadcast-done", &[("sent", &sent), ("failed", &skipped)]));
+    }
+}
+
+impl<'a> module::Module for Module<'a> {
+    fn new() -> Result<Box<module::Module>, String> {
+        let mut map: HashMap<u32, &[&str]> = HashMap::new();
+        static STATUS: [&'static str; 1] = ["status"];
+        map.insert(Commands::Status as u32, &STATUS);
+        static RELOAD_CONFIG: [&'static str; 1] = ["reload-config"];
+        map.insert(Commands::ReloadConfig as u32, &RELOAD_CONFIG);
+        static SHUTDOWN: [&'static str; 1] = ["shutdown"];
+        map.insert(Commands::Shutdown as u32, &SHUTDOWN);
+        static LEAVE: [&'static str; 1] = ["leave"];
+        map.insert(Commands::Leave as u32, &LEAVE);
+        static SERVERS: [&'static str; 1] = ["servers"];
+        map.insert(Commands::Servers as u32, &SERVERS);
+        static BROADCAST: [&'static str; 1] = ["broadcast"];
+        map.insert(Commands::Broadcast as u32, &BROADCAST);
+        Ok(Box::new(Module { commands: map }))
+    }
+
+    fn name(&self) -> &'static str {
+        "Owner"
+    }
+
+    fn description(&self) -> &'static str {
+        "Commands for the owners of the bot."
+    }
+
+    fn commands(&self) -> &HashMap<u32, &[&str]> {
+        &self.commands
+    }
+
+    fn command_description(&self, id: u32) -> &'static str {
+        match id {
+            x if x == Commands::Status as u32 => "Shows how the bot is doing.",
+            x if x == Commands::ReloadConfig as u32 => "Reads the config again.",
+            x if x == Commands::Shutdown as u32 => "Stops the bot.",
+            x if x == Commands::Leave as u32 => "Makes the bot leave a server.",
+            x if x == Commands::Servers as u32 => "Lists the servers the bot is on.",
+            x if x == Commands::Broadcast as u32 => "Sends a message to every server.",
+            _ => panic!("Owner::command_description - invalid id."),
+        }
+    }
+
+    fn command_help_message(&self, id: u32) -> &'static str {
+        match id {
+            x if x == Commands::Status as u32 => "`!status` - Shows the uptime, the number of servers and threads, the loaded modules and how well the cache works.",
+            x if x == Commands::ReloadConfig as u32 => "`!reload-config` - Reads bot.json again. Some settings, like the API keys, still need a restart.",
+            x if x == Commands::Shutdown as u32 => "`!shutdown` - Stops the bot.",
+            x if x == Commands::Leave as u32 => "`!leave [server ID]` - Makes the bot leave the given server, or the current one.",
+            x if x == Commands::Servers as u32 => "`!servers` - Lists the biggest servers the bot is on.",
+            x if x == Commands::Broadcast as u32 => "`!broadcast <message>` - Sends the message to every server the bot is on. This can take a while, it stops after an hour.",
+            _ => panic!("Owner::command_help_message - invalid id."),
+        }
+    }
+
+    fn rerun_on_edit(&self, id: u32) -> bool {
+        id == Commands::Status as u32 || id == Commands::Servers as u32
+    }
+
+    fn command_timeout(&self, id: u32) -> Option<Duration> {
+        match id {
+            x if x == Commands::Broadcast as u32 => Some(Duration::from_secs(BROADCAST_TIMEOUT_SECS)),
+            _ => None,
+        }
+    }
+
+    fn handle(&self, bot: &Bot, message: &Message, id: u32, text: &str) {
+        let locale = bot.get_locale(message);
+
+        if !check_owner(bot, message, &locale) {
+            return;
+        }
+
+        match id {
+            x if x == Commands::Status as u32 => self.handle_status(bot, message, &locale),
+            x if x == Commands::ReloadConfig as u32 => self.handle_reload_config(bot, message, &locale),
+            x if x == Commands::Shutdown as u32 => self.handle_shutdown(bot, message, &locale),
+            x if x == Commands::Leave as u32 => self.handle_leave(bot, message, text, &locale),
+            x if x == Commands::Servers as u32 => self.handle_servers(bot, message, &locale),
+            x if x == Commands::Broadcast as u32 => self.handle_broadcast(bot, message, text, &locale),
+            _ => panic!("Owner::handle - invalid id."),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format_uptime;
+
+    #[test]
+    fn uptime() {
+        assert_eq!("0m 42s", format_uptime(42));
+        assert_eq!("2h 0m", format_uptime(2 * 3600 + 30));
+        assert_eq!("3d 4h 5m", format_uptime(3 * 86400 + 4 * 3600 + 5 * 60 + 6));
+    }
+}
@@ -0,0 +1,574 @@
+use bot::Bot;
+use discord::ChannelRef;
+use discord::model::*;
+use discord::model::permissions;
+use locale::Locale;
+use module::{self, CommandScope};
+use modules::admin;
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::collections::hash_map::HashMap;
+use storage::Scope;
+
+// The name of the storage the self-assignable roles are kept in.
+const STORAGE_NAME: &'static str = "roles";
+
+const MAX_GROUP_NAME_LENGTH: usize = 32;
+const MAX_LIMIT: u64 = 100;
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+struct SelfRole {
+    // Members can only have one role of a group at a time.
+    group: Option<String>,
+}
+
+pub struct Module<'a> {
+    commands: HashMap<u32, &'a [&'a str]>,
+}
+
+enum Commands {
+    SelfRole = 0,
+    Iam = 1,
+    IamNot = 2,
+    Roles = 3,
+}
+
+lazy_static! {
+	static ref SELF_ROLE_REGEX: Regex = Regex::new(r"(?i)^\s*(add|remove|limit)\b\s*(.*?)\s*$").unwrap();
+	static ref GROUP_REGEX: Regex = Regex::new(r"(?i)\bgroup\s+(\S+)").unwrap();
+	static ref GROUP_NAME_REGEX: Regex = Regex::new(r"^[a-z0-9_-]+$").unwrap();
+}
+
+// Permissions which would let whoever takes the role moderate or run the server.
+fn elevated_permissions() -> Permissions {
+    permissions::ADMINISTRATOR | permissions::MANAGE_SERVER | permissions::MANAGE_ROLES |
+    permissions::MANAGE_CHANNELS | permissions::MANAGE_WEBHOOKS | permissions::KICK_MEMBERS |
+    permissions::BAN_MEMBERS | permissions::MANAGE_MESSAGES | permissions::MANAGE_NICKNAMES |
+    permissions::MENTION_EVERYONE
+}
+
+// Why the role can't be made self-assignable, as a locale key, or None if it can.
+// The positions are of the top roles of whoever wants that and of the bot, None for the server owner.
+fn self_role_refusal(role: &Role, actor_position: Option<i64>, bot_position: Option<i64>) -> Option<&'static str> {
+    if role.permissions.intersects(elevated_permissions()) {
+        Some("roles-elevated")
+    } else if actor_position.iter().any(|&x| x <= role.position) {
+        Some("roles-above-you")
+    } else if bot_position.iter().any(|&x| x <= role.position) {
+        Some("roles-above-bot")
+    } else {
+        None
+    }
+}
+
+fn get_self_roles(server: ServerId) -> BTreeMap<u64, SelfRole> {
+    match ::STORAGE.get(STORAGE_NAME, Scope::Server(server), "self_roles") {
+        Ok(roles) => roles.unwrap_or_default(),
+        Err(err) => {
+            println!("[Warning] Failed to get the self-assignable roles: {}", err);
+            BTreeMap::new()
+        }
+    }
+}
+
+fn get_limit(server: ServerId) -> Option<u64> {
+    match ::STORAGE.get(STORAGE_NAME, Scope::Server(server), "limit") {
+        Ok(limit) => limit,
+        Err(err) => {
+            println!("[Warning] Failed to get the self-assignable role limit: {}", err);
+            None
+        }
+    }
+}
+
+// Gets the lowercase group name after `group`, if any. An invalid name is returned as the error.
+fn parse_group(text: &str) -> Result<Option<String>, String> {
+    match GROUP_REGEX.captures(text) {
+        Some(caps) => {
+            let name = caps[1].to_lowercase();
+
+            if name.len() <= MAX_GROUP_NAME_LENGTH && GROUP_NAME_REGEX.is_match(&name) {
+                Ok(Some(name))
+            } else {
+                Err(name)
+            }
+        }
+        None => Ok(None),
+    }
+}
+
+// The roles the member ends up with after taking the role,
+// and the ones taken away for it because they're in the same group.
+fn assign(current: &[RoleId], self_roles: &BTreeMap<u64, SelfRole>, role: RoleId) -> (Vec<RoleId>, Vec<RoleId>) {
+    let group_of = |role: &RoleId| self_roles.get(&role.0).and_then(|x| x.group.as_ref());
+    let group = group_of(&role);
+
+    let (removed, mut roles): (Vec<RoleId>, Vec<RoleId>) =
+        current.iter().cloned().partition(|x| *x != role && group.is_some() && group_of(x) == group);
+
+    if !roles.contains(&role) {
+        roles.push(role);
+    }
+
+    (roles, removed)
+}
+
+fn count_self_roles(roles: &[RoleId], self_roles: &BTreeMap<u64, SelfRole>) -> usize {
+    roles.iter().filter(|x| self_roles.contains_key(&x.0)).count()
+}
+
+// Finds the self-assignable role mentioned or named in the text.
+fn find_role(bot: &Bot, message: &Message, self_roles: &BTreeMap<u64, SelfRole>, text: &str) -> Option<Role> {
+    let name = text.trim().trim_start_matches('@').to_lowercase();
+    let state = bot.get_state_for_channel(message.channel_id).read().unwrap();
+
+    let server = match state.find_channel(message.channel_id) {
+        Some(ChannelRef::Public(server, _)) => server,
+        _ => return None,
+    };
+
+    server.roles
+        .iter()
+        .filter(|x| self_roles.contains_key(&x.id.0))
+        .find(|x| message.mention_roles.contains(&x.id) || x.name.to_lowercase() == name)
+        .cloned()
+}
+
+// Makes sure that the roles can be self-assignable without letting anyone get more than they should.
+// Err has the reply.
+fn check_new_self_roles(bot: &Bot,
+                        message: &Message,
+                        server: ServerId,
+                        roles: &[RoleId],
+                        locale: &Locale)
+                        -> Result<(), String> {
+    let (server_roles, owner, bot_user) = {
+        let state = bot.get_state_for_channel(message.channel_id).read().unwrap();
+
+        match state.find_channel(message.channel_id) {
+            Some(ChannelRef::Public(server, _)) => (server.roles.clone(), server.owner_id, state.user().id),
+            _ => return Err(locale.get("channel-info-error")),
+        }
+    };
+
+    // The owner is above everyone.
+    let position = |user: UserId| if user == owner {
+        Ok(None)
+    } else {
+        bot.get_member(server, user)
+            .map(|x| Some(admin::top_role_position(&server_roles, &x.roles)))
+            .map_err(|_| locale.get("admin-member-info-error"))
+    };
+
+    let actor_position = try!(position(message.author.id));
+    let bot_position = try!(position(bot_user));
+
+    for role in server_roles.iter().filter(|x| roles.contains(&x.id)) {
+        if let Some(key) = self_role_refusal(role, actor_position, bot_position) {
+            return Err(locale.format(key, &[("role", &role.name)]));
+        }
+    }
+
+    Ok(())
+}
+
+fn role_names(bot: &Bot, channel: ChannelId, roles: &[RoleId]) -> String {
+    let state = bot.get_state_for_channel(channel).read().unwrap();
+
+    match state.find_channel(channel) {
+        Some(ChannelRef::Public(server, _)) => admin::role_names(server, roles),
+        _ => roles.iter().map(|x| x.0.to_string()).collect::<Vec<String>>().join(", "),
+    }
+}
+
+impl<'a> module::Module for Module<'a> {
+    fn new() -> Result<Box<module::Module>, String> {
+        let mut map: HashMap<u32, &[&str]> = HashMap::new();
+        static SELF_ROLE: [&'static str; 1] = ["selfrole"];
+        map.insert(Commands::SelfRole as u32, &SELF_ROLE);
+        static IAM: [&'static str; 1] = ["iam"];
+        map.insert(Commands::Iam as u32, &IAM);
+        static IAM_NOT: [&'static str; 1] = ["iamnot"];
+        map.insert(Commands::IamNot as u32, &IAM_NOT);
+        static ROLES: [&'static str; 1] = ["roles"];
+        map.insert(Commands::Roles as u32, &ROLES);
+        Ok(Box::new(Module { commands: map }))
+    }
+
+    fn name(&self) -> &'static str {
+        "Roles"
+    }
+
+    fn description(&self) -> &'static str {
+        "Roles the members can give themselves."
+    }
+
+    fn commands(&self) -> &HashMap<u32, &[&str]> {
+        &self.commands
+    }
+
+    fn command_description(&self, id: u32) -> &'static str {
+        match id {
+            x if x == Commands::SelfRole as u32 => "Manage the self-assignable roles.",
+            x if x == Commands::Iam as u32 => "Gives you a self-assignable role.",
+            x if x == Commands::IamNot as u32 => "Takes a self-assignable role from you.",
+            x if x == Commands::Roles as u32 => "Lists the self-assignable roles.",
+            _ => panic!("Roles::command_description - invalid id."),
+        }
+    }
+
+    fn command_help_message(&self, id: u32) -> &'static str {
+        match id {
+            x if x == Commands::SelfRole as u32 => {
+                "`!selfrole add <role mentions> [group <name>]` - Lets the members give themselves the roles. \
+                 Members can only have one role of a group, taking another one replaces it. \
+                 The roles have to be below your highest role and the bot's, and can't have moderation permissions.\n\
+                 `!selfrole remove <role mentions>` - Stops the roles from being self-assignable.\n\
+                 `!selfrole limit <number>|off` - Sets how many self-assignable roles a member can have.\n\
+                 (admin only)"
+            }
+            x if x == Commands::Iam as u32 => "`!iam <role>` - Gives you the self-assignable role.",
+            x if x == Commands::IamNot as u32 => "`!iamnot <role>` - Takes the self-assignable role from you.",
+            x if x == Commands::Roles as u32 => "`!roles` - Lists the roles you can give yourself with `!iam`.",
+            _ => panic!("Roles::command_help_message - invalid id."),
+        }
+    }
+
+    fn rerun_on_edit(&self, id: u32) -> bool {
+        id == Commands::Roles as u32
+    }
+
+    fn command_scope(&self, _id: u32) -> CommandScope {
+        CommandScope::servers_only()
+    }
+
+    fn handle(&self, bot: &Bot, message: &Message, id: u32, text: &str) {
+        let locale = bot.get_locale(message);
+
+        let server = match bot.get_server_for_channel(message.channel_id) {
+            Some(server) => server,
+            None => {
+                bot.send(message.channel_id, &locale.get("channel-info-error"));
+                return;
+            }
+        };
+
+        match id {
+            x if x == Commands::SelfRole as u32 => {
+                if admin::check_admin(bot, message, &locale) {
+                    self.handle_self_role(bot, message, &locale, server, text);
+                }
+            }
+            x if x == Commands::Iam as u32 => self.handle_iam(bot, message, &locale, server, text, true),
+            x if x == Commands::IamNot as u32 => self.handle_iam(bot, message, &locale, server, text, false),
+            x if x == Commands::Roles as u32 => self.handle_roles(bot, message, &locale, server),
+            _ => panic!("Roles::handle - invalid id."),
+        }
+    }
+}
+
+impl<'a> Module<'a> {
+    fn handle_self_role(&self, bot: &Bot, message: &Message, locale: &Locale, server: ServerId, text: &str) {
+        let caps = match SELF_ROLE_REGEX.captures(text) {
+            Some(caps) => caps,
+            None => {
+                bot.send(message.channel_id,
+                         &locale.command_help_message(self, Commands::SelfRole as u32));
+                return;
+            }
+        };
+
+        let action = caps[1].to_lowercase();
+        let args = &caps[2];
+
+        let (result, reply) = if action == "limit" {
+            let limit = match args.to_lowercase().as_str() {
+                "off" => None,
+                x => {
+                    match x.parse::<u64>() {
+                        Ok(limit) if (1..=MAX_LIMIT).contains(&limit) => Some(limit),
+                        _ => {
+                            bot.send(message.channel_id,
+                                     &locale.format("roles-invalid-limit", &[("max", &MAX_LIMIT)]));
+                            return;
+                        }
+                    }
+                }
+            };
+
+            match limit {
+                Some(limit) => {
+                    (::STORAGE.set(STORAGE_NAME, Scope::Server(server), "limit", &limit),
+                     locale.format("roles-limit-set", &[("limit", &limit)]))
+                }
+                None => {
+                    (::STORAGE.remove(STORAGE_NAME, Scope::Server(server), "limit"),
+                     locale.get("roles-limit-off"))
+                }
+            }
+        } else {
+            // Everyone has @everyone already.
+            let roles = message.mention_roles.iter().cloned().filter(|x| x.0 != server.0).collect::<Vec<_>>();
+
+            if roles.is_empty() {
+                bot.send(message.channel_id, &locale.get("admin-no-role-mentions"));
+                return;
+            }
+
+            let names = role_names(bot, message.channel_id, &roles);
+
+            if action == "add" {
+                let group = match parse_group(args) {
+                    Ok(group) => group,
+                    Err(_) => {
+                        bot.send(message.channel_id,
+                                 &locale.format("roles-invalid-group", &[("max", &MAX_GROUP_NAME_LENGTH)]));
+                        return;
+                    }
+                };
+
+                if let Err(reply) = check_new_self_roles(bot, message, server, &roles, locale) {
+                    bot.send(message.channel_id, &reply);
+                    return;
+                }
+
+                let result = ::STORAGE.update(STORAGE_NAME,
+                                              Scope::Server(server),
+                                              "self_roles",
+                                              |self_roles: Option<BTreeMap<u64, SelfRole>>| {
+                    let mut self_roles = self_roles.unwrap_or_default();
+
+                    for role in &roles {
+                        self_roles.insert(role.0, SelfRole { group: group.clone() });
+                    }
+
+                    Some(self_roles)
+                });
+
+                let reply = match group {
+                    Some(ref group) => {
+                        locale.format("roles-added-to-group", &[("roles", &names), ("group", group)])
+                    }
+                    None => locale.format("roles-added", &[("roles", &names)]),
+                };
+
+                (result, reply)
+            } else {
+                let result = ::STORAGE.update(STORAGE_NAME,
+                                              Scope::Server(server),
+                                              "self_roles",
+                                              |self_roles: Option<BTreeMap<u64, SelfRole>>| {
+                    let mut self_roles = self_roles.unwrap_or_default();
+
+                    for role in &roles {
+                        self_roles.remove(&role.0);
+                    }
+
+                    Some(self_roles)
+                });
+
+                (result, locale.format("roles-removed", &[("roles", &names)]))
+            }
+        };
+
+        match result {
+            Ok(()) => {
+                admin::audit(bot, server, message, "!selfrole", text, &Ok(reply.clone()));
+                bot.send(message.channel_id, &reply);
+            }
+            Err(err) => {
+                admin::audit(bot, server, message, "!selfrole", text, &Err(err.to_string()));
+                bot.send(message.channel_id,
+                         &locale.format("storage-error", &[("error", &err)]));
+            }
+        }
+    }
+
+    fn handle_iam(&self, bot: &Bot, message: &Message, locale: &Locale, server: ServerId, text: &str, give: bool) {
+        let self_roles = get_self_roles(server);
+
+        if self_roles.is_empty() {
+            bot.send(message.channel_id, &locale.get("roles-none"));
+            return;
+        }
+
+        let (role, name) = match find_role(bot, message, &self_roles, text) {
+            // The permissions could have been given to the role after it was made self-assignable.
+            Some(ref role) if give && role.permissions.intersects(elevated_permissions()) => {
+                bot.send(message.channel_id,
+                         &locale.format("roles-elevated", &[("role", &role.name)]));
+                return;
+            }
+            Some(role) => (role.id, role.name),
+            None => {
+                bot.send(message.channel_id,
+                         &locale.format("roles-unknown", &[("role", &text.trim())]));
+                return;
+            }
+        };
+
+        let member = match bot.get_member(server, message.author.id) {
+            Ok(member) => member,
+            Err(_) => {
+                bot.send(message.channel_id, &locale.get("admin-member-info-error"));
+                return;
+            }
+        };
+
+        let has_role = member.roles.contains(&role);
+
+        let (roles, removed) = if give {
+            if has_role {
+                bot.send(message.channel_id,
+                         &locale.format("roles-already-have", &[("role", &name)]));
+                return;
+            }
+
+            let (roles, removed) = assign(&member.roles, &self_roles, role);
+
+            if let Some(limit) = get_limit(server) {
+                let count = count_self_roles(&roles, &self_roles);
+
+                // Swapping roles is fine even if the limit was lowered below what the member has.
+                if count as u64 > limit && count > count_self_roles(&member.roles, &self_roles) {
+                    bot.send(message.channel_id,
+                             &locale.format("roles-limit-reached", &[("limit", &limit)]));
+                    return;
+                }
+            }
+
+            (roles, removed)
+        } else {
+            if !has_role {
+                bot.send(message.channel_id,
+                         &locale.format("roles-dont-have", &[("role", &name)]));
+                return;
+            }
+
+            (member.roles.iter().cloned().filter(|x| *x != role).collect(), Vec::new())
+        };
+
+        if let Err(err) = bot.set_member_roles(server, message.author.id, &roles) {
+            bot.send(message.channel_id, &locale.format("roles-error", &[("error", &err)]));
+            return;
+        }
+
+        let reply = if !give {
+            locale.format("roles-taken", &[("role", &name)])
+        } else if removed.is_empty() {
+            locale.format("roles-given", &[("role", &name)])
+        } else {
+            let removed = role_names(bot, message.channel_id, &removed);
+            locale.format("roles-given-instead", &[("role", &name), ("removed", &removed)])
+        };
+
+        bot.send(message.channel_id, &reply);
+    }
+
+    fn handle_roles(&self, bot: &Bot, message: &Message, locale: &Locale, server: ServerId) {
+        let self_roles = get_self_roles(server);
+
+        // The names of the roles by group, skipping the ones deleted from the server.
+        let mut groups: BTreeMap<Option<String>, Vec<String>> = BTreeMap::new();
+
+        {
+            let state = bot.get_state_for_channel(message.channel_id).read().unwrap();
+
+            if let Some(ChannelRef::Public(live_server, _)) = state.find_channel(message.channel_id) {
+                for role in live_server.roles.iter().filter(|x| self_roles.contains_key(&x.id.0)) {
+                    groups.entry(self_roles[&role.id.0].group.clone())
+                        .or_default()
+                        .push(format!("`{}`", role.name));
+                }
+            }
+        }
+
+        if groups.is_empty() {
+            bot.send(message.channel_id, &locale.get("roles-none"));
+            return;
+        }
+
+        let mut buf = locale.get("roles-list");
+
+        for (group, mut names) in groups {
+            names.sort_by_key(|x| x.to_lowercase());
+            let names = names.join(", ");
+
+            buf.push_str("\n- ");
+            buf.push_str(&match group {
+                             Some(group) => locale.format("roles-group", &[("group", &group), ("roles", &names)]),
+                             None => names,
+                         });
+        }
+
+        if let Some(limit) = get_limit(server) {
+            buf.push('\n');
+            buf.push_str(&locale.format("roles-limit", &[("limit", &limit)]));
+        }
+
+        bot.send(message.channel_id, &buf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn self_roles() -> BTreeMap<u64, SelfRole> {
+        let mut self_roles = BTreeMap::new();
+        self_roles.insert(1, SelfRole { group: None });
+        self_roles.insert(2, SelfRole { group: Some("platform".to_owned()) });
+        self_roles.insert(3, SelfRole { group: Some("platform".to_owned()) });
+        self_roles.insert(4, SelfRole { group: Some("region".to_owned()) });
+        self_roles
+    }
+
+    #[test]
+    fn parse_group_test() {
+        assert_eq!(parse_group("<@&1> <@&2>"), Ok(None));
+        assert_eq!(parse_group("<@&1> group Platform"), Ok(Some("platform".to_owned())));
+        assert_eq!(parse_group("<@&1> group pc/console"), Err("pc/console".to_owned()));
+        assert!(parse_group(&format!("<@&1> group {}", "a".repeat(33))).is_err());
+    }
+
+    #[test]
+    fn assign_test() {
+        let self_roles = self_roles();
+
+        // 10 isn't self-assignable and is always kept.
+        assert_eq!(assign(&[RoleId(10), RoleId(2)], &self_roles, RoleId(1)),
+                   (vec![RoleId(10), RoleId(2), RoleId(1)], vec![]));
+        assert_eq!(assign(&[RoleId(10), RoleId(2), RoleId(4)], &self_roles, RoleId(3)),
+                   (vec![RoleId(10), RoleId(4), RoleId(3)], vec![RoleId(2)]));
+        assert_eq!(assign(&[RoleId(2)], &self_roles, RoleId(2)), (vec![RoleId(2)], vec![]));
+
+        assert_eq!(count_self_roles(&[RoleId(10), RoleId(1), RoleId(4)], &self_roles), 2);
+    }
+
+    #[test]
+    fn self_role_refusal_test() {
+        let role = |position, permissions| {
+            Role {
+                id: RoleId(1),
+                name: "role".to_owned(),
+                color: 0,
+                hoist: false,
+                managed: false,
+                position: position,
+                mentionable: false,
+                permissions: permissions,
+            }
+        };
+
+        let plain = role(5, permissions::SEND_MESSAGES);
+        assert_eq!(self_role_refusal(&plain, Some(6), Some(7)), None);
+        // The owner has no top role to be below of.
+        assert_eq!(self_role_refusal(&plain, None, Some(6)), None);
+        assert_eq!(self_role_refusal(&plain, Some(5), Some(7)), Some("roles-above-you"));
+        assert_eq!(self_role_refusal(&plain, Some(7), Some(4)), Some("roles-above-bot"));
+
+        for &permissions in &[permissions::ADMINISTRATOR, permissions::MANAGE_SERVER, permissions::BAN_MEMBERS] {
+            assert_eq!(self_role_refusal(&role(1, permissions), None, None), Some("roles-elevated"));
+        }
+    }
+}
@@ -0,0 +1,475 @@
+use bot::Bot;
+use discord::model::*;
+use locale::Locale;
+use module::{self, CommandOutput, CommandScope, CustomCommand};
+use modules::admin;
+use rand;
+use rand::distributions::{IndependentSample, Range};
+use script::{self, Host, Limits, Value};
+use serde_json;
+use std::collections::BTreeMap;
+use std::collections::hash_map::HashMap;
+use std::time::Duration;
+use storage::Scope;
+
+// The name of the storage the scripts are kept in.
+const STORAGE_NAME: &'static str = "scripts";
+
+const MAX_SCRIPTS_PER_SERVER: usize = 50;
+const MAX_NAME_LENGTH: usize = 32;
+const MAX_CODE_LENGTH: usize = 1500;
+// The maximum size of the data one script can keep with `set()`, serialized.
+const MAX_DATA_SIZE: usize = 4096;
+// The maximum number of messages one run can send.
+const MAX_REPLIES: usize = 5;
+
+const DEFAULT_DESCRIPTION: &'static str = "A custom script.";
+
+lazy_static! {
+	static ref LIMITS: Limits = Limits {
+		steps: 100_000,
+		memory: 1 << 20,
+		time: Duration::from_secs(1),
+	};
+}
+
+#[derive(Serialize, Deserialize)]
+struct Script {
+    code: String,
+    description: String,
+    // Who last changed the script.
+    author: u64,
+}
+
+pub struct Module<'a> {
+    commands: HashMap<u32, &'a [&'a str]>,
+}
+
+enum Commands {
+    Script = 0,
+}
+
+// Lets a script reply and keep its data.
+struct ScriptHost {
+    replies: Vec<String>,
+    data: serde_json::Map<String, serde_json::Value>,
+    data_changed: bool,
+}
+
+impl Host for ScriptHost {
+    fn reply(&mut self, text: String) -> Result<(), String> {
+        if self.replies.len() == MAX_REPLIES {
+            return Err(format!("a script can reply at most {} times", MAX_REPLIES));
+        }
+
+        self.replies.push(text);
+        Ok(())
+    }
+
+    fn random(&mut self, low: i64, high: i64) -> i64 {
+        if low == high {
+            return low;
+        }
+
+        let mut rng = rand::thread_rng();
+        Range::new(low, high.saturating_add(1)).ind_sample(&mut rng)
+    }
+
+    fn get(&mut self, key: &str) -> Result<Value, String> {
+        Ok(self.data.get(key).map_or(Value::Nil, from_json))
+    }
+
+    fn set(&mut self, key: &str, value: Value) -> Result<(), String> {
+        match value {
+            Value::Nil => self.data.remove(key),
+            value => self.data.insert(key.to_owned(), to_json(&value)),
+        };
+
+        if serde_json::to_string(&self.data).map(|x| x.len()).unwrap_or(0) > MAX_DATA_SIZE {
+            return Err(format!("a script can keep at most {} bytes of data", MAX_DATA_SIZE));
+        }
+
+        self.data_changed = true;
+        Ok(())
+    }
+}
+
+fn to_json(value: &Value) -> serde_json::Value {
+    match *value {
+        Value::Nil => serde_json::Value::Null,
+        Value::Bool(x) => serde_json::Value::Bool(x),
+        Value::Int(x) => serde_json::Value::from(x),
+        Value::Str(ref x) => serde_json::Value::String(x.clone()),
+        Value::List(ref x) => serde_json::Value::Array(x.iter().map(to_json).collect()),
+    }
+}
+
+fn from_json(value: &serde_json::Value) -> Value {
+    match *value {
+        serde_json::Value::Bool(x) => Value::Bool(x),
+        serde_json::Value::Number(ref x) => x.as_i64().map_or(Value::Nil, Value::Int),
+        serde_json::Value::String(ref x) => Value::Str(x.clone()),
+        serde_json::Value::Array(ref x) => Value::List(x.iter().map(from_json).collect()),
+        _ => Value::Nil,
+    }
+}
+
+fn data_key(name: &str) -> String {
+    format!("data:{}", name)
+}
+
+fn get_scripts(server: ServerId) -> BTreeMap<String, Script> {
+    match ::STORAGE.get(STORAGE_NAME, Scope::Server(server), "scripts") {
+        Ok(scripts) => scripts.unwrap_or_default(),
+        Err(err) => {
+            println!("[Warning] Failed to get the scripts: {}", err);
+            BTreeMap::new()
+        }
+    }
+}
+
+// Removes the code block markers around the code, if any.
+fn strip_code_block(code: &str) -> &str {
+    let code = code.trim();
+
+    if code.len() >= 6 && code.starts_with("```") && code.ends_with("```") {
+        code[3..code.len() - 3].trim()
+    } else {
+        code
+    }
+}
+
+impl<'a> module::Module for Module<'a> {
+    fn new() -> Result<Box<module::Module>, String> {
+        let mut map: HashMap<u32, &[&str]> = HashMap::new();
+        static SCRIPT: [&'static str; 2] = ["script", "scripts"];
+        map.insert(Commands::Script as u32, &SCRIPT);
+        Ok(Box::new(Module { commands: map }))
+    }
+
+    fn name(&self) -> &'static str {
+        "Scripts"
+    }
+
+    fn description(&self) -> &'static str {
+        "Custom commands written by the server admins."
+    }
+
+    fn commands(&self) -> &HashMap<u32, &[&str]> {
+        &self.commands
+    }
+
+    fn command_description(&self, _: u32) -> &'static str {
+        "Manages the custom script commands."
+    }
+
+    fn command_help_message(&self, _: u32) -> &'static str {
+        "`!script list` - Lists the scripts on this server.\n\
+         `!script show <name>` - Shows the code of a script.\n\
+         `!script add <name> <code>` - Adds or replaces the `!<name>` command (admin only).\n\
+         `!script describe <name> <description>` - Sets the description shown in `!commands` (admin only).\n\
+         `!script remove <name>` - Removes a script (admin only).\n\n\
+         Scripts are statements separated by `;`, for example `let n = random(1, 6); reply(mention + \" rolled \" + n);`. \
+         There are `let`, `if`/`else`, `while`, `for x in list`, `break` and `return`. \
+         The variables `args`, `argv` (the words of `args`), `author`, `author_id`, `mention` and `channel_id` are set. \
+         Functions: `reply`, `random(low, high)`, `choose(list)`, `len`, `str`, `int`, `split`, `join`, `push`, \
+         `contains`, `replace`, `upper`, `lower`, `trim`, `range(low, high)`, and `get(key)`/`set(key, value)` \
+         to keep data between runs."
+    }
+
+    fn rerun_on_edit(&self, _id: u32) -> bool {
+        false
+    }
+
+    fn command_scope(&self, _id: u32) -> CommandScope {
+        CommandScope::servers_only()
+    }
+
+    fn handle(&self, bot: &Bot, message: &Message, _id: u32, text: &str) {
+        let locale = bot.get_locale(message);
+
+        let server = match bot.get_server_for_channel(message.channel_id) {
+            Some(server) => server,
+            None => {
+                bot.send(message.channel_id, &locale.get("channel-info-error"));
+                return;
+            }
+        };
+
+        let mut parts = text.trim().splitn(2, char::is_whitespace);
+        let action = parts.next().unwrap_or("").to_lowercase();
+        let mut parts = parts.next().unwrap_or("").trim().splitn(2, char::is_whitespace);
+        let name = parts.next().unwrap_or("").to_lowercase();
+        let rest = parts.next().unwrap_or("");
+
+        match action.as_str() {
+            "list" => self.handle_list(bot, message, &locale, server),
+            "show" => self.handle_show(bot, message, &locale, server, &name),
+
+            "add" | "describe" | "remove" => {
+                if !admin::check_admin(bot, message, &locale) {
+                    return;
+                }
+
+                match action.as_str() {
+                    "add" => self.handle_add(bot, message, &locale, server, &name, strip_code_block(rest)),
+                    "describe" => self.handle_describe(bot, message, &locale, server, &name, rest.trim()),
+                    _ => self.handle_remove(bot, message, &locale, server, &name),
+                }
+            }
+
+            _ => {
+                bot.send(message.channel_id,
+                         &locale.command_help_message(self, Commands::Script as u32));
+            }
+        }
+    }
+
+    fn custom_commands(&self, server: ServerId) -> Vec<CustomCommand> {
+        get_scripts(server)
+            .into_iter()
+            .map(|(name, script)| {
+                     CustomCommand {
+                         name: name,
+                         description: if script.description.is_empty() {
+                             DEFAULT_DESCRIPTION.to_owned()
+                         } else {
+                             script.description
+                         },
+                         help_message: format!("```\n{}\n```", script.code),
+                     }
+                 })
+            .collect()
+    }
+
+    fn handle_custom(&self, bot: &Bot, message: &Message, name: &str, text: &str) {
+        match self.run(bot, message, name, text) {
+            // The replies come from the script rather than the bot.
+            Ok(replies) => {
+                for reply in replies {
+                    bot.send_as(message.channel_id, &format!("!{}", name), None, &reply);
+                }
+            }
+            Err(err) => bot.send(message.channel_id, &err),
+        }
+    }
+
+    fn handle_custom_output(&self, bot: &Bot, message: &Message, name: &str, text: &str) -> Option<CommandOutput> {
+        Some(self.run(bot, message, name, text).map(|replies| replies.join("\n")))
+    }
+}
+
+impl<'a> Module<'a> {
+    // Runs the script and returns its replies.
+    fn run(&self, bot: &Bot, message: &Message, name: &str, text: &str) -> Result<Vec<String>, String> {
+        let locale = bot.get_locale(message);
+
+        let server = match bot.get_server_for_channel(message.channel_id) {
+            Some(server) => server,
+            None => return Err(locale.get("channel-info-error")),
+        };
+
+        let script = match get_scripts(server).remove(name) {
+            Some(script) => script,
+            None => return Err(locale.format("scripts-not-found", &[("name", &name)])),
+        };
+
+        let program = try!(script::parse(&script.code)
+                               .map_err(|err| locale.format("scripts-syntax-error", &[("error", &err)])));
+
+        let data = match ::STORAGE.get(STORAGE_NAME, Scope::Server(server), &data_key(name)) {
+            Ok(data) => data.unwrap_or_default(),
+            Err(err) => return Err(locale.format("something-broken", &[("error", &err)])),
+        };
+
+        let mut host = ScriptHost {
+            replies: Vec::new(),
+            data: data,
+            data_changed: false,
+        };
+
+        let argv = text.split_whitespace().map(|x| Value::Str(x.to_owned())).collect();
+        let globals = vec![("args", Value::Str(text.trim().to_owned())),
+                           ("argv", Value::List(argv)),
+                           ("author", Value::Str(message.author.name.clone())),
+                           ("author_id", Value::Str(message.author.id.0.to_string())),
+                           ("mention", Value::Str(message.author.mention().to_string())),
+                           ("channel_id", Value::Str(message.channel_id.0.to_string()))];
+
+        try!(script::run(&program, globals, &mut host, &LIMITS)
+                 .map_err(|err| locale.format("scripts-error", &[("error", &err)])));
+
+        // The data is only kept if the script finished successfully.
+        if host.data_changed {
+            if let Err(err) = ::STORAGE.set(STORAGE_NAME, Scope::Server(server), &data_key(name), &host.data) {
+                return Err(locale.format("storage-error", &[("error", &err)]));
+            }
+        }
+
+        Ok(host.replies)
+    }
+
+    fn handle_list(&self, bot: &Bot, message: &Message, locale: &Locale, server: ServerId) {
+        let scripts = get_scripts(server);
+
+        if scripts.is_empty() {
+            bot.send(message.channel_id, &locale.get("scripts-no-scripts"));
+            return;
+        }
+
+        let mut buf = locale.get("scripts-list");
+
+        for (name, script) in scripts {
+            buf.push_str(&format!("\n- `!{}`", name));
+
+            if !script.description.is_empty() {
+                buf.push_str(&format!(": {}", script.description));
+            }
+        }
+
+        bot.send(message.channel_id, &buf);
+    }
+
+    fn handle_show(&self, bot: &Bot, message: &Message, locale: &Locale, server: ServerId, name: &str) {
+        match get_scripts(server).get(name) {
+            Some(script) => bot.send(message.channel_id, &format!("```\n{}\n```", script.code)),
+            None => {
+                bot.send(message.channel_id,
+                         &locale.format("scripts-not-found", &[("name", &name)]))
+            }
+        }
+    }
+
+    fn handle_add(&self, bot: &Bot, message: &Message, locale: &Locale, server: ServerId, name: &str, code: &str) {
+        if name.is_empty() || code.is_empty() {
+            bot.send(message.channel_id,
+                     &locale.command_help_message(self, Commands::Script as u32));
+            return;
+        }
+
+        if name.len() > MAX_NAME_LENGTH ||
+           !name.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_') {
+            bot.send(message.channel_id,
+                     &locale.format("scripts-invalid-name", &[("max", &MAX_NAME_LENGTH)]));
+            return;
+        }
+
+        // Built-in commands take precedence, so such a script could never run.
+        if bot.get_command_module(server, name).map(|m| m.name() != module::Module::name(self)) == Some(true) {
+            bot.send(message.channel_id,
+                     &locale.format("command-name-taken", &[("name", &name)]));
+            return;
+        }
+
+        if code.len() > MAX_CODE_LENGTH {
+            bot.send(message.channel_id,
+                     &locale.format("scripts-too-long", &[("max", &MAX_CODE_LENGTH)]));
+            return;
+        }
+
+        if let Err(err) = script::parse(code) {
+            bot.send(message.channel_id,
+                     &locale.format("scripts-syntax-error", &[("error", &err)]));
+            return;
+        }
+
+        let mut result = Ok(false);
+        let update = ::STORAGE.update(STORAGE_NAME,
+                                      Scope::Server(server),
+                                      "scripts",
+                                      |scripts: Option<BTreeMap<String, Script>>| {
+            let mut scripts = scripts.unwrap_or_default();
+
+            if !scripts.contains_key(name) && scripts.len() >= MAX_SCRIPTS_PER_SERVER {
+                result = Err(());
+                return Some(scripts);
+            }
+
+            let description = scripts.remove(name).map(|x| x.description);
+            result = Ok(description.is_some());
+
+            scripts.insert(name.to_owned(),
+                           Script {
+                               code: code.to_owned(),
+                               description: description.unwrap_or_default(),
+                               author: message.author.id.0,
+                           });
+
+            Some(scripts)
+        });
+
+        let outcome = match (update, result) {
+            (Err(err), _) => Err(locale.format("storage-error", &[("error", &err)])),
+            (Ok(()), Err(())) => Err(locale.format("scripts-too-many", &[("max", &MAX_SCRIPTS_PER_SERVER)])),
+            (Ok(()), Ok(true)) => Ok(locale.format("scripts-updated", &[("name", &name)])),
+            (Ok(()), Ok(false)) => Ok(locale.format("scripts-added", &[("name", &name)])),
+        };
+
+        admin::audit(bot, server, message, "!script add", name, &outcome);
+
+        match outcome {
+            Ok(text) | Err(text) => bot.send(message.channel_id, &text),
+        }
+    }
+
+    fn handle_describe(&self,
+                       bot: &Bot,
+                       message: &Message,
+                       locale: &Locale,
+                       server: ServerId,
+                       name: &str,
+                       description: &str) {
+        let mut found = false;
+        let update = ::STORAGE.update(STORAGE_NAME,
+                                      Scope::Server(server),
+                                      "scripts",
+                                      |scripts: Option<BTreeMap<String, Script>>| {
+            let mut scripts = scripts.unwrap_or_default();
+
+            if let Some(script) = scripts.get_mut(name) {
+                script.description = description.to_owned();
+                found = true;
+            }
+
+            Some(scripts)
+        });
+
+        let outcome = match update {
+            Err(err) => Err(locale.format("storage-error", &[("error", &err)])),
+            Ok(()) if found => Ok(locale.format("scripts-description-set", &[("name", &name)])),
+            Ok(()) => Err(locale.format("scripts-not-found", &[("name", &name)])),
+        };
+
+        admin::audit(bot, server, message, "!script describe", name, &outcome);
+
+        match outcome {
+            Ok(text) | Err(text) => bot.send(message.channel_id, &text),
+        }
+    }
+
+    fn handle_remove(&self, bot: &Bot, message: &Message, locale: &Locale, server: ServerId, name: &str) {
+        let mut found = false;
+        let update = ::STORAGE.update(STORAGE_NAME,
+                                      Scope::Server(server),
+                                      "scripts",
+                                      |scripts: Option<BTreeMap<String, Script>>| {
+            let mut scripts = scripts.unwrap_or_default();
+            found = scripts.remove(name).is_some();
+
+            if scripts.is_empty() { None } else { Some(scripts) }
+        })
+            .and_then(|_| ::STORAGE.remove(STORAGE_NAME, Scope::Server(server), &data_key(name)));
+
+        let outcome = match update {
+            Err(err) => Err(locale.format("storage-error", &[("error", &err)])),
+            Ok(()) if found => Ok(locale.format("scripts-removed", &[("name", &name)])),
+            Ok(()) => Err(locale.format("scripts-not-found", &[("name", &name)])),
+        };
+
+        admin::audit(bot, server, message, "!script remove", name, &outcome);
+
+        match outcome {
+            Ok(text) | Err(text) => bot.send(message.channel_id, &text),
+        }
+    }
+}
@@ -1,8 +1,10 @@
 use bot::Bot;
 use discord::model::Message;
-use hyper::{self, Client};
-use hyper::header::UserAgent;
+use http;
+use hyper;
+use locale::Locale;
 use module;
+use module::{CancellationToken, CommandOutput};
 use regex::Regex;
 use serde_json;
 use std::collections::BTreeMap;
@@ -19,17 +21,20 @@ pub struct Module<'a> {
 }
 
 lazy_static! {
-	static ref SPEEDRUNCOM_API_BASE: Url = Url::parse("https://www.speedrun.com/api/v1/").unwrap();
-	static ref USERAGENT: UserAgent = UserAgent(concat!("yalter-bot/", env!("CARGO_PKG_VERSION")).to_string());
 	static ref PB_REGEX: Regex = Regex::new(r"\s*(\S+)\s+(.*)").unwrap();
 }
 
+// The name of the API in the config, see http::base_url().
+const SERVICE: &'static str = "speedruncom";
+
 #[derive(Debug)]
 enum MyError {
     Network(hyper::error::Error),
     Json(serde_json::error::Error),
     NoSuchGame,
     NoSuchPlayer,
+    NoCategories(String),
+    Cancelled,
     Custom(String),
 }
 
@@ -40,6 +45,8 @@ impl fmt::Display for MyError {
             MyError::Json(ref err) => write!(f, "JSON error: {}", err),
             MyError::NoSuchGame => write!(f, "There's no such game on speedrun.com!"),
             MyError::NoSuchPlayer => write!(f, "There's no such player on speedrun.com!"),
+            MyError::NoCategories(ref game) => write!(f, "*{}* doesn't seem to have any categories.", game),
+            MyError::Cancelled => write!(f, "The command was cancelled."),
             MyError::Custom(ref err) => write!(f, "{}", err),
         }
     }
@@ -52,6 +59,8 @@ impl error::Error for MyError {
             MyError::Json(ref err) => err.description(),
             MyError::NoSuchGame => "There's no such game on speedrun.com!",
             MyError::NoSuchPlayer => "There's no such player on speedrun.com!",
+            MyError::NoCategories(_) => "The game doesn't have any categories.",
+            MyError::Cancelled => "The command was cancelled.",
             MyError::Custom(ref err) => err,
         }
     }
@@ -62,6 +71,8 @@ impl error::Error for MyError {
             MyError::Json(ref err) => Some(err),
             MyError::NoSuchGame => None,
             MyError::NoSuchPlayer => None,
+            MyError::NoCategories(_) => None,
+            MyError::Cancelled => None,
             MyError::Custom(ref _err) => None,
         }
     }
@@ -128,12 +139,24 @@ impl<'a> module::Module for Module<'a> {
 
     fn command_help_message(&self, id: u32) -> &'static str {
         match id {
-            x if x == Commands::WR as u32 => "`!wr <game>` - Shows the world record times for all categories for the given game. For example, `!wr Half-Life`.",
-            x if x == Commands::PB as u32 => "`!pb <player> <game>` - Shows player's personal bests in the given game. For example, `!pb YaLTeR Half-Life`.",
+            x if x == Commands::WR as u32 => "`!wr <game>` - Shows the world record times for all categories for the given game. For example, `!wr Half-Life`. Add `--fresh` to skip the cache.",
+            x if x == Commands::PB as u32 => "`!pb <player> <game>` - Shows player's personal bests in the given game. For example, `!pb YaLTeR Half-Life`. Add `--fresh` to skip the cache.",
             _ => panic!("Speedrun::command_help_message - invalid id."),
         }
     }
 
+    fn rerun_on_edit(&self, _id: u32) -> bool {
+        true
+    }
+
+    fn command_timeout(&self, id: u32) -> Option<Duration> {
+        match id {
+            // One request per category and subcategory.
+            x if x == Commands::WR as u32 => Some(Duration::from_secs(90)),
+            _ => None,
+        }
+    }
+
     fn handle(&self, bot: &Bot, message: &Message, id: u32, text: &str) {
         match id {
             x if x == Commands::WR as u32 => self.handle_wr(&bot, &message, &text),
@@ -141,100 +164,141 @@ impl<'a> module::Module for Module<'a> {
             _ => panic!("Speedrun::handle - invalid id."),
         }
     }
+
+    fn handle_output(&self, bot: &Bot, message: &Message, id: u32, text: &str) -> Option<CommandOutput> {
+        let locale = bot.get_locale(message);
+        let cancellation = bot.cancellation_token();
+
+        match id {
+            x if x == Commands::WR as u32 => Some(self.wr(&locale, text, &cancellation)),
+            x if x == Commands::PB as u32 => Some(self.pb(&locale, text, &cancellation)),
+            _ => None,
+        }
+    }
 }
 
 impl<'a> Module<'a> {
     fn handle_wr(&self, bot: &Bot, message: &Message, text: &str) {
-        bot.send(message.channel_id,
-                 match get_wrs(&text) {
-                         Ok((game, wrs)) => {
-                             if wrs.len() == 0 {
-                                 format!("**{}** has no world records. :|", game)
-                             } else {
-                                 let mut buf = format!("World records for **{}**:", game);
-                                 for mut wr in wrs {
-                                     buf.push_str(&format!("\n{}", wr.category));
-
-                                     if let Some(subcategory) = wr.subcategory {
-                                         buf.push_str(&format!(" ({})", subcategory));
-                                     }
-
-                                     buf.push_str(&format!(": **{}** by {}", format_time(&wr.time), wr.players[0]));
-
-                                     wr.players.remove(0);
-                                     if let Some(last_player) = wr.players.pop() {
-                                         for player in wr.players {
-                                             buf.push_str(&format!(", {}", player));
-                                         }
-                                         buf.push_str(&format!(" and {}", last_player));
-                                     }
-
-                                     buf.push('!');
-                                 }
-                                 buf
-                             }
-                         }
-                         Err(MyError::Network(err)) => {
-                             format!("Couldn't communicate with https://www.speedrun.com. :( ({})",
-                                     err)
-                         }
-                         Err(MyError::NoSuchGame) => "There's no such game on speedrun.com! :O".to_string(),
-                         Err(err) => format!("Something's broken. :/ ({})", err),
-                     }
-                     .as_str());
+        match self.wr(&bot.get_locale(message), text, &bot.cancellation_token()) {
+            Ok(reply) | Err(reply) => bot.send(message.channel_id, &reply),
+        }
     }
 
     fn handle_pb(&self, bot: &Bot, message: &Message, text: &str) {
+        match self.pb(&bot.get_locale(message), text, &bot.cancellation_token()) {
+            Ok(reply) | Err(reply) => bot.send(message.channel_id, &reply),
+        }
+    }
+
+    fn wr(&self, locale: &Locale, text: &str, cancellation: &CancellationToken) -> CommandOutput {
+        let (text, fresh) = http::take_fresh_flag(text);
+
+        match get_wrs(text, cancellation, fresh) {
+            Ok((game, wrs)) => {
+                if wrs.len() == 0 {
+                    Ok(locale.format("speedrun-no-world-records", &[("game", &game)]))
+                } else {
+                    let mut buf = locale.format("speedrun-world-records", &[("game", &game)]);
+                    for wr in wrs {
+                        let mut category = wr.category;
+
+                        if let Some(subcategory) = wr.subcategory {
+                            category.push_str(&format!(" ({})", subcategory));
+                        }
+
+                        buf.push('\n');
+                        buf.push_str(&locale.format("speedrun-world-record",
+                                                    &[("category", &category),
+                                                      ("time", &format_time(&wr.time)),
+                                                      ("players", &join_players(locale, wr.players))]));
+                    }
+                    Ok(buf)
+                }
+            }
+            Err(err) => Err(error_message(locale, err)),
+        }
+    }
+
+    fn pb(&self, locale: &Locale, text: &str, cancellation: &CancellationToken) -> CommandOutput {
+        let (text, fresh) = http::take_fresh_flag(text);
+
         if let Some(caps) = PB_REGEX.captures(text) {
-            bot.send(message.channel_id,
-                     match get_pbs(caps.get(1).unwrap().as_str(), caps.get(2).unwrap().as_str()) {
-                             Ok((player, game, mut pbs)) => {
-                                 if pbs.len() == 0 {
-                                     format!("**{}** has no personal bests in **{}**. :|", player, game)
-                                 } else {
-                                     let mut buf = format!("**{}**'s personal bests in **{}**:", player, game);
-
-                                     pbs.sort_by_key(|x| x.category.clone());
-
-                                     for pb in pbs {
-                                         buf.push_str(&format!("\n{}", pb.category));
-
-                                         if !pb.subcategories.is_empty() {
-                                             buf.push_str(&format!(" ({}", pb.subcategories[0]));
-
-                                             for subcategory in pb.subcategories.into_iter().skip(1) {
-                                                 buf.push_str(&format!(", {}", subcategory));
-                                             }
-
-                                             buf.push_str(")");
-                                         }
-
-                                         buf.push_str(&format!(": **{}** - {}", format_time(&pb.time), pb.place));
-                                         buf.push_str(&number_suffix(pb.place));
-
-                                         if pb.place == 1 {
-                                             buf.push_str(" 🏆");
-                                         }
-                                     }
-                                     buf
-                                 }
-                             }
-                             Err(MyError::Network(err)) => {
-                                 format!("Couldn't communicate with https://www.speedrun.com. :( ({})",
-                                         err)
-                             }
-                             Err(MyError::NoSuchGame) => "There's no such game on speedrun.com! :O".to_string(),
-                             Err(MyError::NoSuchPlayer) => "There's no such player on speedrun.com! :O".to_string(),
-                             Err(err) => format!("Something's broken. :/ ({})", err),
-                         }
-                         .as_str());
+            match get_pbs(caps.get(1).unwrap().as_str(), caps.get(2).unwrap().as_str(), cancellation, fresh) {
+                Ok((player, game, mut pbs)) => {
+                    if pbs.len() == 0 {
+                        Ok(locale.format("speedrun-no-personal-bests", &[("player", &player), ("game", &game)]))
+                    } else {
+                        let mut buf = locale.format("speedrun-personal-bests", &[("player", &player), ("game", &game)]);
+
+                        pbs.sort_by_key(|x| x.category.clone());
+
+                        for pb in pbs {
+                            let mut category = pb.category;
+
+                            if !pb.subcategories.is_empty() {
+                                category.push_str(&format!(" ({}", pb.subcategories[0]));
+
+                                for subcategory in pb.subcategories.into_iter().skip(1) {
+                                    category.push_str(&format!(", {}", subcategory));
+                                }
+
+                                category.push(')');
+                            }
+
+                            let place = locale.format(&format!("speedrun-ordinal-{}", ordinal_category(pb.place)),
+                                                      &[("place", &pb.place)]);
+
+                            buf.push('\n');
+                            buf.push_str(&locale.format("speedrun-personal-best",
+                                                        &[("category", &category),
+                                                          ("time", &format_time(&pb.time)),
+                                                          ("place", &place)]));
+
+                            if pb.place == 1 {
+                                buf.push_str(" 🏆");
+                            }
+                        }
+                        Ok(buf)
+                    }
+                }
+                Err(err) => Err(error_message(locale, err)),
+            }
         } else {
-            bot.send(message.channel_id,
-                     <Module as module::Module>::command_help_message(&self, Commands::PB as u32));
+            Err(locale.command_help_message(self, Commands::PB as u32))
         }
     }
 }
 
+fn error_message(locale: &Locale, err: MyError) -> String {
+    match err {
+        MyError::Network(err) => locale.format("speedrun-network-error", &[("error", &err)]),
+        MyError::NoSuchGame => locale.get("speedrun-no-such-game"),
+        MyError::NoSuchPlayer => locale.get("speedrun-no-such-player"),
+        MyError::NoCategories(game) => locale.format("speedrun-no-categories", &[("game", &game)]),
+        err => locale.format("something-broken", &[("error", &err)]),
+    }
+}
+
+// Formats a list of players like "A, B and C".
+fn join_players(locale: &Locale, players: Vec<Option<String>>) -> String {
+    let mut players: Vec<String> = players.into_iter()
+        .map(|x| x.unwrap_or_else(|| locale.get("speedrun-nameless-player")))
+        .collect();
+
+    match players.pop() {
+        Some(last_player) => {
+            if players.is_empty() {
+                last_player
+            } else {
+                locale.format("speedrun-player-list",
+                              &[("players", &players.join(", ")), ("last-player", &last_player)])
+            }
+        }
+
+        None => locale.get("speedrun-nameless-player"),
+    }
+}
+
 fn format_time(time: &Duration) -> String {
     let total_seconds = time.as_secs();
     let nanoseconds = time.subsec_nanos();
@@ -261,22 +325,26 @@ fn format_time(time: &Duration) -> String {
 struct WR {
     category: String,
     subcategory: Option<String>,
-    players: Vec<String>,
+    // None for players without a name.
+    players: Vec<Option<String>>,
     time: Duration,
 }
 
-fn get_wrs(text: &str) -> Result<(String, Vec<WR>), MyError> {
-    let mut games = SPEEDRUNCOM_API_BASE.join("games").unwrap();
+fn api_base() -> Url {
+    http::base_url(SERVICE, "https://www.speedrun.com/api/v1/")
+}
+
+fn get_wrs(text: &str, cancellation: &CancellationToken, fresh: bool) -> Result<(String, Vec<WR>), MyError> {
+    let mut games = api_base().join("games").unwrap();
     games
         .query_pairs_mut()
         .append_pair("name", text)
         .append_pair("embed", "categories.variables")
         .append_pair("max", "1");
 
-    let client = Client::new();
-    let result = try!(client.get(games).send());
+    let result = try!(::HTTP.get_cached(SERVICE, games.as_str(), fresh));
 
-    let games: APIGames = try!(serde_json::de::from_reader(result));
+    let games: APIGames = try!(serde_json::from_slice(&result.body));
     if games.data.is_empty() {
         return Err(MyError::NoSuchGame);
     }
@@ -291,13 +359,16 @@ fn get_wrs(text: &str) -> Result<(String, Vec<WR>), MyError> {
         .filter(|x| x.type_ == "per-game")
         .collect();
     if categories.is_empty() {
-        return Err(MyError::Custom(format!("*{}* doesn't seem to have any categories. :/",
-                                           game.names.international)));
+        return Err(MyError::NoCategories(game.names.international));
     }
 
     let mut wrs = Vec::new();
 
     for category in categories.into_iter() {
+        if cancellation.is_cancelled() {
+            return Err(MyError::Cancelled);
+        }
+
         if let Some(subcategory_variable) =
             category
                 .variables
@@ -308,7 +379,11 @@ fn get_wrs(text: &str) -> Result<(String, Vec<WR>), MyError> {
             // Get runs for each subcategory value.
 
             for (value_id, value) in subcategory_variable.values.values {
-                let mut leaderboard = try!(SPEEDRUNCOM_API_BASE
+                if cancellation.is_cancelled() {
+                    return Err(MyError::Cancelled);
+                }
+
+                let mut leaderboard = try!(api_base()
                                                .join(&format!("leaderboards/{}/category/{}", game.id, category.id))
                                                .map_err(|x| x.to_string()));
 
@@ -318,12 +393,9 @@ fn get_wrs(text: &str) -> Result<(String, Vec<WR>), MyError> {
                     .append_pair("embed", "players")
                     .append_pair(&format!("var-{}", subcategory_variable.id), &value_id);
 
-                let result = try!(client
-                                      .get(leaderboard.as_str())
-                                      .header(USERAGENT.clone())
-                                      .send());
+                let result = try!(::HTTP.get_cached(SERVICE, leaderboard.as_str(), fresh));
 
-                let leaderboard: APILeaderboards = try!(serde_json::de::from_reader(result));
+                let leaderboard: APILeaderboards = try!(serde_json::from_slice(&result.body));
 
                 let runs = leaderboard.data.runs;
                 if runs.is_empty() {
@@ -333,7 +405,7 @@ fn get_wrs(text: &str) -> Result<(String, Vec<WR>), MyError> {
 
                 let time = Duration::from_millis((runs[0].run.times.primary_t * 1000f64) as u64);
 
-                let players: Vec<String> = leaderboard
+                let players: Vec<Option<String>> = leaderboard
                     .data
                     .players
                     .data
@@ -342,7 +414,6 @@ fn get_wrs(text: &str) -> Result<(String, Vec<WR>), MyError> {
                              x.names
                                  .map(|n| n.international)
                                  .or(x.name)
-                                 .unwrap_or("nameless player".to_owned())
                          })
                     .collect();
 
@@ -356,7 +427,7 @@ fn get_wrs(text: &str) -> Result<(String, Vec<WR>), MyError> {
         } else {
             // No subcategories, just get runs.
 
-            let mut leaderboard = try!(SPEEDRUNCOM_API_BASE
+            let mut leaderboard = try!(api_base()
                                            .join(&format!("leaderboards/{}/category/{}", game.id, category.id))
                                            .map_err(|x| x.to_string()));
 
@@ -365,11 +436,8 @@ fn get_wrs(text: &str) -> Result<(String, Vec<WR>), MyError> {
                 .append_pair("top", "1")
                 .append_pair("embed", "players");
 
-            let result = try!(client
-                                  .get(leaderboard.as_str())
-                                  .header(USERAGENT.clone())
-                                  .send());
-            let leaderboard: APILeaderboards = try!(serde_json::de::from_reader(result));
+            let result = try!(::HTTP.get_cached(SERVICE, leaderboard.as_str(), fresh));
+            let leaderboard: APILeaderboards = try!(serde_json::from_slice(&result.body));
 
             let runs = leaderboard.data.runs;
             if runs.is_empty() {
@@ -379,7 +447,7 @@ fn get_wrs(text: &str) -> Result<(String, Vec<WR>), MyError> {
 
             let time = Duration::from_millis((runs[0].run.times.primary_t * 1000f64) as u64);
 
-            let players: Vec<String> = leaderboard
+            let players: Vec<Option<String>> = leaderboard
                 .data
                 .players
                 .data
@@ -388,7 +456,6 @@ fn get_wrs(text: &str) -> Result<(String, Vec<WR>), MyError> {
                          x.names
                              .map(|n| n.international)
                              .or(x.name)
-                             .unwrap_or("nameless player".to_owned())
                      })
                 .collect();
 
@@ -411,24 +478,27 @@ struct PB {
     place: u64,
 }
 
-fn get_pbs(player_name: &str, game_name: &str) -> Result<(String, String, Vec<PB>), MyError> {
-    let mut games = SPEEDRUNCOM_API_BASE.join("games").unwrap();
+fn get_pbs(player_name: &str, game_name: &str, cancellation: &CancellationToken, fresh: bool) -> Result<(String, String, Vec<PB>), MyError> {
+    let mut games = api_base().join("games").unwrap();
     games
         .query_pairs_mut()
         .append_pair("name", game_name)
         .append_pair("max", "1");
 
-    let client = Client::new();
-    let result = try!(client.get(games.as_str()).header(USERAGENT.clone()).send());
+    let result = try!(::HTTP.get_cached(SERVICE, games.as_str(), fresh));
 
-    let games: APIGames = try!(serde_json::de::from_reader(result));
+    let games: APIGames = try!(serde_json::from_slice(&result.body));
     if games.data.is_empty() {
         return Err(MyError::NoSuchGame);
     }
 
     let game = games.data.into_iter().next().unwrap();
 
-    let mut users = try!(SPEEDRUNCOM_API_BASE
+    if cancellation.is_cancelled() {
+        return Err(MyError::Cancelled);
+    }
+
+    let mut users = try!(api_base()
                              .join(&format!("users/{}/personal-bests", player_name))
                              .map_err(|x| x.to_string()));
 
@@ -437,9 +507,9 @@ fn get_pbs(player_name: &str, game_name: &str) -> Result<(String, String, Vec<PB
         .append_pair("game", &game.id)
         .append_pair("embed", "category.variables");
 
-    let result = try!(client.get(users.as_str()).header(USERAGENT.clone()).send());
+    let result = try!(::HTTP.get_cached(SERVICE, users.as_str(), fresh));
 
-    let user: APIUsers = try!(serde_json::de::from_reader(result));
+    let user: APIUsers = try!(serde_json::from_slice(&result.body));
 
     if let Some(_) = user.status {
         return Err(MyError::NoSuchPlayer);
@@ -513,19 +583,20 @@ fn get_subcategories(run: &APIRunRun, subcategory_variables: Vec<SubcategoryVari
         .collect::<Vec<String>>()
 }
 
-fn number_suffix(n: u64) -> String {
+// The CLDR plural category of an ordinal number in English, used to pick the suffix.
+fn ordinal_category(n: u64) -> &'static str {
     let t = n % 100;
 
     if t >= 11 && t <= 13 {
-        return "th".to_owned();
+        return "other";
     }
 
     let t = t % 10;
 
     match t {
-        1 => "st".to_owned(),
-        2 => "nd".to_owned(),
-        3 => "rd".to_owned(),
-        _ => "th".to_owned(),
+        1 => "one",
+        2 => "two",
+        3 => "few",
+        _ => "other",
     }
 }
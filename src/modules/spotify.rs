@@ -1,35 +1,31 @@
 use std::collections::HashMap;
-use hyper;
-use hyper::Client;
-use hyper::net::{OpensslClient, HttpsConnector};
-use hyper::header::{Authorization, Bearer};
+use http;
+use hyper::header::{Authorization, Bearer, Headers};
 use hyper::status::StatusCode;
 use module;
-use std::io::Read;
+use module::CommandOutput;
 use discord::model::Message;
+use locale::Locale;
 use bot::Bot;
 use serde_json;
 use serde_json::Value;
 
-pub struct Module<'a, 'b> {
+pub struct Module<'a> {
     commands: HashMap<u32, &'a [&'a str]>,
-    api_key: &'b str,
+    api_key: String,
 }
 
 enum Commands {
     Search = 0,
 }
 
-impl<'a, 'b> module::Module for Module<'a, 'b> {
+impl<'a> module::Module for Module<'a> {
     fn new() -> Result<Box<module::Module>, String> {
-        let mut map: HashMap<u32, &[&str]> = HashMap::new();
-        static SEARCH: [&'static str; 2] = ["spotify", "sp"];
-        map.insert(Commands::Search as u32, &SEARCH);
         let key = {
             let temp;
-            if let Some(v) = ::CONF.pointer("/spotify_key") {
+            if let Some(v) = ::CONF.read().unwrap().pointer("/spotify_key") {
                 if let Some(key) = v.as_str() {
-                    temp = key
+                    temp = key.to_owned()
                 } else {
                     return Err("failed to get spotify key".into());
                 }
@@ -38,10 +34,7 @@ impl<'a, 'b> module::Module for Module<'a, 'b> {
             }
             temp
         };
-        Ok(Box::new(Module {
-                        commands: map,
-                        api_key: key,
-                    }))
+        Ok(Box::new(Module::with_api_key(key)))
     }
 
     fn name(&self) -> &'static str {
@@ -58,82 +51,92 @@ impl<'a, 'b> module::Module for Module<'a, 'b> {
 
     fn command_description(&self, id: u32) -> &'static str {
         match id {
-            x if x == Commands::Search as u32 => "`!spotify, !sp {type} search_term`: Searches Spotify",
-            _ => "invalid id",
+            x if x == Commands::Search as u32 => "Searches Spotify.",
+            _ => panic!("Spotify::command_description - invalid id."),
         }
     }
 
     fn command_help_message(&self, id: u32) -> &'static str {
         match id {
-            x if x == Commands::Search as u32 => "`!sp ?(track|artist|album|playlist) search_term`: Searches Spotify for the given item and embeds it in chat",
-            _ => "invalid id",
+            x if x == Commands::Search as u32 => "`!sp <track|artist|album|playlist> <search term>` - Searches Spotify for the given item and embeds it in chat. Add `--fresh` to skip the cache.",
+            _ => panic!("Spotify::command_help_message - invalid id."),
         }
     }
 
+    fn rerun_on_edit(&self, _id: u32) -> bool {
+        true
+    }
+
     fn handle(&self, bot: &Bot, message: &Message, id: u32, text: &str) {
         match id {
             x if x == Commands::Search as u32 => {
-                let args;
-                let mut url = String::from("https://api.spotify.com/v1/search?type=");
-                let req_type = match text.split_whitespace().nth(0) {
-                    Some(v) => v,
-                    None => {
-                        bot.send(message.channel_id, "invalid invocation of !spotify");
-                        return;
-                    }
-                };
-                match req_type {
-                    "album" | "track" => {
-                        args = String::from(text.split_at(6).1);
-                        url.push_str(req_type);
-                    }
-                    "artist" => {
-                        args = String::from(text.split_at(7).1);
-                        url.push_str(req_type);
-                    }
-                    "playlist" => {
-                        args = String::from(text.split_at(9).1);
-                        url.push_str(req_type);
-                    }
-                    _ => {
-                        bot.send(message.channel_id, "invalid invocation of !spotify");
-                        return;
-                    }
-                }
-                url.push_str("&q=");
-                url.push_str(&args);
-                let parsed_url = hyper::Url::parse(url.as_str()).unwrap();
-                let tls = OpensslClient::default();
-                let connector = HttpsConnector::new(tls);
-                let client = Client::with_connector(connector);
-                println!("{}", self.api_key);
-                let header = Authorization(Bearer { token: self.api_key.to_owned() });
-                println!("header: {:?}", header);
-                let mut response = client.get(parsed_url).header(header).send().unwrap();
-                let status = response.status;
-                let mut json = String::new();
-                if let Err(e) = response.read_to_string(&mut json) {
-                    println!("oh crap {}", e);
-                }
-                let json_root = serde_json::from_str::<Value>(json.as_str()).unwrap();
-                if status == StatusCode::Ok {
-                    let pointer = format!("/{}s/items/0/external_urls/spotify", req_type);
-                    let item_url = json_root.pointer(&pointer).unwrap().as_str().unwrap();
-                    bot.send(message.channel_id, item_url);
-                } else {
-                    bot.send(message.channel_id,
-                             &format!("Spotify doesn't want you to do that: {:?}\n{}",
-                                     status,
-                                     json_root
-                                         .pointer("/error/message")
-                                         .unwrap()
-                                         .as_str()
-                                         .unwrap()));
+                match self.search(&bot.get_locale(message), text) {
+                    Ok(reply) | Err(reply) => bot.send(message.channel_id, &reply),
                 }
             }
             _ => {
-                bot.send(message.channel_id, "Invalid ID");
+                bot.send(message.channel_id, &bot.get_locale(message).get("invalid-command-id"));
             }
         }
     }
+
+    fn handle_output(&self, bot: &Bot, message: &Message, id: u32, text: &str) -> Option<CommandOutput> {
+        match id {
+            x if x == Commands::Search as u32 => Some(self.search(&bot.get_locale(message), text)),
+            _ => None,
+        }
+    }
+}
+
+impl<'a> Module<'a> {
+    // Also used without a key for the command reference, see docs.rs.
+    pub fn with_api_key(api_key: String) -> Self {
+        let mut map: HashMap<u32, &[&str]> = HashMap::new();
+        static SEARCH: [&'static str; 2] = ["spotify", "sp"];
+        map.insert(Commands::Search as u32, &SEARCH);
+        Module {
+            commands: map,
+            api_key: api_key,
+        }
+    }
+
+    fn search(&self, locale: &Locale, text: &str) -> CommandOutput {
+        let (text, fresh) = http::take_fresh_flag(text);
+        let mut url = http::base_url("spotify", "https://api.spotify.com/v1/").join("search").unwrap();
+        let req_type = match text.split_whitespace().nth(0) {
+            Some(v) => v,
+            None => return Err(locale.get("spotify-invalid-invocation")),
+        };
+        let args = match req_type {
+            "album" | "track" => String::from(text.split_at(6).1),
+            "artist" => String::from(text.split_at(7).1),
+            "playlist" => String::from(text.split_at(9).1),
+            _ => return Err(locale.get("spotify-invalid-invocation")),
+        };
+        url.query_pairs_mut()
+            .append_pair("type", req_type)
+            .append_pair("q", &args);
+        let mut headers = Headers::new();
+        headers.set(Authorization(Bearer { token: self.api_key.to_owned() }));
+        let response = match ::HTTP.get_cached_with_headers("spotify", url.as_str(), headers, fresh) {
+            Ok(response) => response,
+            Err(err) => return Err(locale.format("spotify-network-error", &[("error", &err)])),
+        };
+        let status = response.status;
+        let json_root = serde_json::from_slice::<Value>(&response.body).unwrap();
+        if status == StatusCode::Ok {
+            let pointer = format!("/{}s/items/0/external_urls/spotify", req_type);
+            let item_url = json_root.pointer(&pointer).unwrap().as_str().unwrap();
+            Ok(item_url.to_owned())
+        } else {
+            Err(locale.format("spotify-error",
+                              &[("status", &format!("{:?}", status)),
+                                ("error",
+                                 &json_root
+                                      .pointer("/error/message")
+                                      .unwrap()
+                                      .as_str()
+                                      .unwrap())]))
+        }
+    }
 }
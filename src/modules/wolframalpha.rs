@@ -1,20 +1,17 @@
 use bot::Bot;
 use discord::model::Message;
-use hyper::client::Client;
+use http;
+use locale::Locale;
 use module;
+use module::CommandOutput;
 use std::collections::hash_map::HashMap;
 use std::error::Error;
-use url::Url;
 use xml;
 use xml::reader::XmlEvent;
 
-pub struct Module<'a, 'b> {
+pub struct Module<'a> {
     commands: HashMap<u32, &'a [&'a str]>,
-    api_key: &'b str,
-}
-
-lazy_static! {
-	static ref WOLFRAMALPHA_API_BASE: Url = Url::parse("http://api.wolframalpha.com/v2/query").unwrap();
+    api_key: String,
 }
 
 enum Commands {
@@ -32,16 +29,13 @@ enum CurrentPod {
     Results,
 }
 
-impl<'a, 'b> module::Module for Module<'a, 'b> {
+impl<'a> module::Module for Module<'a> {
     fn new() -> Result<Box<module::Module>, String> {
-        static WA: [&'static str; 2] = ["wolphramalpha", "wa"];
-        let mut map: HashMap<u32, &[&str]> = HashMap::new();
-        map.insert(Commands::WA as u32, &WA);
         let key = {
             let temp;
-            if let Some(v) = ::CONF.pointer("/wolfram_key") {
+            if let Some(v) = ::CONF.read().unwrap().pointer("/wolfram_key") {
                 if let Some(key) = v.as_str() {
-                    temp = key
+                    temp = key.to_owned()
                 } else {
                     return Err("failed to get wolfram key".into());
                 }
@@ -50,10 +44,7 @@ impl<'a, 'b> module::Module for Module<'a, 'b> {
             }
             temp
         };
-        Ok(Box::new(Module {
-                        commands: map,
-                        api_key: key,
-                    }))
+        Ok(Box::new(Module::with_api_key(key)))
     }
 
     fn name(&self) -> &'static str {
@@ -73,30 +64,137 @@ impl<'a, 'b> module::Module for Module<'a, 'b> {
     }
 
     fn command_help_message(&self, _: u32) -> &'static str {
-        "`!wa <input>` - Queries Wolfram!Alpha with the given input and returns the result. For example, `!wa int sin x / x dx, 0 < x < +inf`."
+        "`!wa <input>` - Queries Wolfram!Alpha with the given input and returns the result. For example, `!wa int sin x / x dx, 0 < x < +inf`. Add `--fresh` to skip the cache."
+    }
+
+    fn rerun_on_edit(&self, _id: u32) -> bool {
+        true
     }
 
     fn handle(&self, bot: &Bot, message: &Message, _id: u32, text: &str) {
         bot.broadcast_typing(message.channel_id); // This command takes a few seconds to process.
 
-        let mut url = WOLFRAMALPHA_API_BASE.clone();
+        let locale = bot.get_locale(message);
+
+        let (input_interpretation, results) = match self.query(&locale, text) {
+            Ok(pods) => pods,
+            Err(err) => {
+                bot.send(message.channel_id, &err);
+                return;
+            }
+        };
+
+		// The images are downloaded separately.
+		if bot.cancellation_token().is_cancelled() {
+			return;
+		}
+
+		if let Some(pod) = input_interpretation {
+			let mut text = locale.get("wolfram-alpha-input-interpretation");
+			if pod.plaintext.len() > 0 {
+				text.push_str(&format!("\n```\n{}\n```", pod.plaintext));
+			}
+
+			if let Some(img) = pod.image_url {
+				match ::HTTP.get(&img) {
+					Ok(result) => {
+						bot.send_file(message.channel_id, &text, result, "input_interpretation.gif");
+					},
+
+					Err(err) => {
+						if pod.plaintext.len() == 0 {
+							text = "".to_string();
+						}
+
+						if text.len() > 0 {
+							text.push_str("\n\n");
+						}
+						text.push_str(&locale.format("wolfram-alpha-image-error", &[("error", &err.description())]));
+
+						bot.send(message.channel_id, &text);
+					}
+				}
+			}
+		}
+
+		if bot.cancellation_token().is_cancelled() {
+			return;
+		}
+
+		if let Some(pod) = results {
+			let mut text = locale.get("wolfram-alpha-result");
+			if pod.plaintext.len() > 0 {
+				text.push_str(&format!("\n```\n{}\n```", pod.plaintext));
+			}
+
+			if let Some(img) = pod.image_url {
+				match ::HTTP.get(&img) {
+					Ok(result) => {
+						bot.send_file(message.channel_id, &text, result, "result.gif");
+					},
+
+					Err(err) => {
+						if pod.plaintext.len() == 0 {
+							text = "".to_string();
+						}
+
+						if text.len() > 0 {
+							text.push_str("\n\n");
+						}
+						text.push_str(&locale.format("wolfram-alpha-image-error", &[("error", &err.description())]));
+
+						bot.send(message.channel_id, &text);
+					}
+				}
+			}
+		} else {
+			bot.send(message.channel_id, &locale.get("wolfram-alpha-no-result"));
+		}
+    }
+
+    fn handle_output(&self, bot: &Bot, message: &Message, _id: u32, text: &str) -> Option<CommandOutput> {
+        let locale = bot.get_locale(message);
+
+        Some(match self.query(&locale, text) {
+                 Ok((_, Some(ref pod))) if !pod.plaintext.is_empty() => Ok(pod.plaintext.clone()),
+                 Ok((_, Some(_))) => Err(locale.get("wolfram-alpha-image-only")),
+                 Ok((_, None)) => Err(locale.get("wolfram-alpha-no-result")),
+                 Err(err) => Err(err),
+             })
+    }
+}
+
+impl<'a> Module<'a> {
+    // Also used without a key for the command reference, see docs.rs.
+    pub fn with_api_key(api_key: String) -> Self {
+        let mut map: HashMap<u32, &[&str]> = HashMap::new();
+        static WA: [&'static str; 2] = ["wolphramalpha", "wa"];
+        map.insert(Commands::WA as u32, &WA);
+        Module {
+            commands: map,
+            api_key: api_key,
+        }
+    }
+
+    // Returns the input interpretation and the result pods.
+    fn query(&self, locale: &Locale, text: &str) -> Result<(Option<Pod>, Option<Pod>), String> {
+        let (text, fresh) = http::take_fresh_flag(text);
+        let mut url = http::base_url("wolframalpha", "http://api.wolframalpha.com/v2/").join("query").unwrap();
         url.query_pairs_mut()
-            .append_pair("appid", self.api_key)
+            .append_pair("appid", &self.api_key)
             .append_pair("input", text);
 
         println!("URL: {}", url.as_str());
 
-        let client = Client::new();
-        match client.get(url.as_str()).send() {
+        match ::HTTP.get_cached("wolframalpha", url.as_str(), fresh) {
 			Ok(result) => {
 				let mut input_interpretation: Option<Pod> = None;
 				let mut results: Option<Pod> = None;
 				let mut state = CurrentPod::InputInterpretation;
 
 				let mut inside_plaintext = false;
-				let mut error = false;
 
-				let reader = xml::reader::EventReader::new(result);
+				let reader = xml::reader::EventReader::new(&result.body[..]);
 				'xml_loop: for event in reader {
 					match event {
 						Ok(XmlEvent::StartElement { name, attributes, namespace: _ }) => {
@@ -106,9 +204,7 @@ impl<'a, 'b> module::Module for Module<'a, 'b> {
 										if attr.name.local_name == "numpods" {
 											match attr.value.parse::<u8>() {
 												Ok(0) | Err(_) => {
-													bot.send(message.channel_id, "Wolfram!Alpha couldn't understand your input. :/");
-													error = true;
-													break 'xml_loop;
+													return Err(locale.get("wolfram-alpha-not-understood"));
 												},
 
 												_ => {}
@@ -176,67 +272,11 @@ impl<'a, 'b> module::Module for Module<'a, 'b> {
 					}
 				}
 
-				if let Some(pod) = input_interpretation {
-					let mut text = "Input interpretation:".to_string();
-					if pod.plaintext.len() > 0 {
-						text.push_str(&format!("\n```\n{}\n```", pod.plaintext));
-					}
-
-					if let Some(img) = pod.image_url {
-						match client.get(&img).send() {
-							Ok(result) => {
-								bot.send_file(message.channel_id, &text, result, "input_interpretation.gif");
-							},
-
-							Err(err) => {
-								if pod.plaintext.len() == 0 {
-									text = "".to_string();
-								}
-
-								if text.len() > 0 {
-									text.push_str("\n\n");
-								}
-								text.push_str(&format!("Something's broken. :/ (Couldn't get the resulting image returned by the API: {})", err.description()));
-
-								bot.send(message.channel_id, &text);
-							}
-						}
-					}
-				}
-
-				if let Some(pod) = results {
-					let mut text = "Result:".to_string();
-					if pod.plaintext.len() > 0 {
-						text.push_str(&format!("\n```\n{}\n```", pod.plaintext));
-					}
-
-					if let Some(img) = pod.image_url {
-						match client.get(&img).send() {
-							Ok(result) => {
-								bot.send_file(message.channel_id, &text, result, "result.gif");
-							},
-
-							Err(err) => {
-								if pod.plaintext.len() == 0 {
-									text = "".to_string();
-								}
-
-								if text.len() > 0 {
-									text.push_str("\n\n");
-								}
-								text.push_str(&format!("Something's broken. :/ (Couldn't get the resulting image returned by the API: {})", err.description()));
-
-								bot.send(message.channel_id, &text);
-							}
-						}
-					}
-				} else if !error {
-					bot.send(message.channel_id, "Wolfram!Alpha didn't return a result pod. This probably means that the standard computation time exceeded.");
-				}
+				Ok((input_interpretation, results))
 			},
 
 			Err(err) => {
-				bot.send(message.channel_id, &format!("Couldn't communicate with http://api.wolframalpha.com. :( ({})", err.description()));
+				Err(locale.format("wolfram-alpha-network-error", &[("error", &err.description())]))
 			}
 		}
     }
@@ -1,34 +1,30 @@
 use std::collections::HashMap;
-use hyper;
-use hyper::Client;
-use hyper::net::{OpensslClient, HttpsConnector};
+use http;
 use module;
-use std::io::Read;
+use module::CommandOutput;
 use discord::model::Message;
+use locale::Locale;
 use bot::Bot;
 use hyper::status::StatusCode;
 use serde_json;
 use serde_json::Value;
 
-pub struct Module<'a, 'b> {
+pub struct Module<'a> {
     commands: HashMap<u32, &'a [&'a str]>,
-    api_key: &'b str,
+    api_key: String,
 }
 
 enum Commands {
     Embed = 0,
 }
 
-impl<'a, 'b> module::Module for Module<'a, 'b> {
+impl<'a> module::Module for Module<'a> {
     fn new() -> Result<Box<module::Module>, String> {
-        let mut map: HashMap<u32, &[&str]> = HashMap::new();
-        static EMBED: [&'static str; 2] = ["youtube", "yt"];
-        map.insert(Commands::Embed as u32, &EMBED);
         let key = {
             let temp;
-            if let Some(v) = ::CONF.pointer("/google_key") {
+            if let Some(v) = ::CONF.read().unwrap().pointer("/google_key") {
                 if let Some(key) = v.as_str() {
-                    temp = key
+                    temp = key.to_owned()
                 } else {
                     return Err("failed to get google key".into());
                 }
@@ -37,10 +33,7 @@ impl<'a, 'b> module::Module for Module<'a, 'b> {
             }
             temp
         };
-        Ok(Box::new(Module {
-                        commands: map,
-                        api_key: key,
-                    }))
+        Ok(Box::new(Module::with_api_key(key)))
     }
 
     fn name(&self) -> &'static str {
@@ -57,48 +50,74 @@ impl<'a, 'b> module::Module for Module<'a, 'b> {
 
     fn command_description(&self, id: u32) -> &'static str {
         match id {
-            x if x == Commands::Embed as u32 => "`!youtube, !yt search term`: Searches YouTube",
-            _ => "invalid id",
+            x if x == Commands::Embed as u32 => "Searches YouTube.",
+            _ => panic!("YouTube::command_description - invalid id."),
         }
     }
 
     fn command_help_message(&self, id: u32) -> &'static str {
         match id {
-            x if x == Commands::Embed as u32 => "`!yt search term`: Searches YouTube for the given video and embeds it in chat",
-            _ => "invalid id",
+            x if x == Commands::Embed as u32 => "`!yt <search term>` - Searches YouTube for the given video and embeds it in chat. Add `--fresh` to skip the cache.",
+            _ => panic!("YouTube::command_help_message - invalid id."),
         }
     }
 
+    fn rerun_on_edit(&self, _id: u32) -> bool {
+        true
+    }
+
     fn handle(&self, bot: &Bot, message: &Message, id: u32, text: &str) {
         match id {
             x if x == Commands::Embed as u32 => {
-                let mut url = String::from("https://www.googleapis.com/youtube/v3/search?part=snippet&key=");
-                url.push_str(&self.api_key);
-                url.push_str("&q=");
-                url.push_str(text);
-                let parsed_url = hyper::Url::parse(url.as_str()).unwrap();
-                let tls = OpensslClient::default();
-                let connector = HttpsConnector::new(tls);
-                let client = Client::with_connector(connector);
-                let mut response = client.get(parsed_url).send().unwrap();
-                let status = response.status;
-                if status == StatusCode::Ok {
-                    let mut json = String::new();
-                    if let Err(e) = response.read_to_string(&mut json) {
-                        println!("oh crap {}", e);
-                    }
-                    let json_root = serde_json::from_str::<Value>(json.as_str()).unwrap();
-                    let video_id_ = json_root.pointer("/items/0/id/videoId").unwrap();
-                    let video_id = video_id_.as_str().unwrap();
-                    bot.send(message.channel_id,
-                             format!("https://youtu.be/{}", video_id).as_str());
-                } else {
-                    bot.send(message.channel_id, "Google doesn't want you to do that");
+                match self.search(&bot.get_locale(message), text) {
+                    Ok(reply) | Err(reply) => bot.send(message.channel_id, &reply),
                 }
             }
             _ => {
-                bot.send(message.channel_id, "Invalid ID");
+                bot.send(message.channel_id, &bot.get_locale(message).get("invalid-command-id"));
             }
         }
     }
+
+    fn handle_output(&self, bot: &Bot, message: &Message, id: u32, text: &str) -> Option<CommandOutput> {
+        match id {
+            x if x == Commands::Embed as u32 => Some(self.search(&bot.get_locale(message), text)),
+            _ => None,
+        }
+    }
+}
+
+impl<'a> Module<'a> {
+    // Also used without a key for the command reference, see docs.rs.
+    pub fn with_api_key(api_key: String) -> Self {
+        let mut map: HashMap<u32, &[&str]> = HashMap::new();
+        static EMBED: [&'static str; 2] = ["youtube", "yt"];
+        map.insert(Commands::Embed as u32, &EMBED);
+        Module {
+            commands: map,
+            api_key: api_key,
+        }
+    }
+
+    fn search(&self, locale: &Locale, text: &str) -> CommandOutput {
+        let (text, fresh) = http::take_fresh_flag(text);
+        let mut url = http::base_url("youtube", "https://www.googleapis.com/youtube/v3/").join("search").unwrap();
+        url.query_pairs_mut()
+            .append_pair("part", "snippet")
+            .append_pair("key", &self.api_key)
+            .append_pair("q", text);
+        let response = match ::HTTP.get_cached("youtube", url.as_str(), fresh) {
+            Ok(response) => response,
+            Err(_) => return Err(locale.get("youtube-error")),
+        };
+        let status = response.status;
+        if status == StatusCode::Ok {
+            let json_root = serde_json::from_slice::<Value>(&response.body).unwrap();
+            let video_id_ = json_root.pointer("/items/0/id/videoId").unwrap();
+            let video_id = video_id_.as_str().unwrap();
+            Ok(format!("https://youtu.be/{}", video_id))
+        } else {
+            Err(locale.get("youtube-error"))
+        }
+    }
 }
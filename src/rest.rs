@@ -0,0 +1,152 @@
+use discord::model::{ChannelId, MessageId};
+use http;
+use hyper;
+use hyper::header::{Authorization, Headers};
+use hyper::status::StatusCode;
+use serde_json::{self, Value};
+use std::fmt;
+use url::Url;
+
+// The requests discord-rs can't make, sent to the REST API directly.
+
+// For the base URL and the cache TTL in the config.
+const SERVICE: &'static str = "discord";
+
+// The name of the webhooks the bot creates, so that they can be found again after a restart.
+const WEBHOOK_NAME: &'static str = "yalter-bot";
+
+// Discord doesn't accept longer names.
+const MAX_USERNAME_LENGTH: usize = 80;
+
+#[derive(Clone, Deserialize)]
+pub struct Webhook {
+    id: String,
+    // Only present for the webhooks created by the bot.
+    token: Option<String>,
+    name: Option<String>,
+}
+
+// The parts of a channel discord-rs leaves out.
+#[derive(Deserialize)]
+struct Channel {
+    #[serde(default)]
+    nsfw: bool,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    // The bot doesn't have MANAGE_WEBHOOKS in the channel.
+    MissingPermissions,
+    // The webhook or the channel was deleted.
+    NotFound,
+    Status(StatusCode),
+    // The response didn't have the message ID.
+    InvalidResponse,
+    Http(hyper::Error),
+    Json(serde_json::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::MissingPermissions => write!(f, "Missing permissions"),
+            Error::NotFound => write!(f, "Not found"),
+            Error::Status(status) => write!(f, "Discord returned {}", status),
+            Error::InvalidResponse => write!(f, "Invalid response"),
+            Error::Http(ref err) => write!(f, "Network error: {}", err),
+            Error::Json(ref err) => write!(f, "JSON error: {}", err),
+        }
+    }
+}
+
+impl From<hyper::Error> for Error {
+    fn from(err: hyper::Error) -> Error {
+        Error::Http(err)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Error {
+        Error::Json(err)
+    }
+}
+
+fn api_url(path: &str) -> Url {
+    http::base_url(SERVICE, "https://discordapp.com/api/v6/").join(path).unwrap()
+}
+
+fn auth_headers(token: &str) -> Headers {
+    let mut headers = Headers::new();
+    headers.set(Authorization(format!("Bot {}", token)));
+    headers
+}
+
+fn check_status(status: StatusCode) -> Result<(), Error> {
+    match status {
+        x if x.is_success() => Ok(()),
+        StatusCode::Forbidden => Err(Error::MissingPermissions),
+        StatusCode::NotFound => Err(Error::NotFound),
+        x => Err(Error::Status(x)),
+    }
+}
+
+// Returns whether the server channel is marked NSFW.
+// The answer is cached for the TTL of the service, like the other API responses.
+pub fn is_nsfw(token: &str, channel: ChannelId) -> Result<bool, Error> {
+    let url = api_url(&format!("channels/{}", channel.0));
+
+    let response = try!(::HTTP.get_cached_with_headers(SERVICE, url.as_str(), auth_headers(token), false));
+    try!(check_status(response.status));
+
+    let channel: Channel = try!(serde_json::from_slice(&response.body));
+    Ok(channel.nsfw)
+}
+
+// Returns the webhook of the bot in the channel, creating it if there's none yet.
+pub fn find_or_create(token: &str, channel: ChannelId) -> Result<Webhook, Error> {
+    let url = api_url(&format!("channels/{}/webhooks", channel.0));
+
+    let response = try!(::HTTP.get_with_headers(url.as_str(), auth_headers(token)));
+    try!(check_status(response.status));
+
+    let webhooks: Vec<Webhook> = try!(serde_json::from_reader(response));
+
+    if let Some(webhook) = webhooks.into_iter()
+           .find(|x| x.token.is_some() && x.name.as_deref() == Some(WEBHOOK_NAME)) {
+        return Ok(webhook);
+    }
+
+    let mut body = serde_json::Map::new();
+    body.insert("name".to_owned(), Value::from(WEBHOOK_NAME));
+
+    let response = try!(::HTTP.post_json(url.as_str(), auth_headers(token), &Value::Object(body)));
+    try!(check_status(response.status));
+
+    Ok(try!(serde_json::from_reader(response)))
+}
+
+// Posts a message through the webhook under the given name and avatar, returning its ID.
+pub fn execute(webhook: &Webhook, username: &str, avatar_url: Option<&str>, text: &str) -> Result<MessageId, Error> {
+    let mut url = api_url(&format!("webhooks/{}/{}", webhook.id, webhook.token.as_ref().map_or("", |x| x)));
+    url.query_pairs_mut().append_pair("wait", "true");
+
+    let mut body = serde_json::Map::new();
+    body.insert("content".to_owned(), Value::from(text));
+    body.insert("username".to_owned(),
+                Value::from(username.chars().take(MAX_USERNAME_LENGTH).collect::<String>()));
+
+    if let Some(avatar_url) = avatar_url {
+        body.insert("avatar_url".to_owned(), Value::from(avatar_url));
+    }
+
+    let response = try!(::HTTP.post_json(url.as_str(), Headers::new(), &Value::Object(body)));
+    try!(check_status(response.status));
+
+    let message: Value = try!(serde_json::from_reader(response));
+
+    message.get("id")
+        .and_then(|x| x.as_str())
+        .and_then(|x| x.parse().ok())
+        .map(MessageId)
+        .ok_or(Error::InvalidResponse)
+}
@@ -0,0 +1,1243 @@
+// A tiny sandboxed scripting language for custom commands.
+//
+// Scripts are statements separated by semicolons:
+//
+//     let sides = int(args);
+//     if sides < 2 { sides = 6; }
+//     reply(mention + " rolled " + random(1, sides) + "!");
+//
+// There are `let`, assignments, `if`/`else`, `while`, `for x in list`, `break` and `return`;
+// integers, strings, booleans, `nil` and lists; the usual arithmetic, comparison and `&&`/`||`/`!` operators;
+// indexing with `list[i]` and calls of the built-in functions. Scripts can't do anything besides calling
+// the functions of the `Host`, and every run is limited in steps, allocated memory and time.
+
+use std::collections::hash_map::HashMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+// The maximum nesting of expressions and blocks, to keep the parser and the interpreter off the stack limit.
+const MAX_DEPTH: usize = 64;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Nil,
+    Bool(bool),
+    Int(i64),
+    Str(String),
+    List(Vec<Value>),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    // A syntax error on the given line.
+    Syntax(usize, String),
+    // A runtime error on the given line.
+    Runtime(usize, String),
+    // One of the limits was exceeded.
+    Limit(&'static str),
+}
+
+pub struct Limits {
+    // Every evaluated expression and executed statement is a step.
+    pub steps: u64,
+    // The total size of all strings and lists created during the run, in bytes.
+    pub memory: usize,
+    pub time: Duration,
+}
+
+// What the script can do to the world.
+pub trait Host {
+    fn reply(&mut self, text: String) -> Result<(), String>;
+    // Returns a random number between the two, inclusive.
+    fn random(&mut self, low: i64, high: i64) -> i64;
+    fn get(&mut self, key: &str) -> Result<Value, String>;
+    fn set(&mut self, key: &str, value: Value) -> Result<(), String>;
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Int(i64),
+    Str(String),
+    Ident(String),
+    // Punctuation and operators.
+    Symbol(&'static str),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    And,
+    Or,
+}
+
+#[derive(Debug)]
+enum Expr {
+    Literal(Value),
+    Var(String),
+    List(Vec<Expr>),
+    Index(Box<Expr>, Box<Expr>),
+    Call(String, Vec<Expr>),
+    Neg(Box<Expr>),
+    Not(Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug)]
+enum Stmt {
+    Let(String, Expr),
+    Assign(String, Expr),
+    If(Expr, Block, Block),
+    While(Expr, Block),
+    For(String, Expr, Block),
+    Expr(Expr),
+    Break,
+    Return,
+}
+
+// Statements with their line numbers.
+type Block = Vec<(usize, Stmt)>;
+
+// A parsed script, ready to run.
+#[derive(Debug)]
+pub struct Program {
+    statements: Block,
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    position: usize,
+    depth: usize,
+}
+
+enum Flow {
+    Normal,
+    Break,
+    Return,
+}
+
+struct Interpreter<'a> {
+    variables: HashMap<String, Value>,
+    host: &'a mut Host,
+    limits: &'a Limits,
+    steps: u64,
+    memory: usize,
+    start: Instant,
+    depth: usize,
+}
+
+// Symbols, longest first so that `==` isn't lexed as two `=`.
+static SYMBOLS: [&'static str; 23] = ["==", "!=", "<=", ">=", "&&", "||", "(", ")", "{", "}", "[", "]", ",", ";",
+                                      "=", "<", ">", "+", "-", "*", "/", "%", "!"];
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Value::Nil => write!(f, "nil"),
+            Value::Bool(x) => write!(f, "{}", x),
+            Value::Int(x) => write!(f, "{}", x),
+            Value::Str(ref x) => write!(f, "{}", x),
+            Value::List(ref x) => {
+                try!(write!(f, "["));
+
+                for (i, value) in x.iter().enumerate() {
+                    if i > 0 {
+                        try!(write!(f, ", "));
+                    }
+
+                    match *value {
+                        Value::Str(ref x) => try!(write!(f, "{:?}", x)),
+                        ref x => try!(write!(f, "{}", x)),
+                    }
+                }
+
+                write!(f, "]")
+            }
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Syntax(line, ref message) => write!(f, "syntax error on line {}: {}", line, message),
+            Error::Runtime(line, ref message) => write!(f, "error on line {}: {}", line, message),
+            Error::Limit(what) => write!(f, "the script exceeded the {} limit", what),
+        }
+    }
+}
+
+impl Value {
+    fn is_truthy(&self) -> bool {
+        match *self {
+            Value::Nil => false,
+            Value::Bool(x) => x,
+            Value::Int(x) => x != 0,
+            Value::Str(ref x) => !x.is_empty(),
+            Value::List(ref x) => !x.is_empty(),
+        }
+    }
+
+    fn type_name(&self) -> &'static str {
+        match *self {
+            Value::Nil => "nil",
+            Value::Bool(_) => "bool",
+            Value::Int(_) => "int",
+            Value::Str(_) => "string",
+            Value::List(_) => "list",
+        }
+    }
+
+    // An estimate of the memory the value takes.
+    fn size(&self) -> usize {
+        match *self {
+            Value::Str(ref x) => x.len(),
+            Value::List(ref x) => list_size(x),
+            _ => 8,
+        }
+    }
+}
+
+fn list_size(list: &[Value]) -> usize {
+    list.iter().map(|x| x.size() + 8).sum()
+}
+
+fn lex(source: &str) -> Result<Vec<(Token, usize)>, Error> {
+    let mut tokens = Vec::new();
+    let mut line = 1;
+    let mut chars = source.char_indices().peekable();
+
+    while let Some(&(start, c)) = chars.peek() {
+        if c == '\n' {
+            line += 1;
+            chars.next();
+        } else if c.is_whitespace() {
+            chars.next();
+        } else if c == '#' {
+            // Comments last until the end of the line.
+            while let Some(&(_, c)) = chars.peek() {
+                if c == '\n' {
+                    break;
+                }
+
+                chars.next();
+            }
+        } else if c.is_ascii_digit() {
+            let mut end = start;
+
+            while let Some(&(i, c)) = chars.peek() {
+                if !c.is_ascii_digit() {
+                    break;
+                }
+
+                end = i + c.len_utf8();
+                chars.next();
+            }
+
+            match source[start..end].parse() {
+                Ok(x) => tokens.push((Token::Int(x), line)),
+                Err(_) => return Err(Error::Syntax(line, "the number is too big".to_owned())),
+            }
+        } else if c.is_alphabetic() || c == '_' {
+            let mut end = start;
+
+            while let Some(&(i, c)) = chars.peek() {
+                if !c.is_alphanumeric() && c != '_' {
+                    break;
+                }
+
+                end = i + c.len_utf8();
+                chars.next();
+            }
+
+            tokens.push((Token::Ident(source[start..end].to_owned()), line));
+        } else if c == '"' {
+            chars.next();
+
+            let string_line = line;
+            let mut string = String::new();
+
+            loop {
+                match chars.next() {
+                    Some((_, '"')) => break,
+                    Some((_, '\\')) => {
+                        match chars.next() {
+                            Some((_, 'n')) => string.push('\n'),
+                            Some((_, 't')) => string.push('\t'),
+                            Some((_, c)) => string.push(c),
+                            None => return Err(Error::Syntax(string_line, "unterminated string".to_owned())),
+                        }
+                    }
+                    Some((_, c)) => {
+                        if c == '\n' {
+                            line += 1;
+                        }
+
+                        string.push(c);
+                    }
+                    None => return Err(Error::Syntax(string_line, "unterminated string".to_owned())),
+                }
+            }
+
+            tokens.push((Token::Str(string), string_line));
+        } else {
+            match SYMBOLS.iter().find(|x| source[start..].starts_with(*x)) {
+                Some(symbol) => {
+                    for _ in 0..symbol.len() {
+                        chars.next();
+                    }
+
+                    tokens.push((Token::Symbol(symbol), line));
+                }
+
+                None => return Err(Error::Syntax(line, format!("unexpected character `{}`", c))),
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+impl Parser {
+    fn line(&self) -> usize {
+        self.tokens
+            .get(self.position)
+            .or_else(|| self.tokens.last())
+            .map_or(1, |x| x.1)
+    }
+
+    fn error<T>(&self, message: &str) -> Result<T, Error> {
+        Err(Error::Syntax(self.line(), message.to_owned()))
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|x| &x.0)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).map(|x| x.0.clone());
+        self.position += 1;
+        token
+    }
+
+    fn is_symbol(&self, symbol: &str) -> bool {
+        match self.peek() {
+            Some(&Token::Symbol(x)) => x == symbol,
+            _ => false,
+        }
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(Token::Ident(x)) => x == keyword,
+            _ => false,
+        }
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<(), Error> {
+        if self.is_symbol(symbol) {
+            self.position += 1;
+            Ok(())
+        } else {
+            self.error(&format!("expected `{}`", symbol))
+        }
+    }
+
+    fn identifier(&mut self) -> Result<String, Error> {
+        match self.next() {
+            Some(Token::Ident(x)) => Ok(x),
+            _ => {
+                self.position -= 1;
+                self.error("expected a name")
+            }
+        }
+    }
+
+    fn enter(&mut self) -> Result<(), Error> {
+        self.depth += 1;
+
+        if self.depth > MAX_DEPTH {
+            return self.error("the script is nested too deeply");
+        }
+
+        Ok(())
+    }
+
+    fn program(&mut self) -> Result<Block, Error> {
+        let mut statements = Vec::new();
+
+        while self.peek().is_some() {
+            statements.push((self.line(), try!(self.statement())));
+        }
+
+        Ok(statements)
+    }
+
+    fn block(&mut self) -> Result<Block, Error> {
+        try!(self.enter());
+        try!(self.expect("{"));
+
+        let mut statements = Vec::new();
+
+        while !self.is_symbol("}") {
+            if self.peek().is_none() {
+                return self.error("expected `}`");
+            }
+
+            statements.push((self.line(), try!(self.statement())));
+        }
+
+        self.position += 1;
+        self.depth -= 1;
+
+        Ok(statements)
+    }
+
+    fn statement(&mut self) -> Result<Stmt, Error> {
+        if self.is_keyword("let") {
+            self.position += 1;
+            let name = try!(self.identifier());
+            try!(self.expect("="));
+            let value = try!(self.expression());
+            try!(self.expect(";"));
+
+            Ok(Stmt::Let(name, value))
+        } else if self.is_keyword("if") {
+            self.position += 1;
+            self.if_statement()
+        } else if self.is_keyword("while") {
+            self.position += 1;
+            let condition = try!(self.expression());
+            let body = try!(self.block());
+
+            Ok(Stmt::While(condition, body))
+        } else if self.is_keyword("for") {
+            self.position += 1;
+            let name = try!(self.identifier());
+
+            if !self.is_keyword("in") {
+                return self.error("expected `in`");
+            }
+
+            self.position += 1;
+            let list = try!(self.expression());
+            let body = try!(self.block());
+
+            Ok(Stmt::For(name, list, body))
+        } else if self.is_keyword("break") {
+            self.position += 1;
+            try!(self.expect(";"));
+
+            Ok(Stmt::Break)
+        } else if self.is_keyword("return") {
+            self.position += 1;
+            try!(self.expect(";"));
+
+            Ok(Stmt::Return)
+        } else {
+            let expression = try!(self.expression());
+
+            if self.is_symbol("=") {
+                let name = match expression {
+                    Expr::Var(name) => name,
+                    _ => return self.error("only variables can be assigned to"),
+                };
+
+                self.position += 1;
+                let value = try!(self.expression());
+                try!(self.expect(";"));
+
+                return Ok(Stmt::Assign(name, value));
+            }
+
+            try!(self.expect(";"));
+
+            Ok(Stmt::Expr(expression))
+        }
+    }
+
+    fn if_statement(&mut self) -> Result<Stmt, Error> {
+        let condition = try!(self.expression());
+        let then = try!(self.block());
+
+        let otherwise = if self.is_keyword("else") {
+            self.position += 1;
+
+            if self.is_keyword("if") {
+                self.position += 1;
+                let line = self.line();
+                try!(self.enter());
+                let statement = try!(self.if_statement());
+                self.depth -= 1;
+
+                vec![(line, statement)]
+            } else {
+                try!(self.block())
+            }
+        } else {
+            Vec::new()
+        };
+
+        Ok(Stmt::If(condition, then, otherwise))
+    }
+
+    fn expression(&mut self) -> Result<Expr, Error> {
+        try!(self.enter());
+        let expression = try!(self.binary(0));
+        self.depth -= 1;
+
+        Ok(expression)
+    }
+
+    // Parses binary operators by precedence, from the loosest.
+    fn binary(&mut self, level: usize) -> Result<Expr, Error> {
+        static LEVELS: [&'static [(&'static str, BinaryOp)]; 5] =
+            [&[("||", BinaryOp::Or)],
+             &[("&&", BinaryOp::And)],
+             &[("==", BinaryOp::Eq),
+               ("!=", BinaryOp::Ne),
+               ("<=", BinaryOp::Le),
+               (">=", BinaryOp::Ge),
+               ("<", BinaryOp::Lt),
+               (">", BinaryOp::Gt)],
+             &[("+", BinaryOp::Add), ("-", BinaryOp::Sub)],
+             &[("*", BinaryOp::Mul), ("/", BinaryOp::Div), ("%", BinaryOp::Rem)]];
+
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+
+        let mut left = try!(self.binary(level + 1));
+
+        loop {
+            let op = match LEVELS[level].iter().find(|&&(symbol, _)| self.is_symbol(symbol)) {
+                Some(&(_, op)) => op,
+                None => return Ok(left),
+            };
+
+            self.position += 1;
+            let right = try!(self.binary(level + 1));
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, Error> {
+        if self.is_symbol("-") {
+            self.position += 1;
+            try!(self.enter());
+            let operand = try!(self.unary());
+            self.depth -= 1;
+
+            Ok(Expr::Neg(Box::new(operand)))
+        } else if self.is_symbol("!") {
+            self.position += 1;
+            try!(self.enter());
+            let operand = try!(self.unary());
+            self.depth -= 1;
+
+            Ok(Expr::Not(Box::new(operand)))
+        } else {
+            self.postfix()
+        }
+    }
+
+    fn postfix(&mut self) -> Result<Expr, Error> {
+        let mut expression = try!(self.primary());
+
+        while self.is_symbol("[") {
+            self.position += 1;
+            let index = try!(self.expression());
+            try!(self.expect("]"));
+
+            expression = Expr::Index(Box::new(expression), Box::new(index));
+        }
+
+        Ok(expression)
+    }
+
+    fn primary(&mut self) -> Result<Expr, Error> {
+        match self.next() {
+            Some(Token::Int(x)) => Ok(Expr::Literal(Value::Int(x))),
+            Some(Token::Str(x)) => Ok(Expr::Literal(Value::Str(x))),
+
+            Some(Token::Ident(name)) => {
+                match name.as_str() {
+                    "true" => return Ok(Expr::Literal(Value::Bool(true))),
+                    "false" => return Ok(Expr::Literal(Value::Bool(false))),
+                    "nil" => return Ok(Expr::Literal(Value::Nil)),
+                    _ => {}
+                }
+
+                if self.is_symbol("(") {
+                    self.position += 1;
+                    let arguments = try!(self.list(")"));
+
+                    Ok(Expr::Call(name, arguments))
+                } else {
+                    Ok(Expr::Var(name))
+                }
+            }
+
+            Some(Token::Symbol("(")) => {
+                let expression = try!(self.expression());
+                try!(self.expect(")"));
+
+                Ok(expression)
+            }
+
+            Some(Token::Symbol("[")) => Ok(Expr::List(try!(self.list("]")))),
+
+            _ => {
+                self.position -= 1;
+                self.error("expected an expression")
+            }
+        }
+    }
+
+    // Parses comma-separated expressions up to the closing symbol.
+    fn list(&mut self, end: &str) -> Result<Vec<Expr>, Error> {
+        let mut expressions = Vec::new();
+
+        while !self.is_symbol(end) {
+            expressions.push(try!(self.expression()));
+
+            if !self.is_symbol(end) {
+                try!(self.expect(","));
+            }
+        }
+
+        self.position += 1;
+
+        Ok(expressions)
+    }
+}
+
+pub fn parse(source: &str) -> Result<Program, Error> {
+    let mut parser = Parser {
+        tokens: try!(lex(source)),
+        position: 0,
+        depth: 0,
+    };
+
+    Ok(Program { statements: try!(parser.program()) })
+}
+
+// Runs the program with the given global variables.
+pub fn run(program: &Program, globals: Vec<(&str, Value)>, host: &mut Host, limits: &Limits) -> Result<(), Error> {
+    let mut interpreter = Interpreter {
+        variables: globals.into_iter().map(|(name, value)| (name.to_owned(), value)).collect(),
+        host: host,
+        limits: limits,
+        steps: 0,
+        memory: 0,
+        start: Instant::now(),
+        depth: 0,
+    };
+
+    try!(interpreter.block(&program.statements));
+
+    Ok(())
+}
+
+// The line is filled in by the statement the error happens in.
+fn runtime_error<T>(message: String) -> Result<T, Error> {
+    Err(Error::Runtime(0, message))
+}
+
+impl<'a> Interpreter<'a> {
+    fn step(&mut self) -> Result<(), Error> {
+        self.steps += 1;
+
+        if self.steps > self.limits.steps {
+            return Err(Error::Limit("step"));
+        }
+
+        // Checking the clock is slow-ish, so don't do it every time.
+        if self.steps & 1023 == 0 && self.start.elapsed() > self.limits.time {
+            return Err(Error::Limit("time"));
+        }
+
+        Ok(())
+    }
+
+    // Accounts for a newly created value.
+    fn allocate(&mut self, value: Value) -> Result<Value, Error> {
+        try!(self.reserve(value.size()));
+        Ok(value)
+    }
+
+    // Accounts for a value before building it, by an estimate of its size, so that a huge one
+    // hits the limit instead of the allocator. Whatever the estimate missed is accounted for after.
+    fn allocate_estimated<F: FnOnce() -> Value>(&mut self, estimate: usize, build: F) -> Result<Value, Error> {
+        try!(self.reserve(estimate));
+        let value = build();
+        let size = value.size();
+        try!(self.reserve(size.saturating_sub(estimate)));
+        Ok(value)
+    }
+
+    fn reserve(&mut self, size: usize) -> Result<(), Error> {
+        self.memory = self.memory.saturating_add(size);
+
+        if self.memory > self.limits.memory {
+            return Err(Error::Limit("memory"));
+        }
+
+        Ok(())
+    }
+
+    fn block(&mut self, statements: &[(usize, Stmt)]) -> Result<Flow, Error> {
+        for &(line, ref statement) in statements {
+            match self.statement(statement) {
+                Ok(Flow::Normal) => {}
+                Ok(flow) => return Ok(flow),
+                Err(Error::Runtime(0, message)) => return Err(Error::Runtime(line, message)),
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok(Flow::Normal)
+    }
+
+    fn statement(&mut self, statement: &Stmt) -> Result<Flow, Error> {
+        try!(self.step());
+
+        match *statement {
+            Stmt::Let(ref name, ref value) => {
+                let value = try!(self.expression(value));
+                self.variables.insert(name.clone(), value);
+            }
+
+            Stmt::Assign(ref name, ref value) => {
+                if !self.variables.contains_key(name) {
+                    return runtime_error(format!("`{}` isn't defined, use `let {0} = ...;`", name));
+                }
+
+                let value = try!(self.expression(value));
+                self.variables.insert(name.clone(), value);
+            }
+
+            Stmt::If(ref condition, ref then, ref otherwise) => {
+                let flow = if try!(self.expression(condition)).is_truthy() {
+                    try!(self.block(then))
+                } else {
+                    try!(self.block(otherwise))
+                };
+
+                return Ok(flow);
+            }
+
+            Stmt::While(ref condition, ref body) => {
+                while try!(self.expression(condition)).is_truthy() {
+                    match try!(self.block(body)) {
+                        Flow::Normal => {}
+                        Flow::Break => break,
+                        Flow::Return => return Ok(Flow::Return),
+                    }
+                }
+            }
+
+            Stmt::For(ref name, ref list, ref body) => {
+                let list = match try!(self.expression(list)) {
+                    Value::List(x) => x,
+                    x => return runtime_error(format!("can't loop over a {}", x.type_name())),
+                };
+
+                for value in list {
+                    self.variables.insert(name.clone(), value);
+
+                    match try!(self.block(body)) {
+                        Flow::Normal => {}
+                        Flow::Break => break,
+                        Flow::Return => return Ok(Flow::Return),
+                    }
+                }
+            }
+
+            Stmt::Expr(ref expression) => {
+                try!(self.expression(expression));
+            }
+
+            Stmt::Break => return Ok(Flow::Break),
+            Stmt::Return => return Ok(Flow::Return),
+        }
+
+        Ok(Flow::Normal)
+    }
+
+    fn expression(&mut self, expression: &Expr) -> Result<Value, Error> {
+        try!(self.step());
+
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(Error::Limit("nesting"));
+        }
+
+        let value = self.evaluate(expression);
+        self.depth -= 1;
+
+        value
+    }
+
+    fn evaluate(&mut self, expression: &Expr) -> Result<Value, Error> {
+        match *expression {
+            Expr::Literal(ref value) => Ok(value.clone()),
+
+            Expr::Var(ref name) => {
+                let value = match self.variables.get(name) {
+                    Some(value) => value.clone(),
+                    None => return runtime_error(format!("`{}` isn't defined", name)),
+                };
+
+                // Strings and lists are copied.
+                match value {
+                    Value::Str(_) | Value::List(_) => self.allocate(value),
+                    value => Ok(value),
+                }
+            }
+
+            Expr::List(ref expressions) => {
+                let mut list = Vec::new();
+
+                for expression in expressions {
+                    list.push(try!(self.expression(expression)));
+                }
+
+                // The items are accounted for already.
+                try!(self.reserve(list.len() * 8));
+                Ok(Value::List(list))
+            }
+
+            Expr::Index(ref list, ref index) => {
+                let list = try!(self.expression(list));
+                let index = try!(self.expression(index));
+
+                match (list, index) {
+                    (Value::List(list), Value::Int(i)) => {
+                        let i = if i < 0 { list.len() as i64 + i } else { i };
+                        Ok(if i < 0 { None } else { list.into_iter().nth(i as usize) }.unwrap_or(Value::Nil))
+                    }
+                    (Value::Str(string), Value::Int(i)) => {
+                        let count = string.chars().count() as i64;
+                        let i = if i < 0 { count + i } else { i };
+                        Ok(if i < 0 { None } else { string.chars().nth(i as usize) }
+                               .map_or(Value::Nil, |c| Value::Str(c.to_string())))
+                    }
+                    (list, index) => {
+                        runtime_error(format!("can't index a {} with a {}", list.type_name(), index.type_name()))
+                    }
+                }
+            }
+
+            Expr::Call(ref name, ref arguments) => {
+                let mut values = Vec::new();
+
+                for argument in arguments {
+                    values.push(try!(self.expression(argument)));
+                }
+
+                self.call(name, values)
+            }
+
+            Expr::Neg(ref operand) => {
+                match try!(self.expression(operand)) {
+                    Value::Int(x) => x.checked_neg().map(Value::Int).ok_or(Error::Runtime(0, "overflow".to_owned())),
+                    x => runtime_error(format!("can't negate a {}", x.type_name())),
+                }
+            }
+
+            Expr::Not(ref operand) => Ok(Value::Bool(!try!(self.expression(operand)).is_truthy())),
+
+            Expr::Binary(BinaryOp::And, ref left, ref right) => {
+                let left = try!(self.expression(left));
+
+                if left.is_truthy() {
+                    self.expression(right)
+                } else {
+                    Ok(left)
+                }
+            }
+
+            Expr::Binary(BinaryOp::Or, ref left, ref right) => {
+                let left = try!(self.expression(left));
+
+                if left.is_truthy() {
+                    Ok(left)
+                } else {
+                    self.expression(right)
+                }
+            }
+
+            Expr::Binary(op, ref left, ref right) => {
+                let left = try!(self.expression(left));
+                let right = try!(self.expression(right));
+
+                self.binary(op, left, right)
+            }
+        }
+    }
+
+    fn binary(&mut self, op: BinaryOp, left: Value, right: Value) -> Result<Value, Error> {
+        let overflow = || Error::Runtime(0, "overflow".to_owned());
+
+        match (op, left, right) {
+            (BinaryOp::Eq, left, right) => Ok(Value::Bool(left == right)),
+            (BinaryOp::Ne, left, right) => Ok(Value::Bool(left != right)),
+
+            (BinaryOp::Add, Value::Int(a), Value::Int(b)) => a.checked_add(b).map(Value::Int).ok_or_else(overflow),
+            (BinaryOp::Sub, Value::Int(a), Value::Int(b)) => a.checked_sub(b).map(Value::Int).ok_or_else(overflow),
+            (BinaryOp::Mul, Value::Int(a), Value::Int(b)) => a.checked_mul(b).map(Value::Int).ok_or_else(overflow),
+            (BinaryOp::Div, Value::Int(_), Value::Int(0)) |
+            (BinaryOp::Rem, Value::Int(_), Value::Int(0)) => runtime_error("division by zero".to_owned()),
+            (BinaryOp::Div, Value::Int(a), Value::Int(b)) => a.checked_div(b).map(Value::Int).ok_or_else(overflow),
+            (BinaryOp::Rem, Value::Int(a), Value::Int(b)) => a.checked_rem(b).map(Value::Int).ok_or_else(overflow),
+
+            (BinaryOp::Lt, Value::Int(a), Value::Int(b)) => Ok(Value::Bool(a < b)),
+            (BinaryOp::Le, Value::Int(a), Value::Int(b)) => Ok(Value::Bool(a <= b)),
+            (BinaryOp::Gt, Value::Int(a), Value::Int(b)) => Ok(Value::Bool(a > b)),
+            (BinaryOp::Ge, Value::Int(a), Value::Int(b)) => Ok(Value::Bool(a >= b)),
+            (BinaryOp::Lt, Value::Str(a), Value::Str(b)) => Ok(Value::Bool(a < b)),
+            (BinaryOp::Le, Value::Str(a), Value::Str(b)) => Ok(Value::Bool(a <= b)),
+            (BinaryOp::Gt, Value::Str(a), Value::Str(b)) => Ok(Value::Bool(a > b)),
+            (BinaryOp::Ge, Value::Str(a), Value::Str(b)) => Ok(Value::Bool(a >= b)),
+
+            (BinaryOp::Add, Value::List(mut a), Value::List(b)) => {
+                try!(self.reserve(list_size(&b)));
+                a.extend(b);
+                Ok(Value::List(a))
+            }
+
+            // Adding anything to a string concatenates.
+            (BinaryOp::Add, Value::Str(a), b) => {
+                let b = b.to_string();
+                try!(self.reserve(a.len() + b.len()));
+                Ok(Value::Str(a + &b))
+            }
+            (BinaryOp::Add, a, Value::Str(b)) => {
+                let a = a.to_string();
+                try!(self.reserve(a.len() + b.len()));
+                Ok(Value::Str(a + &b))
+            }
+
+            (BinaryOp::Mul, Value::Str(a), Value::Int(b)) => {
+                if b < 0 {
+                    return runtime_error("can't repeat a string a negative number of times".to_owned());
+                }
+
+                try!(self.reserve(a.len().saturating_mul(b as usize)));
+                Ok(Value::Str(a.repeat(b as usize)))
+            }
+
+            (op, left, right) => {
+                runtime_error(format!("can't use {:?} on a {} and a {}",
+                                      op,
+                                      left.type_name(),
+                                      right.type_name()))
+            }
+        }
+    }
+
+    fn call(&mut self, name: &str, mut arguments: Vec<Value>) -> Result<Value, Error> {
+        macro_rules! arity {
+            ($count:expr) => {
+                if arguments.len() != $count {
+                    return runtime_error(format!("`{}` takes {} arguments", name, $count));
+                }
+            }
+        }
+
+        match name {
+            "reply" => {
+                let text = arguments.iter().map(|x| x.to_string()).collect::<Vec<String>>().join(" ");
+                try!(self.host.reply(text).map_err(|x| Error::Runtime(0, x)));
+                Ok(Value::Nil)
+            }
+
+            "len" => {
+                arity!(1);
+                match arguments[0] {
+                    Value::Str(ref x) => Ok(Value::Int(x.chars().count() as i64)),
+                    Value::List(ref x) => Ok(Value::Int(x.len() as i64)),
+                    ref x => runtime_error(format!("a {} has no length", x.type_name())),
+                }
+            }
+
+            "str" => {
+                arity!(1);
+                let value = Value::Str(arguments[0].to_string());
+                self.allocate(value)
+            }
+
+            "int" => {
+                arity!(1);
+                match arguments[0] {
+                    Value::Int(x) => Ok(Value::Int(x)),
+                    Value::Str(ref x) => Ok(x.trim().parse().map(Value::Int).unwrap_or(Value::Nil)),
+                    Value::Bool(x) => Ok(Value::Int(x as i64)),
+                    _ => Ok(Value::Nil),
+                }
+            }
+
+            "random" => {
+                arity!(2);
+                match (&arguments[0], &arguments[1]) {
+                    (&Value::Int(low), &Value::Int(high)) if low <= high => Ok(Value::Int(self.host.random(low, high))),
+                    _ => runtime_error("`random` takes two numbers, the first not bigger than the second".to_owned()),
+                }
+            }
+
+            "choose" => {
+                arity!(1);
+                match arguments.pop().unwrap() {
+                    Value::List(ref x) if x.is_empty() => Ok(Value::Nil),
+                    Value::List(mut x) => {
+                        let i = self.host.random(0, x.len() as i64 - 1);
+                        Ok(x.swap_remove(i as usize))
+                    }
+                    x => runtime_error(format!("can't choose from a {}", x.type_name())),
+                }
+            }
+
+            "range" => {
+                arity!(2);
+                match (&arguments[0], &arguments[1]) {
+                    (&Value::Int(low), &Value::Int(high)) => {
+                        if high > low {
+                            let count = match high.checked_sub(low) {
+                                Some(count) => count,
+                                None => return runtime_error("the range is too big".to_owned()),
+                            };
+
+                            try!(self.reserve((count as usize).saturating_mul(16)));
+                        }
+
+                        Ok(Value::List((low..high).map(Value::Int).collect()))
+                    }
+                    _ => runtime_error("`range` takes two numbers".to_owned()),
+                }
+            }
+
+            "split" => {
+                let list = match (arguments.first(), arguments.get(1), arguments.len()) {
+                    (Some(Value::Str(x)), _, 1) => x.split_whitespace().map(|x| Value::Str(x.to_owned())).collect(),
+                    (Some(Value::Str(x)), Some(Value::Str(separator)), 2) if !separator.is_empty() => {
+                        x.split(separator.as_str()).map(|x| Value::Str(x.to_owned())).collect()
+                    }
+                    _ => return runtime_error("`split` takes a string and an optional separator".to_owned()),
+                };
+
+                self.allocate(Value::List(list))
+            }
+
+            "join" => {
+                arity!(2);
+                match (&arguments[0], &arguments[1]) {
+                    (Value::List(x), Value::Str(separator)) => {
+                        let estimate = list_size(x).saturating_add(separator.len().saturating_mul(x.len()));
+                        self.allocate_estimated(estimate, || {
+                            Value::Str(x.iter().map(|x| x.to_string()).collect::<Vec<String>>().join(separator))
+                        })
+                    }
+                    _ => runtime_error("`join` takes a list and a separator".to_owned()),
+                }
+            }
+
+            "push" => {
+                arity!(2);
+                let value = arguments.pop().unwrap();
+                match arguments.pop().unwrap() {
+                    Value::List(mut x) => {
+                        try!(self.reserve(value.size() + 8));
+                        x.push(value);
+                        Ok(Value::List(x))
+                    }
+                    x => runtime_error(format!("can't push to a {}", x.type_name())),
+                }
+            }
+
+            "contains" => {
+                arity!(2);
+                match (&arguments[0], &arguments[1]) {
+                    (Value::Str(x), Value::Str(y)) => Ok(Value::Bool(x.contains(y.as_str()))),
+                    (Value::List(x), y) => Ok(Value::Bool(x.contains(y))),
+                    (x, _) => runtime_error(format!("a {} can't contain anything", x.type_name())),
+                }
+            }
+
+            "replace" => {
+                arity!(3);
+                match (&arguments[0], &arguments[1], &arguments[2]) {
+                    (Value::Str(x), Value::Str(from), Value::Str(to)) if !from.is_empty() => {
+                        let estimate = x.len().saturating_add((x.len() / from.len()).saturating_mul(to.len()));
+                        self.allocate_estimated(estimate, || Value::Str(x.replace(from.as_str(), to)))
+                    }
+                    _ => runtime_error("`replace` takes three strings".to_owned()),
+                }
+            }
+
+            "upper" | "lower" | "trim" => {
+                arity!(1);
+                match arguments[0] {
+                    Value::Str(ref x) => {
+                        self.allocate_estimated(x.len(), || match name {
+                            "upper" => Value::Str(x.to_uppercase()),
+                            "lower" => Value::Str(x.to_lowercase()),
+                            _ => Value::Str(x.trim().to_owned()),
+                        })
+                    }
+                    ref x => runtime_error(format!("`{}` takes a string, not a {}", name, x.type_name())),
+                }
+            }
+
+            "get" => {
+                arity!(1);
+                match arguments[0] {
+                    Value::Str(ref key) => {
+                        let value = try!(self.host.get(key).map_err(|x| Error::Runtime(0, x)));
+                        self.allocate(value)
+                    }
+                    _ => runtime_error("`get` takes a string key".to_owned()),
+                }
+            }
+
+            "set" => {
+                arity!(2);
+                let value = arguments.pop().unwrap();
+                match arguments[0] {
+                    Value::Str(ref key) => {
+                        try!(self.host.set(key, value).map_err(|x| Error::Runtime(0, x)));
+                        Ok(Value::Nil)
+                    }
+                    _ => runtime_error("`set` takes a string key".to_owned()),
+                }
+            }
+
+            _ => runtime_error(format!("there's no function called `{}`", name)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::hash_map::HashMap;
+    use std::time::Duration;
+
+    struct TestHost {
+        replies: Vec<String>,
+        storage: HashMap<String, Value>,
+    }
+
+    impl Host for TestHost {
+        fn reply(&mut self, text: String) -> Result<(), String> {
+            self.replies.push(text);
+            Ok(())
+        }
+
+        fn random(&mut self, low: i64, _high: i64) -> i64 {
+            low
+        }
+
+        fn get(&mut self, key: &str) -> Result<Value, String> {
+            Ok(self.storage.get(key).cloned().unwrap_or(Value::Nil))
+        }
+
+        fn set(&mut self, key: &str, value: Value) -> Result<(), String> {
+            self.storage.insert(key.to_owned(), value);
+            Ok(())
+        }
+    }
+
+    fn run_script(source: &str) -> (Result<(), Error>, Vec<String>) {
+        let limits = Limits {
+            steps: 10000,
+            memory: 10000,
+            time: Duration::from_secs(1),
+        };
+
+        let mut host = TestHost {
+            replies: Vec::new(),
+            storage: HashMap::new(),
+        };
+
+        let result = parse(source)
+            .and_then(|program| run(&program, vec![("args", Value::Str("a b c".to_owned()))], &mut host, &limits));
+
+        (result, host.replies)
+    }
+
+    #[test]
+    fn script_usual() {
+        let (result, replies) = run_script("let words = split(args);\n\
+                                            for word in words { reply(upper(word) + len(word)); }\n\
+                                            if len(words) > 2 && words[-1] == \"c\" { reply(\"last\", words[-1]); }");
+        assert_eq!(Ok(()), result);
+        assert_eq!(vec!["A1", "B1", "C1", "last c"], replies);
+    }
+
+    #[test]
+    fn script_arithmetic() {
+        let (result, replies) = run_script("let x = 1 + 2 * 3 - -4 % 3; reply(x, 7 / 2, (1 + 2) * 3);");
+        assert_eq!(Ok(()), result);
+        assert_eq!(vec!["8 3 9"], replies);
+    }
+
+    #[test]
+    fn script_loops() {
+        let (result, replies) = run_script("let i = 0; while true { i = i + 1; if i == 5 { break; } } reply(i);\n\
+                                            for x in range(0, 10) { if x == 2 { return; } reply(x); }");
+        assert_eq!(Ok(()), result);
+        assert_eq!(vec!["5", "0", "1"], replies);
+    }
+
+    #[test]
+    fn script_storage() {
+        let (result, replies) = run_script("let count = get(\"count\"); if count == nil { count = 0; }\n\
+                                            set(\"count\", count + 1); reply(get(\"count\"), get(\"x\"));");
+        assert_eq!(Ok(()), result);
+        assert_eq!(vec!["1 nil"], replies);
+
+        let (result, replies) = run_script("set(\"count\", 1); set(\"count\", get(\"count\") + 1); reply(get(\"count\"));");
+        assert_eq!(Ok(()), result);
+        assert_eq!(vec!["2"], replies);
+    }
+
+    #[test]
+    fn script_syntax_error() {
+        assert_eq!(Err(Error::Syntax(2, "expected `;`".to_owned())),
+                   parse("let x = 1;\nlet y = 2").map(|_| ()));
+        assert_eq!(Err(Error::Syntax(1, "unterminated string".to_owned())),
+                   parse("reply(\"hi);").map(|_| ()));
+        assert!(parse(&"(".repeat(1000)).is_err());
+    }
+
+    #[test]
+    fn script_runtime_error() {
+        let (result, _) = run_script("reply(1 / 0);");
+        assert_eq!(Err(Error::Runtime(1, "division by zero".to_owned())), result);
+
+        let (result, _) = run_script("let x = [1];\nif true {\n    x = x + 1;\n}");
+        assert_eq!(Err(Error::Runtime(3, "can't use Add on a list and a int".to_owned())), result);
+    }
+
+    #[test]
+    fn script_limits() {
+        assert_eq!(Err(Error::Limit("step")), run_script("while true { }").0);
+        assert_eq!(Err(Error::Limit("memory")), run_script("let s = \"aaaa\"; while true { s = s + s; }").0);
+        assert_eq!(Err(Error::Limit("memory")), run_script("let x = range(0, 100000000);").0);
+        assert_eq!(Err(Error::Limit("memory")), run_script("let l = [1, 2]; while true { l = l + l; }").0);
+        assert_eq!(Err(Error::Limit("memory")),
+                   run_script("let s = \"x\" * 100; let t = replace(s, \"x\", s);").0);
+        assert_eq!(Err(Error::Runtime(1, "the range is too big".to_owned())),
+                   run_script("let x = range(-9223372036854775807 - 1, 9223372036854775807);").0);
+    }
+}
@@ -0,0 +1,463 @@
+use discord::model::{ServerId, UserId};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json;
+use serde_json::{Map, Value};
+use std;
+use std::collections::hash_map::HashMap;
+use std::error;
+use std::fmt;
+use std::io;
+#[cfg(feature = "sqlite")]
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+mod json;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+pub use self::json::JsonBackend;
+#[cfg(feature = "sqlite")]
+pub use self::sqlite::SqliteBackend;
+
+// The SQLite database is kept in this file in the storage directory.
+#[cfg(feature = "sqlite")]
+const SQLITE_FILENAME: &'static str = "storage.sqlite3";
+
+// What a stored value belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    Global,
+    Server(ServerId),
+    User(UserId),
+    // A user on a specific server.
+    Member(ServerId, UserId),
+}
+
+// Upgrades the data of a module by one schema version.
+// The data is an object from scope keys into objects of stored values.
+pub type Migration = fn(&mut Map<String, Value>) -> std::result::Result<(), String>;
+
+#[derive(Debug)]
+pub enum Error {
+    IO(io::Error),
+    Json(serde_json::error::Error),
+    Sqlite(String),
+    Migration(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+// Where the data is actually kept. Scopes are passed as their keys.
+// The storage never accesses one module from several threads at once, different modules can be.
+pub trait Backend: Send + Sync {
+    // The schema version of the module's data, 0 if there's no data.
+    fn get_version(&self, module: &str) -> Result<u32>;
+
+    // Returns all data of the module, for migrations.
+    fn load(&self, module: &str) -> Result<Map<String, Value>>;
+
+    // Replaces all data of the module at once.
+    fn replace(&self, module: &str, version: u32, data: &Map<String, Value>) -> Result<()>;
+
+    fn get(&self, module: &str, scope: &str, key: &str) -> Result<Option<Value>>;
+
+    // Passing None removes the value.
+    fn set(&self, module: &str, scope: &str, key: &str, value: Option<&Value>) -> Result<()>;
+
+    // Returns the keys of all scopes which have the given key set.
+    fn scopes_with(&self, module: &str, key: &str) -> Result<Vec<String>>;
+
+    // Returns the keys in the scope which start with the prefix, sorted.
+    fn keys(&self, module: &str, scope: &str, prefix: &str) -> Result<Vec<String>>;
+}
+
+// Persistent key-value storage for modules.
+pub struct Storage {
+    backend: Box<Backend>,
+    migrations: Mutex<HashMap<String, &'static [Migration]>>,
+    // Every module has its own lock, holding whether its data was checked to be of the current version.
+    modules: Mutex<HashMap<String, Arc<Mutex<bool>>>>,
+}
+
+impl Scope {
+    // The name of the scope in the storage.
+    pub fn key(&self) -> String {
+        match *self {
+            Scope::Global => "global".to_owned(),
+            Scope::Server(server) => format!("server:{}", server.0),
+            Scope::User(user) => format!("user:{}", user.0),
+            Scope::Member(server, user) => format!("member:{}:{}", server.0, user.0),
+        }
+    }
+
+    fn from_key(key: &str) -> Option<Scope> {
+        let parts: Vec<&str> = key.split(':').collect();
+
+        match (parts[0], parts.len()) {
+            ("global", 1) => Some(Scope::Global),
+            ("server", 2) => parts[1].parse().ok().map(|x| Scope::Server(ServerId(x))),
+            ("user", 2) => parts[1].parse().ok().map(|x| Scope::User(UserId(x))),
+            ("member", 3) => {
+                match (parts[1].parse(), parts[2].parse()) {
+                    (Ok(server), Ok(user)) => Some(Scope::Member(ServerId(server), UserId(user))),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::IO(ref err) => write!(f, "IO error: {}", err),
+            Error::Json(ref err) => write!(f, "JSON error: {}", err),
+            Error::Sqlite(ref err) => write!(f, "SQLite error: {}", err),
+            Error::Migration(ref err) => write!(f, "Migration error: {}", err),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::IO(ref err) => err.description(),
+            Error::Json(ref err) => err.description(),
+            Error::Sqlite(ref err) => err,
+            Error::Migration(ref err) => err,
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            Error::IO(ref err) => Some(err),
+            Error::Json(ref err) => Some(err),
+            Error::Sqlite(_) => None,
+            Error::Migration(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::IO(err)
+    }
+}
+
+impl From<serde_json::error::Error> for Error {
+    fn from(err: serde_json::error::Error) -> Error {
+        Error::Json(err)
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl From<::rusqlite::Error> for Error {
+    fn from(err: ::rusqlite::Error) -> Error {
+        Error::Sqlite(err.to_string())
+    }
+}
+
+impl Storage {
+    pub fn new(backend: Box<Backend>) -> Self {
+        Storage {
+            backend: backend,
+            migrations: Mutex::new(HashMap::new()),
+            modules: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Opens the storage with the backend called `kind` ("json" or "sqlite") in the given directory.
+    pub fn open(kind: &str, directory: &str) -> Result<Self> {
+        let backend: Box<Backend> = match kind {
+            "json" => Box::new(JsonBackend::new(directory)),
+            #[cfg(feature = "sqlite")]
+            "sqlite" => Box::new(try!(SqliteBackend::open(&Path::new(directory).join(SQLITE_FILENAME)))),
+            #[cfg(not(feature = "sqlite"))]
+            "sqlite" => {
+                return Err(Error::Sqlite("the bot was built without SQLite support, build it with \
+                                          `cargo build --features sqlite`"
+                                             .to_owned()))
+            }
+            _ => {
+                return Err(Error::IO(io::Error::new(io::ErrorKind::InvalidInput,
+                                                    format!("unknown storage backend `{}`", kind))))
+            }
+        };
+
+        Ok(Storage::new(backend))
+    }
+
+    // Registers the schema migrations of a module. The schema version of the module is the number of migrations.
+    // Must be called before the module's data is accessed, normally from the module constructor.
+    pub fn register(&self, module: &str, migrations: &'static [Migration]) -> Result<()> {
+        self.migrations.lock().unwrap().insert(module.to_owned(), migrations);
+
+        let lock = self.module_lock(module);
+        let mut migrated = lock.lock().unwrap();
+
+        // Migrate right away so that it happens at startup.
+        *migrated = false;
+        try!(self.migrate(module));
+        *migrated = true;
+
+        Ok(())
+    }
+
+    pub fn get<T: DeserializeOwned>(&self, module: &str, scope: Scope, key: &str) -> Result<Option<T>> {
+        let value = try!(self.with_module(module, |backend| backend.get(module, &scope.key(), key)));
+
+        match value {
+            Some(value) => Ok(Some(try!(serde_json::from_value(value)))),
+            None => Ok(None),
+        }
+    }
+
+    pub fn set<T: Serialize>(&self, module: &str, scope: Scope, key: &str, value: &T) -> Result<()> {
+        let value = try!(serde_json::to_value(value));
+        self.with_module(module, |backend| backend.set(module, &scope.key(), key, Some(&value)))
+    }
+
+    pub fn remove(&self, module: &str, scope: Scope, key: &str) -> Result<()> {
+        self.with_module(module, |backend| backend.set(module, &scope.key(), key, None))
+    }
+
+    // Atomically replaces a value with the result of the function. Returning None removes the value.
+    pub fn update<T, F>(&self, module: &str, scope: Scope, key: &str, f: F) -> Result<()>
+        where T: Serialize + DeserializeOwned,
+              F: FnOnce(Option<T>) -> Option<T>
+    {
+        let scope_key = scope.key();
+
+        self.with_module(module, |backend| {
+            let value = match try!(backend.get(module, &scope_key, key)) {
+                Some(value) => Some(try!(serde_json::from_value(value))),
+                None => None,
+            };
+
+            match f(value) {
+                Some(value) => {
+                    let value = try!(serde_json::to_value(&value));
+                    backend.set(module, &scope_key, key, Some(&value))
+                }
+                None => backend.set(module, &scope_key, key, None),
+            }
+        })
+    }
+
+    // Returns all scopes which have the given key set.
+    pub fn scopes_with(&self, module: &str, key: &str) -> Result<Vec<Scope>> {
+        let scopes = try!(self.with_module(module, |backend| backend.scopes_with(module, key)));
+        Ok(scopes.iter().filter_map(|x| Scope::from_key(x)).collect())
+    }
+
+    // Returns the keys in the scope which start with the prefix, sorted.
+    pub fn keys(&self, module: &str, scope: Scope, prefix: &str) -> Result<Vec<String>> {
+        self.with_module(module, |backend| backend.keys(module, &scope.key(), prefix))
+    }
+
+    fn module_lock(&self, module: &str) -> Arc<Mutex<bool>> {
+        self.modules
+            .lock()
+            .unwrap()
+            .entry(module.to_owned())
+            .or_insert_with(|| Arc::new(Mutex::new(false)))
+            .clone()
+    }
+
+    // Runs the function while holding the lock of the module, once its data is of the current version.
+    fn with_module<T, F>(&self, module: &str, f: F) -> Result<T>
+        where F: FnOnce(&Backend) -> Result<T>
+    {
+        let lock = self.module_lock(module);
+        let mut migrated = lock.lock().unwrap();
+
+        if !*migrated {
+            try!(self.migrate(module));
+            *migrated = true;
+        }
+
+        f(&*self.backend)
+    }
+
+    // Brings the data of a module up to its current schema version. The module lock must be held.
+    fn migrate(&self, module: &str) -> Result<()> {
+        let migrations = self.migrations.lock().unwrap().get(module).cloned().unwrap_or(&[]);
+        let version = try!(self.backend.get_version(module));
+
+        if version as usize > migrations.len() {
+            return Err(Error::Migration(format!("the data of `{}` is of a newer version ({}) than supported ({})",
+                                                module,
+                                                version,
+                                                migrations.len())));
+        }
+
+        if (version as usize) < migrations.len() {
+            let mut data = try!(self.backend.load(module));
+
+            for migration in &migrations[version as usize..] {
+                try!(migration(&mut data).map_err(Error::Migration));
+            }
+
+            try!(self.backend.replace(module, migrations.len() as u32, &data));
+            println!("[Info] Migrated the storage of `{}` to version {}.", module, migrations.len());
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Backend, JsonBackend, Scope, Storage};
+    #[cfg(feature = "sqlite")]
+    use super::SqliteBackend;
+    use discord::model::{ServerId, UserId};
+    use serde_json::{Map, Value};
+    use std::env;
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+    use std::path::{Path, PathBuf};
+
+    fn temp_directory(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("yalter-bot-storage-test-{}", name));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        path
+    }
+
+    fn json(directory: &Path) -> Box<Backend> {
+        Box::new(JsonBackend::new(directory))
+    }
+
+    #[cfg(feature = "sqlite")]
+    fn sqlite(directory: &Path) -> Box<Backend> {
+        Box::new(SqliteBackend::open(&directory.join("test.sqlite3")).unwrap())
+    }
+
+    fn round_trip(directory: PathBuf, backend: fn(&Path) -> Box<Backend>) {
+        {
+            let storage = Storage::new(backend(&directory));
+            storage.set("test", Scope::Server(ServerId(1)), "roles", &vec![1u64, 2]).unwrap();
+            storage.set("test", Scope::Member(ServerId(1), UserId(2)), "warnings", &3u32).unwrap();
+        }
+
+        let storage = Storage::new(backend(&directory));
+        assert_eq!(Some(vec![1u64, 2]),
+                   storage.get("test", Scope::Server(ServerId(1)), "roles").unwrap());
+        assert_eq!(Some(3u32),
+                   storage.get("test", Scope::Member(ServerId(1), UserId(2)), "warnings").unwrap());
+        assert_eq!(None::<u32>, storage.get("test", Scope::User(UserId(2)), "warnings").unwrap());
+        assert_eq!(vec![Scope::Member(ServerId(1), UserId(2))],
+                   storage.scopes_with("test", "warnings").unwrap());
+
+        for key in &["message:2", "other", "message:1"] {
+            storage.set("test", Scope::Server(ServerId(1)), key, &true).unwrap();
+        }
+
+        assert_eq!(vec!["message:1".to_owned(), "message:2".to_owned()],
+                   storage.keys("test", Scope::Server(ServerId(1)), "message:").unwrap());
+
+        storage.remove("test", Scope::Server(ServerId(1)), "roles").unwrap();
+        assert_eq!(None::<Vec<u64>>, storage.get("test", Scope::Server(ServerId(1)), "roles").unwrap());
+
+        for _ in 0..3 {
+            storage.update("test", Scope::Global, "counter", |x: Option<u32>| Some(x.unwrap_or(0) + 1)).unwrap();
+        }
+
+        assert_eq!(Some(3u32), storage.get("test", Scope::Global, "counter").unwrap());
+
+        let _ = fs::remove_dir_all(&directory);
+    }
+
+    #[test]
+    fn json_round_trip() {
+        round_trip(temp_directory("json-round-trip"), json);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn sqlite_round_trip() {
+        round_trip(temp_directory("sqlite-round-trip"), sqlite);
+    }
+
+    #[test]
+    fn json_journal() {
+        let directory = temp_directory("json-journal");
+
+        {
+            let storage = Storage::new(json(&directory));
+
+            for i in 0..5000u32 {
+                storage.set("test", Scope::Global, "counter", &i).unwrap();
+            }
+        }
+
+        // The journal was folded into the file on the way instead of growing with every change.
+        assert!(fs::metadata(directory.join("test.journal")).unwrap().len() < 5000 * 20);
+
+        // The bot stopped in the middle of a change.
+        OpenOptions::new()
+            .append(true)
+            .open(directory.join("test.journal"))
+            .unwrap()
+            .write_all(b"{\"Set\":[\"global\",\"coun")
+            .unwrap();
+
+        {
+            let storage = Storage::new(json(&directory));
+            assert_eq!(Some(4999u32), storage.get("test", Scope::Global, "counter").unwrap());
+            storage.set("test", Scope::Global, "counter", &5000u32).unwrap();
+        }
+
+        let storage = Storage::new(json(&directory));
+        assert_eq!(Some(5000u32), storage.get("test", Scope::Global, "counter").unwrap());
+
+        let _ = fs::remove_dir_all(&directory);
+    }
+
+    fn rename_counter(data: &mut Map<String, Value>) -> Result<(), String> {
+        for (_, values) in data.iter_mut() {
+            if let Some(values) = values.as_object_mut() {
+                if let Some(value) = values.remove("old_counter") {
+                    values.insert("counter".to_owned(), value);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn migration(directory: PathBuf, backend: fn(&Path) -> Box<Backend>) {
+        {
+            let storage = Storage::new(backend(&directory));
+            storage.set("test", Scope::Global, "old_counter", &5u32).unwrap();
+        }
+
+        static MIGRATIONS: [super::Migration; 1] = [rename_counter];
+
+        {
+            let storage = Storage::new(backend(&directory));
+            storage.register("test", &MIGRATIONS).unwrap();
+            assert_eq!(Some(5u32), storage.get("test", Scope::Global, "counter").unwrap());
+        }
+
+        // The data is newer than what a storage without the migration supports.
+        let storage = Storage::new(backend(&directory));
+        assert!(storage.get::<u32>("test", Scope::Global, "counter").is_err());
+
+        let _ = fs::remove_dir_all(&directory);
+    }
+
+    #[test]
+    fn json_migration() {
+        migration(temp_directory("json-migration"), json);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn sqlite_migration() {
+        migration(temp_directory("sqlite-migration"), sqlite);
+    }
+}
@@ -0,0 +1,300 @@
+use serde_json;
+use serde_json::{Map, Value};
+use std::cmp;
+use std::collections::hash_map::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use super::{Backend, Error, Result};
+
+// The journal is folded into the module file once it's larger than the file and at least this large.
+const MIN_COMPACTION_SIZE: u64 = 64 * 1024;
+
+#[derive(Serialize, Deserialize)]
+struct ModuleFile {
+    version: u32,
+    data: Map<String, Value>,
+}
+
+// One line of the journal.
+#[derive(Serialize, Deserialize)]
+enum Change {
+    Set(String, String, Value),
+    Remove(String, String),
+}
+
+struct ModuleState {
+    file: ModuleFile,
+    // The sizes of the module file and of the journal on disk.
+    file_size: u64,
+    journal_size: u64,
+    // Opened on the first change.
+    journal: Option<File>,
+}
+
+// Keeps every module in its own JSON file, which is loaded on first use.
+//
+// Changes are appended to a journal next to the file instead of rewriting it every time,
+// and the journal is folded into the file once it grows larger than the file itself.
+// The journal is written without waiting for the disk, the file is rewritten atomically.
+// Every module has its own lock, so a busy module doesn't hold up the others.
+pub struct JsonBackend {
+    directory: PathBuf,
+    modules: Mutex<HashMap<String, Arc<Mutex<ModuleState>>>>,
+}
+
+impl ModuleState {
+    fn apply(&mut self, change: Change) {
+        let data = &mut self.file.data;
+
+        match change {
+            Change::Set(scope, key, value) => {
+                let values = data.entry(scope).or_insert_with(|| Value::Object(Map::new()));
+
+                if !values.is_object() {
+                    *values = Value::Object(Map::new());
+                }
+
+                values.as_object_mut().unwrap().insert(key, value);
+            }
+
+            Change::Remove(scope, key) => {
+                let empty = match data.get_mut(&scope).and_then(|x| x.as_object_mut()) {
+                    Some(values) => {
+                        values.remove(&key);
+                        values.is_empty()
+                    }
+                    None => false,
+                };
+
+                if empty {
+                    data.remove(&scope);
+                }
+            }
+        }
+    }
+}
+
+impl JsonBackend {
+    pub fn new<P: Into<PathBuf>>(directory: P) -> Self {
+        JsonBackend {
+            directory: directory.into(),
+            modules: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn module(&self, module: &str) -> Result<Arc<Mutex<ModuleState>>> {
+        let mut modules = self.modules.lock().unwrap();
+
+        if !modules.contains_key(module) {
+            let state = try!(self.load_module(module));
+            modules.insert(module.to_owned(), Arc::new(Mutex::new(state)));
+        }
+
+        Ok(modules[module].clone())
+    }
+
+    // Reads the module file and replays the journal on top of it.
+    fn load_module(&self, module: &str) -> Result<ModuleState> {
+        let (file, file_size) = match File::open(self.path(module)) {
+            Ok(f) => {
+                let size = try!(f.metadata()).len();
+                (try!(serde_json::de::from_reader(BufReader::new(f))), size)
+            }
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => {
+                (ModuleFile {
+                     version: 0,
+                     data: Map::new(),
+                 },
+                 0)
+            }
+            Err(err) => return Err(Error::IO(err)),
+        };
+
+        let mut state = ModuleState {
+            file: file,
+            file_size: file_size,
+            journal_size: 0,
+            journal: None,
+        };
+
+        let mut broken = false;
+
+        match File::open(self.journal_path(module)) {
+            Ok(f) => {
+                for line in BufReader::new(f).lines() {
+                    let line = try!(line);
+                    state.journal_size += line.len() as u64 + 1;
+
+                    match serde_json::from_str(&line) {
+                        Ok(change) => state.apply(change),
+                        // The bot stopped in the middle of writing the line, so the change wasn't confirmed.
+                        Err(_) => broken = true,
+                    }
+                }
+            }
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(Error::IO(err)),
+        }
+
+        // New lines would be glued to the broken one otherwise.
+        if broken {
+            println!("[Warning] Skipped a broken line in the storage journal of `{}`.", module);
+            try!(self.compact(module, &mut state));
+        }
+
+        Ok(state)
+    }
+
+    // Writes into a temporary file and renames it over the old one so that a failed write can't lose the data.
+    // Returns the size of the file.
+    fn save(&self, module: &str, file: &ModuleFile) -> Result<u64> {
+        try!(fs::create_dir_all(&self.directory));
+
+        let path = self.path(module);
+        let temp_path = path.with_extension("json.tmp");
+
+        let size = {
+            let mut temp_file = try!(File::create(&temp_path));
+            try!(serde_json::ser::to_writer(&mut temp_file, file));
+            try!(temp_file.flush());
+            try!(temp_file.sync_all());
+            try!(temp_file.metadata()).len()
+        };
+
+        try!(fs::rename(&temp_path, &path));
+
+        Ok(size)
+    }
+
+    // Writes the whole module into its file and empties the journal.
+    fn compact(&self, module: &str, state: &mut ModuleState) -> Result<()> {
+        state.file_size = try!(self.save(module, &state.file));
+        state.journal = None;
+        state.journal_size = 0;
+        try!(File::create(self.journal_path(module)));
+
+        Ok(())
+    }
+
+    fn append(&self, module: &str, state: &mut ModuleState, line: &str) -> Result<()> {
+        if state.journal.is_none() {
+            try!(fs::create_dir_all(&self.directory));
+            state.journal = Some(try!(OpenOptions::new().create(true).append(true).open(self.journal_path(module))));
+        }
+
+        try!(state.journal.as_mut().unwrap().write_all(line.as_bytes()));
+        state.journal_size += line.len() as u64;
+
+        Ok(())
+    }
+
+    fn path(&self, module: &str) -> PathBuf {
+        self.directory.join(format!("{}.json", module))
+    }
+
+    fn journal_path(&self, module: &str) -> PathBuf {
+        self.directory.join(format!("{}.journal", module))
+    }
+}
+
+impl Backend for JsonBackend {
+    fn get_version(&self, module: &str) -> Result<u32> {
+        let state = try!(self.module(module));
+        let version = state.lock().unwrap().file.version;
+        Ok(version)
+    }
+
+    fn load(&self, module: &str) -> Result<Map<String, Value>> {
+        let state = try!(self.module(module));
+        let data = state.lock().unwrap().file.data.clone();
+        Ok(data)
+    }
+
+    fn replace(&self, module: &str, version: u32, data: &Map<String, Value>) -> Result<()> {
+        let state = try!(self.module(module));
+        let mut state = state.lock().unwrap();
+
+        let file = ModuleFile {
+            version: version,
+            data: data.clone(),
+        };
+
+        // Only keep the data if it was saved.
+        state.file_size = try!(self.save(module, &file));
+        state.file = file;
+        state.journal = None;
+        state.journal_size = 0;
+        try!(File::create(self.journal_path(module)));
+
+        Ok(())
+    }
+
+    fn get(&self, module: &str, scope: &str, key: &str) -> Result<Option<Value>> {
+        let state = try!(self.module(module));
+        let value = state.lock().unwrap().file.data.get(scope).and_then(|x| x.get(key)).cloned();
+        Ok(value)
+    }
+
+    fn set(&self, module: &str, scope: &str, key: &str, value: Option<&Value>) -> Result<()> {
+        let state = try!(self.module(module));
+        let mut state = state.lock().unwrap();
+
+        // Don't grow the journal with changes which don't change anything.
+        if state.file.data.get(scope).and_then(|x| x.get(key)) == value {
+            return Ok(());
+        }
+
+        let change = match value {
+            Some(value) => Change::Set(scope.to_owned(), key.to_owned(), value.clone()),
+            None => Change::Remove(scope.to_owned(), key.to_owned()),
+        };
+
+        let mut line = try!(serde_json::to_string(&change));
+        line.push('\n');
+
+        // Only keep the change if it was written.
+        if let Err(err) = self.append(module, &mut state, &line) {
+            // Don't leave a partly written line behind.
+            let _ = self.compact(module, &mut state);
+            return Err(err);
+        }
+
+        state.apply(change);
+
+        if state.journal_size > cmp::max(state.file_size, MIN_COMPACTION_SIZE) {
+            // The change is already in the journal, so this can fail without losing it.
+            if let Err(err) = self.compact(module, &mut state) {
+                println!("[Warning] Couldn't compact the storage journal of `{}`: {}", module, err);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn scopes_with(&self, module: &str, key: &str) -> Result<Vec<String>> {
+        let state = try!(self.module(module));
+        let scopes = state.lock()
+            .unwrap()
+            .file
+            .data
+            .iter()
+            .filter(|&(_, values)| values.get(key).is_some())
+            .map(|(scope, _)| scope.clone())
+            .collect();
+        Ok(scopes)
+    }
+
+    fn keys(&self, module: &str, scope: &str, prefix: &str) -> Result<Vec<String>> {
+        let state = try!(self.module(module));
+
+        let mut keys = match state.lock().unwrap().file.data.get(scope).and_then(|x| x.as_object()) {
+            Some(values) => values.keys().filter(|x| x.starts_with(prefix)).cloned().collect::<Vec<_>>(),
+            None => Vec::new(),
+        };
+
+        keys.sort();
+        Ok(keys)
+    }
+}
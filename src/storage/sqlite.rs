@@ -0,0 +1,183 @@
+use rusqlite::{Connection, OptionalExtension};
+use serde_json;
+use serde_json::{Map, Value};
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+use super::{Backend, Error, Result};
+
+// The schema of the database itself. The current version is kept in `PRAGMA user_version`.
+static SCHEMA_MIGRATIONS: [&'static str; 1] =
+    ["CREATE TABLE modules (
+          name TEXT PRIMARY KEY NOT NULL,
+          version INTEGER NOT NULL
+      );
+      CREATE TABLE data (
+          module TEXT NOT NULL,
+          scope TEXT NOT NULL,
+          key TEXT NOT NULL,
+          value TEXT NOT NULL,
+          PRIMARY KEY (module, scope, key)
+      );
+      CREATE INDEX data_by_key ON data (module, key);"];
+
+// Wait this long for other processes holding the database lock.
+const BUSY_TIMEOUT_MS: u64 = 5000;
+
+// Keeps all modules in one SQLite database, one row per stored value.
+pub struct SqliteBackend {
+    connection: Mutex<Connection>,
+}
+
+impl SqliteBackend {
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(directory) = path.parent() {
+            try!(fs::create_dir_all(directory));
+        }
+
+        let mut connection = try!(Connection::open(path));
+        try!(connection.busy_timeout(Duration::from_millis(BUSY_TIMEOUT_MS)));
+        try!(migrate_schema(&mut connection));
+
+        Ok(SqliteBackend { connection: Mutex::new(connection) })
+    }
+}
+
+fn migrate_schema(connection: &mut Connection) -> Result<()> {
+    let version: i64 = try!(connection.query_row("PRAGMA user_version", [], |row| row.get(0)));
+    let version = version as usize;
+
+    if version > SCHEMA_MIGRATIONS.len() {
+        return Err(Error::Migration(format!("the database is of a newer version ({}) than supported ({})",
+                                            version,
+                                            SCHEMA_MIGRATIONS.len())));
+    }
+
+    for (i, migration) in SCHEMA_MIGRATIONS.iter().enumerate().skip(version) {
+        let transaction = try!(connection.transaction());
+        try!(transaction.execute_batch(migration));
+        try!(transaction.execute_batch(&format!("PRAGMA user_version = {}", i + 1)));
+        try!(transaction.commit());
+    }
+
+    Ok(())
+}
+
+fn set_value(connection: &Connection, module: &str, scope: &str, key: &str, value: &Value) -> Result<()> {
+    let value = try!(serde_json::to_string(value));
+
+    try!(connection.execute("INSERT OR REPLACE INTO data (module, scope, key, value) VALUES (?1, ?2, ?3, ?4)",
+                            [module, scope, key, &value]));
+
+    Ok(())
+}
+
+impl Backend for SqliteBackend {
+    fn get_version(&self, module: &str) -> Result<u32> {
+        let connection = self.connection.lock().unwrap();
+
+        let version: Option<i64> = try!(connection.query_row("SELECT version FROM modules WHERE name = ?1",
+                                                             [module],
+                                                             |row| row.get(0))
+                                            .optional());
+
+        Ok(version.unwrap_or(0) as u32)
+    }
+
+    fn load(&self, module: &str) -> Result<Map<String, Value>> {
+        let connection = self.connection.lock().unwrap();
+
+        let mut stmt = try!(connection.prepare("SELECT scope, key, value FROM data WHERE module = ?1"));
+        let rows = try!(stmt.query_map([module], |row| {
+            Ok((try!(row.get::<_, String>(0)), try!(row.get::<_, String>(1)), try!(row.get::<_, String>(2))))
+        }));
+
+        let mut data = Map::new();
+
+        for row in rows {
+            let (scope, key, value) = try!(row);
+            let value: Value = try!(serde_json::from_str(&value));
+
+            if let Some(values) = data.entry(scope)
+                   .or_insert_with(|| Value::Object(Map::new()))
+                   .as_object_mut() {
+                values.insert(key, value);
+            }
+        }
+
+        Ok(data)
+    }
+
+    fn replace(&self, module: &str, version: u32, data: &Map<String, Value>) -> Result<()> {
+        let mut connection = self.connection.lock().unwrap();
+
+        // Dropping the transaction without committing rolls it back.
+        let transaction = try!(connection.transaction());
+
+        try!(transaction.execute("DELETE FROM data WHERE module = ?1", [module]));
+
+        for (scope, values) in data {
+            if let Some(values) = values.as_object() {
+                for (key, value) in values {
+                    try!(set_value(&transaction, module, scope, key, value));
+                }
+            }
+        }
+
+        try!(transaction.execute("INSERT OR REPLACE INTO modules (name, version) VALUES (?1, ?2)",
+                                 (module, version as i64)));
+
+        try!(transaction.commit());
+        Ok(())
+    }
+
+    fn get(&self, module: &str, scope: &str, key: &str) -> Result<Option<Value>> {
+        let connection = self.connection.lock().unwrap();
+
+        let value: Option<String> =
+            try!(connection.query_row("SELECT value FROM data WHERE module = ?1 AND scope = ?2 AND key = ?3",
+                                      [module, scope, key],
+                                      |row| row.get(0))
+                     .optional());
+
+        match value {
+            Some(value) => Ok(Some(try!(serde_json::from_str(&value)))),
+            None => Ok(None),
+        }
+    }
+
+    fn set(&self, module: &str, scope: &str, key: &str, value: Option<&Value>) -> Result<()> {
+        let connection = self.connection.lock().unwrap();
+
+        match value {
+            Some(value) => set_value(&connection, module, scope, key, value),
+
+            None => {
+                try!(connection.execute("DELETE FROM data WHERE module = ?1 AND scope = ?2 AND key = ?3",
+                                        [module, scope, key]));
+                Ok(())
+            }
+        }
+    }
+
+    fn scopes_with(&self, module: &str, key: &str) -> Result<Vec<String>> {
+        let connection = self.connection.lock().unwrap();
+
+        let mut stmt = try!(connection.prepare("SELECT scope FROM data WHERE module = ?1 AND key = ?2 ORDER BY scope"));
+        let scopes = try!(stmt.query_map([module, key], |row| row.get(0)));
+
+        Ok(try!(scopes.collect::<::rusqlite::Result<Vec<String>>>()))
+    }
+
+    fn keys(&self, module: &str, scope: &str, prefix: &str) -> Result<Vec<String>> {
+        let connection = self.connection.lock().unwrap();
+
+        let mut stmt = try!(connection.prepare("SELECT key FROM data \
+                                                WHERE module = ?1 AND scope = ?2 AND substr(key, 1, length(?3)) = ?3 \
+                                                ORDER BY key"));
+        let keys = try!(stmt.query_map([module, scope, prefix], |row| row.get(0)));
+
+        Ok(try!(keys.collect::<::rusqlite::Result<Vec<String>>>()))
+    }
+}